@@ -0,0 +1,65 @@
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Tracks when modified buffers were last written automatically.
+#[derive(Debug, Clone)]
+pub struct AutoSaveTimer {
+    interval: Duration,
+    last_save: Instant,
+}
+
+impl AutoSaveTimer {
+    pub fn new(interval: Duration) -> Self {
+        Self::starting_at(interval, Instant::now())
+    }
+
+    pub fn starting_at(interval: Duration, start: Instant) -> Self {
+        Self {
+            interval,
+            last_save: start,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_save) >= self.interval
+    }
+
+    /// Restarts the countdown from `now`.
+    pub fn reset(&mut self, now: Instant) {
+        self.last_save = now;
+    }
+}
+
+impl Default for AutoSaveTimer {
+    fn default() -> Self {
+        Self::new(DEFAULT_AUTOSAVE_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline_elapsed() {
+        let start = Instant::now();
+        let mut timer = AutoSaveTimer::starting_at(Duration::from_secs(30), start);
+
+        assert!(!timer.is_due(start));
+        assert!(!timer.is_due(start + Duration::from_secs(29)));
+        assert!(timer.is_due(start + Duration::from_secs(30)));
+
+        timer.reset(start + Duration::from_secs(30));
+        assert!(!timer.is_due(start + Duration::from_secs(45)));
+        assert!(timer.is_due(start + Duration::from_secs(61)));
+    }
+}
@@ -0,0 +1,128 @@
+use crate::core::Result;
+use crate::file_io::{DirEntry, FileSystem};
+use std::path::{Path, PathBuf};
+
+/// A read-only listing of one directory. Subdirectories come first with a
+/// trailing `/`, and `../` leads to the parent directory.
+#[derive(Debug, Clone)]
+pub struct DirBuffer {
+    path: PathBuf,
+    entries: Vec<DirEntry>,
+    selected: usize,
+}
+
+/// What pressing Enter on the selected entry leads to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirTarget {
+    Directory(PathBuf),
+    File(PathBuf),
+}
+
+impl DirBuffer {
+    pub fn open<P: AsRef<Path>>(file_system: &FileSystem, path: P) -> Result<Self> {
+        let resolved = file_system.resolve_path(path);
+        let path = resolved.canonicalize().unwrap_or(resolved);
+        let listing = file_system.list_directory(&path)?;
+        let has_parent = path.parent().is_some();
+
+        Ok(Self {
+            path,
+            entries: sort_entries(listing, has_parent),
+            selected: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entries(&self) -> &[DirEntry] {
+        &self.entries
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn move_selection(&mut self, delta: i32) {
+        let last = self.entries.len().saturating_sub(1);
+        self.selected = (self.selected as i64 + delta as i64).clamp(0, last as i64) as usize;
+    }
+
+    pub fn target(&self) -> Option<DirTarget> {
+        let entry = self.entries.get(self.selected)?;
+        if entry.name == ".." {
+            return self.path.parent().map(|parent| DirTarget::Directory(parent.to_path_buf()));
+        }
+
+        let path = self.path.join(&entry.name);
+        Some(if entry.is_dir {
+            DirTarget::Directory(path)
+        } else {
+            DirTarget::File(path)
+        })
+    }
+
+    /// One line per entry, directories marked with a trailing `/`.
+    pub fn render(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| {
+                if entry.is_dir {
+                    format!("{}/", entry.name)
+                } else {
+                    entry.name.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Directories before files, each group sorted by name, with `..` on top.
+fn sort_entries(mut entries: Vec<DirEntry>, has_parent: bool) -> Vec<DirEntry> {
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    if has_parent {
+        entries.insert(0, DirEntry {
+            name: "..".to_string(),
+            is_dir: true,
+        });
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_listing_is_sorted_dirs_first() {
+        let temp_dir = tempdir().unwrap();
+        fs::write(temp_dir.path().join("b.txt"), "").unwrap();
+        fs::write(temp_dir.path().join("a.txt"), "").unwrap();
+        fs::create_dir(temp_dir.path().join("zdir")).unwrap();
+        fs::create_dir(temp_dir.path().join("src")).unwrap();
+
+        let dir = DirBuffer::open(&FileSystem::default(), temp_dir.path()).unwrap();
+        assert_eq!(dir.render(), "../\nsrc/\nzdir/\na.txt\nb.txt");
+    }
+
+    #[test]
+    fn test_enter_targets() {
+        let temp_dir = tempdir().unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), "").unwrap();
+        fs::create_dir(temp_dir.path().join("sub")).unwrap();
+
+        let mut dir = DirBuffer::open(&FileSystem::default(), temp_dir.path()).unwrap();
+        let root = dir.path().to_path_buf();
+
+        assert_eq!(dir.target(), root.parent().map(|p| DirTarget::Directory(p.to_path_buf())));
+        dir.move_selection(1);
+        assert_eq!(dir.target(), Some(DirTarget::Directory(root.join("sub"))));
+        dir.move_selection(5);
+        assert_eq!(dir.selected(), 2);
+        assert_eq!(dir.target(), Some(DirTarget::File(root.join("notes.txt"))));
+    }
+}
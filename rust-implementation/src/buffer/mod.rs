@@ -1,20 +1,39 @@
 use crate::core::{EditorError, Result, TextBuffer};
-use std::collections::VecDeque;
+use crate::search::Substitution;
+use crate::undo::EditorAction;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 pub mod multi_buffer;
-pub use multi_buffer::MultiBuffer;
+pub use multi_buffer::{DiskChange, MultiBuffer, QuitChoice};
 
 #[derive(Debug, Clone)]
 pub struct Buffer {
     content: String,
     lines: Vec<String>,
+    /// Bumped by every edit. Revisions come from one counter shared by all
+    /// buffers, so a buffer that replaces another is newer than all of it.
+    revision: u64,
+    /// The revision that last changed each line, or moved it to its index.
+    line_revisions: Vec<u64>,
+    /// The edits made since `take_changes` last handed them over, oldest
+    /// first, for the undo history to keep.
+    changes: Vec<EditorAction>,
+    /// Whether `content` ends with a newline that no line holds, as a file
+    /// read in does. The next edit rebuilds the content without it.
+    trailing_newline: bool,
 }
 
 impl Buffer {
     pub fn new() -> Self {
+        let revision = next_revision();
         Self {
             content: String::new(),
             lines: vec![String::new()],
+            revision,
+            line_revisions: vec![revision],
+            changes: Vec::new(),
+            trailing_newline: false,
         }
     }
 
@@ -25,52 +44,373 @@ impl Buffer {
             content.lines().map(|s| s.to_string()).collect()
         };
 
-        Self { content, lines }
+        let revision = next_revision();
+        let line_revisions = vec![revision; lines.len()];
+        let trailing_newline = content.ends_with('\n');
+        Self {
+            content,
+            lines,
+            revision,
+            line_revisions,
+            changes: Vec::new(),
+            trailing_newline,
+        }
     }
 
-    fn rebuild_content(&mut self) {
-        self.content = self.lines.join("\n");
+    pub fn revision(&self) -> u64 {
+        self.revision
     }
 
-    fn rebuild_lines(&mut self) {
-        self.lines = if self.content.is_empty() {
-            vec![String::new()]
-        } else {
-            self.content.lines().map(|s| s.to_string()).collect()
-        };
+    pub fn line_revision(&self, line: usize) -> u64 {
+        self.line_revisions.get(line).copied().unwrap_or(self.revision)
     }
 
-    fn position_to_line_col(&self, pos: usize) -> Result<(usize, usize)> {
-        let mut current_pos = 0;
+    /// The lines changed after `revision`, which are all a redraw showing
+    /// that revision needs to repaint.
+    pub fn changed_lines_since(&self, revision: u64) -> Vec<usize> {
+        (0..self.lines.len()).filter(|&line| self.line_revision(line) > revision).collect()
+    }
 
-        for (line_idx, line) in self.lines.iter().enumerate() {
-            if current_pos + line.len() >= pos {
-                return Ok((line_idx, pos - current_pos));
+    /// Hands over the edits made since the last call, oldest first. Each
+    /// holds only the text it added or removed, and `apply` replays it.
+    pub fn take_changes(&mut self) -> Vec<EditorAction> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Whether there are edits `take_changes` has not handed over yet.
+    pub fn has_changes(&self) -> bool {
+        !self.changes.is_empty()
+    }
+
+    /// Puts `content` in place of the whole text, as reloading a file does.
+    /// Only the lines that differ are kept as the change.
+    pub fn replace_content(&mut self, content: String) {
+        let replacement = Buffer::from_content(content);
+        if replacement.content != self.content {
+            self.record_lines_replaced(0..self.lines.len(), &replacement.lines);
+            if replacement.trailing_newline {
+                let position = replacement.content.len() - 1;
+                self.record(EditorAction::InsertText { position, text: "\n".to_string() });
             }
-            current_pos += line.len() + 1; // +1 for newline
         }
+        let changes = std::mem::take(&mut self.changes);
+        *self = Buffer { changes, ..replacement };
+    }
 
-        Err(EditorError::CursorOutOfBounds)
+    /// Splits `line` in two at byte column `col`; the text from `col` on
+    /// becomes the following line.
+    pub fn split_line(&mut self, line: usize, col: usize) -> Result<()> {
+        let text = self.lines.get(line).ok_or(EditorError::CursorOutOfBounds)?;
+        if col > text.len() || !text.is_char_boundary(col) {
+            return Err(EditorError::CursorOutOfBounds);
+        }
+
+        let position = self.line_col_to_position(line, col)?;
+        self.record(EditorAction::Insert { position, character: '\n' });
+        let right = self.lines[line].split_off(col);
+        self.lines.insert(line + 1, right);
+        self.lines_changed(line, line + 1);
+        self.rebuild_content();
+        Ok(())
     }
 
-    fn line_col_to_position(&self, line: usize, col: usize) -> Result<usize> {
+    /// Appends line `line + 1` to `line`, removing the line break between.
+    pub fn join_line(&mut self, line: usize) -> Result<()> {
         if line >= self.lines.len() {
             return Err(EditorError::CursorOutOfBounds);
         }
+        if line + 1 == self.lines.len() {
+            return Err(EditorError::InvalidOperation("No line below to join".to_string()));
+        }
 
-        let mut pos = 0;
-        for i in 0..line {
-            pos += self.lines[i].len() + 1; // +1 for newline
+        let position = self.line_col_to_position(line, self.lines[line].len())?;
+        self.record(EditorAction::Delete { position, character: '\n' });
+        let next_line = self.lines.remove(line + 1);
+        self.lines[line].push_str(&next_line);
+        self.lines_changed(line, line);
+        self.rebuild_content();
+        Ok(())
+    }
+
+    /// Removes line `index` and returns its text. Removing the only line
+    /// leaves a single empty line, so the buffer always has one.
+    pub fn remove_line(&mut self, index: usize) -> Result<String> {
+        if index >= self.lines.len() {
+            return Err(EditorError::CursorOutOfBounds);
+        }
+
+        self.record_lines_replaced(index..index + 1, &[]);
+        let removed = self.lines.remove(index);
+        if self.lines.is_empty() {
+            self.lines.push(String::new());
+        }
+        self.lines_changed(index, index);
+        self.rebuild_content();
+        Ok(removed)
+    }
+
+    /// Sorts the lines by byte value, as `:sort` does.
+    pub fn sort_lines(&mut self) {
+        let mut sorted = self.lines.clone();
+        sorted.sort();
+        self.record_lines_replaced(0..self.lines.len(), &sorted);
+        self.lines = sorted;
+        self.lines_changed(0, self.lines.len() - 1);
+        self.rebuild_content();
+    }
+
+    /// Removes every line that repeats the line just above it, as `uniq`
+    /// does, and returns how many were removed. Duplicates that are not
+    /// adjacent are kept.
+    pub fn dedup_consecutive_lines(&mut self) -> usize {
+        let Some(first) = (1..self.lines.len()).find(|&line| self.lines[line] == self.lines[line - 1]) else {
+            return 0;
+        };
+
+        let before = self.lines.len();
+        let mut deduped = self.lines.clone();
+        deduped.dedup();
+        self.record_lines_replaced(0..self.lines.len(), &deduped);
+        self.lines = deduped;
+        self.lines_changed(first, first);
+        self.rebuild_content();
+        before - self.lines.len()
+    }
+
+    /// Flips the order of the lines in `range`.
+    pub fn reverse_lines(&mut self, range: Range<usize>) -> Result<()> {
+        if range.start > range.end || range.end > self.lines.len() {
+            return Err(EditorError::CursorOutOfBounds);
         }
+        if range.is_empty() {
+            return Ok(());
+        }
+
+        let reversed: Vec<String> = self.lines[range.clone()].iter().rev().cloned().collect();
+        self.record_lines_replaced(range.clone(), &reversed);
+        self.lines[range.clone()].reverse();
+        self.lines_changed(range.start, range.end - 1);
+        self.rebuild_content();
+        Ok(())
+    }
 
-        if col > self.lines[line].len() {
+    /// Puts `lines` in place of the lines in `range`, which may be more or
+    /// fewer of them. The buffer keeps at least one, empty, line.
+    pub fn replace_lines(&mut self, range: Range<usize>, lines: Vec<String>) -> Result<()> {
+        if range.start > range.end || range.end > self.lines.len() {
             return Err(EditorError::CursorOutOfBounds);
         }
 
-        Ok(pos + col)
+        let first = range.start;
+        self.record_lines_replaced(range.clone(), &lines);
+        self.lines.splice(range, lines);
+        if self.lines.is_empty() {
+            self.lines.push(String::new());
+        }
+        self.lines_changed(first, usize::MAX);
+        self.rebuild_content();
+        Ok(())
+    }
+
+    /// Runs `substitution` over the lines in `range`, returning how many
+    /// replacements were made. Lines without a match are left alone.
+    pub fn substitute(&mut self, range: Range<usize>, substitution: &Substitution) -> Result<usize> {
+        if range.start > range.end || range.end > self.lines.len() {
+            return Err(EditorError::CursorOutOfBounds);
+        }
+
+        let mut total = 0;
+        let mut changed = None;
+        let mut position = self.line_col_to_position(range.start, 0).unwrap_or(self.content.len());
+        for line in range {
+            let (replaced, count) = substitution.apply(&self.lines[line]);
+            if count > 0 {
+                let text = std::mem::take(&mut self.lines[line]);
+                self.record(EditorAction::Compound(vec![
+                    EditorAction::DeleteText { position, text },
+                    EditorAction::InsertText { position, text: replaced.clone() },
+                ]));
+                self.lines[line] = replaced;
+                total += count;
+                changed = Some(changed.map_or((line, line), |(first, _)| (first, line)));
+            }
+            position += self.lines[line].len() + 1;
+        }
+        if let Some((first, last)) = changed {
+            self.lines_changed(first, last);
+            self.rebuild_content();
+        }
+        Ok(total)
+    }
+
+    /// Replays `action`, as undo and redo do, with its positions as byte
+    /// offsets into the content. Text it deletes must be what the buffer
+    /// holds there; otherwise, or when any part of a compound action does
+    /// not fit, the buffer is left as it was.
+    pub fn apply(&mut self, action: &EditorAction) -> Result<()> {
+        let mismatch = || EditorError::InvalidOperation("Change does not match the buffer".to_string());
+        match action {
+            EditorAction::Insert { position, character } => self.insert(*position, *character),
+            EditorAction::InsertText { position, text } => self.insert_str(*position, text),
+            EditorAction::Delete { position, character } => {
+                if !self.content.get(*position..).is_some_and(|rest| rest.starts_with(*character)) {
+                    return Err(mismatch());
+                }
+                self.delete(*position)
+            }
+            EditorAction::DeleteText { position, text } => {
+                let range = *position..*position + text.len();
+                if self.content.get(range.clone()) != Some(text.as_str()) {
+                    return Err(mismatch());
+                }
+                self.delete_range(range)
+            }
+            EditorAction::Compound(actions) => {
+                let (start, trailing_newline) = (self.changes.len(), self.trailing_newline);
+                for action in actions {
+                    if let Err(error) = self.apply(action) {
+                        self.revert_changes_since(start, trailing_newline)?;
+                        return Err(error);
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Takes back the edits recorded after the first `start` changes, for
+    /// a compound change that failed partway.
+    fn revert_changes_since(&mut self, start: usize, trailing_newline: bool) -> Result<()> {
+        let applied = self.changes.split_off(start);
+        // The first edit dropped the newline a file ends with; it goes back
+        // the way it was read, with no line of its own
+        let dropped_newline = usize::from(trailing_newline && !self.trailing_newline);
+        for change in applied[dropped_newline..].iter().rev() {
+            self.apply(&change.inverse())?;
+        }
+        self.changes.truncate(start);
+        if dropped_newline == 1 {
+            self.content.push('\n');
+            self.trailing_newline = true;
+        }
+        Ok(())
+    }
+
+    /// Keeps `change` for `take_changes`. Edits call this once they know
+    /// they will go ahead but before they start, while its positions still
+    /// refer to the text it applies to.
+    fn record(&mut self, change: EditorAction) {
+        self.record_trailing_newline();
+        self.changes.push(change);
+    }
+
+    /// An edit's rebuild drops a newline that no line holds, so that is a
+    /// change too, made before any other.
+    fn record_trailing_newline(&mut self) {
+        if std::mem::take(&mut self.trailing_newline) {
+            let position = self.content.len() - 1;
+            self.changes.push(EditorAction::DeleteText { position, text: "\n".to_string() });
+        }
+    }
+
+    /// Keeps the change putting `lines` in place of the lines in `range`.
+    /// Lines at either end that stay the same are left out of it.
+    fn record_lines_replaced(&mut self, range: Range<usize>, lines: &[String]) {
+        let old = &self.lines[range.clone()];
+        let same_start = old.iter().zip(lines).take_while(|(a, b)| a == b).count();
+        let same_end = old[same_start..]
+            .iter()
+            .rev()
+            .zip(lines[same_start..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let (first, end) = (range.start + same_start, range.end - same_end);
+        let removed = self.lines[first..end].join("\n");
+        let added = lines[same_start..lines.len() - same_end].join("\n");
+        let start: usize = self.lines[..first].iter().map(|line| line.len() + 1).sum();
+
+        // Lines removed or added whole take a line break with them: the
+        // one after them, or the one before at the end of the buffer
+        let at_end = end == self.lines.len();
+        let change = match (first == end, same_start + same_end == lines.len()) {
+            (true, true) => return,
+            (false, true) if !at_end => EditorAction::DeleteText { position: start, text: removed + "\n" },
+            (false, true) if first > 0 => EditorAction::DeleteText { position: start - 1, text: format!("\n{}", removed) },
+            (false, true) if removed.is_empty() => return,
+            (false, true) => EditorAction::DeleteText { position: 0, text: removed },
+            (true, false) if !at_end => EditorAction::InsertText { position: start, text: added + "\n" },
+            (true, false) => EditorAction::InsertText { position: start - 1, text: format!("\n{}", added) },
+            (false, false) if added.is_empty() => EditorAction::DeleteText { position: start, text: removed },
+            (false, false) if removed.is_empty() => EditorAction::InsertText { position: start, text: added },
+            (false, false) => EditorAction::Compound(vec![
+                EditorAction::DeleteText { position: start, text: removed },
+                EditorAction::InsertText { position: start, text: added },
+            ]),
+        };
+        self.record(change);
+    }
+
+    /// Records an edit to lines `first..=last`. When
+    /// the edit added or removed lines, every line after `first` moved and
+    /// counts as changed too.
+    fn lines_changed(&mut self, first: usize, last: usize) {
+        self.revision = next_revision();
+        let last = if self.line_revisions.len() == self.lines.len() {
+            last
+        } else {
+            self.line_revisions.resize(self.lines.len(), self.revision);
+            usize::MAX
+        };
+        let end = last.saturating_add(1).min(self.lines.len());
+        for revision in &mut self.line_revisions[first.min(end)..end] {
+            *revision = self.revision;
+        }
+    }
+
+    fn rebuild_content(&mut self) {
+        self.record_trailing_newline();
+        self.content = self.lines.join("\n");
+    }
+
+    /// The line and column of byte offset `pos`. Each line owns the offsets
+    /// from its start up to and including its newline, so the newline's
+    /// offset is the end of that line: inserting there appends to it and
+    /// deleting there joins the next line on. The offset after the newline
+    /// is column 0 of the next line. A trailing newline, which has no empty
+    /// line of its own in `lines`, is the end of the last line.
+    fn position_to_line_col(&self, pos: usize) -> Result<(usize, usize)> {
+        let mut line_start = 0;
+        for (line_idx, line) in self.lines.iter().enumerate() {
+            let newline = line_start + line.len();
+            if pos <= newline {
+                return Ok((line_idx, pos - line_start));
+            }
+            line_start = newline + 1;
+        }
+
+        let last = self.lines.len() - 1;
+        if pos == line_start && self.content.ends_with('\n') {
+            return Ok((last, self.lines[last].len()));
+        }
+        Err(EditorError::CursorOutOfBounds)
+    }
+
+    /// The byte offset of column `col` of `line`, the inverse of
+    /// `position_to_line_col`. `col` may be the line's length, its newline.
+    fn line_col_to_position(&self, line: usize, col: usize) -> Result<usize> {
+        if line >= self.lines.len() || col > self.lines[line].len() {
+            return Err(EditorError::CursorOutOfBounds);
+        }
+        let line_start: usize = self.lines[..line].iter().map(|text| text.len() + 1).sum();
+        Ok(line_start + col)
     }
 }
 
+fn next_revision() -> u64 {
+    static REVISIONS: AtomicU64 = AtomicU64::new(1);
+    REVISIONS.fetch_add(1, Ordering::Relaxed)
+}
+
 impl Default for Buffer {
     fn default() -> Self {
         Self::new()
@@ -95,17 +435,46 @@ impl TextBuffer for Buffer {
             return Err(EditorError::CursorOutOfBounds);
         }
 
+        let (line_idx, col) = self.position_to_line_col(pos)?;
         if ch == '\n' {
-            let (line_idx, col) = self.position_to_line_col(pos)?;
-            // Avoid holding an immutable borrow while mutating self.lines
-            let right = self.lines[line_idx][col..].to_string();
-            self.lines[line_idx].truncate(col);
-            self.lines.insert(line_idx + 1, right);
-        } else {
-            let (line_idx, col) = self.position_to_line_col(pos)?;
-            self.lines[line_idx].insert(col, ch);
+            return self.split_line(line_idx, col);
         }
 
+        let position = self.line_col_to_position(line_idx, col)?;
+        self.record(EditorAction::Insert { position, character: ch });
+        self.lines[line_idx].insert(col, ch);
+        self.lines_changed(line_idx, line_idx);
+        self.rebuild_content();
+        Ok(())
+    }
+
+    fn insert_str(&mut self, pos: usize, text: &str) -> Result<()> {
+        if pos > self.content.len() {
+            return Err(EditorError::CursorOutOfBounds);
+        }
+
+        let (line_idx, col) = self.position_to_line_col(pos)?;
+        if !self.lines[line_idx].is_char_boundary(col) {
+            return Err(EditorError::CursorOutOfBounds);
+        }
+
+        if !text.is_empty() {
+            let position = self.line_col_to_position(line_idx, col)?;
+            self.record(EditorAction::InsertText { position, text: text.to_string() });
+        }
+
+        // The text's first line joins the head of this line and its last
+        // line takes the tail
+        let mut pieces: Vec<String> = text.split('\n').map(str::to_string).collect();
+        let tail = self.lines[line_idx].split_off(col);
+        if let Some(last) = pieces.last_mut() {
+            last.push_str(&tail);
+        }
+        let first = pieces.remove(0);
+        let added = pieces.len();
+        self.lines[line_idx].push_str(&first);
+        self.lines.splice(line_idx + 1..line_idx + 1, pieces);
+        self.lines_changed(line_idx, line_idx + added);
         self.rebuild_content();
         Ok(())
     }
@@ -116,18 +485,51 @@ impl TextBuffer for Buffer {
         }
 
         let (line_idx, col) = self.position_to_line_col(pos)?;
+        if !self.lines[line_idx].is_char_boundary(col) {
+            return Err(EditorError::InvalidOperation("Cannot delete inside a character".to_string()));
+        }
 
-        if col == 0 && line_idx > 0 {
-            // Delete newline - merge with previous line
-            let current_line = self.lines.remove(line_idx);
-            self.lines[line_idx - 1].push_str(&current_line);
-        } else if col > 0 {
+        if col < self.lines[line_idx].len() {
             // Delete character in current line
-            self.lines[line_idx].remove(col - 1);
+            let position = self.line_col_to_position(line_idx, col)?;
+            let character = self.lines[line_idx][col..].chars().next().unwrap_or_default();
+            self.record(EditorAction::Delete { position, character });
+            self.lines[line_idx].remove(col);
+        } else if line_idx + 1 < self.lines.len() {
+            // Delete newline - merge next line into this one
+            return self.join_line(line_idx);
         } else {
-            return Err(EditorError::InvalidOperation("Cannot delete at beginning of buffer".to_string()));
+            return Err(EditorError::InvalidOperation("Cannot delete at end of buffer".to_string()));
         }
 
+        self.lines_changed(line_idx, line_idx);
+        self.rebuild_content();
+        Ok(())
+    }
+
+    fn delete_range(&mut self, range: std::ops::Range<usize>) -> Result<()> {
+        if range.start > range.end || range.end > self.content.len() {
+            return Err(EditorError::CursorOutOfBounds);
+        }
+
+        let (first_line, first_col) = self.position_to_line_col(range.start)?;
+        let (last_line, last_col) = self.position_to_line_col(range.end)?;
+        if !self.lines[first_line].is_char_boundary(first_col) || !self.lines[last_line].is_char_boundary(last_col) {
+            return Err(EditorError::CursorOutOfBounds);
+        }
+
+        let start = self.line_col_to_position(first_line, first_col)?;
+        let end = self.line_col_to_position(last_line, last_col)?;
+        if end > start {
+            let text = self.content[start..end].to_string();
+            self.record(EditorAction::DeleteText { position: start, text });
+        }
+
+        let tail = self.lines[last_line][last_col..].to_string();
+        self.lines[first_line].truncate(first_col);
+        self.lines[first_line].push_str(&tail);
+        self.lines.drain(first_line + 1..=last_line);
+        self.lines_changed(first_line, first_line);
         self.rebuild_content();
         Ok(())
     }
@@ -137,14 +539,28 @@ impl TextBuffer for Buffer {
             return Ok(());
         }
 
-        self.content.push_str(text);
-        self.rebuild_lines();
+        // The newline a file ends with closes its last line, so the text
+        // starts a line of its own
+        let position = self.content.len() - usize::from(self.trailing_newline);
+        let separator = if self.content.ends_with('\n') { "\n" } else { "" };
+        self.record(EditorAction::InsertText { position, text: format!("{}{}", separator, text) });
+        if self.content.ends_with('\n') {
+            self.lines.push(String::new());
+        }
+        let last_line = self.lines.len() - 1;
+        let mut pieces = text.split('\n');
+        self.lines[last_line].push_str(pieces.next().unwrap_or_default());
+        self.lines.extend(pieces.map(str::to_string));
+        self.lines_changed(last_line, self.lines.len() - 1);
+        self.rebuild_content();
         Ok(())
     }
 
     fn clear(&mut self) {
-        self.content.clear();
+        self.record_lines_replaced(0..self.lines.len(), &[]);
         self.lines = vec![String::new()];
+        self.lines_changed(0, 0);
+        self.rebuild_content();
     }
 
     fn line_count(&self) -> usize {
@@ -158,6 +574,12 @@ impl TextBuffer for Buffer {
     fn get_line(&self, line: usize) -> Option<&str> {
         self.lines.get(line).map(|s| s.as_str())
     }
+
+    fn get_lines(&self, range: std::ops::Range<usize>) -> &[String] {
+        let end = range.end.min(self.lines.len());
+        let start = range.start.min(end);
+        &self.lines[start..end]
+    }
 }
 
 #[cfg(test)]
@@ -196,6 +618,226 @@ mod tests {
         let mut buffer = Buffer::from_content("Hello".to_string());
         buffer.delete(4).unwrap(); // Delete 'o'
         assert_eq!(buffer.content(), "Hell");
+
+        // The whole of a multibyte character goes, and never half of one
+        let mut buffer = Buffer::from_content("café!".to_string());
+        assert!(matches!(buffer.delete(4), Err(EditorError::InvalidOperation(_))));
+        buffer.delete(3).unwrap();
+        assert_eq!(buffer.content(), "caf!");
+    }
+
+    #[test]
+    fn test_line_boundary_positions() {
+        let buffer = Buffer::from_content("ab\n\ncd\n".to_string());
+        // "ab" is 0..=2 with its newline at 2, the empty line is 3, "cd" 4..=6
+        let expected = [(0, 0), (0, 1), (0, 2), (1, 0), (2, 0), (2, 1), (2, 2)];
+        for (pos, &(line, col)) in expected.iter().enumerate() {
+            assert_eq!(buffer.position_to_line_col(pos).unwrap(), (line, col));
+            assert_eq!(buffer.line_col_to_position(line, col).unwrap(), pos);
+        }
+        // The trailing newline ends the last line; nothing lies past it
+        assert_eq!(buffer.position_to_line_col(7).unwrap(), (2, 2));
+        assert!(buffer.position_to_line_col(8).is_err());
+        assert!(buffer.line_col_to_position(0, 3).is_err());
+    }
+
+    #[test]
+    fn test_edit_at_end_of_line() {
+        let mut buffer = Buffer::from_content("one\ntwo\nthree".to_string());
+
+        // At a newline, inserting appends to the line before it
+        buffer.insert(3, '!').unwrap();
+        assert_eq!(buffer.content(), "one!\ntwo\nthree");
+        buffer.insert_str(8, "?").unwrap();
+        assert_eq!(buffer.content(), "one!\ntwo?\nthree");
+        // and just after it, prepends to the next
+        buffer.insert(5, '>').unwrap();
+        assert_eq!(buffer.content(), "one!\n>two?\nthree");
+
+        // Deleting at a newline joins the lines around it
+        buffer.delete(4).unwrap();
+        assert_eq!(buffer.content(), "one!>two?\nthree");
+        assert_eq!(buffer.line_count(), 2);
+        buffer.delete(8).unwrap();
+        assert_eq!(buffer.content(), "one!>two\nthree");
+        buffer.delete(8).unwrap();
+        assert_eq!(buffer.content(), "one!>twothree");
+        assert!(buffer.delete(13).is_err());
+
+        let mut buffer = Buffer::from_content("end\n".to_string());
+        buffer.insert(4, 's').unwrap();
+        assert_eq!(buffer.get_line(0), Some("ends"));
+    }
+
+    #[test]
+    fn test_split_line() {
+        let mut buffer = Buffer::from_content("hello world\nend".to_string());
+
+        buffer.split_line(0, 5).unwrap();
+        assert_eq!(buffer.content(), "hello\n world\nend");
+        buffer.split_line(0, 0).unwrap();
+        assert_eq!(buffer.content(), "\nhello\n world\nend");
+        buffer.split_line(3, 3).unwrap();
+        assert_eq!(buffer.content(), "\nhello\n world\nend\n");
+        assert_eq!(buffer.line_count(), 5);
+
+        assert!(buffer.split_line(1, 6).is_err());
+        assert!(buffer.split_line(9, 0).is_err());
+    }
+
+    #[test]
+    fn test_join_line() {
+        let mut buffer = Buffer::from_content("one\ntwo\nthree".to_string());
+
+        buffer.join_line(0).unwrap();
+        assert_eq!(buffer.content(), "onetwo\nthree");
+        assert!(matches!(buffer.join_line(1), Err(EditorError::InvalidOperation(_))));
+        assert!(buffer.join_line(5).is_err());
+        assert_eq!(buffer.line_count(), 2);
+    }
+
+    #[test]
+    fn test_remove_line() {
+        let mut buffer = Buffer::from_content("a\nb\nc\nd".to_string());
+
+        assert_eq!(buffer.remove_line(1).unwrap(), "b");
+        assert_eq!(buffer.content(), "a\nc\nd");
+        assert_eq!(buffer.remove_line(0).unwrap(), "a");
+        assert_eq!(buffer.remove_line(1).unwrap(), "d");
+        assert_eq!(buffer.content(), "c");
+        assert!(buffer.remove_line(1).is_err());
+    }
+
+    #[test]
+    fn test_remove_only_line() {
+        let mut buffer = Buffer::from_content("only".to_string());
+
+        assert_eq!(buffer.remove_line(0).unwrap(), "only");
+        assert_eq!(buffer.line_count(), 1);
+        assert_eq!(buffer.get_line(0), Some(""));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_dedup_consecutive_lines() {
+        let mut buffer = Buffer::from_content("a\na\nb\na\nc\nc\nc".to_string());
+        assert_eq!(buffer.dedup_consecutive_lines(), 3);
+        // The second "a" was not next to the first, so it stays
+        assert_eq!(buffer.content(), "a\nb\na\nc");
+        assert_eq!(buffer.dedup_consecutive_lines(), 0);
+
+        let mut buffer = Buffer::from_content("pear\napple\npear\napple".to_string());
+        buffer.sort_lines();
+        buffer.dedup_consecutive_lines();
+        assert_eq!(buffer.content(), "apple\npear");
+    }
+
+    #[test]
+    fn test_reverse_lines() {
+        let mut buffer = Buffer::from_content("1\n2\n3\n4\n5".to_string());
+        buffer.reverse_lines(0..5).unwrap();
+        assert_eq!(buffer.content(), "5\n4\n3\n2\n1");
+
+        let revision = buffer.revision();
+        buffer.reverse_lines(1..4).unwrap();
+        assert_eq!(buffer.content(), "5\n2\n3\n4\n1");
+        assert_eq!(buffer.changed_lines_since(revision), vec![1, 2, 3]);
+
+        assert!(buffer.reverse_lines(3..6).is_err());
+    }
+
+    #[test]
+    fn test_replace_lines() {
+        let mut buffer = Buffer::from_content("1\n2\n3\n4".to_string());
+        buffer.replace_lines(1..3, vec!["two".to_string(), "and".to_string(), "three".to_string()]).unwrap();
+        assert_eq!(buffer.content(), "1\ntwo\nand\nthree\n4");
+
+        buffer.replace_lines(0..5, Vec::new()).unwrap();
+        assert_eq!((buffer.content(), buffer.line_count()), ("", 1));
+        assert!(buffer.replace_lines(0..2, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_apply_and_invert_changes() {
+        let old = "one\ntwo\nthree\n";
+        let new = "one\n2\nthree, four";
+        let change = EditorAction::between(old, new).unwrap();
+
+        let mut buffer = Buffer::from_content(old.to_string());
+        buffer.apply(&change).unwrap();
+        assert_eq!(buffer.content(), new);
+        buffer.apply(&change.inverse()).unwrap();
+        assert_eq!(buffer.content(), old);
+
+        // Deleting text the buffer doesn't hold changes nothing
+        let stale = EditorAction::Compound(vec![
+            EditorAction::Insert { position: 0, character: '>' },
+            EditorAction::DeleteText { position: 5, text: "xyz".to_string() },
+        ]);
+        assert!(buffer.apply(&stale).is_err());
+        assert_eq!(buffer.content(), old);
+
+        // Nor does it leave changes behind, or lose a file's final newline
+        let mut buffer = Buffer::from_content(old.to_string());
+        assert!(buffer.apply(&stale).is_err());
+        assert_eq!((buffer.content(), buffer.line_count()), (old, 3));
+        assert!(!buffer.has_changes());
+        buffer.insert(0, '>').unwrap();
+        assert_eq!(buffer.take_changes(), vec![
+            EditorAction::DeleteText { position: 13, text: "\n".to_string() },
+            EditorAction::Insert { position: 0, character: '>' },
+        ]);
+    }
+
+    #[test]
+    fn test_edits_record_changes_that_replay() {
+        let original = "one\ntwo\nthree\nfour\n";
+        let mut buffer = Buffer::from_content(original.to_string());
+        buffer.insert(0, '>').unwrap();
+        // A keystroke costs the newline the first edit drops and its own byte
+        assert_eq!(buffer.changes.iter().map(EditorAction::text_len).sum::<usize>(), 2);
+
+        buffer.insert_str(4, " and\nmore").unwrap();
+        buffer.delete(2).unwrap();
+        buffer.delete_range(6..12).unwrap();
+        buffer.split_line(0, 1).unwrap();
+        buffer.join_line(1).unwrap();
+        buffer.substitute(0..3, &Substitution::parse("s/o/0/g").unwrap()).unwrap();
+        buffer.remove_line(2).unwrap();
+        buffer.reverse_lines(0..2).unwrap();
+        buffer.sort_lines();
+        buffer.replace_lines(1..1, vec!["a".to_string(), "a".to_string()]).unwrap();
+        buffer.dedup_consecutive_lines();
+        buffer.append("end").unwrap();
+        buffer.remove_line(buffer.line_count() - 1).unwrap();
+        buffer.replace_content("one\nthree\n".to_string());
+        let changes = buffer.take_changes();
+        assert!(!buffer.has_changes());
+
+        let mut replayed = Buffer::from_content(original.to_string());
+        for change in &changes {
+            replayed.apply(change).unwrap();
+        }
+        assert_eq!(replayed.content(), buffer.content());
+        for change in changes.iter().rev() {
+            replayed.apply(&change.inverse()).unwrap();
+        }
+        assert_eq!(replayed.content(), original);
+
+        buffer.clear();
+        replayed = Buffer::from_content("one\nthree\n".to_string());
+        replayed.apply(&EditorAction::Compound(buffer.take_changes())).unwrap();
+        assert_eq!(replayed.content(), "");
+    }
+
+    #[test]
+    fn test_get_lines() {
+        let buffer = Buffer::from_content("one\ntwo\nthree\nfour".to_string());
+
+        assert_eq!(buffer.get_lines(1..3), ["two", "three"]);
+        assert_eq!(buffer.get_lines(2..10), ["three", "four"]);
+        assert!(buffer.get_lines(5..9).is_empty());
+        assert!(buffer.get_lines(1..1).is_empty());
     }
 
     #[test]
@@ -204,5 +846,37 @@ mod tests {
         buffer.append("Hello\nWorld").unwrap();
         assert_eq!(buffer.content(), "Hello\nWorld");
         assert_eq!(buffer.line_count(), 2);
+
+        // Only the last line and the new ones count as changed
+        let before = buffer.revision();
+        buffer.append("!\nmore").unwrap();
+        assert_eq!(buffer.content(), "Hello\nWorld!\nmore");
+        assert_eq!(buffer.changed_lines_since(before), vec![1, 2]);
+
+        // After a file's final newline the text starts a new line
+        let mut buffer = Buffer::from_content("one\n".to_string());
+        buffer.append("two").unwrap();
+        assert_eq!(buffer.content(), "one\ntwo");
+        assert_eq!(buffer.get_line(1), Some("two"));
+    }
+
+    #[test]
+    fn test_edit_marks_only_its_line_changed() {
+        let mut buffer = Buffer::from_content("one\ntwo\nthree".to_string());
+        let before = buffer.revision();
+        assert!(buffer.changed_lines_since(before).is_empty());
+
+        buffer.insert(5, 'X').unwrap();
+        assert_eq!(buffer.changed_lines_since(before), vec![1]);
+        assert!(buffer.revision() > before);
+
+        // A new line moves every line below it
+        let before = buffer.revision();
+        buffer.split_line(0, 1).unwrap();
+        assert_eq!(buffer.changed_lines_since(before), vec![0, 1, 2, 3]);
+
+        let before = buffer.revision();
+        buffer.delete_range(0..1).unwrap();
+        assert_eq!(buffer.changed_lines_since(before), vec![0]);
     }
-}
\ No newline at end of file
+}
@@ -1,14 +1,52 @@
-use crate::core::{BufferInfo, BufferManager, EditorError, FileManager, Position, Result, TextBuffer};
+use crate::core::{content_hash, BufferInfo, BOM, BufferManager, EditorError, Encoding, FileManager, Result, TextBuffer};
 use crate::buffer::Buffer;
-use std::collections::HashMap;
+use crate::config::{detect_indentation, find_modeline};
+use crate::datetime::DateTime;
+use crate::syntax::detect_filetype;
+use crate::file_io::LockFile;
+use crate::search::Substitution;
+
+/// The answer to `:qa`'s prompt, which covers every modified buffer at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuitChoice {
+    SaveAll,
+    DiscardAll,
+    Cancel,
+}
+
+impl QuitChoice {
+    /// `y` or `a` save everything, `n` discards, any other key cancels.
+    pub fn from_key(key: char) -> Self {
+        match key {
+            'y' | 'Y' | 'a' | 'A' => QuitChoice::SaveAll,
+            'n' | 'N' => QuitChoice::DiscardAll,
+            _ => QuitChoice::Cancel,
+        }
+    }
+}
+
+/// What `check_disk_change` found for the current buffer's file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiskChange {
+    /// The file is as it was last opened or saved.
+    Unchanged,
+    /// The file changed and the unmodified buffer was reloaded from it.
+    Reloaded,
+    /// The file changed and the user should be asked whether to reload.
+    Changed,
+}
 
-#[derive(Clone)]
 pub struct MultiBuffer<F: FileManager + Clone> {
     buffers: Vec<Buffer>,
     buffer_info: Vec<BufferInfo>,
     current_buffer: usize,
     file_manager: F,
     next_buffer_id: usize,
+    /// Lock held for each buffer, parallel to `buffers`.
+    locks: Vec<Option<LockFile>>,
+    use_lock_files: bool,
+    /// Read modelines from files as they are opened.
+    use_modelines: bool,
 }
 
 impl<F: FileManager + Clone> MultiBuffer<F> {
@@ -19,6 +57,9 @@ impl<F: FileManager + Clone> MultiBuffer<F> {
             current_buffer: 0,
             file_manager,
             next_buffer_id: 0,
+            locks: Vec::new(),
+            use_lock_files: false,
+            use_modelines: true,
         };
 
         // Always start with at least one buffer
@@ -33,6 +74,9 @@ impl<F: FileManager + Clone> MultiBuffer<F> {
             current_buffer: 0,
             file_manager,
             next_buffer_id: 0,
+            locks: Vec::new(),
+            use_lock_files: false,
+            use_modelines: true,
         };
 
         if filenames.is_empty() {
@@ -46,6 +90,157 @@ impl<F: FileManager + Clone> MultiBuffer<F> {
         Ok(multi_buffer)
     }
 
+    pub fn file_manager(&self) -> &F {
+        &self.file_manager
+    }
+
+    pub fn file_manager_mut(&mut self) -> &mut F {
+        &mut self.file_manager
+    }
+
+    /// Changes the encoding the current buffer is written in. The buffer is
+    /// marked modified so the next save rewrites the file.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        if let Some(info) = self.get_current_buffer_info_mut() {
+            if info.encoding != encoding {
+                info.encoding = encoding;
+                info.content_hash = None;
+                info.is_modified = true;
+            }
+        }
+    }
+
+    /// Opens `filename` as `open_file` does, reporting load progress as a
+    /// fraction between 0 and 1.
+    pub fn open_file_with_progress(&mut self, filename: &str, progress: &mut dyn FnMut(f64)) -> Result<usize> {
+        // Check if file is already open
+        if let Some(index) = self.find_buffer_by_name(filename) {
+            self.current_buffer = index;
+            return Ok(index);
+        }
+
+        // Try to open the file
+        let (mut content, encoding) = self.file_manager.open_with_progress(filename, progress)?;
+        let mut info = BufferInfo::new(filename.to_string());
+        if content.starts_with(BOM) {
+            content.remove(0);
+            info.bom = true;
+        }
+        info.content_hash = Some(content_hash(&content));
+        info.encoding = encoding;
+        info.pristine = content.clone();
+        info.modified_time = self.file_manager.modified_time(filename);
+        info.detected_filetype = detect_filetype(filename, &content).map(String::from);
+        if self.use_modelines {
+            info.modeline = find_modeline(&content);
+        }
+        info.indent_style = detect_indentation(content.lines());
+        let buffer = Buffer::from_content(content);
+
+        self.buffers.push(buffer);
+        self.buffer_info.push(info);
+        self.locks.push(None);
+
+        let index = self.buffers.len() - 1;
+        self.current_buffer = index;
+        self.lock_buffer(index);
+        Ok(index)
+    }
+
+    /// Chooses whether the current buffer is written with a byte order mark
+    /// (`:set bomb` / `:set nobomb`).
+    pub fn set_bom(&mut self, bom: bool) {
+        if let Some(info) = self.get_current_buffer_info_mut() {
+            if info.bom != bom {
+                info.bom = bom;
+                info.content_hash = None;
+                info.is_modified = true;
+            }
+        }
+    }
+
+    /// Allows or refuses edits to the current buffer
+    /// (`:setlocal modifiable` / `:setlocal nomodifiable`).
+    pub fn set_modifiable(&mut self, modifiable: bool) {
+        if let Some(info) = self.get_current_buffer_info_mut() {
+            info.modifiable = modifiable;
+        }
+    }
+
+    /// Opens `content` as a new buffer called `name` that has no file and
+    /// refuses edits, such as the help text, and makes it current.
+    pub fn open_view(&mut self, name: &str, content: &str) -> usize {
+        let mut info = BufferInfo::new(name.to_string());
+        info.modifiable = false;
+        info.content_hash = Some(content_hash(content));
+        info.pristine = content.to_string();
+
+        self.buffers.push(Buffer::from_content(content.to_string()));
+        self.buffer_info.push(info);
+        self.locks.push(None);
+        self.current_buffer = self.buffers.len() - 1;
+        self.current_buffer
+    }
+
+    /// Fails unless the current buffer accepts edits.
+    fn check_modifiable(&self) -> Result<()> {
+        if self.get_current_buffer_info().is_some_and(|info| !info.modifiable) {
+            return Err(EditorError::NotModifiable);
+        }
+        Ok(())
+    }
+
+    /// Turns lock files on or off. Enabling locks every named buffer that is
+    /// already open; disabling releases the locks held.
+    pub fn set_lock_files(&mut self, enabled: bool) {
+        self.use_lock_files = enabled;
+        if enabled {
+            for idx in 0..self.buffers.len() {
+                self.lock_buffer(idx);
+            }
+        } else {
+            self.release_locks();
+        }
+    }
+
+    /// Chooses whether files opened from now on have their modelines read
+    /// (`:set modeline` / `:set nomodeline`).
+    pub fn set_modelines(&mut self, enabled: bool) {
+        self.use_modelines = enabled;
+    }
+
+    /// Takes the lock for buffer `idx` unless another running editor holds
+    /// it, in which case `locked_by` records that editor's PID.
+    fn lock_buffer(&mut self, idx: usize) {
+        let info = &mut self.buffer_info[idx];
+        if !self.use_lock_files || info.is_untitled() || self.locks[idx].is_some() {
+            return;
+        }
+
+        let path = self.file_manager.resolve(&info.filename);
+        info.locked_by = LockFile::holder(&path);
+        if info.locked_by.is_none() {
+            self.locks[idx] = LockFile::acquire(&path).ok();
+        }
+    }
+
+    fn release_lock(&mut self, idx: usize) {
+        if let Some(lock) = self.locks.get_mut(idx).and_then(Option::take) {
+            let _ = lock.release();
+        }
+    }
+
+    /// Removes every lock file this editor created, e.g. before quitting.
+    pub fn release_locks(&mut self) {
+        for idx in 0..self.locks.len() {
+            self.release_lock(idx);
+        }
+    }
+
+    pub fn get_buffer(&self, index: usize) -> Option<&Buffer> {
+        self.buffers.get(index)
+    }
+
     pub fn get_current_buffer(&self) -> Option<&Buffer> {
         self.buffers.get(self.current_buffer)
     }
@@ -62,20 +257,261 @@ impl<F: FileManager + Clone> MultiBuffer<F> {
         self.buffer_info.get_mut(self.current_buffer)
     }
 
-    pub fn save_current_buffer(&mut self) -> Result<()> {
-        let idx = self.current_buffer;
+    /// Runs `substitution` over `lines` of the current buffer, marking it
+    /// modified only when something was replaced.
+    pub fn substitute(&mut self, lines: std::ops::Range<usize>, substitution: &Substitution) -> Result<usize> {
+        self.check_modifiable()?;
+        let Some(buffer) = self.get_current_buffer_mut() else {
+            return Err(EditorError::InvalidOperation("No current buffer".to_string()));
+        };
+        let count = buffer.substitute(lines, substitution)?;
+        if count > 0 {
+            if let Some(info) = self.get_current_buffer_info_mut() {
+                info.is_modified = true;
+            }
+        }
+        Ok(count)
+    }
+
+    pub fn save_current_buffer(&mut self) -> Result<bool> {
+        self.save_buffer(self.current_buffer)
+    }
+
+    /// Writes buffer `idx` to its file. Returns `false` without writing when
+    /// the content hashes the same as what was last loaded or saved.
+    pub fn save_buffer(&mut self, idx: usize) -> Result<bool> {
         if idx < self.buffers.len() && idx < self.buffer_info.len() {
             // Clone data to avoid overlapping borrows of self
             let content = self.buffers[idx].content().to_string();
             let filename = self.buffer_info[idx].filename.clone();
-            self.file_manager.save(&filename, &content)?;
+            let hash = content_hash(&content);
+
+            if self.buffer_info[idx].content_hash == Some(hash) {
+                self.buffer_info[idx].is_modified = false;
+                self.buffer_info[idx].pristine = content;
+                return Ok(false);
+            }
+
+            let encoding = self.buffer_info[idx].encoding;
+            // UTF-16 encoding writes its own byte order mark
+            let written = if self.buffer_info[idx].bom && encoding == Encoding::Utf8 {
+                format!("{}{}", BOM, content)
+            } else {
+                content.clone()
+            };
+            self.file_manager.save_with_encoding(&filename, &written, encoding)?;
             self.buffer_info[idx].is_modified = false;
-            Ok(())
+            self.buffer_info[idx].content_hash = Some(hash);
+            self.buffer_info[idx].pristine = content;
+            self.buffer_info[idx].modified_time = self.file_manager.modified_time(&filename);
+            Ok(true)
         } else {
             Err(EditorError::InvalidOperation("No current buffer".to_string()))
         }
     }
 
+    /// Points the current buffer at `new_name` without writing it (`:file`),
+    /// so the next save goes there. With `on_disk` (`:rename`), the file
+    /// already written under the old name is renamed too. Fails when
+    /// another open buffer has that name.
+    pub fn rename_current_buffer(&mut self, new_name: &str, on_disk: bool) -> Result<()> {
+        let idx = self.current_buffer;
+        match self.find_buffer_by_name(new_name) {
+            Some(other) if other == idx => return Ok(()),
+            Some(_) => {
+                return Err(EditorError::InvalidOperation(format!("{} is already open in another buffer", new_name)));
+            }
+            None => {}
+        }
+
+        let info = self
+            .buffer_info
+            .get(idx)
+            .ok_or_else(|| EditorError::InvalidOperation("No current buffer".to_string()))?;
+        let old_name = info.filename.clone();
+        let moved = on_disk && !info.is_untitled() && self.file_manager.resolve(&old_name).exists();
+        if moved {
+            self.file_manager.rename(&old_name, new_name)?;
+        }
+
+        self.release_lock(idx);
+        let info = &mut self.buffer_info[idx];
+        info.filename = new_name.to_string();
+        if !moved {
+            // Nothing is on disk under the new name yet
+            info.content_hash = None;
+            info.modified_time = None;
+        }
+        self.lock_buffer(idx);
+        self.refresh_filetype();
+        Ok(())
+    }
+
+    /// Detects the current buffer's language again, e.g. after its file
+    /// name changed.
+    pub fn refresh_filetype(&mut self) {
+        let idx = self.current_buffer;
+        if let (Some(buffer), Some(info)) = (self.buffers.get(idx), self.buffer_info.get_mut(idx)) {
+            info.detected_filetype = detect_filetype(&info.filename, buffer.content()).map(String::from);
+        }
+    }
+
+    /// Overrides the detected language of the current buffer (`:set
+    /// filetype=`); `None` goes back to detection.
+    pub fn set_filetype(&mut self, filetype: Option<String>) {
+        if let Some(info) = self.get_current_buffer_info_mut() {
+            info.filetype_override = filetype;
+        }
+    }
+
+    /// When the current buffer's file was last written, as of its last open
+    /// or save.
+    pub fn current_buffer_mtime(&self) -> Option<std::time::SystemTime> {
+        self.get_current_buffer_info()?.modified_time
+    }
+
+    /// Compares the current buffer's file with how it was last opened or
+    /// saved: its mtime first, then its content hash, so a file that was
+    /// only touched counts as unchanged. With `autoread`, a changed file is
+    /// reloaded into a buffer without unsaved edits; otherwise the change
+    /// is reported until `reload_current_buffer` or `keep_current_buffer`.
+    pub fn check_disk_change(&mut self, autoread: bool) -> Result<DiskChange> {
+        let idx = self.current_buffer;
+        let Some(info) = self.buffer_info.get(idx) else {
+            return Ok(DiskChange::Unchanged);
+        };
+        let Some(known) = info.modified_time else {
+            return Ok(DiskChange::Unchanged);
+        };
+        // A deleted file is left alone
+        let filename = info.filename.clone();
+        let Some(on_disk) = self.file_manager.modified_time(&filename) else {
+            return Ok(DiskChange::Unchanged);
+        };
+        if on_disk == known {
+            return Ok(DiskChange::Unchanged);
+        }
+
+        let (content, _) = self.file_manager.open_with_encoding(&filename)?;
+        let content = content.strip_prefix(BOM).unwrap_or(&content);
+        if self.buffer_info[idx].content_hash == Some(content_hash(content)) {
+            self.buffer_info[idx].modified_time = Some(on_disk);
+            return Ok(DiskChange::Unchanged);
+        }
+        if autoread && !self.buffer_info[idx].is_modified {
+            self.reload_current_buffer()?;
+            return Ok(DiskChange::Reloaded);
+        }
+        Ok(DiskChange::Changed)
+    }
+
+    /// Replaces the current buffer with its file as it is on disk now,
+    /// dropping any unsaved edits.
+    pub fn reload_current_buffer(&mut self) -> Result<()> {
+        let idx = self.current_buffer;
+        let filename = self
+            .buffer_info
+            .get(idx)
+            .ok_or_else(|| EditorError::InvalidOperation("No current buffer".to_string()))?
+            .filename
+            .clone();
+        let (mut content, encoding) = self.file_manager.open_with_encoding(&filename)?;
+
+        let info = &mut self.buffer_info[idx];
+        info.bom = content.starts_with(BOM);
+        if info.bom {
+            content.remove(0);
+        }
+        info.content_hash = Some(content_hash(&content));
+        info.encoding = encoding;
+        info.pristine = content.clone();
+        info.modified_time = self.file_manager.modified_time(&filename);
+        info.indent_style = detect_indentation(content.lines());
+        info.is_modified = false;
+        self.buffers[idx].replace_content(content);
+        Ok(())
+    }
+
+    /// Keeps the current buffer after its file changed on disk, without
+    /// asking again. The next save writes it even if it matches the old
+    /// file.
+    pub fn keep_current_buffer(&mut self) {
+        let idx = self.current_buffer;
+        if let Some(info) = self.buffer_info.get_mut(idx) {
+            info.modified_time = self.file_manager.modified_time(&info.filename);
+            info.content_hash = None;
+        }
+    }
+
+    /// Reverts the current buffer to the content it was last opened or
+    /// saved with, without touching the file. Untitled buffers are emptied.
+    pub fn discard_changes(&mut self) -> Result<()> {
+        let idx = self.current_buffer;
+        let info = self
+            .buffer_info
+            .get_mut(idx)
+            .ok_or_else(|| EditorError::InvalidOperation("No current buffer".to_string()))?;
+
+        self.buffers[idx].replace_content(info.pristine.clone());
+        info.is_modified = false;
+        Ok(())
+    }
+
+    /// Buffers an autosave would write: modified and backed by a real filename.
+    pub fn autosave_candidates(&self) -> Vec<usize> {
+        self.buffer_info
+            .iter()
+            .enumerate()
+            .filter(|(_, info)| info.is_modified && !info.is_untitled())
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Writes every autosave candidate, returning the indices saved. A
+    /// failed write leaves that buffer modified and does not stop the rest.
+    pub fn autosave(&mut self) -> Vec<usize> {
+        self.autosave_candidates()
+            .into_iter()
+            .filter(|&idx| matches!(self.save_buffer(idx), Ok(true)))
+            .collect()
+    }
+
+    pub fn modified_buffers(&self) -> Vec<usize> {
+        self.buffer_info
+            .iter()
+            .enumerate()
+            .filter(|(_, info)| info.is_modified)
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Decides whether `:qa` may quit. Unless `force` is set (`:qa!`) or no
+    /// buffer is modified, `ask` is called once with the number of modified
+    /// buffers. A buffer that cannot be saved cancels the quit.
+    pub fn quit_all(&mut self, force: bool, ask: impl FnOnce(usize) -> QuitChoice) -> Result<bool> {
+        let modified = self.modified_buffers();
+        if force || modified.is_empty() {
+            return Ok(true);
+        }
+
+        match ask(modified.len()) {
+            QuitChoice::SaveAll => {
+                for idx in modified {
+                    if self.buffer_info[idx].is_untitled() {
+                        return Err(EditorError::InvalidOperation(format!(
+                            "{} has no file name",
+                            self.buffer_info[idx].filename
+                        )));
+                    }
+                    self.save_buffer(idx)?;
+                }
+                Ok(true)
+            }
+            QuitChoice::DiscardAll => Ok(true),
+            QuitChoice::Cancel => Ok(false),
+        }
+    }
+
     pub fn next_buffer(&mut self) -> Result<()> {
         if self.buffers.is_empty() {
             return Err(EditorError::InvalidOperation("No buffers available".to_string()));
@@ -96,6 +532,28 @@ impl<F: FileManager + Clone> MultiBuffer<F> {
         Ok(())
     }
 
+    /// Switches to the next modified buffer after the current one, wrapping
+    /// around. Returns the new index, or `None` if no buffer is modified.
+    pub fn next_modified_buffer(&mut self) -> Option<usize> {
+        let count = self.buffers.len();
+        let index = (1..=count)
+            .map(|step| (self.current_buffer + step) % count)
+            .find(|&idx| self.buffer_info[idx].is_modified)?;
+        self.current_buffer = index;
+        Some(index)
+    }
+
+    /// Switches to the previous modified buffer before the current one,
+    /// wrapping around. Returns the new index, or `None` if no buffer is modified.
+    pub fn previous_modified_buffer(&mut self) -> Option<usize> {
+        let count = self.buffers.len();
+        let index = (1..=count)
+            .map(|step| (self.current_buffer + count - step) % count)
+            .find(|&idx| self.buffer_info[idx].is_modified)?;
+        self.current_buffer = index;
+        Some(index)
+    }
+
     pub fn find_buffer_by_name(&self, filename: &str) -> Option<usize> {
         self.buffer_info
             .iter()
@@ -110,12 +568,18 @@ impl<F: FileManager + Clone> MultiBuffer<F> {
             } else {
                 String::new()
             };
+            let written = info
+                .modified_time
+                .map(|time| format!(" [written {}]", DateTime::from_system_time(time).format("%Y-%m-%d %H:%M UTC")))
+                .unwrap_or_default();
 
             format!(
-                "{}{}{}",
+                "{}{}{}{} [{}]",
                 info.filename,
                 modified_indicator,
-                buffer_indicator
+                buffer_indicator,
+                written,
+                info.encoding.name()
             )
         } else {
             "[No buffer]".to_string()
@@ -125,23 +589,7 @@ impl<F: FileManager + Clone> MultiBuffer<F> {
 
 impl<F: FileManager + Clone> BufferManager for MultiBuffer<F> {
     fn open_file(&mut self, filename: &str) -> Result<usize> {
-        // Check if file is already open
-        if let Some(index) = self.find_buffer_by_name(filename) {
-            self.current_buffer = index;
-            return Ok(index);
-        }
-
-        // Try to open the file
-        let content = self.file_manager.open(filename)?;
-        let buffer = Buffer::from_content(content);
-        let info = BufferInfo::new(filename.to_string());
-
-        self.buffers.push(buffer);
-        self.buffer_info.push(info);
-
-        let index = self.buffers.len() - 1;
-        self.current_buffer = index;
-        Ok(index)
+        self.open_file_with_progress(filename, &mut |_| {})
     }
 
     fn new_buffer(&mut self) -> usize {
@@ -153,6 +601,7 @@ impl<F: FileManager + Clone> BufferManager for MultiBuffer<F> {
 
         self.buffers.push(buffer);
         self.buffer_info.push(info);
+        self.locks.push(None);
 
         let index = self.buffers.len() - 1;
         self.current_buffer = index;
@@ -176,6 +625,8 @@ impl<F: FileManager + Clone> BufferManager for MultiBuffer<F> {
             ));
         }
 
+        self.release_lock(index);
+
         // Don't close the last buffer
         if self.buffers.len() == 1 {
             // Instead of closing, create a new empty buffer
@@ -188,6 +639,7 @@ impl<F: FileManager + Clone> BufferManager for MultiBuffer<F> {
 
         self.buffers.remove(index);
         self.buffer_info.remove(index);
+        self.locks.remove(index);
 
         // Adjust current buffer index
         if self.current_buffer >= index && self.current_buffer > 0 {
@@ -240,6 +692,7 @@ impl<F: FileManager + Clone> TextBuffer for MultiBuffer<F> {
     }
 
     fn insert(&mut self, pos: usize, ch: char) -> Result<()> {
+        self.check_modifiable()?;
         if let Some(buffer) = self.get_current_buffer_mut() {
             let result = buffer.insert(pos, ch);
             if result.is_ok() {
@@ -253,7 +706,23 @@ impl<F: FileManager + Clone> TextBuffer for MultiBuffer<F> {
         }
     }
 
+    fn insert_str(&mut self, pos: usize, text: &str) -> Result<()> {
+        self.check_modifiable()?;
+        if let Some(buffer) = self.get_current_buffer_mut() {
+            let result = buffer.insert_str(pos, text);
+            if result.is_ok() {
+                if let Some(info) = self.get_current_buffer_info_mut() {
+                    info.is_modified = true;
+                }
+            }
+            result
+        } else {
+            Err(EditorError::InvalidOperation("No current buffer".to_string()))
+        }
+    }
+
     fn delete(&mut self, pos: usize) -> Result<()> {
+        self.check_modifiable()?;
         if let Some(buffer) = self.get_current_buffer_mut() {
             let result = buffer.delete(pos);
             if result.is_ok() {
@@ -267,7 +736,23 @@ impl<F: FileManager + Clone> TextBuffer for MultiBuffer<F> {
         }
     }
 
+    fn delete_range(&mut self, range: std::ops::Range<usize>) -> Result<()> {
+        self.check_modifiable()?;
+        if let Some(buffer) = self.get_current_buffer_mut() {
+            let result = buffer.delete_range(range);
+            if result.is_ok() {
+                if let Some(info) = self.get_current_buffer_info_mut() {
+                    info.is_modified = true;
+                }
+            }
+            result
+        } else {
+            Err(EditorError::InvalidOperation("No current buffer".to_string()))
+        }
+    }
+
     fn append(&mut self, text: &str) -> Result<()> {
+        self.check_modifiable()?;
         if let Some(buffer) = self.get_current_buffer_mut() {
             let result = buffer.append(text);
             if result.is_ok() {
@@ -282,6 +767,9 @@ impl<F: FileManager + Clone> TextBuffer for MultiBuffer<F> {
     }
 
     fn clear(&mut self) {
+        if self.check_modifiable().is_err() {
+            return;
+        }
         if let Some(buffer) = self.get_current_buffer_mut() {
             buffer.clear();
             if let Some(info) = self.get_current_buffer_info_mut() {
@@ -306,12 +794,222 @@ impl<F: FileManager + Clone> TextBuffer for MultiBuffer<F> {
         self.get_current_buffer()
             .and_then(|b| b.get_line(line))
     }
+
+    fn get_lines(&self, range: std::ops::Range<usize>) -> &[String] {
+        self.get_current_buffer()
+            .map(|b| b.get_lines(range))
+            .unwrap_or(&[])
+    }
+
+    fn is_modifiable(&self) -> bool {
+        self.check_modifiable().is_ok()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::file_io::FileSystem;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::{Duration, SystemTime};
+    use tempfile::tempdir;
+
+    /// Serves fixed content and records every write.
+    #[derive(Clone, Default)]
+    struct RecordingFiles {
+        writes: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl FileManager for RecordingFiles {
+        fn open(&self, _filename: &str) -> Result<String> {
+            Ok("hello".to_string())
+        }
+
+        fn save(&self, _filename: &str, content: &str) -> Result<()> {
+            self.writes.borrow_mut().push(content.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_quit_all_asks_once_for_every_modified_buffer() {
+        let files = RecordingFiles::default();
+        let mut multi_buffer = MultiBuffer::from_files(files.clone(), vec!["a.txt".to_string(), "b.txt".to_string()]).unwrap();
+        for index in [0, 1] {
+            multi_buffer.switch_to_buffer(index).unwrap();
+            multi_buffer.insert(0, 'x').unwrap();
+        }
+
+        assert!(!multi_buffer.quit_all(false, |_| QuitChoice::Cancel).unwrap());
+        assert_eq!(multi_buffer.modified_buffers(), vec![0, 1]);
+
+        let mut asked = Vec::new();
+        assert!(multi_buffer.quit_all(false, |count| {
+            asked.push(count);
+            QuitChoice::from_key('y')
+        }).unwrap());
+        assert_eq!(asked, vec![2]);
+        assert_eq!(files.writes.borrow().len(), 2);
+        assert!(multi_buffer.modified_buffers().is_empty());
+    }
+
+    #[test]
+    fn test_forced_quit_all_ignores_modifications() {
+        let file_manager = FileSystem::new().unwrap();
+        let mut multi_buffer = MultiBuffer::new(file_manager);
+        multi_buffer.insert(0, 'x').unwrap();
+
+        assert!(multi_buffer.quit_all(true, |_| panic!("forced quit must not ask")).unwrap());
+        assert!(multi_buffer.quit_all(false, |_| QuitChoice::from_key('n')).unwrap());
+        // An untitled buffer cannot be saved, so saving all fails
+        assert!(multi_buffer.quit_all(false, |_| QuitChoice::SaveAll).is_err());
+    }
+
+    #[test]
+    fn test_discard_restores_pristine_content() {
+        let files = RecordingFiles::default();
+        let mut multi_buffer = MultiBuffer::from_files(files.clone(), vec!["a.txt".to_string()]).unwrap();
+
+        multi_buffer.insert(0, 'x').unwrap();
+        multi_buffer.discard_changes().unwrap();
+        assert_eq!(multi_buffer.content(), "hello");
+        assert!(!multi_buffer.get_current_buffer_info().unwrap().is_modified);
+
+        // After a save, discarding goes back to the saved text
+        multi_buffer.insert(5, '!').unwrap();
+        multi_buffer.save_current_buffer().unwrap();
+        multi_buffer.insert(0, 'y').unwrap();
+        multi_buffer.discard_changes().unwrap();
+        assert_eq!(multi_buffer.content(), "hello!");
+        assert!(files.writes.borrow().iter().all(|written| written == "hello!"));
+
+        multi_buffer.new_buffer();
+        multi_buffer.insert(0, 'z').unwrap();
+        multi_buffer.discard_changes().unwrap();
+        assert!(multi_buffer.is_empty());
+    }
+
+    #[test]
+    fn test_save_refreshes_modified_time() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("notes.txt");
+        std::fs::write(&path, "old").unwrap();
+        let written = SystemTime::now() - Duration::from_secs(3600);
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(written).unwrap();
+
+        let mut file_system = FileSystem::new().unwrap();
+        file_system.set_current_directory(temp_dir.path()).unwrap();
+        let mut multi_buffer = MultiBuffer::from_files(file_system, vec!["notes.txt".to_string()]).unwrap();
+        assert_eq!(multi_buffer.current_buffer_mtime(), Some(written));
+
+        multi_buffer.insert(0, 'x').unwrap();
+        multi_buffer.save_current_buffer().unwrap();
+        assert!(multi_buffer.current_buffer_mtime().unwrap() > written);
+    }
+
+    /// Opens `notes.txt` holding "old", written an hour ago, then rewrites
+    /// it with "new" so its mtime changes.
+    fn open_then_change_on_disk() -> (tempfile::TempDir, MultiBuffer<FileSystem>) {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("notes.txt");
+        std::fs::write(&path, "old").unwrap();
+        let written = SystemTime::now() - Duration::from_secs(3600);
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(written).unwrap();
+
+        let mut file_system = FileSystem::new().unwrap();
+        file_system.set_current_directory(temp_dir.path()).unwrap();
+        let mut multi_buffer = MultiBuffer::from_files(file_system, vec!["notes.txt".to_string()]).unwrap();
+        assert_eq!(multi_buffer.check_disk_change(true).unwrap(), DiskChange::Unchanged);
+
+        std::fs::write(&path, "new").unwrap();
+        (temp_dir, multi_buffer)
+    }
+
+    #[test]
+    fn test_autoread_reloads_unmodified_buffer() {
+        let (_temp_dir, mut multi_buffer) = open_then_change_on_disk();
+        assert_eq!(multi_buffer.check_disk_change(true).unwrap(), DiskChange::Reloaded);
+        assert_eq!(multi_buffer.content(), "new");
+        // The reload is kept as a change the undo history can take
+        assert!(multi_buffer.get_current_buffer().unwrap().has_changes());
+        assert!(!multi_buffer.get_current_buffer_info().unwrap().is_modified);
+        assert_eq!(multi_buffer.check_disk_change(true).unwrap(), DiskChange::Unchanged);
+    }
+
+    #[test]
+    fn test_disk_change_to_modified_buffer_asks() {
+        let (_temp_dir, mut multi_buffer) = open_then_change_on_disk();
+        multi_buffer.insert(3, '!').unwrap();
+        assert_eq!(multi_buffer.check_disk_change(true).unwrap(), DiskChange::Changed);
+        assert_eq!(multi_buffer.content(), "old!");
+
+        // Keeping the buffer stops the question
+        multi_buffer.keep_current_buffer();
+        assert_eq!(multi_buffer.check_disk_change(true).unwrap(), DiskChange::Unchanged);
+        assert_eq!(multi_buffer.content(), "old!");
+    }
+
+    #[test]
+    fn test_touched_file_is_unchanged() {
+        let (temp_dir, mut multi_buffer) = open_then_change_on_disk();
+        std::fs::write(temp_dir.path().join("notes.txt"), "old").unwrap();
+        assert_eq!(multi_buffer.check_disk_change(false).unwrap(), DiskChange::Unchanged);
+        assert_eq!(multi_buffer.content(), "old");
+    }
+
+    #[test]
+    fn test_rename_unsaved_buffer() {
+        let temp_dir = tempdir().unwrap();
+        let mut file_system = FileSystem::new().unwrap();
+        file_system.set_current_directory(temp_dir.path()).unwrap();
+        let mut multi_buffer = MultiBuffer::new(file_system);
+
+        multi_buffer.insert(0, 'x').unwrap();
+        multi_buffer.rename_current_buffer("draft.txt", true).unwrap();
+        assert_eq!(multi_buffer.get_current_buffer_info().unwrap().filename, "draft.txt");
+        assert!(!temp_dir.path().join("draft.txt").exists());
+
+        multi_buffer.save_current_buffer().unwrap();
+        assert_eq!(std::fs::read_to_string(temp_dir.path().join("draft.txt")).unwrap(), "x");
+
+        // :rename moves the written file; :file only retargets the buffer
+        multi_buffer.rename_current_buffer("final.txt", true).unwrap();
+        assert!(!temp_dir.path().join("draft.txt").exists());
+        assert!(temp_dir.path().join("final.txt").exists());
+        multi_buffer.rename_current_buffer("copy.txt", false).unwrap();
+        assert!(multi_buffer.save_current_buffer().unwrap());
+        assert!(temp_dir.path().join("final.txt").exists());
+    }
+
+    #[test]
+    fn test_rename_to_open_buffer_fails() {
+        let files = RecordingFiles::default();
+        let mut multi_buffer = MultiBuffer::from_files(files, vec!["a.txt".to_string(), "b.txt".to_string()]).unwrap();
+
+        assert!(matches!(
+            multi_buffer.rename_current_buffer("a.txt", false),
+            Err(EditorError::InvalidOperation(_))
+        ));
+        assert_eq!(multi_buffer.get_current_buffer_info().unwrap().filename, "b.txt");
+        assert!(multi_buffer.rename_current_buffer("b.txt", false).is_ok());
+    }
+
+    #[test]
+    fn test_filetype_override_wins_over_detection() {
+        let mut multi_buffer = MultiBuffer::from_files(RecordingFiles::default(), vec!["notes.txt".to_string()]).unwrap();
+        let filetype = |multi_buffer: &MultiBuffer<RecordingFiles>| {
+            multi_buffer.get_current_buffer_info().unwrap().filetype().map(String::from)
+        };
+        assert_eq!(filetype(&multi_buffer).as_deref(), Some("text"));
+
+        multi_buffer.set_filetype(Some("python".to_string()));
+        multi_buffer.rename_current_buffer("main.rs", false).unwrap();
+        assert_eq!(filetype(&multi_buffer).as_deref(), Some("python"));
+
+        multi_buffer.set_filetype(None);
+        assert_eq!(filetype(&multi_buffer).as_deref(), Some("rust"));
+    }
 
     #[test]
     fn test_multi_buffer_creation() {
@@ -363,4 +1061,224 @@ mod tests {
         assert_eq!(multi_buffer.content(), "Hi");
         assert!(multi_buffer.get_current_buffer_info().unwrap().is_modified);
     }
+
+    #[test]
+    fn test_nomodifiable_buffer_rejects_edits() {
+        let file_manager = FileSystem::new().unwrap();
+        let mut multi_buffer = MultiBuffer::new(file_manager);
+        multi_buffer.new_buffer();
+        multi_buffer.set_modifiable(false);
+
+        assert!(!multi_buffer.is_modifiable());
+        assert!(matches!(multi_buffer.insert(0, 'x'), Err(EditorError::NotModifiable)));
+        assert!(multi_buffer.insert_str(0, "text").is_err());
+        assert_eq!(multi_buffer.content(), "");
+        assert!(!multi_buffer.get_current_buffer_info().unwrap().is_modified);
+
+        // Its sibling still takes edits
+        multi_buffer.switch_to_buffer(0).unwrap();
+        multi_buffer.insert_str(0, "text").unwrap();
+        assert_eq!(multi_buffer.content(), "text");
+
+        // A view opens nomodifiable and unmodified
+        let help = multi_buffer.open_view("*help*", "Help\n");
+        assert_eq!(multi_buffer.get_current_buffer_index(), help);
+        assert!(multi_buffer.delete(0).is_err());
+        assert_eq!(multi_buffer.content(), "Help\n");
+        assert!(!multi_buffer.save_current_buffer().unwrap());
+    }
+
+    #[test]
+    fn test_modified_buffer_navigation() {
+        let file_manager = FileSystem::new().unwrap();
+        let mut multi_buffer = MultiBuffer::new(file_manager);
+        for _ in 0..4 {
+            multi_buffer.new_buffer();
+        }
+
+        // Buffers 1 and 3 are modified, the rest are clean
+        for index in [1, 3] {
+            multi_buffer.switch_to_buffer(index).unwrap();
+            multi_buffer.insert(0, 'x').unwrap();
+        }
+
+        multi_buffer.switch_to_buffer(0).unwrap();
+        assert_eq!(multi_buffer.next_modified_buffer(), Some(1));
+        assert_eq!(multi_buffer.next_modified_buffer(), Some(3));
+        assert_eq!(multi_buffer.next_modified_buffer(), Some(1)); // wraps
+        assert_eq!(multi_buffer.get_current_buffer_index(), 1);
+
+        assert_eq!(multi_buffer.previous_modified_buffer(), Some(3)); // wraps
+        assert_eq!(multi_buffer.previous_modified_buffer(), Some(1));
+    }
+
+    #[test]
+    fn test_autosave_writes_only_named_modified_buffers() {
+        let temp_dir = tempdir().unwrap();
+        let mut file_manager = FileSystem::new().unwrap();
+        file_manager.set_current_directory(temp_dir.path()).unwrap();
+        std::fs::write(temp_dir.path().join("clean.txt"), "clean").unwrap();
+        std::fs::write(temp_dir.path().join("dirty.txt"), "dirty").unwrap();
+
+        let mut multi_buffer = MultiBuffer::from_files(
+            file_manager,
+            vec!["clean.txt".to_string(), "dirty.txt".to_string()],
+        ).unwrap();
+        multi_buffer.insert(0, '!').unwrap(); // dirty.txt is current
+
+        multi_buffer.new_buffer();
+        multi_buffer.insert(0, 'x').unwrap(); // modified but untitled
+
+        assert_eq!(multi_buffer.autosave_candidates(), vec![1]);
+        assert_eq!(multi_buffer.autosave(), vec![1]);
+
+        assert_eq!(std::fs::read_to_string(temp_dir.path().join("dirty.txt")).unwrap(), "!dirty");
+        assert!(!multi_buffer.get_buffer_info(1).unwrap().is_modified);
+        assert!(multi_buffer.get_buffer_info(2).unwrap().is_modified);
+        assert!(multi_buffer.autosave_candidates().is_empty());
+    }
+
+    #[test]
+    fn test_unchanged_content_is_not_rewritten() {
+        let files = RecordingFiles::default();
+        let mut multi_buffer = MultiBuffer::from_files(files.clone(), vec!["a.txt".to_string()]).unwrap();
+
+        assert!(!multi_buffer.save_current_buffer().unwrap());
+        assert!(files.writes.borrow().is_empty());
+
+        multi_buffer.insert(5, '!').unwrap();
+        assert!(multi_buffer.save_current_buffer().unwrap());
+        assert_eq!(*files.writes.borrow(), vec!["hello!".to_string()]);
+
+        // Editing back to the saved text is not a change either
+        multi_buffer.delete(5).unwrap();
+        multi_buffer.insert(5, '!').unwrap();
+        assert!(!multi_buffer.save_current_buffer().unwrap());
+        assert!(!multi_buffer.get_current_buffer_info().unwrap().is_modified);
+        assert_eq!(files.writes.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_saving_keeps_the_detected_encoding() {
+        let temp_dir = tempdir().unwrap();
+        let mut file_manager = FileSystem::new().unwrap();
+        file_manager.set_current_directory(temp_dir.path()).unwrap();
+        let path = temp_dir.path().join("wide.txt");
+        std::fs::write(&path, b"\xff\xfeh\0i\0").unwrap();
+
+        let mut multi_buffer = MultiBuffer::from_files(file_manager, vec!["wide.txt".to_string()]).unwrap();
+        assert_eq!(multi_buffer.content(), "hi");
+        assert_eq!(multi_buffer.get_current_buffer_info().unwrap().encoding, Encoding::Utf16Le);
+        assert!(multi_buffer.get_buffer_status_line().ends_with("[utf-16le]"));
+
+        multi_buffer.insert(2, '!').unwrap();
+        multi_buffer.save_current_buffer().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"\xff\xfeh\0i\0!\0");
+
+        // Converting rewrites the file even though the text is unchanged
+        multi_buffer.set_encoding(Encoding::Utf8);
+        assert!(multi_buffer.save_current_buffer().unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hi!");
+    }
+
+    #[test]
+    fn test_bom_is_stripped_and_restored() {
+        let temp_dir = tempdir().unwrap();
+        let mut file_manager = FileSystem::new().unwrap();
+        file_manager.set_current_directory(temp_dir.path()).unwrap();
+        let path = temp_dir.path().join("bom.txt");
+        std::fs::write(&path, "\u{feff}hello").unwrap();
+
+        let mut multi_buffer = MultiBuffer::from_files(file_manager, vec!["bom.txt".to_string()]).unwrap();
+        assert_eq!(multi_buffer.content(), "hello");
+        assert!(multi_buffer.get_current_buffer_info().unwrap().bom);
+
+        multi_buffer.insert(5, '!').unwrap();
+        multi_buffer.save_current_buffer().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"\xef\xbb\xbfhello!");
+
+        multi_buffer.set_bom(false);
+        multi_buffer.save_current_buffer().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello!");
+    }
+
+    #[test]
+    fn test_modeline_is_read_on_open() {
+        let temp_dir = tempdir().unwrap();
+        let mut file_manager = FileSystem::new().unwrap();
+        file_manager.set_current_directory(temp_dir.path()).unwrap();
+        std::fs::write(temp_dir.path().join("build"), "make all\n# vim: set ts=2 ft=sh:\n").unwrap();
+        std::fs::write(temp_dir.path().join("other"), "# vim: ts=2\n").unwrap();
+
+        let mut multi_buffer = MultiBuffer::from_files(file_manager, vec!["build".to_string()]).unwrap();
+        let info = multi_buffer.get_current_buffer_info().unwrap();
+        assert_eq!(info.modeline.tabstop, Some(2));
+        assert_eq!(info.filetype(), Some("sh"));
+
+        // Files opened after `:set nomodeline` keep their modelines unread
+        multi_buffer.set_modelines(false);
+        multi_buffer.open_file("other").unwrap();
+        assert!(multi_buffer.get_current_buffer_info().unwrap().modeline.is_empty());
+    }
+
+    #[test]
+    fn test_lock_files_follow_open_and_close() {
+        let temp_dir = tempdir().unwrap();
+        let mut file_manager = FileSystem::new().unwrap();
+        file_manager.set_current_directory(temp_dir.path()).unwrap();
+        std::fs::write(temp_dir.path().join("a.txt"), "a").unwrap();
+        std::fs::write(temp_dir.path().join("b.txt"), "b").unwrap();
+        let lock_a = temp_dir.path().join(".a.txt.lock");
+        let lock_b = temp_dir.path().join(".b.txt.lock");
+
+        let mut multi_buffer = MultiBuffer::from_files(file_manager, vec!["a.txt".to_string()]).unwrap();
+        assert!(!lock_a.exists());
+
+        multi_buffer.set_lock_files(true);
+        assert!(lock_a.exists());
+        multi_buffer.open_file("b.txt").unwrap();
+        assert!(lock_b.exists());
+        assert_eq!(multi_buffer.get_buffer_info(1).unwrap().locked_by, None);
+
+        multi_buffer.close_buffer(1).unwrap();
+        assert!(!lock_b.exists());
+        multi_buffer.release_locks();
+        assert!(!lock_a.exists());
+    }
+
+    #[test]
+    fn test_substitute_in_every_buffer() {
+        let file_manager = FileSystem::new().unwrap();
+        let mut multi_buffer = MultiBuffer::new(file_manager);
+        multi_buffer.insert_str(0, "old one\nold two old").unwrap();
+        multi_buffer.get_current_buffer_info_mut().unwrap().is_modified = false;
+        multi_buffer.new_buffer();
+        multi_buffer.insert_str(0, "nothing\nhere").unwrap();
+        multi_buffer.get_current_buffer_info_mut().unwrap().is_modified = false;
+
+        // As :bufdo %s/old/new/g does
+        let substitution = Substitution::parse("s/old/new/g").unwrap();
+        let mut counts = Vec::new();
+        for index in 0..multi_buffer.get_buffer_count() {
+            multi_buffer.switch_to_buffer(index).unwrap();
+            let lines = 0..multi_buffer.line_count();
+            counts.push(multi_buffer.substitute(lines, &substitution).unwrap());
+        }
+
+        assert_eq!(counts, vec![3, 0]);
+        assert_eq!(multi_buffer.get_buffer(0).unwrap().content(), "new one\nnew two new");
+        assert!(multi_buffer.buffer_info[0].is_modified);
+        assert!(!multi_buffer.buffer_info[1].is_modified);
+    }
+
+    #[test]
+    fn test_modified_buffer_navigation_none_modified() {
+        let file_manager = FileSystem::new().unwrap();
+        let mut multi_buffer = MultiBuffer::new(file_manager);
+        multi_buffer.new_buffer();
+
+        assert_eq!(multi_buffer.next_modified_buffer(), None);
+        assert_eq!(multi_buffer.previous_modified_buffer(), None);
+        assert_eq!(multi_buffer.get_current_buffer_index(), 1);
+    }
 }
\ No newline at end of file
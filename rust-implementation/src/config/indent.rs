@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::EditorConfig;
+
+/// How many lines at the top of a file are sampled for its indentation.
+const SAMPLE_LINES: usize = 1000;
+
+/// How a file is indented, as guessed from its leading whitespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndentStyle {
+    /// Too few indented lines, or tabs and spaces mixed evenly.
+    #[default]
+    Unknown,
+    Tabs,
+    /// Spaces, this many per level.
+    Spaces(usize),
+}
+
+impl IndentStyle {
+    /// Sets `expandtab` and `shiftwidth` to match. Unknown changes nothing.
+    pub fn apply(&self, config: &mut EditorConfig) {
+        match *self {
+            IndentStyle::Unknown => {}
+            IndentStyle::Tabs => {
+                config.expandtab = false;
+                config.shiftwidth = 0;
+            }
+            IndentStyle::Spaces(width) => {
+                config.expandtab = true;
+                config.shiftwidth = width;
+            }
+        }
+    }
+}
+
+impl fmt::Display for IndentStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndentStyle::Unknown => write!(f, "unknown"),
+            IndentStyle::Tabs => write!(f, "tabs"),
+            IndentStyle::Spaces(width) => write!(f, "{} spaces", width),
+        }
+    }
+}
+
+/// Guesses the indentation of `lines` from their leading whitespace, like
+/// vim's `detectindent`. Tabs win when at least twice as many lines start
+/// with a tab as with spaces, and the other way round; otherwise the style
+/// is unknown. The space width is the commonest step between the indents
+/// of neighbouring lines.
+pub fn detect_indentation<'a>(lines: impl IntoIterator<Item = &'a str>) -> IndentStyle {
+    let mut tab_lines = 0;
+    let mut space_lines = 0;
+    let mut steps: HashMap<usize, usize> = HashMap::new();
+    let mut previous = 0;
+
+    for line in lines.into_iter().take(SAMPLE_LINES) {
+        let text = line.trim_start();
+        // Blank lines and the ` * ` of block comments say nothing
+        if text.is_empty() || text.starts_with('*') {
+            continue;
+        }
+        let indent = &line[..line.len() - text.len()];
+        if indent.starts_with('\t') {
+            tab_lines += 1;
+            continue;
+        }
+
+        let width = indent.chars().take_while(|&ch| ch == ' ').count();
+        if width > 0 {
+            space_lines += 1;
+        }
+        let step = width.abs_diff(previous);
+        if (1..=8).contains(&step) {
+            *steps.entry(step).or_default() += 1;
+        }
+        previous = width;
+    }
+
+    if tab_lines > 0 && tab_lines >= space_lines * 2 {
+        return IndentStyle::Tabs;
+    }
+    if space_lines == 0 || space_lines < tab_lines * 2 {
+        return IndentStyle::Unknown;
+    }
+    // The commonest step, the narrower one on a tie
+    steps
+        .into_iter()
+        .max_by_key(|&(step, count)| (count, std::cmp::Reverse(step)))
+        .map_or(IndentStyle::Unknown, |(step, _)| IndentStyle::Spaces(step))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_space_indentation() {
+        let two = "fn main() {\n  if ready {\n    go();\n  }\n  done();\n}\n";
+        assert_eq!(detect_indentation(two.lines()), IndentStyle::Spaces(2));
+
+        // Continuation lines and block comments do not change the guess
+        let four = "def f():\n    x = [\n        1,\n    ]\n    return x\n\n/*\n * note\n */\nclass A:\n    pass\n";
+        assert_eq!(detect_indentation(four.lines()), IndentStyle::Spaces(4));
+    }
+
+    #[test]
+    fn test_detect_tab_indentation() {
+        let tabs = "int main() {\n\tif (x) {\n\t\treturn 1;\n\t}\n  /* stray */\n\treturn 0;\n}\n";
+        assert_eq!(detect_indentation(tabs.lines()), IndentStyle::Tabs);
+
+        let mut config = EditorConfig::new();
+        IndentStyle::Tabs.apply(&mut config);
+        assert!(!config.expandtab);
+        IndentStyle::Spaces(2).apply(&mut config);
+        assert_eq!((config.expandtab, config.indent_width()), (true, 2));
+    }
+
+    #[test]
+    fn test_ambiguous_or_empty_indentation() {
+        assert_eq!(detect_indentation("".lines()), IndentStyle::Unknown);
+        assert_eq!(detect_indentation("no\nindent\nhere".lines()), IndentStyle::Unknown);
+        // As many tab-indented lines as space-indented ones
+        let mixed = "a\n\tb\n    c\n\td\n    e\n";
+        assert_eq!(detect_indentation(mixed.lines()), IndentStyle::Unknown);
+    }
+}
@@ -0,0 +1,343 @@
+use crate::core::{EditorError, Result, TAB_SIZE};
+use crate::file_io::parse_size;
+use crate::syntax::DEFAULT_ATTENTION_WORDS;
+
+pub mod indent;
+pub mod modeline;
+pub use indent::{detect_indentation, IndentStyle};
+pub use modeline::{find_modeline, parse_modeline, EditorConfigPatch};
+
+/// Editor-wide options changed with `:set`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditorConfig {
+    pub hlsearch: bool,
+    pub incsearch: bool,
+    /// Searches continue from the top when they reach the end of the buffer.
+    pub wrapscan: bool,
+    /// Searches match regardless of case.
+    pub ignorecase: bool,
+    /// Highlight the bracket that pairs with the one at the cursor.
+    pub showmatch: bool,
+    /// Color brackets by how deeply they are nested.
+    pub rainbow: bool,
+    /// Number lines in a gutter beside the text.
+    pub number: bool,
+    /// Number lines by their distance from the cursor's line.
+    pub relativenumber: bool,
+    pub spell: bool,
+    pub spellfile: Option<String>,
+    pub autosave: bool,
+    /// Show buffers as a read-only hex dump.
+    pub binary: bool,
+    /// Create `.filename.lock` files to detect concurrent edits.
+    pub lockfile: bool,
+    /// Seconds between automatic writes when `autosave` is on.
+    pub autosave_interval: u64,
+    /// The maximum line width, or 0 for none.
+    pub textwidth: usize,
+    /// Comma-separated 1-based columns to draw a guide at: `80`, or `+1`
+    /// and `-2` relative to `textwidth`.
+    pub colorcolumn: String,
+    /// Comma-separated words highlighted inside comments.
+    pub todowords: String,
+    /// Files over this many bytes are paged read-only instead of loaded.
+    pub maxfilesize: u64,
+    /// Copy a file to a `.backup` file before overwriting it.
+    pub backup: bool,
+    /// Directory to keep backups in instead of beside each file.
+    pub backupdir: Option<String>,
+    /// Spaces inserted for a Tab.
+    pub tabstop: usize,
+    /// Columns per indent level, or 0 to follow `tabstop`.
+    pub shiftwidth: usize,
+    /// Tab inserts spaces rather than a tab character.
+    pub expandtab: bool,
+    /// Read options from modelines in files as they are opened.
+    pub modeline: bool,
+    /// Reload an unmodified buffer when its file changes on disk.
+    pub autoread: bool,
+    /// Follow the indentation each file already uses.
+    pub autoindentdetect: bool,
+    /// Keep each file's undo history in a `.name.un~` file beside it.
+    pub undofile: bool,
+    /// `strftime`-style patterns for the `date` and `time` expressions.
+    pub dateformat: String,
+    pub timeformat: String,
+}
+
+impl EditorConfig {
+    pub fn new() -> Self {
+        Self {
+            hlsearch: true,
+            incsearch: false,
+            wrapscan: true,
+            ignorecase: false,
+            showmatch: false,
+            rainbow: false,
+            number: false,
+            relativenumber: false,
+            spell: false,
+            spellfile: None,
+            autosave: false,
+            binary: false,
+            lockfile: true,
+            autosave_interval: 30,
+            textwidth: 0,
+            colorcolumn: String::new(),
+            todowords: DEFAULT_ATTENTION_WORDS.join(","),
+            maxfilesize: 10_000_000,
+            backup: true,
+            backupdir: None,
+            tabstop: TAB_SIZE,
+            shiftwidth: 0,
+            expandtab: true,
+            modeline: true,
+            autoread: false,
+            autoindentdetect: true,
+            undofile: false,
+            dateformat: "%Y-%m-%d".to_string(),
+            timeformat: "%H:%M:%S".to_string(),
+        }
+    }
+
+    /// Applies a single `:set` argument such as `hlsearch`, `nohlsearch`
+    /// or `name=value`.
+    pub fn set(&mut self, arg: &str) -> Result<()> {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg, None),
+        };
+
+        if let Some(flag) = self.flag_mut(name) {
+            if value.is_some() {
+                return Err(EditorError::InvalidOperation(format!("Option {} takes no value", name)));
+            }
+            *flag = true;
+            return Ok(());
+        }
+
+        if let Some(flag) = name.strip_prefix("no").and_then(|name| self.flag_mut(name)) {
+            *flag = false;
+            return Ok(());
+        }
+
+        if let Some(value) = value {
+            match name {
+                "spellfile" | "spf" => {
+                    self.spellfile = (!value.is_empty()).then(|| value.to_string());
+                    return Ok(());
+                }
+                "autosaveinterval" | "asi" => {
+                    self.autosave_interval = parse_number(name, value)?.max(1);
+                    return Ok(());
+                }
+                "tabstop" | "ts" => {
+                    self.tabstop = (parse_number(name, value)? as usize).max(1);
+                    return Ok(());
+                }
+                "shiftwidth" | "sw" => {
+                    self.shiftwidth = parse_number(name, value)? as usize;
+                    return Ok(());
+                }
+                "textwidth" | "tw" => {
+                    self.textwidth = parse_number(name, value)? as usize;
+                    return Ok(());
+                }
+                "backupdir" | "bdir" => {
+                    self.backupdir = (!value.is_empty()).then(|| value.to_string());
+                    return Ok(());
+                }
+                "maxfilesize" | "mfs" => {
+                    self.maxfilesize = parse_size(value)
+                        .ok_or_else(|| EditorError::InvalidOperation(format!("Invalid size for {}: {}", name, value)))?;
+                    return Ok(());
+                }
+                "dateformat" | "df" => {
+                    self.dateformat = value.to_string();
+                    return Ok(());
+                }
+                "timeformat" | "tf" => {
+                    self.timeformat = value.to_string();
+                    return Ok(());
+                }
+                "todowords" => {
+                    self.todowords = value.to_string();
+                    return Ok(());
+                }
+                "colorcolumn" | "cc" => {
+                    for column in value.split(',').filter(|column| !column.is_empty()) {
+                        let digits = column.strip_prefix(['+', '-']).unwrap_or(column);
+                        parse_number(name, digits)?;
+                    }
+                    self.colorcolumn = value.to_string();
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        Err(EditorError::InvalidOperation(format!("Unknown option: {}", name)))
+    }
+
+    /// The 0-based buffer columns `colorcolumn` marks, in order. Relative
+    /// columns are skipped while `textwidth` is 0.
+    pub fn color_columns(&self) -> Vec<usize> {
+        let mut columns: Vec<usize> = self
+            .colorcolumn
+            .split(',')
+            .filter_map(|column| {
+                let (relative, digits) = match column.strip_prefix(['+', '-']) {
+                    Some(digits) => (if column.starts_with('+') { 1 } else { -1 }, digits),
+                    None => (0, column),
+                };
+                let number = digits.parse::<i64>().ok()?;
+                let column = match relative {
+                    0 => number,
+                    _ if self.textwidth == 0 => return None,
+                    sign => self.textwidth as i64 + sign * number,
+                };
+                (column > 0).then(|| column as usize - 1)
+            })
+            .collect();
+        columns.sort_unstable();
+        columns.dedup();
+        columns
+    }
+
+    /// Columns per indent level.
+    pub fn indent_width(&self) -> usize {
+        if self.shiftwidth == 0 {
+            self.tabstop
+        } else {
+            self.shiftwidth
+        }
+    }
+
+    pub fn attention_words(&self) -> Vec<String> {
+        self.todowords
+            .split(',')
+            .filter(|word| !word.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "hlsearch" | "hls" => Some(&mut self.hlsearch),
+            "incsearch" | "is" => Some(&mut self.incsearch),
+            "wrapscan" | "ws" => Some(&mut self.wrapscan),
+            "ignorecase" | "ic" => Some(&mut self.ignorecase),
+            "showmatch" | "sm" => Some(&mut self.showmatch),
+            "rainbow" => Some(&mut self.rainbow),
+            "number" | "nu" => Some(&mut self.number),
+            "relativenumber" | "rnu" => Some(&mut self.relativenumber),
+            "spell" => Some(&mut self.spell),
+            "autosave" | "as" => Some(&mut self.autosave),
+            "binary" | "bin" => Some(&mut self.binary),
+            "lockfile" | "lf" => Some(&mut self.lockfile),
+            "backup" | "bk" => Some(&mut self.backup),
+            "expandtab" | "et" => Some(&mut self.expandtab),
+            "modeline" | "ml" => Some(&mut self.modeline),
+            "autoread" | "ar" => Some(&mut self.autoread),
+            "autoindentdetect" => Some(&mut self.autoindentdetect),
+            "undofile" | "udf" => Some(&mut self.undofile),
+            _ => None,
+        }
+    }
+}
+
+fn parse_number(name: &str, value: &str) -> Result<u64> {
+    value
+        .parse()
+        .map_err(|_| EditorError::InvalidOperation(format!("Invalid number for {}: {}", name, value)))
+}
+
+impl Default for EditorConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_boolean_options() {
+        let mut config = EditorConfig::new();
+
+        config.set("nohlsearch").unwrap();
+        config.set("is").unwrap();
+        config.set("nowrapscan").unwrap();
+        assert!(!config.hlsearch);
+        assert!(config.incsearch);
+        assert!(!config.wrapscan);
+
+        config.set("hls").unwrap();
+        assert!(config.hlsearch);
+    }
+
+    #[test]
+    fn test_set_rejects_unknown_options() {
+        let mut config = EditorConfig::new();
+        assert!(config.set("bogus").is_err());
+        assert!(config.set("nobogus").is_err());
+        assert!(config.set("hlsearch=1").is_err());
+    }
+
+    #[test]
+    fn test_set_string_options() {
+        let mut config = EditorConfig::new();
+
+        config.set("spellfile=/tmp/words.txt").unwrap();
+        assert_eq!(config.spellfile.as_deref(), Some("/tmp/words.txt"));
+
+        config.set("spellfile=").unwrap();
+        assert_eq!(config.spellfile, None);
+
+        assert_eq!(config.attention_words(), ["TODO", "FIXME", "HACK", "XXX"]);
+        config.set("todowords=NOTE,,BUG").unwrap();
+        assert_eq!(config.attention_words(), ["NOTE", "BUG"]);
+    }
+
+    #[test]
+    fn test_set_numeric_options() {
+        let mut config = EditorConfig::new();
+
+        config.set("autosave").unwrap();
+        config.set("autosaveinterval=5").unwrap();
+        assert!(config.autosave);
+        assert_eq!(config.autosave_interval, 5);
+
+        assert!(config.set("autosaveinterval=soon").is_err());
+        assert_eq!(config.autosave_interval, 5);
+
+        config.set("maxfilesize=50M").unwrap();
+        assert_eq!(config.maxfilesize, 50 * 1024 * 1024);
+        assert!(config.set("maxfilesize=big").is_err());
+
+        assert_eq!(config.indent_width(), TAB_SIZE);
+        config.set("sw=2").unwrap();
+        config.set("noet").unwrap();
+        assert_eq!(config.indent_width(), 2);
+        assert!(!config.expandtab);
+    }
+
+    #[test]
+    fn test_color_columns() {
+        let mut config = EditorConfig::new();
+        assert!(config.color_columns().is_empty());
+
+        config.set("colorcolumn=120,80,+1").unwrap();
+        // Relative columns need a textwidth
+        assert_eq!(config.color_columns(), vec![79, 119]);
+
+        config.set("textwidth=72").unwrap();
+        assert_eq!(config.color_columns(), vec![72, 79, 119]);
+        config.set("cc=-2,+0").unwrap();
+        assert_eq!(config.color_columns(), vec![69, 71]);
+
+        assert!(config.set("cc=80,wide").is_err());
+        assert_eq!(config.colorcolumn, "-2,+0");
+    }
+}
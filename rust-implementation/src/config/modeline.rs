@@ -0,0 +1,185 @@
+use super::EditorConfig;
+
+/// How many lines at each end of a file are searched for a modeline.
+pub const MODELINES: usize = 5;
+
+/// Options a file sets for itself in a modeline. Each is `None` unless the
+/// modeline names it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EditorConfigPatch {
+    pub tabstop: Option<usize>,
+    pub shiftwidth: Option<usize>,
+    pub expandtab: Option<bool>,
+    pub filetype: Option<String>,
+}
+
+impl EditorConfigPatch {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Copies the options this patch sets into `config`. The filetype
+    /// belongs to the buffer rather than the config.
+    pub fn apply(&self, config: &mut EditorConfig) {
+        if let Some(tabstop) = self.tabstop {
+            config.tabstop = tabstop;
+        }
+        if let Some(shiftwidth) = self.shiftwidth {
+            config.shiftwidth = shiftwidth;
+        }
+        if let Some(expandtab) = self.expandtab {
+            config.expandtab = expandtab;
+        }
+    }
+
+    /// Forgets the option `:set` just changed, so it is no longer
+    /// overridden for this buffer.
+    pub fn unset(&mut self, option: &str) {
+        let name = option.split_once('=').map_or(option, |(name, _)| name);
+        match name.strip_prefix("no").unwrap_or(name) {
+            "tabstop" | "ts" => self.tabstop = None,
+            "shiftwidth" | "sw" => self.shiftwidth = None,
+            "expandtab" | "et" => self.expandtab = None,
+            _ => {}
+        }
+    }
+
+    /// Takes every option `later` sets, as a later modeline wins.
+    fn merge(&mut self, later: EditorConfigPatch) {
+        self.tabstop = later.tabstop.or(self.tabstop);
+        self.shiftwidth = later.shiftwidth.or(self.shiftwidth);
+        self.expandtab = later.expandtab.or(self.expandtab);
+        self.filetype = later.filetype.or(self.filetype.take());
+    }
+
+    /// Applies one `name[=value]` from a modeline. Options that are not
+    /// on the list are skipped; `None` means a listed option had a bad
+    /// value.
+    fn set(&mut self, option: &str) -> Option<()> {
+        let (name, value) = match option.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (option, None),
+        };
+        match (name, value) {
+            ("tabstop" | "ts", Some(value)) => self.tabstop = Some(value.parse().ok().filter(|&width| width > 0)?),
+            ("shiftwidth" | "sw", Some(value)) => self.shiftwidth = Some(value.parse().ok()?),
+            ("expandtab" | "et", None) => self.expandtab = Some(true),
+            ("noexpandtab" | "noet", None) => self.expandtab = Some(false),
+            ("filetype" | "ft", Some(value)) => {
+                let valid = !value.is_empty()
+                    && value.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-');
+                self.filetype = Some(valid.then(|| value.to_string())?);
+            }
+            ("tabstop" | "ts" | "shiftwidth" | "sw" | "filetype" | "ft", None)
+            | ("expandtab" | "et" | "noexpandtab" | "noet", Some(_)) => return None,
+            _ => {}
+        }
+        Some(())
+    }
+}
+
+/// Reads a vim-style modeline, either `vim: ts=2 sw=2 et` with options
+/// separated by blanks or colons, or `vim: set ts=2 sw=2 et:` ended by a
+/// colon. Only `tabstop`, `shiftwidth`, `expandtab` and `filetype` are
+/// taken. A line without a modeline, or a malformed one, gives an empty
+/// patch.
+pub fn parse_modeline(line: &str) -> EditorConfigPatch {
+    parse_options(line).unwrap_or_default()
+}
+
+fn parse_options(line: &str) -> Option<EditorConfigPatch> {
+    let options = modeline_options(line)?;
+    let mut patch = EditorConfigPatch::default();
+
+    let options = match options.strip_prefix("set ").or_else(|| options.strip_prefix("se ")) {
+        // The second form ends at the next colon; text after it is ignored
+        Some(options) => options.split_once(':')?.0.split_whitespace().collect::<Vec<_>>(),
+        None => options.split([' ', '\t', ':']).filter(|option| !option.is_empty()).collect(),
+    };
+    if options.is_empty() {
+        return None;
+    }
+    for option in options {
+        patch.set(option)?;
+    }
+    Some(patch)
+}
+
+/// The text after `vi:`, `vim:` or `ex:`, which must start the line or
+/// follow a blank, as in vim. `ex:` always needs the blank.
+fn modeline_options(line: &str) -> Option<&str> {
+    line.char_indices().find_map(|(at, _)| {
+        let after_blank = line[..at].ends_with([' ', '\t']);
+        ["vim:", "Vim:", "vi:", "ex:"]
+            .iter()
+            .filter(|marker| after_blank || (at == 0 && **marker != "ex:"))
+            .find_map(|marker| line[at..].strip_prefix(marker))
+            .map(str::trim_start)
+    })
+}
+
+/// The options set by modelines in the first and last [`MODELINES`] lines
+/// of `content`, later lines winning.
+pub fn find_modeline(content: &str) -> EditorConfigPatch {
+    let lines: Vec<&str> = content.lines().collect();
+    let head = lines.len().min(MODELINES);
+    let tail = lines.len().saturating_sub(MODELINES).max(head);
+
+    let mut patch = EditorConfigPatch::default();
+    for line in lines[..head].iter().chain(&lines[tail..]) {
+        patch.merge(parse_modeline(line));
+    }
+    patch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_modeline() {
+        let patch = parse_modeline("# vim: set ts=2 sw=2 et:");
+        assert_eq!(patch.tabstop, Some(2));
+        assert_eq!(patch.shiftwidth, Some(2));
+        assert_eq!(patch.expandtab, Some(true));
+
+        // The first form, with options the whitelist skips
+        let patch = parse_modeline("/* vi:noet:ft=c:foldmethod=marker */");
+        assert_eq!(patch.expandtab, Some(false));
+        assert_eq!(patch.filetype.as_deref(), Some("c"));
+        assert_eq!(patch.tabstop, None);
+
+        let mut config = EditorConfig::new();
+        parse_modeline("vim: ts=8 noet").apply(&mut config);
+        assert_eq!(config.tabstop, 8);
+        assert!(!config.expandtab);
+    }
+
+    #[test]
+    fn test_malformed_modeline_is_ignored() {
+        // No closing colon after `set`
+        assert!(parse_modeline("# vim: set ts=2 sw=2 et").is_empty());
+        // A bad value spoils the whole line
+        assert!(parse_modeline("# vim: ts=0 sw=2").is_empty());
+        assert!(parse_modeline("# vim: ts=2 ft=$(rm)").is_empty());
+        assert!(parse_modeline("# vim: et=1").is_empty());
+        // The marker must follow a blank
+        assert!(parse_modeline("see xvim: ts=2").is_empty());
+        assert!(parse_modeline("ex: ts=2").is_empty());
+        assert!(parse_modeline("just a comment").is_empty());
+    }
+
+    #[test]
+    fn test_find_modeline_at_either_end() {
+        let mut lines: Vec<String> = (0..20).map(|n| format!("line {}", n)).collect();
+        lines[1] = "# vim: ts=2 et".to_string();
+        lines[18] = "# vim: set ts=4:".to_string();
+        // Only the first and last five lines are read
+        lines[10] = "# vim: ft=python".to_string();
+
+        let patch = find_modeline(&lines.join("\n"));
+        assert_eq!(patch.tabstop, Some(4));
+        assert_eq!(patch.expandtab, Some(true));
+        assert_eq!(patch.filetype, None);
+    }
+}
@@ -1,3 +1,12 @@
+use crate::config::{EditorConfigPatch, IndentStyle};
+use crate::display::CursorShape;
+use crate::editor_ops::BracketMatch;
+use crate::fold::FoldSet;
+use crate::layout::Rect;
+use crate::signs::Signs;
+use crate::spell::SpellChecker;
+use crate::syntax::SyntaxProfile;
+use std::rc::Rc;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -12,6 +21,10 @@ pub enum EditorError {
     Display(String),
     #[error("Invalid operation: {0}")]
     InvalidOperation(String),
+    #[error("Buffer is read-only")]
+    ReadOnly,
+    #[error("Cannot make changes, buffer is not modifiable")]
+    NotModifiable,
 }
 
 pub type Result<T> = std::result::Result<T, EditorError>;
@@ -32,15 +45,22 @@ impl Position {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum EditorMode {
+    #[default]
     Edit,
     Command,
+    Search,
+    /// Selecting text from where `v` was pressed to the cursor.
+    Visual,
 }
 
-impl Default for EditorMode {
-    fn default() -> Self {
-        EditorMode::Edit
+impl EditorMode {
+    /// Whether `key` starts `:` command input in this mode rather than
+    /// being typed. As in vim only command mode, its Normal mode, takes
+    /// `:` that way; in edit mode it is text like any other character.
+    pub fn opens_command_line(self, key: i32) -> bool {
+        self == EditorMode::Command && key == ':' as i32
     }
 }
 
@@ -49,12 +69,22 @@ pub trait TextBuffer {
     fn length(&self) -> usize;
     fn is_empty(&self) -> bool;
     fn insert(&mut self, pos: usize, ch: char) -> Result<()>;
+    /// Inserts `text`, which may span lines, at byte offset `pos`.
+    fn insert_str(&mut self, pos: usize, text: &str) -> Result<()>;
     fn delete(&mut self, pos: usize) -> Result<()>;
+    /// Removes the text between byte offsets `range`, joining lines it spans.
+    fn delete_range(&mut self, range: std::ops::Range<usize>) -> Result<()>;
     fn append(&mut self, text: &str) -> Result<()>;
     fn clear(&mut self);
     fn line_count(&self) -> usize;
     fn line_length(&self, line: usize) -> usize;
     fn get_line(&self, line: usize) -> Option<&str>;
+    /// The lines in `range`, clamped to the buffer's line count.
+    fn get_lines(&self, range: std::ops::Range<usize>) -> &[String];
+    /// Whether edits are allowed. Views such as help turn this off.
+    fn is_modifiable(&self) -> bool {
+        true
+    }
 }
 
 pub trait UndoRedoSystem<T: Clone> {
@@ -66,21 +96,216 @@ pub trait UndoRedoSystem<T: Clone> {
     fn clear(&mut self);
 }
 
+/// Character encoding a file is read and written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-16le" | "utf16le" | "utf-16" => Some(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+        }
+    }
+}
+
 pub trait FileManager {
     fn open(&self, filename: &str) -> Result<String>;
     fn save(&self, filename: &str, content: &str) -> Result<()>;
+
+    /// Opens a file along with the encoding it was decoded from.
+    fn open_with_encoding(&self, filename: &str) -> Result<(String, Encoding)> {
+        Ok((self.open(filename)?, Encoding::Utf8))
+    }
+
+    /// Like `open_with_encoding`, calling `progress` with the fraction of
+    /// the file read so far (ending at `1.0`) while it loads.
+    fn open_with_progress(&self, filename: &str, progress: &mut dyn FnMut(f64)) -> Result<(String, Encoding)> {
+        let opened = self.open_with_encoding(filename)?;
+        progress(1.0);
+        Ok(opened)
+    }
+
+    /// Saves a file in the given encoding.
+    fn save_with_encoding(&self, filename: &str, content: &str, encoding: Encoding) -> Result<()> {
+        match encoding {
+            Encoding::Utf8 => self.save(filename, content),
+            other => Err(EditorError::InvalidOperation(format!("Cannot write {} files", other.name()))),
+        }
+    }
+
+    /// Adds `content` to the end of `filename`, which is created if it
+    /// doesn't exist yet, without touching what is already there. The
+    /// content starts on a line of its own.
+    fn append(&self, filename: &str, content: &str) -> Result<()> {
+        use std::io::{Read, Seek, SeekFrom, Write};
+
+        let mut file = std::fs::OpenOptions::new().read(true).append(true).create(true).open(self.resolve(filename))?;
+        let mut last = [b'\n'];
+        if file.seek(SeekFrom::End(0))? > 0 {
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+        }
+        if last[0] != b'\n' {
+            file.write_all(b"\n")?;
+        }
+        file.write_all(content.as_bytes())?;
+        Ok(())
+    }
+
+    /// When the file was last written, if it exists.
+    fn modified_time(&self, filename: &str) -> Option<std::time::SystemTime> {
+        std::fs::metadata(self.resolve(filename)).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    /// Renames the file `from` to `to`.
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        std::fs::rename(self.resolve(from), self.resolve(to))?;
+        Ok(())
+    }
+
+    /// The on-disk path `filename` refers to.
+    fn resolve(&self, filename: &str) -> std::path::PathBuf {
+        std::path::PathBuf::from(filename)
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    Key(i32),
+    /// No key arrived before the input timeout expired.
+    Timeout,
+}
+
+impl InputEvent {
+    pub fn from_code(code: i32) -> Self {
+        if code == KEY_TIMEOUT {
+            InputEvent::Timeout
+        } else {
+            InputEvent::Key(code)
+        }
+    }
+}
+
+/// A screen the editor draws on and reads keys from. Besides the text and
+/// the status line a display may draw decorations such as folds, signs
+/// and highlights, which the editor hands it before each render; a
+/// display that draws none of them keeps the defaults, which ignore them.
 pub trait DisplayManager {
     fn init(&mut self) -> Result<()>;
     fn cleanup(&mut self) -> Result<()>;
     fn clear(&mut self) -> Result<()>;
     fn refresh(&mut self) -> Result<()>;
-    fn render_text(&mut self, text: &str, position: Position) -> Result<()>;
+    /// Draws `text` from line `top_line` on, marking the cursor line of
+    /// `position`.
+    fn render_text(&mut self, text: &str, position: Position, top_line: usize) -> Result<()>;
     fn render_status(&mut self, status: &str) -> Result<()>;
     fn get_input(&mut self) -> Result<i32>;
     fn get_size(&self) -> (usize, usize);
     fn move_cursor(&mut self, position: Position) -> Result<()>;
+
+    /// Like `get_input`, but reports an expired input timeout as its own event.
+    fn poll_input(&mut self) -> Result<InputEvent> {
+        self.get_input().map(InputEvent::from_code)
+    }
+
+    /// Makes `get_input` return `KEY_TIMEOUT` when no key arrives within
+    /// `ms` milliseconds, or block forever with `None`.
+    fn set_input_timeout(&mut self, _ms: Option<u32>) {}
+
+    /// Changes the cursor to `shape`, where the display can.
+    fn set_cursor_shape(&mut self, _shape: CursorShape) {}
+
+    /// Folds to collapse on the next render.
+    fn set_folds(&mut self, _folds: FoldSet) {}
+
+    /// Pattern whose matches are highlighted on the next render.
+    fn set_search_highlight(&mut self, _pattern: Option<String>) {}
+
+    /// Whether the search highlight matches regardless of case.
+    fn set_search_ignore_case(&mut self, _ignore_case: bool) {}
+
+    /// Spell checker used to underline misspelled words, or `None` to disable.
+    fn set_spell_checker(&mut self, _checker: Option<Rc<SpellChecker>>) {}
+
+    /// Signs drawn in the sign column on the next render.
+    fn set_signs(&mut self, _signs: Signs) {}
+
+    /// Buffer columns to draw a `colorcolumn` guide at on the next render.
+    fn set_color_columns(&mut self, _columns: Vec<usize>) {}
+
+    /// The bracket `showmatch` highlights on the next render.
+    fn set_bracket_match(&mut self, _bracket: Option<BracketMatch>) {}
+
+    /// The visual selection drawn reversed on the next render.
+    fn set_selection(&mut self, _selection: Option<(Position, Position)>) {}
+
+    /// Colors brackets by their nesting depth from the next render on.
+    fn set_rainbow(&mut self, _rainbow: bool) {}
+
+    /// Numbers lines in a gutter from the next render on: by line with
+    /// `number`, by distance from the cursor's line with `relative`.
+    fn set_line_numbers(&mut self, _number: bool, _relative: bool) {}
+
+    /// Highlights the next render as `filetype`.
+    fn set_filetype(&mut self, _filetype: Option<&str>) {}
+
+    /// Highlights `word` as a keyword from the next render on, whatever the
+    /// filetype.
+    fn add_keyword(&mut self, _word: String) {}
+
+    /// Stops highlighting `word`, whether it was added or is one of the
+    /// filetype's own keywords.
+    fn remove_keyword(&mut self, _word: &str) {}
+
+    /// Words to pick out inside comments, for every filetype.
+    fn set_attention_words(&mut self, _words: Vec<String>) {}
+
+    /// The keywords and comment syntax the display highlights with, if it
+    /// highlights at all.
+    fn syntax_profile(&self) -> Option<&SyntaxProfile> {
+        None
+    }
+
+    /// The buffer lines changed since the last render, from
+    /// `Buffer::changed_lines_since`. `None` when the text may be unrelated
+    /// to what was drawn last, such as after switching buffers, so that
+    /// every row is drawn again.
+    fn set_dirty_lines(&mut self, _lines: Option<Vec<usize>>) {}
+
+    /// Makes the next frame paint every cell, for `:redraw` and Ctrl-L when
+    /// something else has written over the screen. Nothing drawn before is
+    /// trusted.
+    fn force_redraw(&mut self) {}
+
+    /// Draws `text` into `rect` of the editor window, starting at buffer
+    /// line `top_line`, for split windows and diffs.
+    fn render_pane(&mut self, _rect: Rect, _text: &str, _cursor: Option<Position>, _top_line: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Draws the divider between split panes.
+    fn render_divider(&mut self, _rect: Rect) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub trait EditorOperations {
@@ -120,6 +345,28 @@ pub struct BufferInfo {
     pub is_modified: bool,
     pub cursor: Position,
     pub scroll_offset: usize,
+    /// PID of another editor that held the file's lock when it was opened.
+    pub locked_by: Option<u32>,
+    /// `content_hash` of the text last read from or written to the file.
+    pub content_hash: Option<u64>,
+    pub encoding: Encoding,
+    /// Whether the file started with a UTF-8 byte order mark.
+    pub bom: bool,
+    /// Content as last opened or saved, restored by `:discard`.
+    pub pristine: String,
+    /// When the file was last written, as of the last open or save.
+    pub modified_time: Option<std::time::SystemTime>,
+    /// Language detected from the file name or its `#!` line.
+    pub detected_filetype: Option<String>,
+    /// Language chosen with `:set filetype=`, which wins over detection.
+    pub filetype_override: Option<String>,
+    /// Options the file's modeline set when it was opened.
+    pub modeline: EditorConfigPatch,
+    /// The indentation the file's content uses, as last opened.
+    pub indent_style: IndentStyle,
+    /// Whether this buffer accepts edits (`:setlocal nomodifiable`),
+    /// separately from `--readonly`, which covers every buffer.
+    pub modifiable: bool,
 }
 
 impl BufferInfo {
@@ -129,8 +376,52 @@ impl BufferInfo {
             is_modified: false,
             cursor: Position::origin(),
             scroll_offset: 0,
+            locked_by: None,
+            content_hash: None,
+            encoding: Encoding::Utf8,
+            bom: false,
+            pristine: String::new(),
+            modified_time: None,
+            detected_filetype: None,
+            filetype_override: None,
+            modeline: EditorConfigPatch::default(),
+            indent_style: IndentStyle::Unknown,
+            modifiable: true,
+        }
+    }
+
+    /// The language the buffer is highlighted as: `:set filetype=`, then
+    /// the modeline, then detection.
+    pub fn filetype(&self) -> Option<&str> {
+        self.filetype_override
+            .as_deref()
+            .or(self.modeline.filetype.as_deref())
+            .or(self.detected_filetype.as_deref())
+    }
+
+    /// Stops the modeline and the detected indentation overriding the
+    /// option `:set` just changed.
+    pub fn forget_local_option(&mut self, option: &str) {
+        self.modeline.unset(option);
+        let name = option.split_once('=').map_or(option, |(name, _)| name);
+        if matches!(name.strip_prefix("no").unwrap_or(name), "shiftwidth" | "sw" | "expandtab" | "et") {
+            self.indent_style = IndentStyle::Unknown;
         }
     }
+
+    /// Whether the buffer has no real file behind it: `*untitled*`, or a
+    /// view such as `*help*`.
+    pub fn is_untitled(&self) -> bool {
+        self.filename.starts_with('*')
+    }
+}
+
+/// Hash used to tell whether buffer content differs from what is on disk.
+pub fn content_hash(content: &str) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
 }
 
 pub trait BufferManager {
@@ -144,5 +435,25 @@ pub trait BufferManager {
     fn list_buffers(&self) -> Vec<(usize, &BufferInfo)>;
 }
 
+/// Byte order mark, kept out of buffer content and restored on save.
+pub const BOM: char = '\u{feff}';
 pub const TAB_SIZE: usize = 4;
-pub const MAX_HISTORY: usize = 100;
\ No newline at end of file
+/// Returned by `DisplayManager::get_input` when no key arrived in time.
+pub const KEY_TIMEOUT: i32 = -1;
+pub const MAX_HISTORY: usize = 100;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colon_opens_command_line_only_in_command_mode() {
+        let colon = ':' as i32;
+        assert!(EditorMode::Command.opens_command_line(colon));
+        assert!(!EditorMode::Command.opens_command_line('x' as i32));
+        // Typed as text everywhere else
+        assert!(!EditorMode::Edit.opens_command_line(colon));
+        assert!(!EditorMode::Search.opens_command_line(colon));
+        assert!(!EditorMode::Visual.opens_command_line(colon));
+    }
+}
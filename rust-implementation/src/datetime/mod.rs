@@ -0,0 +1,101 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A calendar date and time of day in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let seconds = match time.duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs() as i64),
+        };
+        Self::from_unix_seconds(seconds)
+    }
+
+    pub fn from_unix_seconds(seconds: i64) -> Self {
+        let days = seconds.div_euclid(86_400);
+        let time_of_day = seconds.rem_euclid(86_400) as u32;
+        let (year, month, day) = civil_from_days(days);
+
+        Self {
+            year,
+            month,
+            day,
+            hour: time_of_day / 3600,
+            minute: time_of_day / 60 % 60,
+            second: time_of_day % 60,
+        }
+    }
+
+    /// Formats with `strftime`-style fields: `%Y`, `%m`, `%d`, `%H`, `%M`,
+    /// `%S` and `%%`. Other characters are copied as they are.
+    pub fn format(&self, pattern: &str) -> String {
+        let mut formatted = String::new();
+        let mut chars = pattern.chars();
+
+        while let Some(ch) = chars.next() {
+            if ch != '%' {
+                formatted.push(ch);
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => formatted.push_str(&format!("{:04}", self.year)),
+                Some('m') => formatted.push_str(&format!("{:02}", self.month)),
+                Some('d') => formatted.push_str(&format!("{:02}", self.day)),
+                Some('H') => formatted.push_str(&format!("{:02}", self.hour)),
+                Some('M') => formatted.push_str(&format!("{:02}", self.minute)),
+                Some('S') => formatted.push_str(&format!("{:02}", self.second)),
+                Some('%') => formatted.push('%'),
+                Some(other) => {
+                    formatted.push('%');
+                    formatted.push(other);
+                }
+                None => formatted.push('%'),
+            }
+        }
+        formatted
+    }
+}
+
+/// Year, month and day of the given day count since 1970-01-01, using the
+/// proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_unix_seconds() {
+        assert_eq!(DateTime::from_unix_seconds(0).format("%Y-%m-%d %H:%M:%S"), "1970-01-01 00:00:00");
+        // 2024-02-29 is a leap day
+        assert_eq!(DateTime::from_unix_seconds(1_709_210_096).format("%Y-%m-%d %H:%M:%S"), "2024-02-29 12:34:56");
+        assert_eq!(DateTime::from_unix_seconds(-1).format("%Y-%m-%d %H:%M:%S"), "1969-12-31 23:59:59");
+    }
+
+    #[test]
+    fn test_format_fields() {
+        let time = DateTime::from_unix_seconds(1_000_000_000);
+        assert_eq!(time.format("%d/%m/%Y"), "09/09/2001");
+        assert_eq!(time.format("100%% at %H%M %q"), "100% at 0146 %q");
+    }
+}
@@ -0,0 +1,233 @@
+use crate::layout::{Layout, Rect, SplitDirection};
+use crate::signs::{SignKind, Signs};
+
+/// One step of a line diff from `old` to `new`, by zero-based line index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffOp {
+    Equal { old: usize, new: usize },
+    /// The old line was removed.
+    Delete { old: usize },
+    /// The new line was inserted.
+    Insert { new: usize },
+    /// The old line was replaced by the new one.
+    Change { old: usize, new: usize },
+}
+
+/// Diffs two line sequences using their longest common subsequence.
+/// Within each run of removed and inserted lines, removals pair up with
+/// insertions as changes; whatever is left over stays a deletion or an
+/// insertion.
+pub fn diff_lines(old: &[&str], new: &[&str]) -> Vec<DiffOp> {
+    let lcs = lcs_table(old, new);
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push(DiffOp::Equal { old: i, new: j });
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        // Collect one run of removals and insertions
+        let (old_start, new_start) = (i, j);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                break;
+            }
+            if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+                j += 1;
+            } else {
+                i += 1;
+            }
+        }
+
+        let paired = (i - old_start).min(j - new_start);
+        for offset in 0..paired {
+            ops.push(DiffOp::Change {
+                old: old_start + offset,
+                new: new_start + offset,
+            });
+        }
+        for old in old_start + paired..i {
+            ops.push(DiffOp::Delete { old });
+        }
+        for new in new_start + paired..j {
+            ops.push(DiffOp::Insert { new });
+        }
+    }
+
+    ops
+}
+
+/// `table[i][j]` is the length of the longest common subsequence of
+/// `a[i..]` and `b[j..]`.
+fn lcs_table(a: &[&str], b: &[&str]) -> Vec<Vec<usize>> {
+    let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            table[i][j] = if a[i] == b[j] {
+                table[i + 1][j + 1] + 1
+            } else {
+                table[i + 1][j].max(table[i][j + 1])
+            };
+        }
+    }
+    table
+}
+
+/// Two texts laid out side by side for `:diff`. Rows are aligned so that
+/// equal and changed lines share a row; a line with no counterpart faces a
+/// blank filler row on the other side.
+#[derive(Debug, Clone)]
+pub struct DiffView {
+    left_name: String,
+    right_name: String,
+    left: Vec<String>,
+    right: Vec<String>,
+    left_signs: Signs,
+    right_signs: Signs,
+    top_line: usize,
+}
+
+impl DiffView {
+    pub fn new(left_name: &str, left_text: &str, right_name: &str, right_text: &str) -> Self {
+        let old: Vec<&str> = left_text.lines().collect();
+        let new: Vec<&str> = right_text.lines().collect();
+        let mut view = Self {
+            left_name: left_name.to_string(),
+            right_name: right_name.to_string(),
+            left: Vec::new(),
+            right: Vec::new(),
+            left_signs: Signs::new(),
+            right_signs: Signs::new(),
+            top_line: 0,
+        };
+
+        for op in diff_lines(&old, &new) {
+            let row = view.left.len();
+            let (left, right) = match op {
+                DiffOp::Equal { old: i, new: j } => (old[i], new[j]),
+                DiffOp::Delete { old: i } => {
+                    view.left_signs.place_sign(row, SignKind::Removed);
+                    (old[i], "")
+                }
+                DiffOp::Insert { new: j } => {
+                    view.right_signs.place_sign(row, SignKind::Added);
+                    ("", new[j])
+                }
+                DiffOp::Change { old: i, new: j } => {
+                    view.left_signs.place_sign(row, SignKind::Modified);
+                    view.right_signs.place_sign(row, SignKind::Modified);
+                    (old[i], new[j])
+                }
+            };
+            view.left.push(left.to_string());
+            view.right.push(right.to_string());
+        }
+        view
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.left.len()
+    }
+
+    pub fn top_line(&self) -> usize {
+        self.top_line
+    }
+
+    /// Number of rows that differ between the two sides.
+    pub fn difference_count(&self) -> usize {
+        (0..self.row_count())
+            .filter(|&row| self.left_signs.get(row).is_some() || self.right_signs.get(row).is_some())
+            .count()
+    }
+
+    pub fn scroll(&mut self, delta: i32) {
+        let last = self.row_count().saturating_sub(1);
+        self.top_line = (self.top_line as i64 + delta as i64).clamp(0, last as i64) as usize;
+    }
+
+    pub fn left_text(&self) -> String {
+        self.left.join("\n")
+    }
+
+    pub fn right_text(&self) -> String {
+        self.right.join("\n")
+    }
+
+    pub fn left_signs(&self) -> &Signs {
+        &self.left_signs
+    }
+
+    pub fn right_signs(&self) -> &Signs {
+        &self.right_signs
+    }
+
+    /// The two sides' rectangles and the divider between them, using the
+    /// same geometry as a `:vsplit`.
+    pub fn rects(&self, width: usize, height: usize) -> (Rect, Rect, Option<Rect>) {
+        let mut layout = Layout::new(0);
+        layout.split(SplitDirection::Vertical);
+        let rects = layout.pane_rects(width, height);
+        (rects[0], rects[1], layout.divider(width, height))
+    }
+
+    /// e.g. `diff: a.txt | b.txt (3 differences)`.
+    pub fn title(&self) -> String {
+        format!(
+            "diff: {} | {} ({} differences)",
+            self.left_name,
+            self.right_name,
+            self.difference_count()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insertions_and_deletions() {
+        assert_eq!(diff_lines(&["a", "c"], &["a", "b", "c"]), vec![
+            DiffOp::Equal { old: 0, new: 0 },
+            DiffOp::Insert { new: 1 },
+            DiffOp::Equal { old: 1, new: 2 },
+        ]);
+        assert_eq!(diff_lines(&["a", "b", "c"], &["c"]), vec![
+            DiffOp::Delete { old: 0 },
+            DiffOp::Delete { old: 1 },
+            DiffOp::Equal { old: 2, new: 0 },
+        ]);
+        assert_eq!(diff_lines(&[], &["x"]), vec![DiffOp::Insert { new: 0 }]);
+        assert!(diff_lines(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_changes_pair_removals_with_insertions() {
+        assert_eq!(diff_lines(&["a", "old", "z"], &["a", "new", "more", "z"]), vec![
+            DiffOp::Equal { old: 0, new: 0 },
+            DiffOp::Change { old: 1, new: 1 },
+            DiffOp::Insert { new: 2 },
+            DiffOp::Equal { old: 2, new: 3 },
+        ]);
+        assert_eq!(diff_lines(&["x", "y"], &["q"]), vec![
+            DiffOp::Change { old: 0, new: 0 },
+            DiffOp::Delete { old: 1 },
+        ]);
+    }
+
+    #[test]
+    fn test_view_aligns_rows() {
+        let view = DiffView::new("a", "one\ntwo\nthree", "b", "one\n2\nthree\nfour");
+
+        assert_eq!(view.left_text(), "one\ntwo\nthree\n");
+        assert_eq!(view.right_text(), "one\n2\nthree\nfour");
+        assert_eq!(view.left_signs().get(1), Some(SignKind::Modified));
+        assert_eq!(view.right_signs().get(3), Some(SignKind::Added));
+        assert_eq!(view.difference_count(), 2);
+        assert_eq!(view.title(), "diff: a | b (2 differences)");
+    }
+}
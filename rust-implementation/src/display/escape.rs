@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const ESC: i32 = 27;
+
+/// How long to wait after `Esc` for the rest of an escape sequence.
+pub const ESCAPE_TIMEOUT: Duration = Duration::from_millis(25);
+
+/// Turns raw escape sequences that slip past curses' keypad translation
+/// into the editor's key codes. A lone `Esc` is only reported once
+/// `ESCAPE_TIMEOUT` passes without further bytes; bytes arriving sooner
+/// are buffered as part of a sequence.
+#[derive(Debug, Clone)]
+pub struct EscapeDecoder {
+    pending: Vec<i32>,
+    started: Option<Instant>,
+    timeout: Duration,
+}
+
+enum Decoded {
+    Key(i32),
+    Incomplete,
+    Invalid,
+}
+
+impl EscapeDecoder {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            pending: Vec::new(),
+            started: None,
+            timeout,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Time left before a pending `Esc` is given up on as a lone keypress.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        match self.started {
+            Some(started) => self.timeout.saturating_sub(now.saturating_duration_since(started)),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Feeds one raw key code, returning the keys that are now complete.
+    pub fn feed(&mut self, code: i32, now: Instant) -> VecDeque<i32> {
+        let mut keys = self.flush_if_expired(now);
+
+        if self.pending.is_empty() {
+            if code == ESC {
+                self.pending.push(code);
+                self.started = Some(now);
+            } else {
+                keys.push_back(code);
+            }
+            return keys;
+        }
+
+        self.pending.push(code);
+        match decode(&self.pending) {
+            Decoded::Key(key) => {
+                self.reset();
+                keys.push_back(key);
+            }
+            Decoded::Incomplete => {}
+            Decoded::Invalid => {
+                // Not a sequence: Esc was a keypress of its own
+                keys.extend(self.pending.drain(..));
+                self.reset();
+            }
+        }
+        keys
+    }
+
+    /// Releases buffered bytes as plain keys once the escape timeout has passed.
+    pub fn flush_if_expired(&mut self, now: Instant) -> VecDeque<i32> {
+        if self.is_pending() && self.remaining(now).is_zero() {
+            let keys = self.pending.drain(..).collect();
+            self.reset();
+            keys
+        } else {
+            VecDeque::new()
+        }
+    }
+
+    fn reset(&mut self) {
+        self.pending.clear();
+        self.started = None;
+    }
+}
+
+impl Default for EscapeDecoder {
+    fn default() -> Self {
+        Self::new(ESCAPE_TIMEOUT)
+    }
+}
+
+fn decode(bytes: &[i32]) -> Decoded {
+    let chars: Vec<char> = bytes[1..]
+        .iter()
+        .map(|&b| u8::try_from(b).map(char::from).unwrap_or('\0'))
+        .collect();
+
+    match chars.as_slice() {
+        ['['] | ['O'] | ['[', '0'..='9'] => Decoded::Incomplete,
+        ['[' | 'O', 'A'] => Decoded::Key(1001),
+        ['[' | 'O', 'B'] => Decoded::Key(1002),
+        ['[' | 'O', 'D'] => Decoded::Key(1003),
+        ['[' | 'O', 'C'] => Decoded::Key(1004),
+        ['[' | 'O', 'H'] | ['[', '1' | '7', '~'] => Decoded::Key(1006),
+        ['[' | 'O', 'F'] | ['[', '4' | '8', '~'] => Decoded::Key(1007),
+        ['[', '3', '~'] => Decoded::Key(1005),
+        _ => Decoded::Invalid,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(decoder: &mut EscapeDecoder, codes: &[i32], now: Instant) -> Vec<i32> {
+        codes.iter().flat_map(|&code| decoder.feed(code, now)).collect()
+    }
+
+    #[test]
+    fn test_arrow_sequence_within_timeout() {
+        let mut decoder = EscapeDecoder::default();
+        let now = Instant::now();
+
+        assert_eq!(keys(&mut decoder, &[27, '[' as i32, 'A' as i32], now), vec![1001]);
+        assert_eq!(keys(&mut decoder, &[27, '[' as i32, '3' as i32, '~' as i32], now), vec![1005]);
+        assert!(!decoder.is_pending());
+    }
+
+    #[test]
+    fn test_lone_escape_after_timeout() {
+        let mut decoder = EscapeDecoder::default();
+        let start = Instant::now();
+
+        assert!(decoder.feed(27, start).is_empty());
+        assert!(decoder.is_pending());
+        assert!(decoder.flush_if_expired(start + Duration::from_millis(10)).is_empty());
+
+        let flushed: Vec<i32> = decoder.flush_if_expired(start + ESCAPE_TIMEOUT).into_iter().collect();
+        assert_eq!(flushed, vec![27]);
+        assert!(!decoder.is_pending());
+    }
+
+    #[test]
+    fn test_late_bytes_are_not_swallowed() {
+        let mut decoder = EscapeDecoder::default();
+        let start = Instant::now();
+
+        decoder.feed(27, start);
+        // `[` arrives too late to belong to the Esc, so both are plain keys
+        let late: Vec<i32> = decoder.feed('[' as i32, start + Duration::from_millis(100)).into_iter().collect();
+        assert_eq!(late, vec![27, '[' as i32]);
+    }
+
+    #[test]
+    fn test_escape_followed_by_ordinary_key() {
+        let mut decoder = EscapeDecoder::default();
+        let now = Instant::now();
+
+        assert_eq!(keys(&mut decoder, &[27, 'j' as i32], now), vec![27, 'j' as i32]);
+        assert_eq!(decoder.remaining(now), Duration::ZERO);
+    }
+}
@@ -0,0 +1,106 @@
+use pancurses::chtype;
+
+/// One character cell and the attributes it is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    pub attrs: chtype,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self { ch: ' ', attrs: 0 }
+    }
+}
+
+/// An off-screen copy of the editor window. Each render draws a fresh frame,
+/// and only the cells that differ from the last frame shown are written to
+/// the terminal, so nothing is blanked and redrawn in between.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frame {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Frame {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Cell::default(); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Blanks every cell, like curses' `erase`.
+    pub fn clear(&mut self) {
+        self.cells.fill(Cell::default());
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<Cell> {
+        (x < self.width && y < self.height).then(|| self.cells[y * self.width + x])
+    }
+
+    /// Sets one cell. Cells off the frame are ignored.
+    pub fn put(&mut self, x: usize, y: usize, ch: char, attrs: chtype) {
+        if x < self.width && y < self.height {
+            self.cells[y * self.width + x] = Cell { ch, attrs };
+        }
+    }
+
+    /// Writes `text` from column `x`, one character per cell, clipped at the
+    /// right edge.
+    pub fn put_str(&mut self, x: usize, y: usize, text: &str, attrs: chtype) {
+        for (offset, ch) in text.chars().enumerate() {
+            self.put(x + offset, y, ch, attrs);
+        }
+    }
+
+    /// The `(x, y)` of each cell that must be repainted to turn `previous`
+    /// into this frame, row by row. Every cell changes when the sizes differ.
+    pub fn changed_cells(&self, previous: &Frame) -> Vec<(usize, usize)> {
+        let same_size = self.width == previous.width && self.height == previous.height;
+        (0..self.cells.len())
+            .filter(|&index| !same_size || self.cells[index] != previous.cells[index])
+            .map(|index| (index % self.width, index / self.width))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_cells() {
+        let mut previous = Frame::new(4, 2);
+        previous.put_str(0, 0, "abcd", 0);
+        let mut next = previous.clone();
+        assert!(next.changed_cells(&previous).is_empty());
+
+        // A new character and a new attribute on an unchanged one both count
+        next.put(1, 0, 'X', 0);
+        next.put(3, 0, 'd', 1);
+        next.put_str(2, 1, "hello", 0);
+        assert_eq!(next.changed_cells(&previous), vec![(1, 0), (3, 0), (2, 1), (3, 1)]);
+        assert_eq!(next.get(3, 1), Some(Cell { ch: 'e', attrs: 0 }));
+
+        next.clear();
+        assert_eq!(next.changed_cells(&previous).len(), 4);
+    }
+
+    #[test]
+    fn test_resize_repaints_everything() {
+        let previous = Frame::new(3, 2);
+        let next = Frame::new(2, 2);
+        assert_eq!(next.changed_cells(&previous), vec![(0, 0), (1, 0), (0, 1), (1, 1)]);
+    }
+}
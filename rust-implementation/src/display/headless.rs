@@ -0,0 +1,59 @@
+use crate::core::{DisplayManager, Position, Result};
+
+/// A display with no terminal behind it, for `--batch` runs. Nothing is
+/// drawn; status messages are kept so they can be reported afterwards, and
+/// every prompt is answered with Escape, which cancels it.
+#[derive(Debug, Default)]
+pub struct HeadlessDisplay {
+    messages: Vec<String>,
+}
+
+impl HeadlessDisplay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The status messages shown so far, oldest first.
+    pub fn messages(&self) -> &[String] {
+        &self.messages
+    }
+}
+
+impl DisplayManager for HeadlessDisplay {
+    fn init(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn cleanup(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn refresh(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn render_text(&mut self, _text: &str, _position: Position, _top_line: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn render_status(&mut self, status: &str) -> Result<()> {
+        self.messages.push(status.to_string());
+        Ok(())
+    }
+
+    fn get_input(&mut self) -> Result<i32> {
+        Ok(27)
+    }
+
+    fn get_size(&self) -> (usize, usize) {
+        (80, 24)
+    }
+
+    fn move_cursor(&mut self, _position: Position) -> Result<()> {
+        Ok(())
+    }
+}
@@ -1,33 +1,226 @@
-use crate::core::{DisplayManager, EditorError, EditorMode, Position, Result};
-use pancurses::{curs_set, endwin, has_colors, init_pair, initscr, noecho, raw, start_color, Window, Input, COLOR_PAIR};
-use std::collections::HashMap;
+use crate::core::{DisplayManager, EditorError, EditorMode, Position, Result, KEY_TIMEOUT};
+use crate::editor_ops::BracketMatch;
+use crate::fold::{fold_summary, FoldSet};
+use crate::layout::Rect;
+use crate::search::match_ranges;
+use crate::signs::{SignKind, Signs};
+use crate::spell::SpellChecker;
+use crate::syntax::{bracket_depths, token_at, tokenize_line, SyntaxProfile, TokenKind};
+use pancurses::{chtype, curs_set, endwin, has_colors, init_pair, initscr, noecho, raw, start_color, Window, Input, A_UNDERLINE, COLOR_PAIR};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Write;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub mod escape;
+pub mod frame;
+pub mod headless;
+pub use escape::EscapeDecoder;
+pub use frame::{Cell, Frame};
+pub use headless::HeadlessDisplay;
 
 const COLOR_KEYWORD: i16 = 1;
 const COLOR_NUMBER: i16 = 2;
 const COLOR_STRING: i16 = 3;
 const COLOR_CURSOR: i16 = 4;
+const COLOR_FOLD: i16 = 5;
+const COLOR_SEARCH: i16 = 6;
+const COLOR_COMMENT: i16 = 7;
+const COLOR_SPELL: i16 = 8;
+const COLOR_SIGN_ERROR: i16 = 9;
+const COLOR_SIGN_WARNING: i16 = 10;
+const COLOR_SIGN_ADDED: i16 = 11;
+const COLOR_GUIDE: i16 = 12;
+const COLOR_MATCH: i16 = 13;
+const COLOR_UNMATCHED: i16 = 14;
+const COLOR_ESCAPE: i16 = 19;
+const COLOR_ATTENTION: i16 = 20;
+const COLOR_LINE_NUMBER: i16 = 21;
+/// Bracket colors for successive nesting depths, cycling.
+const COLOR_RAINBOW: [i16; 4] = [15, 16, 17, 18];
 
 pub struct TerminalDisplay {
     main_window: Option<Window>,
     status_window: Option<Window>,
+    /// The editor window being drawn, written out on `refresh`.
+    frame: Frame,
+    /// The frame last written to the terminal.
+    shown: Frame,
+    cursor_position: Position,
     screen_size: (usize, usize),
-    keywords: Vec<String>,
+    syntax: SyntaxProfile,
+    /// The filetype `syntax` was built for.
+    syntax_filetype: Option<String>,
+    /// Keywords added and removed with `:syntax keyword`, kept across
+    /// profile changes.
+    added_keywords: Vec<String>,
+    removed_keywords: Vec<String>,
+    /// The `todowords` list, replacing the profile's attention words.
+    attention_words: Option<Vec<String>>,
+    input_timeout: Option<u32>,
+    escape_decoder: EscapeDecoder,
+    ready_keys: VecDeque<i32>,
+    folds: FoldSet,
+    search_highlight: Option<String>,
+    search_ignore_case: bool,
+    spell_checker: Option<Rc<SpellChecker>>,
+    signs: Signs,
+    /// Buffer columns marked by `colorcolumn`.
+    color_columns: Vec<usize>,
+    bracket_match: Option<BracketMatch>,
+    /// The visual selection, from its start up to but not including its end.
+    selection: Option<(Position, Position)>,
+    /// Color brackets by nesting depth.
+    rainbow: bool,
+    /// Number each line in a gutter, as vim's `number`.
+    show_line_numbers: bool,
+    /// Number lines by their distance from the cursor's, as vim's
+    /// `relativenumber`.
+    relative_numbers: bool,
+    /// Whether the terminal's own cursor is shown, as last set by `curs_set`.
+    cursor_visible: bool,
+    cursor_shape: Option<CursorShape>,
+    /// Whether the terminal understands the cursor shape escape.
+    cursor_shapes_supported: bool,
+    dirty_lines: Option<HashSet<usize>>,
+    /// The rows drawn so far, by screen column and row.
+    drawn_rows: HashMap<(usize, usize), DrawnRow>,
+}
+
+/// A row of the editor window as last drawn, with what it showed.
+#[derive(Debug, Clone)]
+struct DrawnRow {
+    line: usize,
+    width: usize,
+    cursor_line: bool,
+    /// The bracket depth the line starts at, with rainbow brackets on.
+    depth: Option<usize>,
+    /// The line number gutter, padded to its width.
+    number: Option<String>,
+    cells: Vec<Cell>,
+}
+
+impl DrawnRow {
+    /// Whether `other` shows the same line in the same way, so the cells of
+    /// this row still hold when that line is unchanged.
+    fn same_place(&self, other: &DrawnRow) -> bool {
+        self.line == other.line
+            && self.width == other.width
+            && self.cursor_line == other.cursor_line
+            && self.depth == other.depth
+            && self.number == other.number
+    }
+}
+
+/// The terminal cursor's shape, which follows the mode as in vim: a block
+/// in command mode and a bar while typing text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    Block,
+    Bar,
+}
+
+impl CursorShape {
+    pub fn for_mode(mode: EditorMode) -> Self {
+        match mode {
+            EditorMode::Command | EditorMode::Visual => CursorShape::Block,
+            EditorMode::Edit | EditorMode::Search => CursorShape::Bar,
+        }
+    }
+
+    /// The DECSCUSR sequence selecting this shape, steady rather than blinking.
+    fn escape(self) -> &'static str {
+        match self {
+            CursorShape::Block => "\x1b[2 q",
+            CursorShape::Bar => "\x1b[6 q",
+        }
+    }
 }
 
 impl TerminalDisplay {
     pub fn new() -> Self {
-        let keywords = vec![
-            "fn", "let", "mut", "if", "else", "while", "for", "match", "struct", "enum",
-            "impl", "trait", "pub", "use", "mod", "return", "break", "continue", "loop",
-            "true", "false", "None", "Some", "Ok", "Err", "const", "static", "unsafe",
-            "async", "await", "move", "ref", "where", "type", "as", "in"
-        ].into_iter().map(|s| s.to_string()).collect();
-
         Self {
             main_window: None,
             status_window: None,
+            frame: Frame::default(),
+            shown: Frame::default(),
+            cursor_position: Position::origin(),
             screen_size: (0, 0),
-            keywords,
+            syntax: SyntaxProfile::default(),
+            syntax_filetype: None,
+            added_keywords: Vec::new(),
+            removed_keywords: Vec::new(),
+            attention_words: None,
+            input_timeout: None,
+            escape_decoder: EscapeDecoder::default(),
+            ready_keys: VecDeque::new(),
+            folds: FoldSet::new(),
+            search_highlight: None,
+            search_ignore_case: false,
+            spell_checker: None,
+            signs: Signs::new(),
+            color_columns: Vec::new(),
+            bracket_match: None,
+            selection: None,
+            rainbow: false,
+            show_line_numbers: false,
+            relative_numbers: false,
+            cursor_visible: true,
+            cursor_shape: None,
+            cursor_shapes_supported: false,
+            dirty_lines: None,
+            drawn_rows: HashMap::new(),
+        }
+    }
+
+    pub fn cursor_shape(&self) -> Option<CursorShape> {
+        self.cursor_shape
+    }
+
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        curs_set(i32::from(visible));
+        self.cursor_visible = visible;
+    }
+
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    /// Reads one key code, waiting at most `timeout` (forever with `None`).
+    fn read_key(window: &Window, timeout: Option<Duration>) -> Option<i32> {
+        window.timeout(timeout.map(|t| t.as_millis() as i32).unwrap_or(-1));
+        match window.getch()? {
+            Input::Character(ch) => Some(ch as i32),
+            Input::KeyUp => Some(1001),
+            Input::KeyDown => Some(1002),
+            Input::KeyLeft => Some(1003),
+            Input::KeyRight => Some(1004),
+            Input::KeyBackspace => Some(127),
+            Input::KeyDC => Some(1005), // Delete key
+            Input::KeyHome => Some(1006),
+            Input::KeyEnd => Some(1007),
+            Input::KeyEnter => Some(10), // Enter
+            _ => Some(0), // Unknown input
+        }
+    }
+
+    /// Keywords and comment syntax used to highlight the next render.
+    pub fn set_syntax_profile(&mut self, profile: SyntaxProfile) {
+        self.syntax = profile;
+        self.apply_keyword_changes();
+        self.drawn_rows.clear();
+    }
+
+    fn apply_keyword_changes(&mut self) {
+        if let Some(ref words) = self.attention_words {
+            self.syntax.attention_words = words.clone();
+        }
+        let keywords = &mut self.syntax.keywords;
+        keywords.retain(|keyword| !self.removed_keywords.contains(keyword));
+        for word in &self.added_keywords {
+            if !keywords.contains(word) {
+                keywords.push(word.clone());
+            }
         }
     }
 
@@ -38,6 +231,23 @@ impl TerminalDisplay {
             init_pair(COLOR_NUMBER, pancurses::COLOR_CYAN, pancurses::COLOR_BLACK);
             init_pair(COLOR_STRING, pancurses::COLOR_RED, pancurses::COLOR_BLACK);
             init_pair(COLOR_CURSOR, pancurses::COLOR_BLACK, pancurses::COLOR_WHITE);
+            init_pair(COLOR_FOLD, pancurses::COLOR_CYAN, pancurses::COLOR_BLUE);
+            init_pair(COLOR_SEARCH, pancurses::COLOR_BLACK, pancurses::COLOR_YELLOW);
+            init_pair(COLOR_COMMENT, pancurses::COLOR_GREEN, pancurses::COLOR_BLACK);
+            init_pair(COLOR_SPELL, pancurses::COLOR_MAGENTA, pancurses::COLOR_BLACK);
+            init_pair(COLOR_SIGN_ERROR, pancurses::COLOR_RED, pancurses::COLOR_BLACK);
+            init_pair(COLOR_SIGN_WARNING, pancurses::COLOR_YELLOW, pancurses::COLOR_BLACK);
+            init_pair(COLOR_SIGN_ADDED, pancurses::COLOR_GREEN, pancurses::COLOR_BLACK);
+            init_pair(COLOR_GUIDE, pancurses::COLOR_WHITE, pancurses::COLOR_RED);
+            init_pair(COLOR_MATCH, pancurses::COLOR_BLACK, pancurses::COLOR_CYAN);
+            init_pair(COLOR_UNMATCHED, pancurses::COLOR_WHITE, pancurses::COLOR_MAGENTA);
+            init_pair(COLOR_ESCAPE, pancurses::COLOR_MAGENTA, pancurses::COLOR_BLACK);
+            init_pair(COLOR_ATTENTION, pancurses::COLOR_YELLOW, pancurses::COLOR_BLACK);
+            init_pair(COLOR_LINE_NUMBER, pancurses::COLOR_WHITE, pancurses::COLOR_BLACK);
+            let rainbow = [pancurses::COLOR_YELLOW, pancurses::COLOR_MAGENTA, pancurses::COLOR_CYAN, pancurses::COLOR_GREEN];
+            for (pair, color) in COLOR_RAINBOW.iter().zip(rainbow) {
+                init_pair(*pair, color, pancurses::COLOR_BLACK);
+            }
         }
         Ok(())
     }
@@ -62,85 +272,136 @@ impl TerminalDisplay {
 
         self.main_window = Some(editor_win);
         self.status_window = Some(status_win);
+        self.frame = Frame::new(width as usize, height as usize - 1);
+        self.shown = Frame::default();
 
         Ok(())
     }
 
-    fn highlight_syntax(&self, window: &Window, text: &str, line_y: i32, cursor_pos: Option<usize>) {
-        let mut x = 0;
-        let mut chars = text.char_indices().peekable();
+    /// Repaints the given byte ranges of an already drawn line with `attrs`.
+    fn highlight_ranges(&self, frame: &mut Frame, text: &str, row: Rect, ranges: &[(usize, usize)], attrs: chtype) {
+        for (x, (byte_idx, ch)) in text.char_indices().enumerate().take(row.width) {
+            let in_range = ranges.iter().any(|&(start, end)| byte_idx >= start && byte_idx < end);
+            if in_range {
+                frame.put(row.x + x, row.y, ch, attrs);
+            }
+        }
+    }
+
+    fn sign_color(kind: SignKind) -> i16 {
+        match kind {
+            SignKind::Breakpoint | SignKind::Error | SignKind::Deleted | SignKind::Removed => COLOR_SIGN_ERROR,
+            SignKind::Warning | SignKind::Modified => COLOR_SIGN_WARNING,
+            SignKind::Added => COLOR_SIGN_ADDED,
+        }
+    }
 
-        while let Some((byte_idx, ch)) = chars.next() {
-            let mut highlighted = false;
+    /// Draws the sign column cells for buffer line `line` on screen row `row`.
+    fn render_sign_column(&self, frame: &mut Frame, line: usize, row: Rect) {
+        let gutter: String = self.signs.gutter_text(line).chars().take(row.width).collect();
+        let attrs = self.signs.get(line).map_or(0, |kind| COLOR_PAIR(Self::sign_color(kind) as u32));
+        frame.put_str(row.x, row.y, &gutter, attrs);
+    }
 
-            // Check if this is the cursor position
-            if let Some(cursor_x) = cursor_pos {
-                if byte_idx == cursor_x {
-                    window.attron(COLOR_PAIR(COLOR_CURSOR as u32));
-                    window.mvaddch(line_y, x, ch);
-                    window.attroff(COLOR_PAIR(COLOR_CURSOR as u32));
-                    x += 1;
-                    continue;
-                }
-            }
+    /// Colors `text` by token. Given the bracket depth the line starts at,
+    /// brackets outside strings and comments take their depth's color.
+    fn highlight_syntax(&self, frame: &mut Frame, text: &str, row: Rect, depth: Option<usize>) {
+        let tokens = tokenize_line(text, &self.syntax);
+        let brackets = depth.map_or_else(Vec::new, |depth| bracket_depths(text, &self.syntax, depth).0);
 
-            // Check for keywords
-            if ch.is_alphabetic() || ch == '_' {
-                let word_start = byte_idx;
-                let mut word_end = byte_idx + ch.len_utf8();
-
-                // Find the end of the word
-                while let Some((_, next_ch)) = chars.peek() {
-                    if next_ch.is_alphanumeric() || *next_ch == '_' {
-                        let (next_idx, next_ch) = chars.next().unwrap();
-                        word_end = next_idx + next_ch.len_utf8();
-                    } else {
-                        break;
-                    }
-                }
+        for (col, (byte_idx, ch)) in text.char_indices().enumerate().take(row.width) {
+            let color = token_at(&tokens, byte_idx).map(|token| match token.kind {
+                TokenKind::Keyword => COLOR_KEYWORD,
+                TokenKind::Number => COLOR_NUMBER,
+                TokenKind::String | TokenKind::Char => COLOR_STRING,
+                TokenKind::Escape => COLOR_ESCAPE,
+                TokenKind::Comment => COLOR_COMMENT,
+                TokenKind::Attention => COLOR_ATTENTION,
+            });
+            let color = color.or_else(|| {
+                let (_, depth) = brackets.iter().find(|(idx, _)| *idx == byte_idx)?;
+                Some(COLOR_RAINBOW[depth % COLOR_RAINBOW.len()])
+            });
 
-                let word = &text[word_start..word_end];
-                if self.keywords.contains(&word.to_string()) {
-                    window.attron(COLOR_PAIR(COLOR_KEYWORD as u32));
-                    window.mvaddstr(line_y, x, word);
-                    window.attroff(COLOR_PAIR(COLOR_KEYWORD as u32));
-                    x += word.chars().count() as i32;
-                    highlighted = true;
-                }
+            let attrs = color.map_or(0, |color| COLOR_PAIR(color as u32));
+            frame.put(row.x + col, row.y, ch, attrs);
+        }
+    }
+}
+
+impl TerminalDisplay {
+    /// Draws `line` on `row` as `drawn` describes it, with its sign, line
+    /// number, highlighting and matches.
+    fn draw_line(&self, frame: &mut Frame, line: &str, row: Rect, drawn: &DrawnRow) {
+        let i = drawn.line;
+        let sign_width = self.signs.column_width().min(row.width);
+        if sign_width > 0 {
+            self.render_sign_column(frame, i, Rect::new(row.x, row.y, sign_width, 1));
+        }
+        let row = Rect::new(row.x + sign_width, row.y, row.width - sign_width, 1);
+
+        let number: String = drawn.number.as_deref().unwrap_or("").chars().take(row.width).collect();
+        frame.put_str(row.x, row.y, &number, COLOR_PAIR(COLOR_LINE_NUMBER as u32) | pancurses::A_DIM);
+        let number_width = number.chars().count();
+        let row = Rect::new(row.x + number_width, row.y, row.width - number_width, 1);
+
+        if let Some(fold) = self.folds.closed_fold_at(i) {
+            let color = if drawn.cursor_line { COLOR_CURSOR } else { COLOR_FOLD };
+            let summary: String = fold_summary(fold, line).chars().take(row.width).collect();
+            frame.put_str(row.x, row.y, &summary, COLOR_PAIR(color as u32));
+            return;
+        }
+
+        self.highlight_syntax(frame, line, row, drawn.depth);
+
+        if let Some(ref checker) = self.spell_checker {
+            let ranges = checker.misspelled_ranges(line, &self.syntax);
+            if !ranges.is_empty() {
+                let attrs = COLOR_PAIR(COLOR_SPELL as u32) | A_UNDERLINE;
+                self.highlight_ranges(frame, line, row, &ranges, attrs);
             }
+        }
 
-            // Check for numbers
-            if !highlighted && ch.is_ascii_digit() {
-                window.attron(COLOR_PAIR(COLOR_NUMBER as u32));
-                window.mvaddch(line_y, x, ch);
-                window.attroff(COLOR_PAIR(COLOR_NUMBER as u32));
-                highlighted = true;
+        if let Some(ref pattern) = self.search_highlight {
+            let ranges = match_ranges(line, pattern, !self.search_ignore_case);
+            if !ranges.is_empty() {
+                let attrs = COLOR_PAIR(COLOR_SEARCH as u32);
+                self.highlight_ranges(frame, line, row, &ranges, attrs);
             }
+        }
 
-            // Check for strings
-            if !highlighted && ch == '"' {
-                window.attron(COLOR_PAIR(COLOR_STRING as u32));
-                window.mvaddch(line_y, x, ch);
-
-                // Continue until closing quote
-                while let Some((_, next_ch)) = chars.next() {
-                    x += 1;
-                    window.mvaddch(line_y, x, next_ch);
-                    if next_ch == '"' {
-                        break;
-                    }
+        if let Some(bracket) = self.bracket_match {
+            let (position, color) = match bracket {
+                BracketMatch::Partner(position) => (position, COLOR_MATCH),
+                BracketMatch::Unmatched(position) => (position, COLOR_UNMATCHED),
+            };
+            let x = line.get(..position.x).map_or(0, |before| before.chars().count());
+            if position.y == i && x < row.width {
+                if let Some(cell) = frame.get(row.x + x, row.y) {
+                    frame.put(row.x + x, row.y, cell.ch, COLOR_PAIR(color as u32));
                 }
-                window.attroff(COLOR_PAIR(COLOR_STRING as u32));
-                highlighted = true;
             }
+        }
 
-            if !highlighted {
-                window.mvaddch(line_y, x, ch);
-            }
+        if let Some((start, end)) = self.selection.filter(|(start, end)| (start.y..=end.y).contains(&i)) {
+            let from = if start.y == i { start.x } else { 0 };
+            // The line break of a line the selection runs on from
+            let to = if end.y == i { end.x } else { line.len() + 1 };
+            self.highlight_ranges(frame, line, row, &[(from, to)], pancurses::A_REVERSE);
+        }
 
-            x += 1;
+        // The guide only colors blank cells, so it never hides text
+        for x in guide_columns(&self.color_columns, 0, row.width) {
+            if frame.get(row.x + x, row.y) == Some(Cell::default()) {
+                frame.put(row.x + x, row.y, ' ', COLOR_PAIR(COLOR_GUIDE as u32));
+            }
         }
     }
+
+    /// The editor window as drawn since the last `clear`.
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
 }
 
 impl Default for TerminalDisplay {
@@ -156,7 +417,8 @@ impl DisplayManager for TerminalDisplay {
 
         noecho();
         raw();
-        curs_set(0); // Hide cursor
+        self.set_cursor_visible(true);
+        self.cursor_shapes_supported = std::env::var("TERM").is_ok_and(|term| !matches!(term.as_str(), "" | "dumb" | "linux"));
 
         if let Some(ref main_win) = self.main_window {
             main_win.keypad(true);
@@ -166,22 +428,36 @@ impl DisplayManager for TerminalDisplay {
     }
 
     fn cleanup(&mut self) -> Result<()> {
+        if self.cursor_shape.take().is_some() && self.cursor_shapes_supported {
+            // Back to the terminal's default shape
+            let mut stdout = std::io::stdout();
+            let _ = stdout.write_all(b"\x1b[0 q").and_then(|_| stdout.flush());
+        }
         endwin();
         Ok(())
     }
 
+    /// Starts a new frame. Unlike curses' `clear`, nothing on the terminal
+    /// is blanked: the next `refresh` paints only what changed.
     fn clear(&mut self) -> Result<()> {
-        if let Some(ref main_win) = self.main_window {
-            main_win.clear();
-        }
+        self.frame.clear();
         if let Some(ref status_win) = self.status_window {
-            status_win.clear();
+            status_win.erase();
         }
         Ok(())
     }
 
     fn refresh(&mut self) -> Result<()> {
         if let Some(ref main_win) = self.main_window {
+            for (x, y) in self.frame.changed_cells(&self.shown) {
+                if let Some(cell) = self.frame.get(x, y) {
+                    main_win.attrset(cell.attrs);
+                    main_win.mvaddch(y as i32, x as i32, cell.ch);
+                }
+            }
+            main_win.attrset(pancurses::A_NORMAL);
+            main_win.mv(self.cursor_position.y as i32, self.cursor_position.x as i32);
+            self.shown = self.frame.clone();
             main_win.refresh();
         }
         if let Some(ref status_win) = self.status_window {
@@ -190,32 +466,12 @@ impl DisplayManager for TerminalDisplay {
         Ok(())
     }
 
-    fn render_text(&mut self, text: &str, position: Position) -> Result<()> {
-        if let Some(ref main_win) = self.main_window {
-            let lines: Vec<&str> = text.lines().collect();
-            let (_, height) = self.screen_size;
-            let editor_height = height - 1; // Subtract status bar
-
-            for (i, line) in lines.iter().enumerate() {
-                let y = i as i32;
-                if y >= editor_height as i32 {
-                    break;
-                }
-
-                main_win.mv(y, 0);
-                main_win.clrtoeol();
-
-                // Check if cursor is on this line
-                let cursor_pos = if position.y == i {
-                    Some(position.x)
-                } else {
-                    None
-                };
-
-                self.highlight_syntax(main_win, line, y, cursor_pos);
-            }
-        }
-        Ok(())
+    /// Renders the whole editor area. The cursor's screen column is shifted
+    /// right by the sign column and line numbers when they are shown.
+    fn render_text(&mut self, text: &str, position: Position, top_line: usize) -> Result<()> {
+        let (width, height) = self.screen_size;
+        let editor_area = Rect::new(0, 0, width, height.saturating_sub(1)); // Subtract status bar
+        self.render_pane(editor_area, text, Some(position), top_line)
     }
 
     fn render_status(&mut self, status: &str) -> Result<()> {
@@ -228,22 +484,36 @@ impl DisplayManager for TerminalDisplay {
     }
 
     fn get_input(&mut self) -> Result<i32> {
-        if let Some(ref main_win) = self.main_window {
-            match main_win.getch() {
-                Some(Input::Character(ch)) => Ok(ch as i32),
-                Some(Input::KeyUp) => Ok(1001),
-                Some(Input::KeyDown) => Ok(1002),
-                Some(Input::KeyLeft) => Ok(1003),
-                Some(Input::KeyRight) => Ok(1004),
-                Some(Input::KeyBackspace) => Ok(127),
-                Some(Input::KeyDC) => Ok(1005), // Delete key
-                Some(Input::KeyHome) => Ok(1006),
-                Some(Input::KeyEnd) => Ok(1007),
-                Some(Input::KeyEnter) => Ok(10), // Enter
-                _ => Ok(0), // Unknown input
+        let Some(ref main_win) = self.main_window else {
+            return Err(EditorError::Display("No main window available".to_string()));
+        };
+        let input_timeout = self.input_timeout.map(|ms| Duration::from_millis(ms as u64));
+
+        loop {
+            if let Some(key) = self.ready_keys.pop_front() {
+                return Ok(key);
+            }
+
+            // While an Esc is pending, only wait as long as the escape timeout allows
+            let now = Instant::now();
+            let wait = if self.escape_decoder.is_pending() {
+                Some(self.escape_decoder.remaining(now))
+            } else {
+                input_timeout
+            };
+
+            match Self::read_key(main_win, wait) {
+                Some(code) => {
+                    let keys = self.escape_decoder.feed(code, Instant::now());
+                    self.ready_keys.extend(keys);
+                }
+                None if self.escape_decoder.is_pending() => {
+                    let keys = self.escape_decoder.flush_if_expired(Instant::now());
+                    self.ready_keys.extend(keys);
+                }
+                None if input_timeout.is_some() => return Ok(KEY_TIMEOUT),
+                None => return Ok(0),
             }
-        } else {
-            Err(EditorError::Display("No main window available".to_string()))
         }
     }
 
@@ -252,11 +522,323 @@ impl DisplayManager for TerminalDisplay {
     }
 
     fn move_cursor(&mut self, position: Position) -> Result<()> {
+        self.cursor_position = position;
         if let Some(ref main_win) = self.main_window {
             main_win.mv(position.y as i32, position.x as i32);
         }
         Ok(())
     }
+
+    fn set_input_timeout(&mut self, ms: Option<u32>) {
+        self.input_timeout = ms;
+    }
+
+    /// On terminals without cursor shapes, such as the Linux console, only
+    /// the state is kept.
+    fn set_cursor_shape(&mut self, shape: CursorShape) {
+        if self.cursor_shape == Some(shape) {
+            return;
+        }
+        if self.cursor_shapes_supported && self.main_window.is_some() {
+            let mut stdout = std::io::stdout();
+            let _ = stdout.write_all(shape.escape().as_bytes()).and_then(|_| stdout.flush());
+        }
+        self.cursor_shape = Some(shape);
+    }
+
+    fn set_folds(&mut self, folds: FoldSet) {
+        if self.folds != folds {
+            self.folds = folds;
+            self.drawn_rows.clear();
+        }
+    }
+
+    fn set_search_highlight(&mut self, pattern: Option<String>) {
+        if self.search_highlight != pattern {
+            self.search_highlight = pattern;
+            self.drawn_rows.clear();
+        }
+    }
+
+    fn set_search_ignore_case(&mut self, ignore_case: bool) {
+        if self.search_ignore_case != ignore_case {
+            self.search_ignore_case = ignore_case;
+            self.drawn_rows.clear();
+        }
+    }
+
+    fn set_spell_checker(&mut self, checker: Option<Rc<SpellChecker>>) {
+        let same = match (&self.spell_checker, &checker) {
+            (Some(current), Some(new)) => Rc::ptr_eq(current, new),
+            (current, new) => current.is_none() && new.is_none(),
+        };
+        if !same {
+            self.spell_checker = checker;
+            self.drawn_rows.clear();
+        }
+    }
+
+    fn set_signs(&mut self, signs: Signs) {
+        if self.signs != signs {
+            self.signs = signs;
+            self.drawn_rows.clear();
+        }
+    }
+
+    fn set_color_columns(&mut self, columns: Vec<usize>) {
+        if self.color_columns != columns {
+            self.color_columns = columns;
+            self.drawn_rows.clear();
+        }
+    }
+
+    fn set_bracket_match(&mut self, bracket: Option<BracketMatch>) {
+        if self.bracket_match != bracket {
+            self.bracket_match = bracket;
+            self.drawn_rows.clear();
+        }
+    }
+
+    fn set_selection(&mut self, selection: Option<(Position, Position)>) {
+        if self.selection != selection {
+            self.selection = selection;
+            self.drawn_rows.clear();
+        }
+    }
+
+    fn set_rainbow(&mut self, rainbow: bool) {
+        if self.rainbow != rainbow {
+            self.rainbow = rainbow;
+            self.drawn_rows.clear();
+        }
+    }
+
+    fn set_line_numbers(&mut self, number: bool, relative: bool) {
+        if (self.show_line_numbers, self.relative_numbers) != (number, relative) {
+            self.show_line_numbers = number;
+            self.relative_numbers = relative;
+            self.drawn_rows.clear();
+        }
+    }
+
+    /// The profile is only rebuilt when the filetype changes.
+    fn set_filetype(&mut self, filetype: Option<&str>) {
+        if self.syntax_filetype.as_deref() != filetype {
+            self.syntax = SyntaxProfile::for_filetype(filetype);
+            self.syntax_filetype = filetype.map(String::from);
+            self.apply_keyword_changes();
+            self.drawn_rows.clear();
+        }
+    }
+
+    fn add_keyword(&mut self, word: String) {
+        self.removed_keywords.retain(|removed| *removed != word);
+        if !self.added_keywords.contains(&word) {
+            self.added_keywords.push(word);
+        }
+        self.apply_keyword_changes();
+        self.drawn_rows.clear();
+    }
+
+    fn remove_keyword(&mut self, word: &str) {
+        self.added_keywords.retain(|added| added != word);
+        if !self.removed_keywords.iter().any(|removed| removed == word) {
+            self.removed_keywords.push(word.to_string());
+        }
+        self.apply_keyword_changes();
+        self.drawn_rows.clear();
+    }
+
+    fn set_attention_words(&mut self, words: Vec<String>) {
+        if self.attention_words.as_ref() != Some(&words) {
+            self.attention_words = Some(words);
+            self.apply_keyword_changes();
+            self.drawn_rows.clear();
+        }
+    }
+
+    fn syntax_profile(&self) -> Option<&SyntaxProfile> {
+        Some(&self.syntax)
+    }
+
+    fn set_dirty_lines(&mut self, lines: Option<Vec<usize>>) {
+        if lines.is_none() {
+            self.drawn_rows.clear();
+        }
+        self.dirty_lines = lines.map(|lines| lines.into_iter().collect());
+    }
+
+    fn force_redraw(&mut self) {
+        self.set_dirty_lines(None);
+        self.shown = Frame::default();
+        if let Some(ref main_win) = self.main_window {
+            main_win.clearok(true);
+        }
+        if let Some(ref status_win) = self.status_window {
+            status_win.touch();
+        }
+    }
+
+    /// Lines are clipped to the pane width; the cursor line is only marked
+    /// when `cursor` is given. While any sign is placed, the leftmost
+    /// columns are reserved for the sign column, and line numbers follow
+    /// it when shown. Rows showing a line that
+    /// is not in the dirty set are copied from the last render instead of
+    /// being highlighted again.
+    fn render_pane(&mut self, rect: Rect, text: &str, cursor: Option<Position>, top_line: usize) -> Result<()> {
+        let mut frame = std::mem::take(&mut self.frame);
+        let blank = " ".repeat(rect.width);
+        for row in 0..rect.height {
+            frame.put_str(rect.x, rect.y + row, &blank, 0);
+        }
+
+        let numbered = self.show_line_numbers || self.relative_numbers;
+        let number_width = numbered.then(|| number_column_width(top_line, rect.height, text.lines().count(), &self.folds));
+        let cursor_line = cursor.map(|position| position.y);
+
+        let mut y = 0;
+        let mut depth = 0;
+        for (i, line) in text.lines().enumerate() {
+            // Lines above the pane still open and close brackets
+            let line_depth = self.rainbow.then_some(depth);
+            if self.rainbow {
+                depth = bracket_depths(line, &self.syntax, depth).1;
+            }
+            if i < top_line {
+                continue;
+            }
+            if y >= rect.height {
+                break;
+            }
+            if self.folds.is_hidden(i) {
+                continue;
+            }
+            let row = Rect::new(rect.x, rect.y + y, rect.width, 1);
+            let on_cursor_line = cursor_line == Some(i);
+            let number = number_width.map(|width| {
+                let number = line_number(i, cursor_line, self.show_line_numbers, self.relative_numbers);
+                format!("{:>1$} ", number, width - 1)
+            });
+            let drawn = DrawnRow {
+                line: i,
+                width: rect.width,
+                cursor_line: on_cursor_line,
+                depth: line_depth,
+                number,
+                cells: Vec::new(),
+            };
+
+            let unchanged = self.dirty_lines.as_ref().is_some_and(|dirty| !dirty.contains(&i));
+            match self.drawn_rows.get(&(row.x, row.y)) {
+                Some(previous) if unchanged && previous.same_place(&drawn) => {
+                    for (x, cell) in previous.cells.iter().enumerate() {
+                        frame.put(row.x + x, row.y, cell.ch, cell.attrs);
+                    }
+                }
+                _ => {
+                    self.draw_line(&mut frame, line, row, &drawn);
+                    let cells = (0..row.width).filter_map(|x| frame.get(row.x + x, row.y)).collect();
+                    self.drawn_rows.insert((row.x, row.y), DrawnRow { cells, ..drawn });
+                }
+            }
+            y += 1;
+        }
+        self.frame = frame;
+        Ok(())
+    }
+
+    fn render_divider(&mut self, rect: Rect) -> Result<()> {
+        let glyph = if rect.height == 1 { '-' } else { '|' };
+        for row in 0..rect.height {
+            for col in 0..rect.width {
+                self.frame.put(rect.x + col, rect.y + row, glyph, pancurses::A_REVERSE);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where buffer `position` appears on screen when `rect` shows the buffer
+/// from line `top_line`: lines hidden in closed folds take no row, and the
+/// column starts after a gutter `gutter_width` wide. `None` when the line
+/// is scrolled out of `rect`.
+pub fn buffer_to_screen(position: Position, rect: Rect, top_line: usize, gutter_width: usize, folds: &FoldSet) -> Option<Position> {
+    if position.y < top_line {
+        return None;
+    }
+    let row = (top_line..position.y).filter(|&line| !folds.is_hidden(line)).count();
+    if row >= rect.height {
+        return None;
+    }
+    let column = (gutter_width + position.x).min(rect.width.saturating_sub(1));
+    Some(Position::new(rect.x + column, rect.y + row))
+}
+
+/// How wide the line number gutter is for a pane `rows` high showing the
+/// buffer from line `top_line`: the digits of the last line number in view
+/// and a space after them.
+pub fn number_column_width(top_line: usize, rows: usize, line_count: usize, folds: &FoldSet) -> usize {
+    let last = (top_line..line_count).filter(|&line| !folds.is_hidden(line)).take(rows.max(1)).last();
+    last.map_or(1, |line| line + 1).to_string().len() + 1
+}
+
+/// The number shown beside line `line`. With `relative` on, other lines show
+/// how far they are from the cursor's, and the cursor's own line shows its
+/// number when `number` is also on or 0 when it is not, as in vim.
+pub fn line_number(line: usize, cursor_line: Option<usize>, number: bool, relative: bool) -> usize {
+    match cursor_line {
+        Some(cursor_line) if relative && line != cursor_line => line.abs_diff(cursor_line),
+        Some(_) if relative && !number => 0,
+        _ => line + 1,
+    }
+}
+
+/// The screen column, from 0, where the character at byte `column` of
+/// `line` starts: tabs reach the next multiple of `tab_size` and wide
+/// characters such as CJK take two cells. Columns past the end of the
+/// line count from its last cell.
+pub fn virtual_column(line: &str, column: usize, tab_size: usize) -> usize {
+    let tab_size = tab_size.max(1);
+    line.char_indices()
+        .take_while(|&(x, _)| x < column)
+        .fold(0, |cells, (_, ch)| match ch {
+            '\t' => (cells / tab_size + 1) * tab_size,
+            _ => cells + char_width(ch),
+        })
+}
+
+/// How many cells `ch` takes on a terminal: two for East Asian wide and
+/// fullwidth characters and emoji, none for combining marks and
+/// zero-width spaces, one otherwise.
+pub fn char_width(ch: char) -> usize {
+    match ch as u32 {
+        0x0300..=0x036F | 0x200B..=0x200F | 0xFE00..=0xFE0F => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// The screen columns of a row `width` cells wide that show one of the
+/// buffer `columns` when the row starts at buffer column `left_column`.
+pub fn guide_columns(columns: &[usize], left_column: usize, width: usize) -> Vec<usize> {
+    columns
+        .iter()
+        .filter(|&&column| column >= left_column && column - left_column < width)
+        .map(|&column| column - left_column)
+        .collect()
 }
 
 pub struct StatusLine {
@@ -264,6 +846,13 @@ pub struct StatusLine {
     position: Position,
     mode: EditorMode,
     is_modified: bool,
+    readonly: bool,
+    undo_counts: (usize, usize),
+    /// Keys typed toward an unfinished command, like vim's `showcmd`.
+    pending_keys: String,
+    /// The text of the cursor's line and the tab size, from which the
+    /// column shown is worked out.
+    cursor_line: Option<(String, usize)>,
 }
 
 impl StatusLine {
@@ -273,7 +862,39 @@ impl StatusLine {
             position: Position::origin(),
             mode: EditorMode::Edit,
             is_modified: false,
+            readonly: false,
+            undo_counts: (0, 0),
+            pending_keys: String::new(),
+            cursor_line: None,
+        }
+    }
+
+    /// Shows `keys` at the right of the status line until replaced; control
+    /// keys show as `^W` and the like.
+    pub fn set_pending_keys(&mut self, keys: &str) {
+        self.pending_keys = keys
+            .chars()
+            .map(|ch| match ch {
+                '\x01'..='\x1a' => format!("^{}", (b'A' + ch as u8 - 1) as char),
+                ch => ch.to_string(),
+            })
+            .collect();
+    }
+
+    pub fn pending_keys(&self) -> &str {
+        &self.pending_keys
+    }
+
+    /// `left` followed by the pending keys at the right edge of a status
+    /// line `width` columns wide. The keys win when both do not fit.
+    pub fn with_pending_keys(&self, left: &str, width: usize) -> String {
+        if self.pending_keys.is_empty() {
+            return left.to_string();
         }
+        let keys_width = self.pending_keys.chars().count();
+        let room = width.saturating_sub(keys_width + 1);
+        let left: String = left.chars().take(room).collect();
+        format!("{:<room$} {}", left, self.pending_keys)
     }
 
     pub fn update(&mut self, filename: &str, position: Position, mode: EditorMode, is_modified: bool) {
@@ -283,21 +904,63 @@ impl StatusLine {
         self.is_modified = is_modified;
     }
 
+    /// Marks the file `[RO]` while edits are refused.
+    pub fn set_readonly(&mut self, readonly: bool) {
+        self.readonly = readonly;
+    }
+
+    /// The text of the line the cursor is on, so the column shown counts
+    /// characters rather than bytes, followed by the screen column where
+    /// tabs or wide characters make it differ, like vim's `5-17` ruler.
+    pub fn set_cursor_line(&mut self, line: &str, tab_size: usize) {
+        self.cursor_line = Some((line.to_string(), tab_size));
+    }
+
+    /// The cursor column as shown: `col` or `col-virtcol`, both from 1.
+    fn column(&self) -> String {
+        let Some((line, tab_size)) = &self.cursor_line else {
+            return (self.position.x + 1).to_string();
+        };
+        let before = line.get(..self.position.x).unwrap_or(line);
+        let column = before.chars().count() + 1;
+        let screen_column = virtual_column(line, self.position.x, *tab_size) + 1;
+        if screen_column == column {
+            column.to_string()
+        } else {
+            format!("{}-{}", column, screen_column)
+        }
+    }
+
+    /// How many changes can be undone and redone, shown once there are any.
+    pub fn set_undo_counts(&mut self, undo: usize, redo: usize) {
+        self.undo_counts = (undo, redo);
+    }
+
     pub fn format(&self) -> String {
         let mode_str = match self.mode {
             EditorMode::Edit => "EDIT",
             EditorMode::Command => "COMMAND",
+            EditorMode::Search => "SEARCH",
+            EditorMode::Visual => "VISUAL",
         };
 
         let modified_indicator = if self.is_modified { "*" } else { "" };
+        let readonly_indicator = if self.readonly { " [RO]" } else { "" };
+
+        let undo = match self.undo_counts {
+            (0, 0) => String::new(),
+            (undo, redo) => format!(" | Undo: {} Redo: {}", undo, redo),
+        };
 
         format!(
-            "File: {}{} | Position: {}:{} | Mode: {}",
+            "File: {}{}{} | Position: {}:{} | Mode: {}{}",
             self.filename,
             modified_indicator,
+            readonly_indicator,
             self.position.y + 1,
-            self.position.x + 1,
-            mode_str
+            self.column(),
+            mode_str,
+            undo
         )
     }
 }
@@ -306,4 +969,295 @@ impl Default for StatusLine {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EditorConfig;
+    use crate::core::InputEvent;
+    use std::collections::VecDeque;
+
+    struct MockDisplay {
+        inputs: VecDeque<i32>,
+    }
+
+    impl DisplayManager for MockDisplay {
+        fn init(&mut self) -> Result<()> { Ok(()) }
+        fn cleanup(&mut self) -> Result<()> { Ok(()) }
+        fn clear(&mut self) -> Result<()> { Ok(()) }
+        fn refresh(&mut self) -> Result<()> { Ok(()) }
+        fn render_text(&mut self, _text: &str, _position: Position, _top_line: usize) -> Result<()> { Ok(()) }
+        fn render_status(&mut self, _status: &str) -> Result<()> { Ok(()) }
+        fn get_input(&mut self) -> Result<i32> {
+            self.inputs.pop_front().ok_or_else(|| EditorError::Display("No input".to_string()))
+        }
+        fn get_size(&self) -> (usize, usize) { (80, 24) }
+        fn move_cursor(&mut self, _position: Position) -> Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn test_timeout_sentinel_is_surfaced() {
+        let mut display = MockDisplay {
+            inputs: VecDeque::from(vec!['a' as i32, KEY_TIMEOUT, 0, 1001]),
+        };
+
+        assert_eq!(display.poll_input().unwrap(), InputEvent::Key('a' as i32));
+        assert_eq!(display.poll_input().unwrap(), InputEvent::Timeout);
+        // Unknown keys keep their existing code and are not mistaken for a timeout
+        assert_eq!(display.poll_input().unwrap(), InputEvent::Key(0));
+        assert_eq!(display.poll_input().unwrap(), InputEvent::Key(1001));
+    }
+
+    #[test]
+    fn test_blocking_is_the_default() {
+        let mut display = TerminalDisplay::new();
+        assert_eq!(display.input_timeout, None);
+
+        display.set_input_timeout(Some(250));
+        assert_eq!(display.input_timeout, Some(250));
+        display.set_input_timeout(None);
+        assert_eq!(display.input_timeout, None);
+    }
+
+    #[test]
+    fn test_buffer_to_screen() {
+        let rect = Rect::new(10, 1, 40, 20);
+        let folds = FoldSet::new();
+
+        assert_eq!(buffer_to_screen(Position::new(3, 30), rect, 25, 2, &folds), Some(Position::new(15, 6)));
+        // Above or below the visible lines
+        assert_eq!(buffer_to_screen(Position::new(0, 24), rect, 25, 0, &folds), None);
+        assert_eq!(buffer_to_screen(Position::new(0, 45), rect, 25, 0, &folds), None);
+        // Long lines are clipped to the last column
+        assert_eq!(buffer_to_screen(Position::new(100, 25), rect, 25, 0, &folds), Some(Position::new(49, 1)));
+
+        // Lines 3 to 5 are folded into line 2
+        let mut folds = FoldSet::new();
+        folds.create(2, 5);
+        assert_eq!(buffer_to_screen(Position::new(0, 7), rect, 0, 0, &folds), Some(Position::new(10, 5)));
+    }
+
+    #[test]
+    fn test_guide_columns() {
+        let config = EditorConfig {
+            textwidth: 72,
+            colorcolumn: "+1,100,10".to_string(),
+            ..EditorConfig::new()
+        };
+        let columns = config.color_columns();
+        assert_eq!(guide_columns(&columns, 0, 80), vec![9, 72]);
+        // Scrolled right by 20: column 10 is off screen and 100 comes in
+        assert_eq!(guide_columns(&columns, 20, 80), vec![52, 79]);
+        assert!(guide_columns(&columns, 101, 80).is_empty());
+    }
+
+    #[test]
+    fn test_cursor_shape_follows_mode() {
+        let mut display = TerminalDisplay::new();
+        assert!(display.cursor_visible());
+        assert_eq!(display.cursor_shape(), None);
+
+        for (mode, shape) in [
+            (EditorMode::Command, CursorShape::Block),
+            (EditorMode::Edit, CursorShape::Bar),
+            (EditorMode::Search, CursorShape::Bar),
+            (EditorMode::Visual, CursorShape::Block),
+        ] {
+            display.set_cursor_shape(CursorShape::for_mode(mode));
+            assert_eq!(display.cursor_shape(), Some(shape));
+        }
+    }
+
+    #[test]
+    fn test_panes_draw_into_the_frame() {
+        let mut display = TerminalDisplay::new();
+        display.frame = Frame::new(12, 3);
+
+        display.render_pane(Rect::new(0, 0, 5, 3), "let x\ny", None, 0).unwrap();
+        display.render_divider(Rect::new(5, 0, 1, 3)).unwrap();
+        assert_eq!(display.frame().get(0, 0).map(|cell| cell.ch), Some('l'));
+        assert_eq!(display.frame().get(0, 1).map(|cell| cell.ch), Some('y'));
+        assert_eq!(display.frame().get(5, 2).map(|cell| cell.ch), Some('|'));
+
+        display.clear().unwrap();
+        assert_eq!(display.frame().get(0, 0).map(|cell| cell.ch), Some(' '));
+    }
+
+    #[test]
+    fn test_clean_rows_are_reused() {
+        let mut display = TerminalDisplay::new();
+        display.frame = Frame::new(10, 3);
+        let rect = Rect::new(0, 0, 10, 3);
+        let row_text = |display: &TerminalDisplay, y: usize| -> String {
+            (0..4).filter_map(|x| display.frame().get(x, y)).map(|cell| cell.ch).collect()
+        };
+
+        display.render_pane(rect, "aaaa\nbbbb\ncccc", None, 0).unwrap();
+
+        // Only line 1 is dirty, so the stale rows 0 and 2 are copied as drawn
+        display.set_dirty_lines(Some(vec![1]));
+        display.clear().unwrap();
+        display.render_pane(rect, "xxxx\nyyyy\nzzzz", None, 0).unwrap();
+        assert_eq!(row_text(&display, 0), "aaaa");
+        assert_eq!(row_text(&display, 1), "yyyy");
+        assert_eq!(row_text(&display, 2), "cccc");
+
+        display.set_dirty_lines(None);
+        display.render_pane(rect, "xxxx\nyyyy\nzzzz", None, 0).unwrap();
+        assert_eq!(row_text(&display, 0), "xxxx");
+    }
+
+    #[test]
+    fn test_line_number_gutter() {
+        let mut display = TerminalDisplay::new();
+        display.frame = Frame::new(10, 3);
+        let rect = Rect::new(0, 0, 10, 3);
+        let text: Vec<String> = (1..=12).map(|line| format!("line{}", line)).collect();
+        let text = text.join("\n");
+        let row_text = |display: &TerminalDisplay, y: usize| -> String {
+            (0..6).filter_map(|x| display.frame().get(x, y)).map(|cell| cell.ch).collect()
+        };
+
+        // Lines 9 to 11 are in view, so the gutter fits two digits
+        display.set_line_numbers(true, false);
+        display.render_pane(rect, &text, Some(Position::new(0, 9)), 8).unwrap();
+        assert_eq!(row_text(&display, 0), " 9 lin");
+        assert_eq!(row_text(&display, 1), "10 lin");
+
+        // Relative numbers follow the cursor even on rows that are not dirty
+        display.set_line_numbers(true, true);
+        display.set_dirty_lines(Some(Vec::new()));
+        display.render_pane(rect, &text, Some(Position::new(0, 9)), 8).unwrap();
+        display.render_pane(rect, &text, Some(Position::new(0, 10)), 8).unwrap();
+        assert_eq!(row_text(&display, 0), " 2 lin");
+        assert_eq!(row_text(&display, 2), "11 lin");
+        assert_eq!(line_number(10, Some(10), false, true), 0);
+        assert_eq!(number_column_width(0, 3, 0, &FoldSet::new()), 2);
+    }
+
+    #[test]
+    fn test_forced_redraw_repaints_every_cell() {
+        let mut display = TerminalDisplay::new();
+        display.frame = Frame::new(4, 2);
+        let rect = Rect::new(0, 0, 4, 2);
+        display.render_pane(rect, "ab\ncd", None, 0).unwrap();
+        display.shown = display.frame.clone();
+
+        // An unchanged frame after an incremental render paints nothing
+        display.set_dirty_lines(Some(Vec::new()));
+        display.clear().unwrap();
+        display.render_pane(rect, "ab\ncd", None, 0).unwrap();
+        assert!(display.frame.changed_cells(&display.shown).is_empty());
+
+        display.force_redraw();
+        assert!(display.drawn_rows.is_empty());
+        display.clear().unwrap();
+        display.render_pane(rect, "ab\ncd", None, 0).unwrap();
+        assert_eq!(display.frame.changed_cells(&display.shown).len(), 8);
+    }
+
+    #[test]
+    fn test_added_keywords_highlight() {
+        let mut display = TerminalDisplay::new();
+        display.frame = Frame::new(10, 1);
+        let rect = Rect::new(0, 0, 10, 1);
+        let keyword = COLOR_PAIR(COLOR_KEYWORD as u32);
+        let attrs_at = |display: &TerminalDisplay, x: usize| display.frame().get(x, 0).map(|cell| cell.attrs);
+
+        display.render_pane(rect, "todo let", None, 0).unwrap();
+        assert_eq!(attrs_at(&display, 0), Some(0));
+        assert_eq!(attrs_at(&display, 5), Some(keyword));
+
+        display.add_keyword("todo".to_string());
+        display.add_keyword("todo".to_string());
+        display.render_pane(rect, "todo let", None, 0).unwrap();
+        assert_eq!(attrs_at(&display, 0), Some(keyword));
+        assert_eq!(display.syntax_profile().unwrap().keywords.iter().filter(|word| *word == "todo").count(), 1);
+
+        // Added keywords survive a filetype change
+        display.set_filetype(Some("c"));
+        assert!(display.syntax_profile().unwrap().keywords.iter().any(|word| word == "todo"));
+    }
+
+    #[test]
+    fn test_removed_keywords_stop_highlighting() {
+        let mut display = TerminalDisplay::new();
+        display.frame = Frame::new(10, 1);
+        let rect = Rect::new(0, 0, 10, 1);
+
+        display.add_keyword("todo".to_string());
+        display.remove_keyword("todo");
+        display.remove_keyword("let");
+        display.render_pane(rect, "todo let", None, 0).unwrap();
+        assert_eq!(display.frame().get(0, 0).map(|cell| cell.attrs), Some(0));
+        assert_eq!(display.frame().get(5, 0).map(|cell| cell.attrs), Some(0));
+
+        display.set_filetype(Some("rust"));
+        assert!(!display.syntax_profile().unwrap().keywords.iter().any(|word| word == "let"));
+        display.add_keyword("let".to_string());
+        assert!(display.syntax_profile().unwrap().keywords.iter().any(|word| word == "let"));
+    }
+
+    #[test]
+    fn test_pending_keys_on_the_status_line() {
+        let mut status = StatusLine::new();
+        assert_eq!(status.with_pending_keys("main.rs", 12), "main.rs");
+
+        // After `d`, waiting for the motion
+        status.set_pending_keys("d");
+        assert_eq!(status.with_pending_keys("main.rs", 12), "main.rs    d");
+        status.set_pending_keys("2\x17");
+        assert_eq!(status.pending_keys(), "2^W");
+        assert_eq!(status.with_pending_keys("a long status", 10), "a long 2^W");
+
+        // Cleared once the command completes
+        status.set_pending_keys("");
+        assert_eq!(status.with_pending_keys("main.rs", 12), "main.rs");
+    }
+
+    #[test]
+    fn test_readonly_on_the_status_line() {
+        let mut status = StatusLine::new();
+        status.update("notes.txt", Position::new(0, 2), EditorMode::Command, true);
+        assert_eq!(status.format(), "File: notes.txt* | Position: 3:1 | Mode: COMMAND");
+        status.set_readonly(true);
+        assert_eq!(status.format(), "File: notes.txt* [RO] | Position: 3:1 | Mode: COMMAND");
+    }
+
+    #[test]
+    fn test_undo_counts_on_the_status_line() {
+        let mut status = StatusLine::new();
+        status.update("notes.txt", Position::new(0, 0), EditorMode::Edit, false);
+        status.set_undo_counts(0, 0);
+        assert_eq!(status.format(), "File: notes.txt | Position: 1:1 | Mode: EDIT");
+        status.set_undo_counts(2, 1);
+        assert_eq!(status.format(), "File: notes.txt | Position: 1:1 | Mode: EDIT | Undo: 2 Redo: 1");
+    }
+
+    #[test]
+    fn test_virtual_column_with_tabs_and_wide_characters() {
+        assert_eq!(virtual_column("abc", 2, 8), 2);
+        // A tab reaches the next tab stop, however far away it is
+        assert_eq!(virtual_column("\tx", 1, 8), 8);
+        assert_eq!(virtual_column("ab\tx", 3, 4), 4);
+        assert_eq!(virtual_column("abcd\tx", 5, 4), 8);
+        // 日本 takes four cells in six bytes
+        assert_eq!(virtual_column("日本x", 6, 8), 4);
+        assert_eq!(virtual_column("\t日\tx", 5, 8), 16);
+        assert_eq!(virtual_column("e\u{301}x", 3, 8), 1);
+        assert_eq!(virtual_column("ab", 10, 8), 2);
+    }
+
+    #[test]
+    fn test_status_line_shows_character_and_screen_column() {
+        let mut status = StatusLine::new();
+        status.update("t.rs", Position::new(7, 1), EditorMode::Edit, false);
+        status.set_cursor_line("\t日本x", 4);
+        // Byte 7 is the x: the fourth character, in the ninth cell
+        assert_eq!(status.format(), "File: t.rs | Position: 2:4-9 | Mode: EDIT");
+
+        status.set_cursor_line("abcdefgh", 4);
+        assert_eq!(status.format(), "File: t.rs | Position: 2:8 | Mode: EDIT");
+    }
+}
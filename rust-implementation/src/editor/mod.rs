@@ -0,0 +1,3401 @@
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::autosave::AutoSaveTimer;
+use crate::browser::{DirBuffer, DirTarget};
+use crate::buffer::{Buffer, DiskChange, MultiBuffer, QuitChoice};
+use crate::config::{EditorConfig, IndentStyle};
+use crate::core::{
+    BufferInfo, BufferManager, DisplayManager, EditorError, EditorMode, EditorOperations, Encoding, FileManager, InputEvent, Position,
+    Result, TextBuffer,
+};
+use crate::datetime::DateTime;
+use crate::diff::DiffView;
+use crate::display::{buffer_to_screen, number_column_width, CursorShape, StatusLine};
+use crate::editor_ops::{delimiter_pair, BracketMatch, EditorOps, Motion, Operator};
+use crate::expression::{Expression, ExpressionContext};
+use crate::file_io::{split_file_position, LargeFileView, LazyFile, SafeFileManager};
+use crate::fold::FoldSet;
+use crate::git::{self, LineChange};
+use crate::hex::HexView;
+use crate::jumplist::{Jump, JumpList};
+use crate::layout::{Layout, Rect, SplitDirection};
+use crate::quickfix::QuickfixList;
+use crate::search::{IncrementalSearch, Substitution};
+use crate::shell;
+use crate::signs::{SignKind, Signs};
+use crate::snippet::{SnippetLibrary, SnippetSession};
+use crate::spell::{SpellChecker, DEFAULT_WORD_LIST};
+use crate::syntax::SyntaxProfile;
+use crate::undo::{EditorAction, HistoryStep, TimestampedHistory, UndoFile};
+
+/// The name of the buffer `:help` opens.
+const HELP_BUFFER: &str = "*help*";
+
+/// How long undo snapshots are kept for `:earlier`.
+const UNDO_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The whole editor: its buffers, modes, undo history and commands, drawn
+/// on any `DisplayManager`. The terminal binary runs it with `run`; it can
+/// equally be driven a key at a time with `feed_key`, as tests do.
+pub struct Editor<D: DisplayManager> {
+    /// Owns the buffers; everything else borrows them through it.
+    editor_ops: EditorOps<MultiBuffer<SafeFileManager>>,
+    display: D,
+    status_line: StatusLine,
+    /// The changes between successive states of the text, oldest first.
+    undo_system: TimestampedHistory<EditorAction>,
+    selection_start: Option<usize>,
+    mode: EditorMode,
+    command_buffer: String,
+    pending_keys: String,
+    /// A count typed before a command, as in `3x`.
+    count: Option<usize>,
+    search: IncrementalSearch,
+    search_pattern: Option<String>,
+    search_highlight_suppressed: bool,
+    /// A closing bracket was the last key typed, so `showmatch` points at
+    /// its partner until the next key.
+    typed_bracket: bool,
+    folds: FoldSet,
+    spell_checker: Option<Rc<SpellChecker>>,
+    autosave_timer: AutoSaveTimer,
+    hex_view: Option<HexView>,
+    large_file: Option<LargeFileView>,
+    dir_buffer: Option<DirBuffer>,
+    quickfix: QuickfixList,
+    quickfix_open: bool,
+    diff_view: Option<DiffView>,
+    jumps: JumpList,
+    layout: Layout,
+    signs: Signs,
+    config: EditorConfig,
+    /// Shown on the status line once, after the next render.
+    pending_message: Option<String>,
+    /// Whether a command has failed, which a batch run exits non-zero for.
+    error_reported: bool,
+    /// The buffer index and revision the last render showed, when it showed
+    /// a buffer in the single window.
+    rendered: Option<(usize, u64)>,
+    readonly: bool,
+    snippets: SnippetLibrary,
+    /// The tab stops of the snippet being filled in, if any.
+    snippet_session: Option<SnippetSession>,
+    /// Replacements and buffers changed so far by a `:bufdo` substitution.
+    substitute_tally: Option<(usize, usize)>,
+}
+
+impl<D: DisplayManager> Editor<D> {
+    /// Opens `files`, each as `file`, `file:line` or `file:line:column`,
+    /// on `display`, which is initialized here.
+    pub fn new(mut display: D, files: Vec<String>, readonly: bool, config: EditorConfig) -> Result<Self> {
+        let mut file_manager = SafeFileManager::new(config.backup, config.maxfilesize)?;
+        file_manager.set_backup_dir(config.backupdir.as_ref().map(PathBuf::from));
+        // `file:42` and `file:42:5` open the file with the cursor there
+        let positions: Vec<(String, Option<(usize, usize)>)> = files
+            .iter()
+            .map(|file| {
+                let (name, position) = split_file_position(file, |name| Path::new(name).exists());
+                (name.to_string(), position)
+            })
+            .collect();
+        let files: Vec<String> = positions.iter().map(|(name, _)| name.clone()).collect();
+        let (dirs, files): (Vec<String>, Vec<String>) =
+            files.into_iter().partition(|file| Path::new(file).is_dir());
+        // Files over the size limit are paged lazily instead of loaded
+        let (large, files): (Vec<String>, Vec<String>) =
+            files.into_iter().partition(|file| file_manager.exceeds_size_limit(file));
+        let large_file = match large.first() {
+            Some(file) => Some(LargeFileView::new(LazyFile::open(file_manager.file_system().resolve_path(file))?)),
+            None => None,
+        };
+        let dir_buffer = match dirs.first() {
+            Some(dir) => Some(DirBuffer::open(file_manager.file_system(), dir)?),
+            None => None,
+        };
+        let mut multi_buffer = if files.is_empty() {
+            MultiBuffer::new(file_manager)
+        } else {
+            MultiBuffer::from_files(file_manager, files)?
+        };
+        multi_buffer.set_lock_files(config.lockfile && !readonly);
+        let pending_message = (0..multi_buffer.get_buffer_count())
+            .filter_map(|idx| multi_buffer.get_buffer_info(idx))
+            .find_map(Self::lock_warning);
+
+        let status_line = StatusLine::new();
+        let undo_system = TimestampedHistory::new(UNDO_MAX_AGE);
+
+        // Initialize display
+        display.init()?;
+        let screen_size = display.get_size();
+        let layout = Layout::new(multi_buffer.get_current_buffer_index());
+        let editor_ops = EditorOps::new(multi_buffer, screen_size);
+
+        let mut editor = Self {
+            editor_ops,
+            display,
+            status_line,
+            undo_system,
+            selection_start: None,
+            mode: EditorMode::Edit,
+            command_buffer: String::new(),
+            pending_keys: String::new(),
+            count: None,
+            search: IncrementalSearch::default(),
+            search_pattern: None,
+            search_highlight_suppressed: false,
+            typed_bracket: false,
+            folds: FoldSet::new(),
+            spell_checker: None,
+            autosave_timer: AutoSaveTimer::default(),
+            hex_view: None,
+            large_file,
+            dir_buffer,
+            quickfix: QuickfixList::default(),
+            quickfix_open: false,
+            diff_view: None,
+            jumps: JumpList::new(),
+            layout,
+            signs: Signs::new(),
+            config,
+            pending_message,
+            error_reported: false,
+            rendered: None,
+            readonly,
+            snippets: SnippetLibrary::with_defaults(),
+            snippet_session: None,
+            substitute_tally: None,
+        };
+        editor.apply_buffer_options();
+        let current = editor.editor_ops.buffer().get_current_buffer_info().map(|info| info.filename.clone());
+        if let Some(&(_, Some((line, column)))) = positions.iter().find(|(name, _)| Some(name) == current.as_ref()) {
+            editor.editor_ops.goto(line, column);
+        }
+
+        // The initial state, which undoing stops at
+        editor.undo_system.save_action(EditorAction::Compound(Vec::new()));
+        if let Some(message) = editor.read_undo_file() {
+            editor.pending_message = Some(message);
+        }
+        Ok(editor)
+    }
+
+    /// Reads and handles keys from the display until the editor quits,
+    /// then releases the display.
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            self.render()?;
+            if let Some(message) = self.pending_message.take() {
+                self.display.render_status(&message)?;
+                self.display.refresh()?;
+            }
+
+            let input = match self.display.poll_input()? {
+                InputEvent::Key(input) => input,
+                InputEvent::Timeout => {
+                    self.run_autosave()?;
+                    self.check_disk_change()?;
+                    continue;
+                }
+            };
+            let buffer_index = self.editor_ops.buffer().get_current_buffer_index();
+            if self.feed_key(input)? {
+                break;
+            }
+            self.run_autosave()?;
+            // A buffer switched to may have changed on disk while it was hidden
+            if buffer_index != self.editor_ops.buffer().get_current_buffer_index() {
+                self.check_disk_change()?;
+            }
+        }
+        self.close()
+    }
+
+    /// Runs each of `commands` as if typed on the command line, as
+    /// `-c` does, with or without the leading `:`. Returns `true` once one
+    /// of them quits, and runs none after it.
+    pub fn run_commands(&mut self, commands: &[String]) -> Result<bool> {
+        for command in commands {
+            if self.mode != EditorMode::Command {
+                self.feed_key(27)?;
+            }
+            if self.feed_keys(&format!(":{}\n", command.trim_start_matches(':')))? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Releases the buffers' lock files and the display, once the editor
+    /// is done with them.
+    pub fn close(&mut self) -> Result<()> {
+        self.editor_ops.buffer_mut().release_locks();
+        self.display.cleanup()
+    }
+
+    /// Handles one key code, as the display reports them, in the current
+    /// mode. Returns `true` once the editor has quit. Prompts the key opens,
+    /// such as the one `:q` shows for unsaved buffers, read their answer
+    /// from the display.
+    pub fn feed_key(&mut self, input: i32) -> Result<bool> {
+        self.typed_bracket = false;
+
+        // `:` opens the command line before any mode reads it as a key
+        if self.mode.opens_command_line(input) && self.command_buffer.is_empty() && self.pending_keys.is_empty() {
+            self.count = None;
+            self.command_buffer.push(':');
+            return Ok(false);
+        }
+
+        match self.mode {
+            EditorMode::Edit => self.handle_edit_mode_input(input),
+            EditorMode::Command => self.handle_command_mode_input(input),
+            EditorMode::Search => self.handle_search_mode_input(input).map(|_| false),
+            EditorMode::Visual => self.handle_visual_mode_input(input).map(|_| false),
+        }
+    }
+
+    /// Feeds each character of `keys` in turn, as if typed, stopping early
+    /// if the editor quits. Returns whether it did.
+    pub fn feed_keys(&mut self, keys: &str) -> Result<bool> {
+        for ch in keys.chars() {
+            if self.feed_key(ch as i32)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn mode(&self) -> EditorMode {
+        self.mode
+    }
+
+    /// The text of the current buffer.
+    pub fn content(&self) -> &str {
+        self.editor_ops.buffer().content()
+    }
+
+    pub fn cursor(&self) -> Position {
+        self.editor_ops.get_cursor_position()
+    }
+
+    /// What is typed on the command line so far, `:` included.
+    pub fn command_line(&self) -> &str {
+        &self.command_buffer
+    }
+
+    /// The message the next render puts on the status line, if any.
+    pub fn message(&self) -> Option<&str> {
+        self.pending_message.as_deref()
+    }
+
+    /// Takes the message the next render would show, so that it is shown
+    /// only once, as a batch run prints it.
+    pub fn take_message(&mut self) -> Option<String> {
+        self.pending_message.take()
+    }
+
+    /// Whether a command has failed since the editor started.
+    pub fn error_reported(&self) -> bool {
+        self.error_reported
+    }
+
+    pub fn editor_ops(&self) -> &EditorOps<MultiBuffer<SafeFileManager>> {
+        &self.editor_ops
+    }
+
+    pub fn display(&self) -> &D {
+        &self.display
+    }
+
+    /// Notices the current buffer's file changing on disk. With `autoread`
+    /// an unmodified buffer is reloaded quietly; otherwise the user is
+    /// asked whether to reload. Checked on input timeouts, on switching
+    /// buffers and after shell commands, rather than on every key.
+    fn check_disk_change(&mut self) -> Result<()> {
+        let change = self.editor_ops.buffer_mut().check_disk_change(self.config.autoread);
+        let reload = match change {
+            Ok(DiskChange::Unchanged) => return Ok(()),
+            // The buffer keeps the reload as a change of its own
+            Ok(DiskChange::Reloaded) => false,
+            Ok(DiskChange::Changed) => {
+                let (name, modified) = self
+                    .editor_ops
+                    .buffer()
+                    .get_current_buffer_info()
+                    .map(|info| (info.filename.clone(), info.is_modified))
+                    .unwrap_or_default();
+                let unsaved = if modified { " and the buffer has unsaved changes" } else { "" };
+                self.display.render_status(&format!("{} changed on disk{}. Reload? (y/n)", name, unsaved))?;
+                self.display.refresh()?;
+                if !matches!(char::from_u32(Self::prompt_key(&mut self.display)? as u32), Some('y' | 'Y')) {
+                    self.editor_ops.buffer_mut().keep_current_buffer();
+                    return Ok(());
+                }
+                true
+            }
+            Err(e) => {
+                self.report_error(format!("Cannot check for changes on disk: {}", e));
+                return Ok(());
+            }
+        };
+
+        let cursor = self.editor_ops.get_cursor_position();
+        if reload {
+            self.save_undo_state();
+            self.editor_ops.buffer_mut().reload_current_buffer()?;
+        }
+        self.update_editor_ops();
+        self.editor_ops.move_to_position(cursor)?;
+        Ok(())
+    }
+
+    fn lock_warning(info: &BufferInfo) -> Option<String> {
+        info.locked_by.map(|pid| {
+            format!("Warning: {} may already be open in another editor (pid {})", info.filename, pid)
+        })
+    }
+
+    /// Converts the current buffer to `name` the next time it is written.
+    fn set_file_encoding(&mut self, name: &str) -> Result<()> {
+        let encoding = Encoding::from_name(name)
+            .ok_or_else(|| EditorError::InvalidOperation(format!("Unknown encoding: {}", name)))?;
+        self.editor_ops.buffer_mut().set_encoding(encoding);
+        Ok(())
+    }
+
+    fn apply_file_manager_settings(&mut self) {
+        let file_manager = self.editor_ops.buffer_mut().file_manager_mut();
+        file_manager.set_max_file_size(self.config.maxfilesize);
+        file_manager.set_auto_backup(self.config.backup);
+        file_manager.set_backup_dir(self.config.backupdir.as_ref().map(PathBuf::from));
+    }
+
+    fn apply_lockfile_setting(&mut self) {
+        self.editor_ops.buffer_mut().set_lock_files(self.config.lockfile && !self.readonly);
+    }
+
+    /// Draws the current buffer, or whatever view is open, and the status
+    /// line.
+    pub fn render(&mut self) -> Result<()> {
+        self.display.clear()?;
+
+        // Render text content
+        let overlay = self.quickfix_open
+            || self.diff_view.is_some()
+            || self.dir_buffer.is_some()
+            || self.hex_view.is_some()
+            || self.large_file.is_some();
+        let shows_buffer = !overlay && !self.layout.is_split();
+        self.display.set_signs(if overlay { Signs::new() } else { self.signs.clone() });
+        self.display.set_color_columns(if overlay { Vec::new() } else { self.config.color_columns() });
+        self.display.set_bracket_match(if overlay { None } else { self.bracket_match() });
+        self.display.set_selection(if overlay { None } else { self.editor_ops.visual_positions() });
+        self.display.set_rainbow(self.config.rainbow && !overlay);
+        self.display.set_line_numbers(self.config.number && !overlay, self.config.relativenumber && !overlay);
+        self.display.set_attention_words(self.config.attention_words());
+        let filetype = self.editor_ops.buffer().get_current_buffer_info().and_then(|info| info.filetype());
+        self.display.set_filetype(filetype);
+        if let Some(ref mut large_file) = self.large_file {
+            let (_, height) = self.display.get_size();
+            let page = large_file.render(height.saturating_sub(1))?;
+            self.display.set_folds(FoldSet::new());
+            self.display.set_search_highlight(None);
+            self.display.render_text(&page, Position::origin(), 0)?;
+        } else if self.quickfix_open {
+            self.display.set_folds(FoldSet::new());
+            self.display.set_search_highlight(None);
+            self.display.render_text(&self.quickfix.render(), Position::new(0, self.quickfix.selected()), 0)?;
+        } else if self.diff_view.is_some() {
+            self.render_diff()?;
+        } else if let Some(ref dir_buffer) = self.dir_buffer {
+            self.display.set_folds(FoldSet::new());
+            self.display.set_search_highlight(None);
+            self.display.render_text(&dir_buffer.render(), Position::new(0, dir_buffer.selected()), 0)?;
+        } else if let Some(ref hex_view) = self.hex_view {
+            let (_, height) = self.display.get_size();
+            self.display.set_folds(FoldSet::new());
+            self.display.set_search_highlight(None);
+            self.display.render_text(&hex_view.render(height.saturating_sub(1)), Position::origin(), 0)?;
+        } else if self.layout.is_split() {
+            self.render_panes()?;
+        } else {
+            self.display.set_folds(self.folds.clone());
+            self.display.set_search_highlight(self.active_search_highlight());
+            self.set_dirty_lines();
+            self.editor_ops.scroll_to_cursor();
+            self.display.render_text(
+                self.editor_ops.buffer().content(),
+                self.editor_ops.get_cursor_position(),
+                self.editor_ops.scroll_offset(),
+            )?;
+        }
+
+        if !shows_buffer {
+            self.display.set_dirty_lines(None);
+            self.rendered = None;
+        }
+
+        // Update and render status line
+        let current_info = self.editor_ops.buffer().get_current_buffer_info();
+        let filename = current_info.map(|info| info.filename.as_str()).unwrap_or("No buffer");
+        let is_modified = current_info.map(|info| info.is_modified).unwrap_or(false);
+
+        self.status_line.update(
+            filename,
+            self.editor_ops.get_cursor_position(),
+            self.mode,
+            is_modified,
+        );
+        let cursor_line = self.editor_ops.buffer().get_line(self.editor_ops.get_cursor_position().y).unwrap_or("");
+        self.status_line.set_cursor_line(cursor_line, self.editor_ops.tab_size());
+        self.status_line.set_readonly(self.readonly);
+        // The last edit's change is only recorded once the next edit starts
+        let unrecorded = self.has_unrecorded_change();
+        let redo_count = if unrecorded { 0 } else { self.undo_system.redo_count() };
+        self.status_line.set_undo_counts(self.undo_system.undo_count() + usize::from(unrecorded), redo_count);
+
+        let status_text = if let Some(ref diff_view) = self.diff_view {
+            format!("{} (Esc to close)", diff_view.title())
+        } else if self.mode == EditorMode::Search {
+            format!("/{} | {}", self.search.pattern(), self.status_line.format())
+        } else if !self.command_buffer.is_empty() {
+            format!(":{} | {}", self.command_buffer, self.status_line.format())
+        } else {
+            format!("{} | {}", self.editor_ops.buffer().get_buffer_status_line(), self.status_line.format())
+        };
+        let count = self.count.map(|count| count.to_string()).unwrap_or_default();
+        self.status_line.set_pending_keys(&format!("{}{}", count, self.pending_keys));
+        // The bottom-right cell cannot be written without scrolling
+        let (width, _) = self.display.get_size();
+        let status_text = self.status_line.with_pending_keys(&status_text, width.saturating_sub(1));
+
+        self.display.render_status(&status_text)?;
+
+        // Move cursor to correct position
+        self.display.set_cursor_shape(CursorShape::for_mode(self.mode));
+        self.display.move_cursor(self.screen_cursor())?;
+
+        self.display.refresh()?;
+        Ok(())
+    }
+
+    /// `:redraw` and Ctrl-L: the next render clears the terminal and draws
+    /// everything afresh, whatever it thinks is already on screen.
+    fn redraw(&mut self) {
+        self.display.force_redraw();
+        self.rendered = None;
+    }
+
+    /// Tells the display which lines changed since the last render, so it
+    /// only draws those rows again.
+    fn set_dirty_lines(&mut self) {
+        let index = self.editor_ops.buffer().get_current_buffer_index();
+        let Some(buffer) = self.editor_ops.buffer().get_current_buffer() else {
+            self.display.set_dirty_lines(None);
+            return;
+        };
+        let dirty = match self.rendered {
+            Some((rendered_index, revision)) if rendered_index == index => Some(buffer.changed_lines_since(revision)),
+            _ => None,
+        };
+        self.rendered = Some((index, buffer.revision()));
+        self.display.set_dirty_lines(dirty);
+    }
+
+    /// Draws both sides of `:diff` in a vertical split, each with its own
+    /// change signs.
+    fn render_diff(&mut self) -> Result<()> {
+        let Some(ref diff_view) = self.diff_view else {
+            return Ok(());
+        };
+        let (width, height) = self.display.get_size();
+        let (left, right, divider) = diff_view.rects(width, height.saturating_sub(1));
+        self.display.set_folds(FoldSet::new());
+        self.display.set_search_highlight(None);
+
+        self.display.set_signs(diff_view.left_signs().clone());
+        self.display.render_pane(left, &diff_view.left_text(), None, diff_view.top_line())?;
+        self.display.set_signs(diff_view.right_signs().clone());
+        self.display.render_pane(right, &diff_view.right_text(), None, diff_view.top_line())?;
+        self.display.set_signs(Signs::new());
+
+        if let Some(divider) = divider {
+            self.display.render_divider(divider)?;
+        }
+        Ok(())
+    }
+
+    /// Draws every pane of a split layout. Only the focused pane shows the
+    /// cursor and folds; the others show their own buffer and scroll offset.
+    fn render_panes(&mut self) -> Result<()> {
+        self.store_focused_pane();
+        let (width, height) = self.display.get_size();
+        let editor_height = height.saturating_sub(1);
+        let rects = self.layout.pane_rects(width, editor_height);
+
+        let focused = self.layout.focused();
+        self.layout.focused_pane_mut().scroll_to_cursor(rects[focused].height);
+        self.display.set_search_highlight(self.active_search_highlight());
+        let bracket = self.bracket_match();
+
+        for (index, (pane, rect)) in self.layout.panes().iter().zip(rects).enumerate() {
+            if index == focused {
+                self.display.set_folds(self.folds.clone());
+                self.display.set_bracket_match(bracket);
+                self.display.render_pane(rect, self.editor_ops.buffer().content(), Some(pane.cursor), pane.top_line)?;
+            } else {
+                let text = self
+                    .editor_ops
+                    .buffer()
+                    .get_buffer(pane.buffer_index)
+                    .map(|buffer| buffer.content())
+                    .unwrap_or("");
+                self.display.set_folds(FoldSet::new());
+                self.display.set_bracket_match(None);
+                self.display.render_pane(rect, text, None, pane.top_line)?;
+            }
+        }
+
+        if let Some(divider) = self.layout.divider(width, editor_height) {
+            self.display.render_divider(divider)?;
+        }
+        Ok(())
+    }
+
+    /// Cursor position on screen: past the sign column, below the scrolled
+    /// and folded lines, and offset into the focused pane when split.
+    fn screen_cursor(&self) -> Position {
+        let cursor = self.editor_ops.get_cursor_position();
+        let (width, height) = self.display.get_size();
+        let (rect, top_line) = if self.layout.is_split() {
+            let rect = self.layout.pane_rects(width, height.saturating_sub(1))[self.layout.focused()];
+            (rect, self.layout.focused_pane().top_line)
+        } else {
+            (Rect::new(0, 0, width, height.saturating_sub(1)), self.editor_ops.scroll_offset())
+        };
+        let number_width = if self.config.number || self.config.relativenumber {
+            let line_count = self.editor_ops.buffer().content().lines().count();
+            number_column_width(top_line, rect.height, line_count, &self.folds)
+        } else {
+            0
+        };
+        let gutter_width = (self.signs.column_width() + number_width).min(rect.width);
+        buffer_to_screen(cursor, rect, top_line, gutter_width, &self.folds).unwrap_or(Position::new(rect.x, rect.y))
+    }
+
+    /// Marks lines that differ from the staged version of the current file.
+    /// Other signs are left in place.
+    fn refresh_git_gutter(&mut self) -> Result<()> {
+        let git_kinds = [LineChange::Added, LineChange::Modified, LineChange::Deleted].map(|change| change.sign());
+        self.signs.remove_kinds(&git_kinds);
+
+        let Some(info) = self.editor_ops.buffer().get_current_buffer_info().filter(|info| !info.is_untitled()) else {
+            return Ok(());
+        };
+        let path = self.editor_ops.buffer().file_manager().file_system().resolve_path(&info.filename);
+        let Some(committed) = git::committed_text(&path) else {
+            return Ok(());
+        };
+
+        let original: Vec<&str> = committed.lines().collect();
+        let current: Vec<&str> = self.editor_ops.buffer().content().lines().collect();
+        for (line, change) in git::classify_changes(&original, &current) {
+            self.signs.place_sign(line, change.sign());
+        }
+        Ok(())
+    }
+
+    /// `:sign place <line> <kind>`, `:sign unplace <line>` and `:sign clear`.
+    fn sign_command(&mut self, args: &[&str]) -> Result<()> {
+        let line = args.get(1).and_then(|n| n.parse::<usize>().ok()).map(|n| n.saturating_sub(1));
+        let result = match (args.first().copied(), line) {
+            (Some("place"), Some(line)) => match args.get(2).and_then(|name| SignKind::from_name(name)) {
+                Some(kind) => {
+                    self.signs.place_sign(line, kind);
+                    Ok(())
+                }
+                None => Err("Unknown sign kind".to_string()),
+            },
+            (Some("unplace"), Some(line)) => {
+                self.signs.remove_sign(line);
+                Ok(())
+            }
+            (Some("clear"), _) => {
+                self.signs.clear();
+                Ok(())
+            }
+            _ => Err("Usage: :sign place <line> <kind> | unplace <line> | clear".to_string()),
+        };
+
+        if let Err(message) = result {
+            self.display.render_status(&message)?;
+            self.display.refresh()?;
+        }
+        Ok(())
+    }
+
+    /// `:syntax keyword add|remove <word>...` changes which words are
+    /// highlighted as keywords.
+    fn syntax_command(&mut self, args: &[&str]) -> Result<()> {
+        match args {
+            ["keyword", "add", words @ ..] if !words.is_empty() => {
+                for word in words {
+                    self.display.add_keyword(word.to_string());
+                }
+            }
+            ["keyword", "remove", words @ ..] if !words.is_empty() => {
+                for word in words {
+                    self.display.remove_keyword(word);
+                }
+            }
+            _ => {
+                self.display.render_status("Usage: :syntax keyword add|remove <word>...")?;
+                self.display.refresh()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the live buffer and cursor into the focused pane.
+    fn store_focused_pane(&mut self) {
+        let buffer_index = self.editor_ops.buffer().get_current_buffer_index();
+        let cursor = self.editor_ops.get_cursor_position();
+        let pane = self.layout.focused_pane_mut();
+        pane.buffer_index = buffer_index;
+        pane.cursor = cursor;
+    }
+
+    /// Makes the focused pane's buffer and cursor live again.
+    fn load_focused_pane(&mut self) -> Result<()> {
+        let pane = *self.layout.focused_pane();
+        if pane.buffer_index < self.editor_ops.buffer().get_buffer_count() {
+            self.editor_ops.buffer_mut().switch_to_buffer(pane.buffer_index)?;
+        }
+        self.update_editor_ops();
+        self.editor_ops.move_to_position(pane.cursor)?;
+        Ok(())
+    }
+
+    fn split_window(&mut self, direction: SplitDirection, filename: Option<&str>) -> Result<()> {
+        self.store_focused_pane();
+        if !self.layout.split(direction) {
+            self.display.render_status("Only two windows are supported")?;
+            self.display.refresh()?;
+            return Ok(());
+        }
+        if let Some(filename) = filename {
+            self.open_file(filename)?;
+        }
+        Ok(())
+    }
+
+    /// `Ctrl-W` followed by `w`/`Ctrl-W` cycles focus, `h`/`j`/`k`/`l` move
+    /// it toward a side, `c` closes and `o` keeps only the focused window.
+    fn handle_window_command(&mut self, key: char) -> Result<()> {
+        self.store_focused_pane();
+        let changed = match key {
+            'w' | '\x17' => {
+                self.layout.focus_next();
+                self.layout.is_split()
+            }
+            'h' | 'j' | 'k' | 'l' => self.layout.focus_toward(key),
+            'c' => self.layout.close_focused(),
+            'o' => {
+                self.layout.only();
+                false
+            }
+            _ => false,
+        };
+        if changed {
+            self.load_focused_pane()?;
+        }
+        Ok(())
+    }
+
+    fn handle_edit_mode_input(&mut self, input: i32) -> Result<bool> {
+        if self.large_file.is_some() {
+            self.handle_large_file_input(input)?;
+            return Ok(false);
+        }
+        if self.quickfix_open {
+            self.handle_quickfix_input(input)?;
+            return Ok(false);
+        }
+        if self.diff_view.is_some() {
+            self.handle_diff_input(input);
+            return Ok(false);
+        }
+        if self.dir_buffer.is_some() {
+            self.handle_dir_buffer_input(input)?;
+            return Ok(false);
+        }
+        if self.hex_view.is_some() {
+            self.handle_hex_view_input(input);
+            return Ok(false);
+        }
+
+        match input {
+            // Arrow keys
+            1001 => { // Up
+                self.move_vertically(-1)?;
+            }
+            1002 => { // Down
+                self.move_vertically(1)?;
+            }
+            1003 => { // Left
+                self.editor_ops.move_cursor(-1, 0)?;
+            }
+            1004 => { // Right
+                self.editor_ops.move_cursor(1, 0)?;
+            }
+
+            // Backspace
+            127 | 8 if self.can_modify() => {
+                self.save_undo_state();
+                self.editor_ops.delete_char()?;
+                self.mark_modified();
+            }
+
+            // Delete key
+            1005 if self.can_modify() => {
+                self.save_undo_state();
+                if self.editor_ops.delete_forward()? {
+                    self.mark_modified();
+                }
+            }
+
+            // Ctrl-W / Ctrl-U - delete the word or everything before the cursor
+            23 | 21 if self.can_modify() => {
+                self.save_undo_state();
+                let deleted = if input == 23 {
+                    self.editor_ops.delete_word_before()?
+                } else {
+                    self.editor_ops.delete_to_line_start()?
+                };
+                if deleted {
+                    self.mark_modified();
+                }
+            }
+
+            // Tab - next snippet stop, expand a snippet, or indent
+            9 if self.snippet_session.is_some() => self.next_snippet_stop(),
+            9 if self.can_modify() => {
+                self.save_undo_state();
+                if !self.expand_snippet()? {
+                    self.editor_ops.insert_tab()?;
+                }
+                self.mark_modified();
+            }
+
+            // Enter
+            10 | 13 if self.can_modify() => {
+                self.save_undo_state();
+                self.editor_ops.insert_char('\n')?;
+                self.mark_modified();
+            }
+
+            // Escape - switch to command mode
+            27 => {
+                self.mode = EditorMode::Command;
+                self.selection_start = None;
+                self.snippet_session = None;
+                self.command_buffer.clear();
+            }
+
+            // Ctrl-L - repaint the whole screen
+            12 => self.redraw(),
+
+            // Ctrl-R = - insert the value of an expression
+            18 => {
+                let name = match Self::prompt_key(&mut self.display)? {
+                    61 => self.prompt("=")?, // '='
+                    _ => None,
+                };
+                if let Some(name) = name {
+                    self.put_expression(&name, false)?;
+                }
+            }
+
+            // Home key
+            1006 => {
+                let current_pos = self.editor_ops.get_cursor_position();
+                self.editor_ops.move_to_position(Position::new(0, current_pos.y))?;
+            }
+
+            // End key
+            1007 => {
+                let current_pos = self.editor_ops.get_cursor_position();
+                let line_length = self.editor_ops.buffer().line_length(current_pos.y);
+                self.editor_ops.move_to_position(Position::new(line_length, current_pos.y))?;
+            }
+
+            // Printable characters
+            ch if (32..=126).contains(&ch) && self.can_modify() => {
+                self.save_undo_state();
+                self.editor_ops.insert_char(ch as u8 as char)?;
+                self.mark_modified();
+                self.typed_bracket = matches!(ch as u8, b')' | b']' | b'}');
+            }
+
+            _ => {
+                // Unknown input, ignore
+            }
+        }
+
+        // Update buffer reference
+        Ok(false) // Continue running
+    }
+
+    fn handle_visual_mode_input(&mut self, input: i32) -> Result<()> {
+        if self.pending_keys == "S" {
+            // S{delimiter}: wrap the selection
+            let pair = delimiter_pair(input as u8 as char);
+            if let (Some((open, close)), Some(range)) = (pair, self.editor_ops.visual_range()) {
+                if self.can_modify() {
+                    self.save_undo_state();
+                    self.editor_ops.surround(range, open, close)?;
+                    self.mark_modified();
+                }
+            }
+            self.leave_visual_mode();
+            return Ok(());
+        }
+
+        match input {
+            27 => self.leave_visual_mode(),
+            1001 | 107 => self.move_vertically(-1)?, // Up, k
+            1002 | 106 => self.move_vertically(1)?,  // Down, j
+            1003 | 104 => self.editor_ops.move_cursor(-1, 0)?, // Left, h
+            1004 | 108 => self.editor_ops.move_cursor(1, 0)?,  // Right, l
+            83 => self.pending_keys.push('S'), // 'S'
+            103 => self.pending_keys.push('g'), // 'g'
+            122 => self.pending_keys.push('z'), // 'z'
+            102 if self.pending_keys == "z" => {
+                // zf: fold the selected lines
+                if let Some((first, last)) = self.editor_ops.selection_lines() {
+                    self.folds.create(first, last);
+                }
+                self.leave_visual_mode();
+                let cursor = self.editor_ops.get_cursor_position();
+                let visible = self.folds.visible_line(cursor.y);
+                if visible != cursor.y {
+                    self.editor_ops.move_to_position(Position::new(0, visible))?;
+                }
+            }
+            ch if ch < 256 => {
+                // d x y c > < ~ u U, or gu gU g~ after g
+                let key = ch as u8 as char;
+                let operator = if self.pending_keys == "g" {
+                    Operator::parse(&format!("g{}", key)).map(|(operator, _)| operator)
+                } else {
+                    match key {
+                        'x' => Some(Operator::Delete),
+                        '~' => Some(Operator::ToggleCase),
+                        'u' => Some(Operator::Lowercase),
+                        'U' => Some(Operator::Uppercase),
+                        key => Operator::parse(&key.to_string()).map(|(operator, _)| operator),
+                    }
+                };
+                self.pending_keys.clear();
+                if let Some(operator) = operator {
+                    self.operate_on_selection(operator)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn leave_visual_mode(&mut self) {
+        self.pending_keys.clear();
+        self.editor_ops.clear_selection();
+        self.mode = EditorMode::Command;
+    }
+
+    fn handle_command_mode_input(&mut self, input: i32) -> Result<bool> {
+        match input {
+            // Enter - execute command
+            10 | 13 => {
+                if !self.command_buffer.is_empty() {
+                    if let Some(should_quit) = self.execute_command()? {
+                        return Ok(should_quit);
+                    }
+                }
+                self.command_buffer.clear();
+                self.mode = EditorMode::Edit;
+            }
+
+            // Escape - cancel command
+            27 => {
+                self.command_buffer.clear();
+                self.pending_keys.clear();
+                self.count = None;
+                self.mode = EditorMode::Edit;
+            }
+
+            // Backspace in command buffer
+            127 | 8 if !self.command_buffer.is_empty() => {
+                self.command_buffer.pop();
+                if self.command_buffer.is_empty() {
+                    self.mode = EditorMode::Edit;
+                }
+            }
+
+            // Continuation of a multi-key command such as `zf`
+            ch if self.command_buffer.is_empty() && !self.pending_keys.is_empty() => {
+                self.handle_pending_keys(ch)?;
+            }
+
+            // Single character commands (when no command buffer)
+            ch if self.command_buffer.is_empty() => {
+                let key = ch as u8 as char;
+                let count = if key.is_ascii_digit() { self.count } else { self.count.take() };
+                match key {
+                    // A leading 0 is not a count
+                    digit @ '0'..='9' if digit != '0' || count.is_some() => {
+                        let digit = digit.to_digit(10).unwrap_or(0) as usize;
+                        self.count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                    }
+                    'x' | 'X' if self.can_modify() => {
+                        let count = count.unwrap_or(1);
+                        self.save_undo_state();
+                        let deleted = if key == 'x' {
+                            self.editor_ops.delete_under_cursor(count)?
+                        } else {
+                            self.editor_ops.delete_before_cursor(count)?
+                        };
+                        if !deleted.is_empty() {
+                            self.mark_modified();
+                        }
+                    }
+                    'q' => {
+                        return self.handle_quit();
+                    }
+                    's' => {
+                        self.save_current_file()?;
+                    }
+                    'i' => {
+                        self.mode = EditorMode::Edit;
+                    }
+                    'v' => {
+                        self.editor_ops.start_selection();
+                        self.mode = EditorMode::Visual;
+                    }
+                    'u' if self.can_modify() => {
+                        self.undo()?;
+                    }
+                    'r' if self.can_modify() => {
+                        self.redo()?;
+                    }
+                    'n' | 'N' => {
+                        for _ in 0..count.unwrap_or(1) {
+                            self.jump_to_next_match(key == 'n')?;
+                        }
+                    }
+                    'p' | 'P' if self.can_modify() => {
+                        let before = self.editor_ops.buffer().get_current_buffer().map(Buffer::revision);
+                        self.save_undo_state();
+                        if key == 'p' {
+                            self.editor_ops.paste_after()?;
+                        } else {
+                            self.editor_ops.paste_before()?;
+                        }
+                        if self.editor_ops.buffer().get_current_buffer().map(Buffer::revision) != before {
+                            self.mark_modified();
+                        }
+                    }
+                    'h' => {
+                        self.show_help()?;
+                    }
+                    '\x0c' => self.redraw(), // Ctrl-L
+                    '\x07' => { // Ctrl-G
+                        let (name, modified, indent) = self
+                            .editor_ops
+                            .buffer()
+                            .get_current_buffer_info()
+                            .map(|info| (info.filename.clone(), info.is_modified, info.indent_style))
+                            .unwrap_or_default();
+                        let mut message = self.editor_ops.file_info(&name, modified);
+                        if self.config.autoindentdetect && indent != IndentStyle::Unknown {
+                            message.push_str(&format!(" [indent: {}]", indent));
+                        }
+                        self.pending_message = Some(message);
+                    }
+                    '\x0f' => { // Ctrl-O
+                        let current = self.current_jump();
+                        if let Some(jump) = self.jumps.back(current) {
+                            self.follow_jump(jump)?;
+                        }
+                    }
+                    '\t' => { // Ctrl-I
+                        if let Some(jump) = self.jumps.forward() {
+                            self.follow_jump(jump)?;
+                        }
+                    }
+                    '(' | ')' => {
+                        for _ in 0..count.unwrap_or(1) {
+                            self.editor_ops.move_sentence(key == ')');
+                        }
+                    }
+                    'w' | 'b' | 'e' => {
+                        for _ in 0..count.unwrap_or(1) {
+                            match key {
+                                'w' => self.editor_ops.move_word_forward(),
+                                'b' => self.editor_ops.move_word_backward(),
+                                _ => self.editor_ops.move_word_end(),
+                            }
+                        }
+                    }
+                    'G' => {
+                        // The last line, or line {count}
+                        self.record_jump();
+                        let last = self.editor_ops.buffer().line_count();
+                        self.editor_ops.goto_line(count.unwrap_or(last));
+                    }
+                    '{' | '}' => {
+                        self.record_jump();
+                        for _ in 0..count.unwrap_or(1) {
+                            self.editor_ops.move_paragraph(key == '}');
+                        }
+                    }
+                    'z' | ']' | '[' | '\x17' | '"' => {
+                        self.pending_keys.push(key);
+                    }
+                    'd' | 'c' | 'y' | '>' | '<' | 'g' => {
+                        // Operator-pending until a motion follows; a count
+                        // before the operator multiplies the motion's
+                        if let Some(count) = count {
+                            self.pending_keys.push_str(&count.to_string());
+                        }
+                        self.pending_keys.push(key);
+                    }
+                    '/' => {
+                        self.search = IncrementalSearch::new(self.editor_ops.get_cursor_position());
+                        self.mode = EditorMode::Search;
+                    }
+                    _ => {
+                        // Unknown command, ignore
+                    }
+                }
+            }
+
+            // Add character to command buffer
+            ch if (32..=126).contains(&ch) => {
+                self.command_buffer.push(ch as u8 as char);
+            }
+
+            _ => {
+                // Unknown input, ignore
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn handle_search_mode_input(&mut self, input: i32) -> Result<()> {
+        match input {
+            // Enter - search for the pattern (or repeat the last one)
+            10 | 13 => {
+                if !self.search.is_empty() {
+                    self.search_pattern = Some(self.search.finish());
+                }
+                // Search from where the cursor was, not from the preview,
+                // so the jumplist records the real origin
+                self.editor_ops.move_to_position(self.search.origin())?;
+                self.search_highlight_suppressed = false;
+                self.mode = EditorMode::Command;
+                self.jump_to_next_match(true)?;
+            }
+
+            // Escape - cancel the search
+            27 => {
+                let origin = self.search.cancel();
+                self.editor_ops.move_to_position(origin)?;
+                self.mode = EditorMode::Command;
+            }
+
+            127 | 8 => {
+                self.search.pop();
+                self.preview_search()?;
+                if self.search.is_empty() {
+                    self.mode = EditorMode::Command;
+                }
+            }
+
+            ch if (32..=126).contains(&ch) => {
+                self.search.push(ch as u8 as char);
+                self.preview_search()?;
+            }
+
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// With `incsearch`, moves the cursor to the first match of the pattern
+    /// typed so far, or back to where the search began.
+    fn preview_search(&mut self) -> Result<()> {
+        if !self.config.incsearch {
+            return Ok(());
+        }
+        let origin = self.search.origin();
+        let target = self.editor_ops.find_next(self.search.pattern(), origin).unwrap_or(origin);
+        self.editor_ops.move_to_position(target)?;
+        Ok(())
+    }
+
+    /// `n`, or `N` going back: moves to the next match of the last search.
+    fn jump_to_next_match(&mut self, forward: bool) -> Result<()> {
+        let Some(pattern) = self.search_pattern.clone() else {
+            self.report_error("No previous search pattern".to_string());
+            return Ok(());
+        };
+
+        let cursor = self.editor_ops.get_cursor_position();
+        let found = if forward {
+            self.editor_ops.find_next(&pattern, cursor)
+        } else {
+            self.editor_ops.find_previous(&pattern, cursor)
+        };
+
+        match found {
+            Some(position) => {
+                self.record_jump();
+                self.editor_ops.move_to_position(position)?;
+            }
+            None => {
+                let message = match (self.config.wrapscan, forward) {
+                    (true, _) => format!("Pattern not found: {}", pattern),
+                    (false, true) => format!("search hit BOTTOM without match for: {}", pattern),
+                    (false, false) => format!("search hit TOP without match for: {}", pattern),
+                };
+                self.display.render_status(&message)?;
+                self.display.refresh()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// What `showmatch` highlights: the partner of the bracket under the
+    /// cursor, or of the closing bracket just typed before it.
+    fn bracket_match(&self) -> Option<BracketMatch> {
+        if !self.config.showmatch {
+            return None;
+        }
+        let cursor = self.editor_ops.get_cursor_position();
+        self.editor_ops.bracket_match(cursor).or_else(|| {
+            let typed = Position::new(cursor.x.checked_sub(1)?, cursor.y);
+            self.typed_bracket.then(|| self.editor_ops.bracket_match(typed)).flatten()
+        })
+    }
+
+    /// The pattern the renderer should highlight this frame, if any.
+    fn active_search_highlight(&self) -> Option<String> {
+        if self.mode == EditorMode::Search && self.config.incsearch && !self.search.is_empty() {
+            return Some(self.search.pattern().to_string());
+        }
+        if self.config.hlsearch && !self.search_highlight_suppressed {
+            return self.search_pattern.clone();
+        }
+        None
+    }
+
+    /// Moves through the directory listing; Enter descends into a
+    /// directory or opens the selected file.
+    fn handle_dir_buffer_input(&mut self, input: i32) -> Result<()> {
+        let Some(ref mut dir_buffer) = self.dir_buffer else {
+            return Ok(());
+        };
+
+        match input {
+            1001 => dir_buffer.move_selection(-1), // Up
+            1002 => dir_buffer.move_selection(1),  // Down
+            10 | 13 => match dir_buffer.target() {
+                Some(DirTarget::Directory(path)) => self.open_directory(&path)?,
+                Some(DirTarget::File(path)) => {
+                    self.dir_buffer = None;
+                    self.open_file(&path.to_string_lossy())?;
+                }
+                None => {}
+            },
+            27 => {
+                // Leaving the listing returns to the current buffer
+                self.dir_buffer = None;
+                self.mode = EditorMode::Command;
+                self.command_buffer.clear();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Large files are paged read-only; Esc closes the view.
+    fn handle_large_file_input(&mut self, input: i32) -> Result<()> {
+        let Some(ref mut large_file) = self.large_file else {
+            return Ok(());
+        };
+        let (_, height) = self.display.get_size();
+        let page = height.saturating_sub(1).max(1) as i32;
+
+        match input {
+            1001 => large_file.scroll(-1)?, // Up
+            1002 => large_file.scroll(1)?,  // Down
+            32 => large_file.scroll(page)?, // Space pages down
+            27 => {
+                self.large_file = None;
+                self.mode = EditorMode::Command;
+                self.command_buffer.clear();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_quickfix_input(&mut self, input: i32) -> Result<()> {
+        match input {
+            1001 => self.quickfix.move_selection(-1), // Up
+            1002 => self.quickfix.move_selection(1),  // Down
+            10 | 13 => self.jump_to_quickfix_entry()?,
+            27 => {
+                self.quickfix_open = false;
+                self.mode = EditorMode::Command;
+                self.command_buffer.clear();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_diff_input(&mut self, input: i32) {
+        let Some(ref mut diff_view) = self.diff_view else {
+            return;
+        };
+        let (_, height) = self.display.get_size();
+        let page = height.saturating_sub(1).max(1) as i32;
+
+        match input {
+            1001 => diff_view.scroll(-1), // Up
+            1002 => diff_view.scroll(1),  // Down
+            1006 => diff_view.scroll(i32::MIN), // Home
+            1007 => diff_view.scroll(i32::MAX), // End
+            32 => diff_view.scroll(page), // Space pages down
+            27 => {
+                self.diff_view = None;
+                self.mode = EditorMode::Command;
+                self.command_buffer.clear();
+            }
+            _ => {}
+        }
+    }
+
+    /// `:diff <n|file>`: compares the current buffer with buffer `n` or with
+    /// `file` (an open buffer of that name, otherwise read from disk).
+    fn diff(&mut self, target: &str) -> Result<()> {
+        let other = match target.parse::<usize>() {
+            Ok(number) => {
+                let index = number.saturating_sub(1);
+                match (self.editor_ops.buffer().get_buffer(index), self.editor_ops.buffer().get_buffer_info(index)) {
+                    (Some(buffer), Some(info)) => Ok((info.filename.clone(), buffer.content().to_string())),
+                    _ => Err(format!("No buffer {}", number)),
+                }
+            }
+            Err(_) => match self.editor_ops.buffer().find_buffer_by_name(target) {
+                Some(index) => Ok((
+                    target.to_string(),
+                    self.editor_ops.buffer().get_buffer(index).map(|buffer| buffer.content().to_string()).unwrap_or_default(),
+                )),
+                None => self
+                    .editor_ops
+                    .buffer()
+                    .file_manager()
+                    .open(target)
+                    .map(|content| (target.to_string(), content))
+                    .map_err(|e| format!("Error opening {}: {}", target, e)),
+            },
+        };
+
+        match other {
+            Ok((name, content)) => {
+                let current = self
+                    .editor_ops
+                    .buffer()
+                    .get_current_buffer_info()
+                    .map(|info| info.filename.clone())
+                    .unwrap_or_default();
+                self.diff_view = Some(DiffView::new(&current, self.editor_ops.buffer().content(), &name, &content));
+                self.mode = EditorMode::Edit;
+            }
+            Err(message) => {
+                self.display.render_status(&message)?;
+                self.display.refresh()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Searches files matching `glob` under the current directory and shows
+    /// the hits in the quickfix list.
+    fn grep(&mut self, pattern: &str, glob: &str) -> Result<()> {
+        let file_system = self.editor_ops.buffer().file_manager().file_system();
+        let matches = file_system.find_files(glob).and_then(|files| file_system.grep(pattern, &files));
+
+        match matches {
+            Ok(matches) if matches.is_empty() => {
+                self.display.render_status(&format!("No matches for {}", pattern))?;
+                self.display.refresh()?;
+            }
+            Ok(matches) => {
+                self.quickfix = QuickfixList::new(matches);
+                self.quickfix_open = true;
+                self.mode = EditorMode::Edit;
+            }
+            Err(e) => {
+                self.display.render_status(&format!("Error: {}", e))?;
+                self.display.refresh()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the selected quickfix entry and reports its place in the list.
+    fn jump_to_quickfix_entry(&mut self) -> Result<()> {
+        let Some(entry) = self.quickfix.current().cloned() else {
+            self.display.render_status("No quickfix entries")?;
+            self.display.refresh()?;
+            return Ok(());
+        };
+
+        self.quickfix_open = false;
+        self.record_jump();
+        self.open_file(&entry.path.to_string_lossy())?;
+        self.editor_ops.move_to_position(Position::new(entry.column, entry.line))?;
+        self.display.render_status(&format!("{} {}", self.quickfix.position_label(), entry.text.trim()))?;
+        self.display.refresh()?;
+        Ok(())
+    }
+
+    fn current_jump(&self) -> Jump {
+        (self.editor_ops.buffer().get_current_buffer_index(), self.editor_ops.get_cursor_position())
+    }
+
+    /// Remembers the cursor position before a large motion.
+    fn record_jump(&mut self) {
+        let jump = self.current_jump();
+        self.jumps.push(jump);
+    }
+
+    /// Returns to a jumplist entry, switching buffers if needed.
+    fn follow_jump(&mut self, (buffer_index, position): Jump) -> Result<()> {
+        if buffer_index >= self.editor_ops.buffer().get_buffer_count() {
+            return Ok(());
+        }
+        if buffer_index != self.editor_ops.buffer().get_current_buffer_index() {
+            self.editor_ops.buffer_mut().switch_to_buffer(buffer_index)?;
+            self.update_editor_ops();
+        }
+        self.editor_ops.move_to_position(position)?;
+        Ok(())
+    }
+
+    fn open_directory(&mut self, path: &Path) -> Result<()> {
+        match DirBuffer::open(self.editor_ops.buffer().file_manager().file_system(), path) {
+            Ok(dir_buffer) => {
+                self.dir_buffer = Some(dir_buffer);
+                self.mode = EditorMode::Edit;
+            }
+            Err(e) => {
+                self.display.render_status(&format!("Error reading {}: {}", path.display(), e))?;
+                self.display.refresh()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The hex view is read-only: only scrolling is handled, and Esc
+    /// closes the view along with the `binary` option.
+    fn handle_hex_view_input(&mut self, input: i32) {
+        let Some(ref mut hex_view) = self.hex_view else {
+            return;
+        };
+        let (_, height) = self.display.get_size();
+        let page = height.saturating_sub(1).max(1) as i32;
+
+        match input {
+            1001 => hex_view.scroll(-1), // Up
+            1002 => hex_view.scroll(1),  // Down
+            1006 => hex_view.scroll(i32::MIN), // Home
+            1007 => hex_view.scroll(i32::MAX), // End
+            32 => hex_view.scroll(page), // Space pages down
+            27 => {
+                self.hex_view = None;
+                self.config.binary = false;
+                self.mode = EditorMode::Command;
+                self.command_buffer.clear();
+            }
+            _ => {}
+        }
+    }
+
+    /// Enters or leaves the hex view to match the `binary` option, reading
+    /// the file's raw bytes so non-UTF-8 content is shown faithfully.
+    fn apply_binary_setting(&mut self) -> Result<()> {
+        if !self.config.binary {
+            self.hex_view = None;
+            return Ok(());
+        }
+        if self.hex_view.is_some() {
+            return Ok(());
+        }
+
+        let info = self.editor_ops.buffer().get_current_buffer_info();
+        let on_disk = info.filter(|info| !info.is_modified && !info.is_untitled());
+        let bytes = match on_disk {
+            Some(info) => self.editor_ops.buffer().file_manager().file_system().read_bytes(&info.filename)?,
+            None => self.editor_ops.buffer().content().as_bytes().to_vec(),
+        };
+        self.hex_view = Some(HexView::new(bytes));
+        self.mode = EditorMode::Edit;
+        Ok(())
+    }
+
+    /// Wakes `get_input` periodically while autosave is on so the deadline
+    /// is checked even when no keys arrive.
+    fn apply_autosave_setting(&mut self) {
+        self.autosave_timer.set_interval(Duration::from_secs(self.config.autosave_interval));
+        self.autosave_timer.reset(Instant::now());
+
+        let timeout = self.config.autosave.then_some(1000);
+        self.display.set_input_timeout(timeout);
+    }
+
+    fn run_autosave(&mut self) -> Result<()> {
+        let now = Instant::now();
+        if !self.config.autosave || self.readonly || !self.autosave_timer.is_due(now) {
+            return Ok(());
+        }
+        self.autosave_timer.reset(now);
+
+        let saved = self.editor_ops.buffer_mut().autosave();
+        if !saved.is_empty() {
+            self.display.render_status(&format!("Autosaved {} buffer(s)", saved.len()))?;
+            self.display.refresh()?;
+        }
+        Ok(())
+    }
+
+    /// Loads or drops the spell checker to match the `spell` option.
+    fn apply_spell_setting(&mut self) -> Result<()> {
+        if !self.config.spell {
+            self.spell_checker = None;
+        } else if self.spell_checker.is_none() {
+            let path = self.config.spellfile.as_deref().unwrap_or(DEFAULT_WORD_LIST);
+            match SpellChecker::load(path) {
+                Ok(checker) => self.spell_checker = Some(Rc::new(checker)),
+                Err(e) => {
+                    self.config.spell = false;
+                    self.display.render_status(&format!("Cannot load word list {}: {}", path, e))?;
+                    self.display.refresh()?;
+                }
+            }
+        }
+        self.display.set_spell_checker(self.spell_checker.clone());
+        Ok(())
+    }
+
+    fn jump_to_misspelling(&mut self, forward: bool) -> Result<()> {
+        let Some(checker) = self.spell_checker.clone() else {
+            return Ok(());
+        };
+
+        let cursor = self.editor_ops.get_cursor_position();
+        // Displays that do not highlight still check the filetype's comments and strings
+        let profile = self.display.syntax_profile().cloned().unwrap_or_else(|| {
+            let filetype = self.editor_ops.buffer().get_current_buffer_info().and_then(|info| info.filetype());
+            SyntaxProfile::for_filetype(filetype)
+        });
+        if let Some(position) = checker.find_misspelling(self.editor_ops.buffer(), cursor, forward, &profile) {
+            self.editor_ops.move_to_position(position)?;
+        }
+        Ok(())
+    }
+
+    fn handle_pending_keys(&mut self, input: i32) -> Result<()> {
+        let key = match input {
+            1001 => 'k',
+            1002 => 'j',
+            ch => ch as u8 as char,
+        };
+        self.pending_keys.push(key);
+
+        let line = self.editor_ops.get_cursor_position().y;
+        match self.pending_keys.as_str() {
+            "zo" => {
+                self.folds.open_at(line);
+            }
+            "zc" => {
+                self.folds.close_at(line);
+            }
+            "za" => {
+                self.folds.toggle_at(line);
+            }
+            "]s" | "[s" => {
+                self.jump_to_misspelling(self.pending_keys.starts_with(']'))?;
+            }
+            "ds" | "cs" => {
+                return Ok(()); // Still waiting for the delimiter
+            }
+            keys if keys.chars().count() == 3 && keys.starts_with("cs") => {
+                return Ok(()); // Still waiting for the new delimiter
+            }
+            keys if keys.starts_with("ds") || keys.starts_with("cs") => {
+                let mut delimiters = keys[2..].chars();
+                let from = delimiters.next().unwrap_or(' ');
+                self.change_surround(from, delimiters.next())?;
+            }
+            keys if split_count(keys).1 == "gg" => {
+                // gg, or 42gg for line 42
+                let line = split_count(keys).0.unwrap_or(1);
+                self.record_jump();
+                self.editor_ops.goto_line(line);
+            }
+            keys if keys.trim_start_matches(|c: char| c.is_ascii_digit()).starts_with(['d', 'c', 'y', '>', '<', 'g']) => {
+                // [count]{operator}[count]{motion}
+                let (count, rest) = split_count(keys);
+                if rest == "g" {
+                    return Ok(()); // Still waiting for the rest of gu, gU or g~
+                }
+                if let Some((operator, rest)) = Operator::parse(rest) {
+                    let (motion_count, motion) = split_count(rest);
+                    if matches!(motion, "" | "i" | "a") {
+                        return Ok(()); // Still waiting for the motion
+                    }
+                    if let Some(motion) = Motion::from_keys(motion, operator.line_key()) {
+                        self.operate(operator, motion, count.unwrap_or(1) * motion_count.unwrap_or(1))?;
+                    }
+                }
+            }
+            keys if keys.starts_with('\x17') => {
+                self.handle_window_command(key)?;
+            }
+            // "{register} for the yank, delete or put that follows
+            keys if keys.starts_with('"') && !self.editor_ops.select_register(key) => {
+                self.report_error(format!("Invalid register name: {}", key));
+            }
+            keys if keys.starts_with("zf") => {
+                // zf[count]{motion}: fold the lines the motion covers
+                let (count, motion) = split_count(&keys[2..]);
+                if matches!(motion, "" | "i" | "a") {
+                    return Ok(()); // Still waiting for the motion
+                }
+                let lines = Motion::from_keys(motion, '\0')
+                    .and_then(|motion| self.editor_ops.motion_lines(motion, count.unwrap_or(1)));
+                if let Some((first, last)) = lines {
+                    self.folds.create(first, last);
+                }
+            }
+            _ => {}
+        }
+
+        self.pending_keys.clear();
+
+        // Keep the cursor off lines hidden by a newly closed fold
+        let cursor = self.editor_ops.get_cursor_position();
+        let visible = self.folds.visible_line(cursor.y);
+        if visible != cursor.y {
+            self.editor_ops.move_to_position(Position::new(0, visible))?;
+        }
+        Ok(())
+    }
+
+    /// Reads a line typed on the status line after `label`: the text on
+    /// Enter, or `None` on Escape.
+    fn prompt(&mut self, label: &str) -> Result<Option<String>> {
+        let mut text = String::new();
+        loop {
+            self.display.render_status(&format!("{}{}", label, text))?;
+            self.display.refresh()?;
+            match Self::prompt_key(&mut self.display)? {
+                10 | 13 => return Ok(Some(text)),
+                27 => return Ok(None),
+                127 | 8 => {
+                    text.pop();
+                }
+                ch if (32..=126).contains(&ch) => text.push(ch as u8 as char),
+                _ => {}
+            }
+        }
+    }
+
+    /// Expands the snippet named by the word before the cursor, for this
+    /// buffer's filetype or any. `false` when there is none.
+    fn expand_snippet(&mut self) -> Result<bool> {
+        let filetype = self.editor_ops.buffer().get_current_buffer_info().and_then(|info| info.filetype());
+        let trigger = self.editor_ops.word_before_cursor();
+        let Some(snippet) = self.snippets.get(filetype, trigger).cloned() else {
+            return Ok(false);
+        };
+        let session = self.editor_ops.insert_snippet(trigger.len(), &snippet)?;
+        // A snippet with only its final stop is finished once inserted
+        self.snippet_session = (!session.is_last()).then_some(session);
+        Ok(true)
+    }
+
+    /// Tab inside a snippet: moves to the end of the next stop's default,
+    /// finishing the snippet at its last stop.
+    fn next_snippet_stop(&mut self) {
+        let length = self.editor_ops.buffer().content().len();
+        let Some(session) = self.snippet_session.as_mut() else {
+            return;
+        };
+        let stop = session.advance(length);
+        if stop.is_none() || session.is_last() {
+            self.snippet_session = None;
+        }
+        if let Some((_, end)) = stop {
+            self.editor_ops.jump_to_offset(end);
+        }
+    }
+
+    /// `:put <name>` and `Ctrl-R =<name>`: inserts the value of the named
+    /// expression at the cursor, or with `linewise` on a new line below
+    /// the cursor line.
+    fn put_expression(&mut self, name: &str, linewise: bool) -> Result<()> {
+        let Some(expression) = Expression::from_name(name) else {
+            self.report_error(format!("Unknown expression: {} (try date, time, filename or line)", name));
+            return Ok(());
+        };
+        if self.can_modify() {
+            self.insert_expression(expression, linewise)?;
+        }
+        Ok(())
+    }
+
+    fn insert_expression(&mut self, expression: Expression, linewise: bool) -> Result<()> {
+        let filename = self
+            .editor_ops
+            .buffer()
+            .get_current_buffer_info()
+            .map(|info| info.filename.clone())
+            .unwrap_or_default();
+        let cursor = self.editor_ops.get_cursor_position();
+        let text = expression.evaluate(&ExpressionContext {
+            now: DateTime::from_system_time(SystemTime::now()),
+            date_format: &self.config.dateformat,
+            time_format: &self.config.timeformat,
+            filename: &filename,
+            line: cursor.y,
+        });
+
+        self.save_undo_state();
+        if linewise {
+            self.insert_below_cursor_line(&text)?;
+        } else {
+            self.editor_ops.type_text(&text)?;
+        }
+        self.mark_modified();
+        Ok(())
+    }
+
+    /// Inserts `text` as new lines below the cursor line, in one edit, and
+    /// moves the cursor to the start of the first of them.
+    fn insert_below_cursor_line(&mut self, text: &str) -> Result<()> {
+        let line = self.editor_ops.get_cursor_position().y;
+        let line_length = self.editor_ops.buffer().line_length(line);
+        self.editor_ops.move_to_position(Position::new(line_length, line))?;
+        self.editor_ops.type_text(&format!("\n{}", text))?;
+        self.editor_ops.move_to_position(Position::new(0, line + 1))
+    }
+
+    /// `:r !cmd`: inserts what `command` prints below the cursor line, as
+    /// one change.
+    fn read_command_output(&mut self, command: &str) -> Result<()> {
+        if !self.can_modify() {
+            return Ok(());
+        }
+        let output = match shell::run(command, None) {
+            Ok(output) => output,
+            Err(e) => {
+                self.report_error(e.to_string());
+                return Ok(());
+            }
+        };
+        // A command's last line ends with a line break which isn't a line
+        let text = output.stdout.strip_suffix('\n').unwrap_or(&output.stdout);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        if !output.stdout.is_empty() {
+            self.save_undo_state();
+            self.insert_below_cursor_line(text)?;
+            self.mark_modified();
+        }
+        if let Some(failure) = output.failure_message() {
+            self.report_error(failure);
+        }
+        Ok(())
+    }
+
+    /// `:10,20!cmd`: pipes the lines in `range` through `command` and puts
+    /// what it prints in their place, as one change. When the command
+    /// fails the lines are left as they were.
+    fn filter_lines(&mut self, (first, last): (usize, usize), command: &str) -> Result<()> {
+        if !self.can_modify() {
+            return Ok(());
+        }
+        let input = self.editor_ops.buffer().get_lines(first..last + 1).join("\n") + "\n";
+        let output = match shell::run(command, Some(&input)) {
+            Ok(output) => output,
+            Err(e) => {
+                self.report_error(e.to_string());
+                return Ok(());
+            }
+        };
+        if let Some(failure) = output.failure_message() {
+            self.report_error(match output.stderr.lines().next() {
+                Some(error) => format!("{}: {}", failure, error),
+                None => failure,
+            });
+            return Ok(());
+        }
+
+        let text = output.stdout.strip_suffix('\n').unwrap_or(&output.stdout);
+        let lines: Vec<String> = if output.stdout.is_empty() {
+            Vec::new()
+        } else {
+            text.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line).to_string()).collect()
+        };
+        self.editor_ops.move_to_line(first);
+        self.edit_lines(|buffer| buffer.replace_lines(first..last + 1, lines))?.transpose()?;
+        self.pending_message = Some(format!("{} lines filtered", last + 1 - first));
+        Ok(())
+    }
+
+    /// `:!cmd`: runs `command` and shows what it printed, on the status
+    /// line when it fits on one line and as a page otherwise.
+    fn run_shell_command(&mut self, command: &str) -> Result<()> {
+        let output = match shell::run(command, None) {
+            Ok(output) => output,
+            Err(e) => {
+                self.report_error(e.to_string());
+                return Ok(());
+            }
+        };
+        let mut text = format!("{}{}", output.stdout, output.stderr).trim_end().to_string();
+        if let Some(failure) = output.failure_message() {
+            self.error_reported = true;
+            text = if text.is_empty() { failure } else { format!("{}\n\n{}", text, failure) };
+        }
+        if text.contains('\n') {
+            text.push_str("\n\nPress any key to continue...");
+            self.show_page(&text)
+        } else {
+            self.pending_message = Some(text).filter(|text| !text.is_empty());
+            Ok(())
+        }
+    }
+
+    /// `ds(`, or `cs"'` when `to` is given.
+    fn change_surround(&mut self, from: char, to: Option<char>) -> Result<()> {
+        if !self.can_modify() {
+            return Ok(());
+        }
+        self.save_undo_state();
+        let changed = match to {
+            Some(to) => self.editor_ops.change_surround(from, to)?,
+            None => self.editor_ops.delete_surround(from)?,
+        };
+        if changed {
+            self.mark_modified();
+        }
+        Ok(())
+    }
+
+    /// Applies an operator such as `d` over a motion such as `w`, then for
+    /// a change starts inserting where the text was.
+    fn operate(&mut self, operator: Operator, motion: Motion, count: usize) -> Result<()> {
+        self.apply_operator(operator, |editor| editor.editor_ops.operate(operator, motion, count))
+    }
+
+    /// Applies an operator pressed in visual mode to the selection, the
+    /// same way it applies over a motion, and leaves visual mode.
+    fn operate_on_selection(&mut self, operator: Operator) -> Result<()> {
+        let result = self.apply_operator(operator, |editor| editor.editor_ops.operate_on_selection(operator));
+        if self.mode == EditorMode::Visual {
+            self.leave_visual_mode();
+        }
+        result
+    }
+
+    /// Runs `act`, which applies `operator`, as one undoable change.
+    fn apply_operator(&mut self, operator: Operator, act: impl FnOnce(&mut Self) -> Result<Option<String>>) -> Result<()> {
+        if operator.modifies() {
+            if !self.can_modify() {
+                return Ok(());
+            }
+            self.save_undo_state();
+        }
+        let revision = |editor: &Self| editor.editor_ops.buffer().get_current_buffer().map(Buffer::revision);
+        let before = revision(self);
+        let taken = act(self)?;
+        if operator.modifies() && taken.is_some() && revision(self) != before {
+            self.mark_modified();
+        }
+        if operator == Operator::Change && taken.is_some() {
+            self.mode = EditorMode::Edit;
+        }
+        Ok(())
+    }
+
+    /// Applies an edit to the lines of the current buffer as one undoable
+    /// change, keeping the cursor on a line that still exists. Returns
+    /// `None` without editing in read-only mode.
+    fn edit_lines<T>(&mut self, edit: impl FnOnce(&mut Buffer) -> T) -> Result<Option<T>> {
+        if !self.can_modify() {
+            return Ok(None);
+        }
+        self.save_undo_state();
+        let Some(buffer) = self.editor_ops.buffer_mut().get_current_buffer_mut() else {
+            return Ok(None);
+        };
+
+        let revision = buffer.revision();
+        let result = edit(buffer);
+        if buffer.revision() != revision {
+            if let Some(info) = self.editor_ops.buffer_mut().get_current_buffer_info_mut() {
+                info.is_modified = true;
+            }
+        }
+        let cursor = self.editor_ops.get_cursor_position();
+        self.editor_ops.move_to_line(cursor.y);
+        Ok(Some(result))
+    }
+
+    /// Parses an ex line range: `%`, a single address, or two addresses
+    /// joined by a comma, where an address is a line number, `.` for the
+    /// cursor line or `$` for the last line. Returns 0-based lines with the
+    /// smaller first.
+    fn parse_line_range(&self, spec: &str) -> Option<(usize, usize)> {
+        let last = self.editor_ops.buffer().line_count().saturating_sub(1);
+        let current = self.editor_ops.get_cursor_position().y;
+        let address = |text: &str| match text {
+            "." => Some(current),
+            "$" => Some(last),
+            number => number.parse::<usize>().ok().filter(|&n| n > 0).map(|n| (n - 1).min(last)),
+        };
+
+        let (first, second) = match spec.split_once(',') {
+            _ if spec == "%" => (0, last),
+            Some((first, second)) => (address(first)?, address(second)?),
+            None => {
+                let line = address(spec)?;
+                (line, line)
+            }
+        };
+        Some((first.min(second), first.max(second)))
+    }
+
+    fn move_vertically(&mut self, delta: i32) -> Result<()> {
+        let cursor = self.editor_ops.get_cursor_position();
+        let target = self.folds.move_line(cursor.y, delta, self.editor_ops.buffer().line_count());
+        self.editor_ops.move_to_line(target);
+        Ok(())
+    }
+
+    fn execute_command(&mut self) -> Result<Option<bool>> {
+        let command_owned = self.command_buffer.clone();
+        let command = command_owned.trim_start_matches(':');
+        let range_length = command
+            .find(|c: char| !(c.is_ascii_digit() || ",.$%".contains(c)))
+            .unwrap_or(command.len());
+        let (range_spec, command) = command.split_at(range_length);
+        let parts: Vec<&str> = command.split_whitespace().collect();
+
+        if parts.is_empty() {
+            // `:42` goes to line 42
+            if let Some((_, last)) = self.parse_line_range(range_spec) {
+                self.record_jump();
+                self.editor_ops.goto_line(last + 1);
+            }
+            return Ok(None);
+        }
+
+        let substitution = Substitution::parse(command.trim());
+        // `:w >> file` and `:w>>file` append instead of overwriting
+        let append_to = ["write", "w"]
+            .iter()
+            .find_map(|name| command.trim().strip_prefix(name))
+            .and_then(|rest| rest.trim_start().strip_prefix(">>"))
+            .map(str::trim);
+        // `:r !cmd` reads a command's output in below the cursor line
+        let read_command = ["read", "r"]
+            .iter()
+            .find_map(|name| command.trim().strip_prefix(name))
+            .and_then(|rest| rest.trim_start().strip_prefix('!'));
+        let range = if range_spec.is_empty() {
+            None
+        } else if !matches!(parts[0], "rev" | "reverse" | "w" | "write" | "w!" | "write!")
+            && !parts[0].starts_with('!')
+            && substitution.is_none()
+            && append_to.is_none()
+        {
+            self.display.render_status(&format!("No range allowed: {}", parts[0]))?;
+            self.display.refresh()?;
+            return Ok(None);
+        } else {
+            match self.parse_line_range(range_spec) {
+                Some(range) => Some(range),
+                None => {
+                    self.display.render_status(&format!("Invalid range: {}", range_spec))?;
+                    self.display.refresh()?;
+                    return Ok(None);
+                }
+            }
+        };
+
+        if let Some(substitution) = substitution {
+            self.substitute(range, &substitution)?;
+            return Ok(None);
+        }
+        if let Some(filename) = append_to {
+            self.append_to_file(range, filename)?;
+            return Ok(None);
+        }
+        // A shell command may change the file on disk too
+        if let Some(shell_command) = read_command {
+            self.read_command_output(shell_command)?;
+            self.check_disk_change()?;
+            return Ok(None);
+        }
+        if let Some(shell_command) = command.trim().strip_prefix('!') {
+            match range {
+                Some(range) => self.filter_lines(range, shell_command)?,
+                None => self.run_shell_command(shell_command)?,
+            }
+            self.check_disk_change()?;
+            return Ok(None);
+        }
+
+        match parts[0] {
+            "q" | "quit" => {
+                return Ok(Some(self.handle_quit()?));
+            }
+            "f" | "file" | "rename" => {
+                if parts.len() > 1 {
+                    self.rename_buffer(parts[1], parts[0] == "rename")?;
+                } else {
+                    let status = self.editor_ops.buffer().get_buffer_status_line();
+                    self.display.render_status(&status)?;
+                    self.display.refresh()?;
+                }
+            }
+            "discard" => {
+                self.discard_changes()?;
+            }
+            "qa" | "qall" | "qa!" | "qall!" => {
+                return Ok(Some(self.quit_all(parts[0].ends_with('!'))?));
+            }
+            "bufdo" => {
+                let inner = command.trim_start()[parts[0].len()..].trim();
+                if inner.is_empty() {
+                    self.display.render_status("Usage: :bufdo <command>")?;
+                    self.display.refresh()?;
+                } else if let Some(quit) = self.bufdo(inner)? {
+                    return Ok(Some(quit));
+                }
+            }
+            "w" | "write" | "w!" | "write!" if range.is_some() => {
+                let filename = parts.get(1).copied().unwrap_or("");
+                self.write_lines(range.unwrap_or_default(), filename, parts[0].ends_with('!'))?;
+            }
+            "w" | "write" | "w!" | "write!" => {
+                if parts.len() > 1 {
+                    // Save as different filename
+                    self.save_as(parts[1])?;
+                } else {
+                    self.save_current_file()?;
+                }
+            }
+            "wq" => {
+                self.save_current_file()?;
+                return Ok(Some(true));
+            }
+            "e" | "edit" => {
+                if parts.len() > 1 {
+                    self.open_file(parts[1])?;
+                }
+            }
+            "o" | "open" => {
+                if parts.len() > 1 {
+                    self.open_file(parts[1])?;
+                }
+            }
+            "new" => {
+                self.editor_ops.buffer_mut().new_buffer();
+                self.update_editor_ops();
+            }
+            "bd" | "bdelete" => {
+                let index = if parts.len() > 1 {
+                    parts[1].parse().unwrap_or(self.editor_ops.buffer().get_current_buffer_index())
+                } else {
+                    self.editor_ops.buffer().get_current_buffer_index()
+                };
+                self.editor_ops.buffer_mut().close_buffer(index)?;
+                self.jumps.remove_buffer(index);
+                self.update_editor_ops();
+            }
+            "ls" | "buffers" => {
+                self.show_buffer_list()?;
+            }
+            "b" | "buffer" => {
+                if parts.len() > 1 {
+                    if let Ok(index) = parts[1].parse::<usize>() {
+                        if index > 0 {
+                            self.editor_ops.buffer_mut().switch_to_buffer(index - 1)?;
+                            self.update_editor_ops();
+                        }
+                    }
+                }
+            }
+            "bn" | "bnext" => {
+                self.editor_ops.buffer_mut().next_buffer()?;
+                self.update_editor_ops();
+            }
+            "bp" | "bprevious" => {
+                self.editor_ops.buffer_mut().previous_buffer()?;
+                self.update_editor_ops();
+            }
+            "bmn" | "bmnext" => {
+                self.switch_to_modified_buffer(true)?;
+            }
+            "bmp" | "bmprevious" => {
+                self.switch_to_modified_buffer(false)?;
+            }
+            "set" | "se" | "setlocal" | "setl" => {
+                for arg in &parts[1..] {
+                    if arg.starts_with("spellfile=") || arg.starts_with("spf=") {
+                        self.spell_checker = None; // Reload from the new list
+                    }
+                    // fileencoding and filetype belong to the buffer rather than the editor
+                    let result = match arg.split_once('=') {
+                        Some(("fileencoding" | "fenc", name)) => self.set_file_encoding(name),
+                        Some(("filetype" | "ft", name)) => {
+                            let filetype = Some(name.to_string()).filter(|name| !name.is_empty());
+                            self.editor_ops.buffer_mut().set_filetype(filetype);
+                            Ok(())
+                        }
+                        _ if matches!(*arg, "readonly" | "ro" | "noreadonly" | "noro") => {
+                            self.set_readonly(!arg.starts_with("no"));
+                            Ok(())
+                        }
+                        _ if matches!(*arg, "modifiable" | "ma" | "nomodifiable" | "noma") => {
+                            self.editor_ops.buffer_mut().set_modifiable(!arg.starts_with("no"));
+                            Ok(())
+                        }
+                        _ if *arg == "bomb" || *arg == "nobomb" => {
+                            self.editor_ops.buffer_mut().set_bom(*arg == "bomb");
+                            Ok(())
+                        }
+                        _ => self.config.set(arg).map(|()| {
+                            // :set wins over the modeline and detection from then on
+                            if let Some(info) = self.editor_ops.buffer_mut().get_current_buffer_info_mut() {
+                                info.forget_local_option(arg);
+                            }
+                        }),
+                    };
+                    if let Err(e) = result {
+                        self.display.render_status(&e.to_string())?;
+                        self.display.refresh()?;
+                    }
+                }
+                self.apply_spell_setting()?;
+                self.apply_autosave_setting();
+                self.apply_binary_setting()?;
+                self.apply_lockfile_setting();
+                self.apply_file_manager_settings();
+                self.editor_ops.buffer_mut().set_modelines(self.config.modeline);
+                self.apply_buffer_options();
+            }
+            "sp" | "split" => {
+                self.split_window(SplitDirection::Horizontal, parts.get(1).copied())?;
+            }
+            "vs" | "vsplit" => {
+                self.split_window(SplitDirection::Vertical, parts.get(1).copied())?;
+            }
+            "clo" | "close" => {
+                self.handle_window_command('c')?;
+            }
+            "on" | "only" => {
+                self.handle_window_command('o')?;
+            }
+            "sign" => {
+                self.sign_command(&parts[1..])?;
+            }
+            "syn" | "syntax" => {
+                self.syntax_command(&parts[1..])?;
+            }
+            "GitGutter" => {
+                self.refresh_git_gutter()?;
+            }
+            "goto" => {
+                let line = parts.get(1).and_then(|n| n.parse::<usize>().ok());
+                let column = parts.get(2).map_or(Some(1), |n| n.parse::<usize>().ok());
+                match (line, column) {
+                    (Some(line), Some(column)) => self.editor_ops.goto(line, column),
+                    _ => {
+                        self.display.render_status("Usage: :goto <line> [column]")?;
+                        self.display.refresh()?;
+                    }
+                }
+            }
+            "ea" | "earlier" => {
+                self.travel_history(parts.get(1).copied(), true)?;
+            }
+            "lat" | "later" => {
+                self.travel_history(parts.get(1).copied(), false)?;
+            }
+            "undol" | "undolist" => {
+                self.show_undo_list()?;
+            }
+            "diff" => {
+                if parts.len() > 1 {
+                    self.diff(parts[1])?;
+                } else {
+                    self.display.render_status("Usage: :diff <buffer number|file>")?;
+                    self.display.refresh()?;
+                }
+            }
+            "gr" | "grep" => {
+                if parts.len() > 1 {
+                    self.grep(parts[1], parts.get(2).copied().unwrap_or("*"))?;
+                }
+            }
+            "cn" | "cnext" => {
+                self.quickfix.next_entry();
+                self.jump_to_quickfix_entry()?;
+            }
+            "cp" | "cprevious" => {
+                self.quickfix.previous_entry();
+                self.jump_to_quickfix_entry()?;
+            }
+            "cc" => {
+                let number = parts.get(1).and_then(|n| n.parse().ok()).unwrap_or(self.quickfix.selected() + 1);
+                if self.quickfix.jump(number).is_some() {
+                    self.jump_to_quickfix_entry()?;
+                } else {
+                    self.display.render_status(&format!("No quickfix entry {}", number))?;
+                    self.display.refresh()?;
+                }
+            }
+            "sor" | "sort" => {
+                let unique = parts.get(1) == Some(&"u");
+                self.edit_lines(|buffer| {
+                    buffer.sort_lines();
+                    if unique {
+                        buffer.dedup_consecutive_lines();
+                    }
+                })?;
+            }
+            "uniq" => {
+                if let Some(removed) = self.edit_lines(Buffer::dedup_consecutive_lines)? {
+                    self.display.render_status(&format!("{} fewer lines", removed))?;
+                    self.display.refresh()?;
+                }
+            }
+            "rev" | "reverse" => {
+                let last = self.editor_ops.buffer().line_count().saturating_sub(1);
+                let (first, last) = range.unwrap_or((0, last));
+                self.edit_lines(|buffer| buffer.reverse_lines(first..last + 1))?.transpose()?;
+            }
+            "pu" | "put" => {
+                let name = parts[1..].join(" ");
+                self.put_expression(name.trim_start_matches('='), true)?;
+            }
+            "redr" | "redraw" => self.redraw(),
+            "noh" | "nohlsearch" => {
+                self.search_highlight_suppressed = true;
+            }
+            "help" => {
+                self.show_help()?;
+            }
+            _ => self.report_error(format!("Unknown command: {}", command)),
+        }
+
+        Ok(None)
+    }
+
+    fn handle_quit(&mut self) -> Result<bool> {
+        // Check if any buffers are modified
+        let modified_indices: Vec<usize> = self.editor_ops.buffer()
+            .list_buffers()
+            .into_iter()
+            .filter(|(_, info)| info.is_modified)
+            .map(|(idx, _)| idx)
+            .collect();
+
+        if !modified_indices.is_empty() {
+            let msg = format!("{} file(s) modified. Save before quit? (y/n/a)", modified_indices.len());
+            self.display.render_status(&msg)?;
+            self.display.refresh()?;
+
+            let choice = Self::prompt_key(&mut self.display)?;
+            match char::from_u32(choice as u32).unwrap_or('\x1b') {
+                'y' | 'Y' => {
+                    // Save current buffer and quit
+                    self.save_current_file()?;
+                    return Ok(true);
+                }
+                'a' | 'A' => {
+                    // Save all modified buffers
+                    for index in modified_indices {
+                        self.editor_ops.buffer_mut().switch_to_buffer(index)?;
+                        self.editor_ops.buffer_mut().save_current_buffer()?;
+                    }
+                    return Ok(true);
+                }
+                'n' | 'N' => {
+                    return Ok(true); // Quit without saving
+                }
+                _ => {
+                    return Ok(false); // Cancel quit
+                }
+            }
+        }
+
+        Ok(true) // No modified buffers, safe to quit
+    }
+
+    /// `:qa` asks once what to do with every modified buffer; `:qa!` quits
+    /// without asking.
+    fn quit_all(&mut self, force: bool) -> Result<bool> {
+        let display = &mut self.display;
+        let readonly = self.readonly;
+        let mut refused_save = false;
+
+        let result = self.editor_ops.buffer_mut().quit_all(force, |count| {
+            let prompt = format!("{} buffer(s) modified. Save all before quit? (y = save all, n = discard all)", count);
+            let _ = display.render_status(&prompt);
+            let _ = display.refresh();
+            let key = Self::prompt_key(display).ok().and_then(|code| char::from_u32(code as u32)).unwrap_or('\x1b');
+            match QuitChoice::from_key(key) {
+                QuitChoice::SaveAll if readonly => {
+                    refused_save = true;
+                    QuitChoice::Cancel
+                }
+                choice => choice,
+            }
+        });
+
+        let message = match result {
+            Ok(quit) if !refused_save => return Ok(quit),
+            Ok(_) => "Cannot save in read-only mode".to_string(),
+            Err(e) => format!("Cannot quit: {}", e),
+        };
+        self.display.render_status(&message)?;
+        self.display.refresh()?;
+        Ok(false)
+    }
+
+    /// `:file <name>` retargets the current buffer; `:rename <name>` also
+    /// renames the file on disk.
+    fn rename_buffer(&mut self, new_name: &str, on_disk: bool) -> Result<()> {
+        let renamed = self.editor_ops.buffer_mut().rename_current_buffer(new_name, on_disk);
+
+        let message = match renamed {
+            Ok(()) if on_disk => format!("Renamed to {}", new_name),
+            Ok(()) => format!("Buffer now writes to {}", new_name),
+            Err(e) => format!("Cannot rename: {}", e),
+        };
+        self.display.render_status(&message)?;
+        self.display.refresh()?;
+        Ok(())
+    }
+
+    /// `:discard`: drops unsaved edits, keeping the cursor where it is when
+    /// that position still exists.
+    fn discard_changes(&mut self) -> Result<()> {
+        let cursor = self.editor_ops.get_cursor_position();
+        self.editor_ops.buffer_mut().discard_changes()?;
+        self.update_editor_ops();
+        self.editor_ops.move_to_position(cursor)?;
+        self.save_undo_state();
+        self.display.render_status("Changes discarded")?;
+        self.display.refresh()?;
+        Ok(())
+    }
+
+    /// `:bufdo <cmd>`: runs `cmd` in each buffer in turn, finishing in the
+    /// last one. Stops early if the command quits the editor. A
+    /// substitution reports its total over every buffer at the end.
+    fn bufdo(&mut self, command: &str) -> Result<Option<bool>> {
+        let unranged = command.trim_start_matches(|c: char| c.is_ascii_digit() || ",.$%".contains(c));
+        let substitution = Substitution::parse(unranged.trim());
+        if substitution.is_some() {
+            self.substitute_tally = Some((0, 0));
+        }
+
+        let mut index = 0;
+        while index < self.editor_ops.buffer().get_buffer_count() {
+            self.editor_ops.buffer_mut().switch_to_buffer(index)?;
+            self.update_editor_ops();
+            self.command_buffer = command.to_string();
+            if let Some(true) = self.execute_command()? {
+                self.substitute_tally = None;
+                return Ok(Some(true));
+            }
+            index += 1;
+        }
+        self.command_buffer.clear();
+
+        if let (Some((count, buffers)), Some(substitution)) = (self.substitute_tally.take(), substitution) {
+            if count == 0 {
+                self.report_error(format!("Pattern not found: {}", substitution.pattern));
+            } else {
+                self.pending_message = Some(format!("{} substitutions in {} buffers", count, buffers));
+            }
+        }
+        Ok(None)
+    }
+
+    /// `:s/old/new/flags`: replaces within the range, the cursor line when
+    /// none is given, as one undoable change.
+    fn substitute(&mut self, range: Option<(usize, usize)>, substitution: &Substitution) -> Result<()> {
+        if !self.can_modify() {
+            return Ok(());
+        }
+        let cursor = self.editor_ops.get_cursor_position();
+        let (first, last) = range.unwrap_or((cursor.y, cursor.y));
+        self.save_undo_state();
+        let count = self.editor_ops.buffer_mut().substitute(first..last + 1, substitution)?;
+        if count > 0 {
+            self.editor_ops.move_to_line(cursor.y);
+        }
+
+        // Within :bufdo the total is reported once at the end
+        if let Some((total, buffers)) = self.substitute_tally.as_mut() {
+            *total += count;
+            *buffers += usize::from(count > 0);
+        } else if count == 0 {
+            self.report_error(format!("Pattern not found: {}", substitution.pattern));
+        } else {
+            self.pending_message = Some(format!("{} substitutions", count));
+        }
+        Ok(())
+    }
+
+    fn save_current_file(&mut self) -> Result<()> {
+        if self.readonly {
+            self.display.render_status("Cannot save in read-only mode")?;
+            self.display.refresh()?;
+            return Ok(());
+        }
+
+        let written = self.editor_ops.buffer_mut().save_current_buffer()?;
+        self.write_undo_file()?;
+        self.refresh_git_gutter()?;
+        self.display.render_status(if written { "File saved" } else { "No changes" })?;
+        self.display.refresh()?;
+        Ok(())
+    }
+
+    /// The path of the current buffer's file, unless it has none yet.
+    fn current_file_path(&self) -> Option<PathBuf> {
+        let info = self.editor_ops.buffer().get_current_buffer_info().filter(|info| !info.is_untitled())?;
+        Some(self.editor_ops.buffer().file_manager().file_system().resolve_path(&info.filename))
+    }
+
+    /// With `undofile`, saves the undo history beside the file just written.
+    fn write_undo_file(&mut self) -> Result<()> {
+        if let Some(path) = self.current_file_path().filter(|_| self.config.undofile) {
+            self.save_undo_state();
+            UndoFile::write(&path, &self.undo_system, self.editor_ops.buffer().content())?;
+        }
+        Ok(())
+    }
+
+    /// With `undofile`, takes up the history saved beside the current
+    /// buffer's file, so undo reaches back into earlier sessions. Returns
+    /// why a saved history was refused.
+    fn read_undo_file(&mut self) -> Option<String> {
+        let path = self.current_file_path().filter(|_| self.config.undofile)?;
+        match UndoFile::read(&path, self.editor_ops.buffer().content(), UNDO_MAX_AGE) {
+            Ok(Some(history)) => {
+                // Its newest change left the text as it is now
+                self.undo_system = history;
+                None
+            }
+            Ok(None) => None,
+            Err(e) => Some(e.to_string()),
+        }
+    }
+
+    fn save_as(&mut self, filename: &str) -> Result<()> {
+        if self.readonly {
+            self.display.render_status("Cannot save in read-only mode")?;
+            self.display.refresh()?;
+            return Ok(());
+        }
+
+        if let Some(info) = self.editor_ops.buffer_mut().get_current_buffer_info_mut() {
+            info.filename = filename.to_string();
+            // A new file needs writing even if the content is unchanged
+            info.content_hash = None;
+            self.editor_ops.buffer_mut().refresh_filetype();
+            self.editor_ops.buffer_mut().save_current_buffer()?;
+            self.write_undo_file()?;
+            self.display.render_status(&format!("Saved as {}", filename))?;
+            self.display.refresh()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the lines in `range` to `filename`, or to the buffer's own
+    /// file when it is empty, for `:10,20w file`. A file that already
+    /// exists is only overwritten when `force`d, as with `:10,20w! file`.
+    /// The buffer keeps its name and stays modified.
+    fn write_lines(&mut self, (first, last): (usize, usize), filename: &str, force: bool) -> Result<()> {
+        let Some(filename) = self.target_file(filename)? else {
+            return Ok(());
+        };
+        let buffer = self.editor_ops.buffer();
+        let status = if !force && buffer.file_manager().resolve(&filename).exists() {
+            format!("{} exists (add ! to override)", filename)
+        } else {
+            let lines = buffer.get_lines(first..last + 1).join("\n");
+            match buffer.file_manager().save(&filename, &lines) {
+                Ok(()) => format!("{} lines written to {}", last + 1 - first, filename),
+                Err(e) => format!("Cannot write {}: {}", filename, e),
+            }
+        };
+        self.display.render_status(&status)?;
+        self.display.refresh()
+    }
+
+    /// The file a `:w` of some lines writes to: `filename`, or the buffer's
+    /// own file when that is empty. `None`, after saying why, when there is
+    /// no file or the editor is read-only.
+    fn target_file(&mut self, filename: &str) -> Result<Option<String>> {
+        let current = self.editor_ops.buffer().get_current_buffer_info().map(|info| info.filename.clone());
+        let filename = match (filename, current) {
+            ("", Some(current)) if !current.is_empty() => current,
+            ("", _) => {
+                self.display.render_status("No file name")?;
+                self.display.refresh()?;
+                return Ok(None);
+            }
+            (filename, _) => filename.to_string(),
+        };
+        if self.readonly {
+            self.display.render_status("Cannot save in read-only mode")?;
+            self.display.refresh()?;
+            return Ok(None);
+        }
+        Ok(Some(filename))
+    }
+
+    /// Appends the lines in `range`, or the whole buffer, to `filename`,
+    /// or to the buffer's own file when it is empty, for `:w >> file`.
+    fn append_to_file(&mut self, range: Option<(usize, usize)>, filename: &str) -> Result<()> {
+        let Some(filename) = self.target_file(filename)? else {
+            return Ok(());
+        };
+
+        let buffer = self.editor_ops.buffer();
+        let (first, last) = range.unwrap_or((0, buffer.line_count().saturating_sub(1)));
+        let lines = buffer.get_lines(first..last + 1).join("\n");
+        let status = match buffer.file_manager().append(&filename, &lines) {
+            Ok(()) => format!("Appended {} lines to {}", last + 1 - first, filename),
+            Err(e) => format!("Cannot append to {}: {}", filename, e),
+        };
+        self.display.render_status(&status)?;
+        self.display.refresh()
+    }
+
+    fn switch_to_modified_buffer(&mut self, forward: bool) -> Result<()> {
+        let switched = if forward {
+            self.editor_ops.buffer_mut().next_modified_buffer()
+        } else {
+            self.editor_ops.buffer_mut().previous_modified_buffer()
+        };
+
+        if switched.is_some() {
+            self.update_editor_ops();
+        } else {
+            self.display.render_status("No modified buffers")?;
+            self.display.refresh()?;
+        }
+        Ok(())
+    }
+
+    fn open_file(&mut self, filename: &str) -> Result<()> {
+        let file_system = self.editor_ops.buffer().file_manager().file_system();
+        let (filename, position) = split_file_position(filename, |name| file_system.file_exists(name));
+        if file_system.resolve_path(filename).is_dir() {
+            return self.open_directory(Path::new(filename));
+        }
+        self.dir_buffer = None;
+
+        // Files read in one chunk only report completion, so small files
+        // open without a progress message flashing by.
+        let display = &mut self.display;
+        let opened = self.editor_ops.buffer_mut().open_file_with_progress(filename, &mut |fraction| {
+            if fraction < 1.0 {
+                let _ = display.render_status(&format!("Loading {}... {:.0}%", filename, fraction * 100.0));
+                let _ = display.refresh();
+            }
+        });
+
+        match opened {
+            Ok(_) => {
+                self.update_editor_ops();
+                if let Some((line, column)) = position {
+                    self.editor_ops.goto(line, column);
+                }
+                let message = self
+                    .editor_ops
+                    .buffer()
+                    .get_current_buffer_info()
+                    .and_then(Self::lock_warning)
+                    .or_else(|| self.read_undo_file())
+                    .unwrap_or_else(|| format!("Opened {}", filename));
+                self.display.render_status(&message)?;
+                self.display.refresh()?;
+            }
+            Err(e) => {
+                let file_manager = self.editor_ops.buffer().file_manager();
+                let file_system = file_manager.file_system();
+                if file_manager.exceeds_size_limit(filename) {
+                    let lazy = LazyFile::open(file_system.resolve_path(filename))?;
+                    self.large_file = Some(LargeFileView::new(lazy));
+                    self.mode = EditorMode::Edit;
+                    self.display.render_status(&format!(
+                        "{} is over {} bytes, showing it read-only (Esc to close)",
+                        filename,
+                        file_manager.max_file_size()
+                    ))?;
+                } else if file_system.is_binary(filename).unwrap_or(false) {
+                    self.hex_view = Some(HexView::new(file_system.read_bytes(filename)?));
+                    self.config.binary = true;
+                    self.mode = EditorMode::Edit;
+                    self.display.render_status(&format!(
+                        "{} is a binary file, showing hex view (Esc to close)",
+                        filename
+                    ))?;
+                } else {
+                    self.display.render_status(&format!("Error opening {}: {}", filename, e))?;
+                }
+                self.display.refresh()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn show_buffer_list(&mut self) -> Result<()> {
+        let buffers = self.editor_ops.buffer().list_buffers();
+        let current_index = self.editor_ops.buffer().get_current_buffer_index();
+
+        let mut buffer_text = String::from("Buffers:\n");
+        for (index, info) in buffers {
+            let marker = if index == current_index { "*" } else { " " };
+            let modified = if info.is_modified { "+" } else { " " };
+            buffer_text.push_str(&format!("{}{}{:3}: {}\n", marker, modified, index + 1, info.filename));
+        }
+        buffer_text.push_str("\nPress any key to continue...");
+
+        self.show_page(&buffer_text)?;
+
+        Ok(())
+    }
+
+    fn undo(&mut self) -> Result<()> {
+        self.save_undo_state();
+        match self.undo_system.undo_entry() {
+            Some(change) => self.replay(&[change.inverse()]),
+            None => Ok(()),
+        }
+    }
+
+    /// An edit since the last undo leaves nothing to redo: recording it
+    /// clears the changes undone.
+    fn redo(&mut self) -> Result<()> {
+        self.save_undo_state();
+        match self.undo_system.redo() {
+            Some(change) => self.replay(&[change]),
+            None => Ok(()),
+        }
+    }
+
+    /// `:earlier` / `:later`: moves through the undo history by a count of
+    /// changes or a time span.
+    fn travel_history(&mut self, arg: Option<&str>, backward: bool) -> Result<()> {
+        if !self.can_modify() {
+            return Ok(());
+        }
+        let Some(step) = HistoryStep::parse(arg) else {
+            self.display.render_status("Usage: :earlier/:later [count|{n}s|{n}m|{n}h]")?;
+            self.display.refresh()?;
+            return Ok(());
+        };
+        self.save_undo_state();
+        let changes = if backward {
+            let undone = self.undo_system.earlier_entries(step);
+            undone.iter().map(EditorAction::inverse).collect()
+        } else {
+            self.undo_system.later_entries(step)
+        };
+        if changes.is_empty() {
+            let edge = if backward { "oldest" } else { "newest" };
+            self.display.render_status(&format!("Already at {} change", edge))?;
+            self.display.refresh()?;
+            return Ok(());
+        }
+        self.replay(&changes)
+    }
+
+    /// `:undolist`: every state in the undo history with its age, marking
+    /// the current one and those that were undone.
+    fn show_undo_list(&mut self) -> Result<()> {
+        self.save_undo_state();
+        let entries = self.undo_system.entries();
+        let current = entries.iter().rposition(|&(_, undone)| !undone);
+        let mut text = format!(
+            "Undo history: {} to undo, {} to redo\n\n  number  saved\n",
+            self.undo_system.undo_count(),
+            self.undo_system.redo_count()
+        );
+        for (number, &(age, undone)) in entries.iter().enumerate() {
+            let marker = if Some(number) == current { '>' } else { ' ' };
+            let note = if undone { "  (undone)" } else { "" };
+            text.push_str(&format!("{} {:>6}  {} ago{}\n", marker, number, format_age(age), note));
+        }
+        text.push_str("\nPress any key to continue...");
+        self.show_page(&text)
+    }
+
+    /// Applies `changes` from the undo history to the current buffer in
+    /// order, leaving the text as the history has it.
+    fn replay(&mut self, changes: &[EditorAction]) -> Result<()> {
+        let Some(buffer) = self.editor_ops.buffer_mut().get_current_buffer_mut() else {
+            return Ok(());
+        };
+        let result = changes.iter().try_for_each(|change| buffer.apply(change));
+        // Replaying makes no change of its own to record
+        buffer.take_changes();
+        self.update_editor_ops();
+        result
+    }
+
+    /// Starts the view afresh on the current buffer, as after switching to
+    /// it, and applies the buffer's options.
+    fn update_editor_ops(&mut self) {
+        self.editor_ops.reset_view(self.display.get_size());
+        self.apply_buffer_options();
+    }
+
+    /// The editor's options with the current buffer's detected indentation
+    /// and then its modeline applied.
+    fn buffer_config(&self) -> EditorConfig {
+        let mut config = self.config.clone();
+        if let Some(info) = self.editor_ops.buffer().get_current_buffer_info() {
+            if self.config.autoindentdetect {
+                info.indent_style.apply(&mut config);
+            }
+            info.modeline.apply(&mut config);
+        }
+        config
+    }
+
+    fn apply_buffer_options(&mut self) {
+        let config = self.buffer_config();
+        self.editor_ops.set_tab_size(config.tabstop);
+        self.editor_ops.set_expandtab(config.expandtab);
+        self.editor_ops.set_shift_width(config.indent_width());
+        self.editor_ops.set_search_options(!config.ignorecase, config.wrapscan);
+        self.display.set_search_ignore_case(config.ignorecase);
+        self.editor_ops.set_readonly(self.readonly);
+    }
+
+    /// Shows `message` on the status line after the next render, as the
+    /// failure of the command that led to it.
+    fn report_error(&mut self, message: String) {
+        self.error_reported = true;
+        self.pending_message = Some(message);
+    }
+
+    /// Whether the current buffer may be edited. When it may not, says so
+    /// on the status line after the next render.
+    fn can_modify(&mut self) -> bool {
+        match self.editor_ops.check_modifiable() {
+            Ok(()) => true,
+            Err(e) => {
+                self.report_error(e.to_string());
+                false
+            }
+        }
+    }
+
+    /// `:set readonly` / `:set noreadonly`.
+    fn set_readonly(&mut self, readonly: bool) {
+        self.readonly = readonly;
+        self.editor_ops.set_readonly(readonly);
+        self.apply_lockfile_setting();
+    }
+
+    /// Records the edits the buffer made since the last change recorded
+    /// as one change. Edits call this before they start, so each can be
+    /// undone on its own, and undo calls it to record the last edit before
+    /// stepping back past it. Only the edits are kept, not the text: a
+    /// keystroke in a large file costs the bytes it typed.
+    fn save_undo_state(&mut self) {
+        let Some(buffer) = self.editor_ops.buffer_mut().get_current_buffer_mut() else {
+            return;
+        };
+        let mut changes = buffer.take_changes();
+        match changes.len() {
+            0 => {}
+            1 => self.undo_system.save_action(changes.remove(0)),
+            _ => self.undo_system.save_action(EditorAction::Compound(changes)),
+        }
+    }
+
+    /// Whether the text has changed since the last change was recorded.
+    fn has_unrecorded_change(&self) -> bool {
+        self.editor_ops.buffer().get_current_buffer().is_some_and(Buffer::has_changes)
+    }
+
+    fn mark_modified(&mut self) {
+        if let Some(info) = self.editor_ops.buffer_mut().get_current_buffer_info_mut() {
+            info.is_modified = true;
+        }
+    }
+
+    /// Shows `text` in place of the buffer until a key is pressed.
+    fn show_page(&mut self, text: &str) -> Result<()> {
+        self.display.clear()?;
+        self.display.set_dirty_lines(None);
+        self.rendered = None;
+        self.display.render_text(text, Position::origin(), 0)?;
+        self.display.refresh()?;
+        Self::prompt_key(&mut self.display)?; // Wait for any key
+        Ok(())
+    }
+
+    /// Waits for the key answering a prompt. The display times out while
+    /// autosave is on, and a timeout is not an answer.
+    fn prompt_key(display: &mut D) -> Result<i32> {
+        loop {
+            if let InputEvent::Key(key) = display.poll_input()? {
+                return Ok(key);
+            }
+        }
+    }
+
+    fn show_help(&mut self) -> Result<()> {
+        let help_text = r#"
+Vim-like Text Editor Help
+=========================
+
+File Operations:
+  :e <file>    - Edit/open file (a directory opens a file listing,
+                 a file over 10MB opens read-only and loads lazily)
+  :o <file>    - Open file (same as :e)
+  :w           - Write/save current file
+  :w <file>    - Save as different filename
+  :w >> <file> - Append the buffer, or a range of lines, to a file
+  :10,20w <file> - Write lines 10 to 20 to a file; w! overwrites it
+  :r !<cmd>    - Insert the output of a shell command below the cursor line
+  :!<cmd>      - Run a shell command and show its output
+  :%!<cmd>     - Filter lines through a shell command, e.g. :%!sort
+  :file <name> - Write this buffer to <name> from now on
+  :rename <name> - Rename the file and its buffer
+  :wq          - Write and quit
+  :q           - Quit (prompts if modified)
+  :discard     - Drop unsaved changes to this buffer
+  :qa / :qa!   - Quit, asking once to save or discard all modified
+                 buffers / quit discarding every change
+  :grep <pattern> [glob] - Search files under the current directory;
+                 Enter on a result jumps to it
+  :cn / :cp    - Next/previous grep result (wraps around)
+  :cc <n>      - Jump to grep result n
+  :diff <n|file> - Compare this buffer side by side with buffer n or
+                 a file (Esc to close)
+  :sort [u]    - Sort lines (u also drops duplicates)
+  :uniq        - Drop lines that repeat the line above
+  :reverse     - Reverse the line order; :3,7reverse or :.,$reverse
+                 for a range of lines
+  Tab          - In edit mode after todo or fixme, expand the snippet;
+                 Tab again jumps to its next stop
+  :put <expr>  - Put date, time, filename or line on a new line below;
+                 Ctrl-R =<expr> in edit mode inserts it at the cursor
+
+Signs:
+  :sign place <line> <kind> - Mark a line (breakpoint, error, warning,
+                 added, modified, deleted)
+  :sign unplace <line> / :sign clear - Remove one or all signs
+  :GitGutter   - Mark lines changed since the last commit (also on save)
+
+Syntax:
+  :syntax keyword add <word>... - Highlight more words as keywords
+  :syntax keyword remove <word>... - Stop highlighting words as keywords
+
+Windows:
+  :split / :vsplit [file] - Split the editor into two windows
+  :close / :only - Close this window / close the other window
+  Ctrl-W w     - Focus the other window (in command mode)
+  Ctrl-W h/j/k/l - Focus the window to the left/below/above/right
+
+Buffer Operations:
+  :new         - Create new buffer
+  :ls          - List all buffers
+  :b <num>     - Switch to buffer number
+  :bn / :bp    - Next/previous buffer
+  :bd          - Delete current buffer
+  :bmn / :bmp  - Next/previous modified buffer
+  :s/old/new/g - Replace old with new on the cursor line; %s for every
+                 line, :3,7s for a range; flags g (all on a line), i
+                 (ignore case); & in new is the matched text
+  :bufdo <cmd> - Run a command in every buffer, e.g. :bufdo %s/old/new/g
+
+Edit Mode:
+  Arrow keys   - Move cursor
+  Backspace    - Delete character before cursor
+  Delete       - Delete character at cursor
+  Ctrl-W       - Delete the word before cursor
+  Ctrl-U       - Delete to the start of the line
+  Tab          - Insert spaces
+  Enter        - New line
+  Escape       - Switch to command mode
+  :            - Start command input (in edit mode : is typed)
+
+Command Mode:
+  i            - Switch to edit mode
+  zf{motion}   - Fold the lines the motion covers (zf in visual mode: the selection)
+  zo / zc / za - Open/close/toggle the fold under the cursor
+  /<pattern>   - Search forward for pattern
+  n / N        - Next/previous match of the last search, wrapping around
+  ]s / [s      - Next/previous misspelled word (with :set spell)
+  Ctrl-O / Ctrl-I - Back/forward through jumps (searches, grep results)
+  { / }        - Back/forward to the empty line around a paragraph
+  gg / G       - First/last line, or line {n} with a count (5G)
+  :<n>         - Go to line n
+  ( / )        - Back/forward to the start of a sentence
+  w / b / e    - Forward/back to the start of a word, or forward to its end
+  Ctrl-G       - Show the cursor position, buffer size and detected indent
+  x / X        - Delete the character under/before the cursor (3x for three)
+  p / P        - Paste after/before the cursor, or below/above the line
+                 for whole lines (xp swaps two characters)
+  diw / daw    - Delete the word under the cursor (daw takes a space too)
+  ciw / caw    - Change the word under the cursor
+  di( / da(    - Delete inside/around the enclosing (), [] or {} (also
+                 i) i[ i] i{ i} ib iB), or quotes with i" i' i`
+  ci( / ci"    - Change inside the enclosing brackets or quotes
+  dip / dap    - Delete the paragraph (dap takes the blank lines after it)
+  d / c / y {motion} - Delete, change or yank over w b e 0 $ { } ( ) j k G
+                 or a text object: dw, c$, y}, d2w, dj, 3dd, yy, cc
+  "{reg}        - Use register a-z or 0-9 for the next yank, delete or
+                 put: "ayw, "ap, "0p (last yank), "1p to "9p (last
+                 nine deletions, newest in "1)
+  > / < {motion} - Indent or unindent the lines covered: >>, <}, 3>>
+  gu / gU / g~ {motion} - Lower, upper or switch case: guiw, gUU, g~w
+  v            - Select from the cursor; S) S] S} S" wraps the selection,
+                 and d x y c > < u U ~ gu gU g~ act on it
+  ds( / cs"'   - Delete the enclosing (), or change enclosing "" to ''
+
+Options:
+  :set hlsearch / nohlsearch - Highlight all search matches
+  :set incsearch             - Jump to and highlight matches while typing
+  :set nowrapscan            - Stop searches at the end of the buffer
+  :set ignorecase            - Search without telling upper and lower case apart
+  :set showmatch             - Highlight the bracket matching the cursor's
+  :set rainbow               - Color brackets by nesting depth
+  :set number / nu           - Number lines in a gutter
+  :set relativenumber / rnu  - Number lines by distance from the cursor
+  :set todowords=TODO,FIXME  - Words to pick out inside comments
+  :set colorcolumn=80,+1     - Draw guides at columns (+N/-N from textwidth)
+  :set textwidth=<n>         - Maximum line width for relative guides
+  :set spell                 - Check spelling in comments and strings
+  :set spellfile=<path>      - Word list to check against
+  :set binary / nobinary     - Toggle the read-only hex view
+  :set fileencoding=<enc>    - Write as utf-8, utf-16le, utf-16be or latin1
+  :set bomb / nobomb         - Write the file with/without a byte order mark
+  :set readonly / noreadonly - Refuse/allow edits to every buffer
+  :setlocal nomodifiable     - Refuse edits to this buffer only (help opens so)
+  :set filetype=<lang>       - Highlight as <lang> instead of the detected type
+  :set nolockfile            - Stop creating .file.lock files
+  :set autosave              - Periodically write modified files
+  :set autosaveinterval=<s>  - Seconds between autosaves (default 30)
+  :set tabstop=<n>           - Spaces inserted for Tab (also --tab-size)
+  :set shiftwidth=<n>        - Columns per indent level (0 follows tabstop)
+  :set noexpandtab           - Insert a tab character for Tab
+  :set dateformat=%d/%m/%Y   - Format of :put date (also timeformat)
+  :set autoread              - Reload unmodified buffers changed on disk
+  :set noautoindentdetect    - Keep et/sw instead of following each file
+  :set undofile              - Save undo history in .name.un~ and reload it on open
+  :set nomodeline            - Ignore "vim: set ts=2 et:" lines in opened files
+  :set maxfilesize=<size>    - Page larger files read-only (e.g. 500K, 10M)
+  :set nobackup              - Stop writing .backup copies before saving
+  :set backupdir=<dir>       - Keep backups in <dir> instead of beside files
+  :goto <line> [col] - Move the cursor to a line and column
+  :noh         - Clear search highlighting until the next search
+  :redraw      - Clear and repaint the whole screen (also Ctrl-L)
+  u            - Undo
+  :earlier / :later [n|{n}s|{n}m|{n}h] - Step n changes, or a time span,
+                 back or forward through the undo history
+  :undolist    - List the undo history with the age of each change
+  r            - Redo
+  h            - Show this help
+
+Command-line Arguments:
+  text-editor [files...]  - Open multiple files
+  text-editor file:42[:5] - Open file at line 42 (column 5); :e file:42 too
+  --readonly              - Read-only mode
+  --tab-size <n>          - Set tab size
+  --max-file-size <size> - Page larger files read-only (e.g. 500K, 10M)
+
+Close this buffer with :bd
+"#;
+
+        // The help opens as a buffer of its own, which refuses edits
+        let help = self.editor_ops.buffer().find_buffer_by_name(HELP_BUFFER);
+        match help {
+            Some(index) => self.editor_ops.buffer_mut().switch_to_buffer(index)?,
+            None => {
+                self.editor_ops.buffer_mut().open_view(HELP_BUFFER, help_text.trim_start());
+            }
+        }
+        self.update_editor_ops();
+        Ok(())
+    }
+}
+
+/// Splits a leading count such as the `3` of `3dw` off `keys`. A lone `0`
+/// is the `0` motion rather than a count.
+fn split_count(keys: &str) -> (Option<usize>, &str) {
+    let digits = if keys.starts_with('0') { 0 } else { keys.find(|c: char| !c.is_ascii_digit()).unwrap_or(keys.len()) };
+    let (count, rest) = keys.split_at(digits);
+    (count.parse().ok(), rest)
+}
+
+/// A history age such as `45s`, `3m 20s` or `2h 5m`.
+fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds / 60 % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HeadlessDisplay, KEY_TIMEOUT};
+    use std::collections::VecDeque;
+
+    /// A display that remembers what was drawn last and answers prompts
+    /// from a queue of keys.
+    #[derive(Default)]
+    struct MockDisplay {
+        keys: VecDeque<i32>,
+        text: String,
+        top_line: usize,
+        status: String,
+    }
+
+    impl DisplayManager for MockDisplay {
+        fn init(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn cleanup(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn clear(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn refresh(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn render_text(&mut self, text: &str, _position: Position, top_line: usize) -> Result<()> {
+            self.text = text.to_string();
+            self.top_line = top_line;
+            Ok(())
+        }
+
+        fn render_status(&mut self, status: &str) -> Result<()> {
+            self.status = status.to_string();
+            Ok(())
+        }
+
+        fn get_input(&mut self) -> Result<i32> {
+            self.keys.pop_front().ok_or_else(|| EditorError::Display("No keys left".to_string()))
+        }
+
+        fn get_size(&self) -> (usize, usize) {
+            (80, 24)
+        }
+
+        fn move_cursor(&mut self, _position: Position) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn editor_with(prompt_keys: &str) -> Editor<MockDisplay> {
+        let display = MockDisplay {
+            keys: prompt_keys.chars().map(|ch| ch as i32).collect(),
+            ..MockDisplay::default()
+        };
+        Editor::new(display, Vec::new(), false, EditorConfig::new()).unwrap()
+    }
+
+    #[test]
+    fn test_edit_session_through_feed_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.txt");
+        let mut editor = editor_with("");
+        assert_eq!(editor.mode(), EditorMode::Edit);
+
+        assert!(!editor.feed_keys("hello world").unwrap());
+        assert_eq!((editor.content(), editor.cursor()), ("hello world", Position::new(11, 0)));
+
+        // Escape, back to the start, delete a word and then a character
+        editor.feed_key(27).unwrap();
+        assert_eq!(editor.mode(), EditorMode::Command);
+        editor.feed_keys("(dw").unwrap();
+        assert_eq!(editor.content(), "world");
+        editor.feed_keys("x").unwrap();
+        assert_eq!(editor.content(), "orld");
+
+        editor.feed_keys(&format!(":w {}", path.display())).unwrap();
+        assert_eq!(editor.command_line(), format!(":w {}", path.display()));
+        editor.feed_key(10).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "orld");
+
+        editor.render().unwrap();
+        assert!(editor.display().text.starts_with("orld"));
+        assert_eq!(editor.display().top_line, 0);
+        editor.feed_key(27).unwrap();
+        assert!(editor.feed_keys(":q\n").unwrap());
+    }
+
+    #[test]
+    fn test_prompts_read_from_the_display() {
+        // `q` on a modified buffer asks whether to save; `n` quits without
+        let mut editor = editor_with("n");
+        editor.feed_keys("draft").unwrap();
+        editor.feed_key(27).unwrap();
+        assert!(editor.feed_key('q' as i32).unwrap());
+        assert!(editor.display().status.contains("Save before quit?"));
+    }
+
+    #[test]
+    fn test_prompts_wait_past_input_timeouts() {
+        let mut editor = editor_with("");
+        editor.display.keys = [KEY_TIMEOUT, KEY_TIMEOUT, 'n' as i32].into();
+        editor.feed_keys("draft").unwrap();
+        editor.feed_key(27).unwrap();
+        assert!(editor.feed_key('q' as i32).unwrap());
+
+        // A key code past 255 is not read as the letter it truncates to
+        let mut editor = editor_with("");
+        editor.display.keys = [KEY_TIMEOUT, 'n' as i32 + 256].into();
+        editor.feed_keys("draft").unwrap();
+        editor.feed_key(27).unwrap();
+        assert!(!editor.feed_key('q' as i32).unwrap());
+    }
+
+    #[test]
+    fn test_batch_substitution_on_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("batch.txt");
+        std::fs::write(&path, "foo one\nfoo foo two").unwrap();
+        let files = vec![path.display().to_string()];
+
+        let mut editor = Editor::new(HeadlessDisplay::new(), files.clone(), false, EditorConfig::new()).unwrap();
+        let commands = [":%s/foo/bar/g", "wq", ":s/bar/never run/"].map(String::from);
+        assert!(editor.run_commands(&commands).unwrap());
+        editor.close().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "bar one\nbar bar two");
+
+        // Quitting a modified buffer asks to save, which a batch run cancels
+        let mut editor = Editor::new(HeadlessDisplay::new(), files, false, EditorConfig::new()).unwrap();
+        assert!(!editor.run_commands(&["%s/bar/baz/g".to_string(), "q".to_string()]).unwrap());
+        assert!(editor.display().messages().iter().any(|message| message.contains("Save before quit?")));
+        editor.close().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "bar one\nbar bar two");
+    }
+
+    #[test]
+    fn test_write_appends_to_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.txt");
+        std::fs::write(&path, "existing").unwrap();
+        let mut editor = editor_with("");
+        editor.feed_keys("one\ntwo\nthree").unwrap();
+
+        editor.run_commands(&[format!("w >> {}", path.display()), format!("2,3w>>{}", path.display())]).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "existing\none\ntwo\nthree\ntwo\nthree");
+        assert_eq!(editor.display().status, format!("Appended 2 lines to {}", path.display()));
+
+        let new_file = dir.path().join("new.txt");
+        editor.run_commands(&[format!("1w >> {}", new_file.display())]).unwrap();
+        assert_eq!(std::fs::read_to_string(&new_file).unwrap(), "one");
+        // The buffer is still unnamed and unsaved
+        assert!(editor.editor_ops().buffer().get_current_buffer_info().unwrap().is_modified);
+    }
+
+    #[test]
+    fn test_write_a_range_of_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("part.txt");
+        let mut editor = editor_with("");
+        editor.feed_keys("one\ntwo\nthree\nfour").unwrap();
+
+        editor.run_commands(&[format!("2,3w {}", path.display())]).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "two\nthree");
+        assert_eq!(editor.display().status, format!("2 lines written to {}", path.display()));
+
+        // An existing file is only replaced with !
+        editor.run_commands(&[format!("4w {}", path.display())]).unwrap();
+        assert!(editor.display().status.ends_with("exists (add ! to override)"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "two\nthree");
+        editor.run_commands(&[format!("$w! {}", path.display())]).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "four");
+        assert_eq!(editor.content(), "one\ntwo\nthree\nfour");
+    }
+
+    #[test]
+    fn test_read_command_output() {
+        let mut editor = editor_with("");
+        editor.feed_keys("first\nlast").unwrap();
+        editor.feed_key(27).unwrap();
+        editor.feed_keys("(").unwrap();
+
+        editor.run_commands(&["r !echo inserted".to_string()]).unwrap();
+        assert_eq!(editor.content(), "first\ninserted\nlast");
+        assert_eq!(editor.cursor(), Position::new(0, 1));
+        assert_eq!(editor.message(), None);
+        assert!(!editor.error_reported());
+
+        // One undo takes the whole output back out
+        editor.feed_keys("\x1bu").unwrap();
+        assert_eq!(editor.content(), "first\nlast");
+
+        editor.run_commands(&["r !echo partial && exit 2".to_string(), "!exit 1".to_string()]).unwrap();
+        assert_eq!(editor.content(), "first\npartial\nlast");
+        assert_eq!(editor.take_message().as_deref(), Some("shell returned 1"));
+        assert_eq!(editor.message(), None);
+        assert!(editor.error_reported());
+    }
+
+    #[test]
+    fn test_disk_changes_are_noticed_after_shell_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "one\n").unwrap();
+        let mut editor = Editor::new(MockDisplay::default(), vec![path.display().to_string()], false, EditorConfig::new()).unwrap();
+        editor.run_commands(&["set autoread".to_string()]).unwrap();
+
+        // Keys alone don't look at the disk
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+        editor.feed_keys("\x1b").unwrap();
+        assert_eq!(editor.content(), "one\n");
+        editor.run_commands(&["!true".to_string()]).unwrap();
+        assert_eq!(editor.content(), "one\ntwo\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_filter_lines_through_a_command() {
+        let mut editor = editor_with("");
+        editor.feed_keys("keep\npear\napple\nfig\nlast").unwrap();
+
+        editor.run_commands(&["2,4!sort".to_string()]).unwrap();
+        assert_eq!(editor.content(), "keep\napple\nfig\npear\nlast");
+        assert_eq!(editor.message(), Some("3 lines filtered"));
+
+        // A failing command leaves the lines alone
+        editor.run_commands(&["%!grep nothing-matches".to_string()]).unwrap();
+        assert_eq!(editor.content(), "keep\napple\nfig\npear\nlast");
+        assert_eq!(editor.message(), Some("shell returned 1"));
+
+        editor.run_commands(&["%!grep p".to_string()]).unwrap();
+        assert_eq!(editor.content(), "keep\napple\npear");
+        // Each filter is one change to undo
+        editor.feed_keys("\x1bu").unwrap();
+        assert_eq!(editor.content(), "keep\napple\nfig\npear\nlast");
+        editor.feed_keys("u").unwrap();
+        assert_eq!(editor.content(), "keep\npear\napple\nfig\nlast");
+    }
+
+    #[test]
+    fn test_undo_keeps_changes_not_copies_of_the_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("large.txt");
+        let original = vec!["abcdefghijklmnopqrstuvwxyz0123456789"; 1000].join("\n");
+        std::fs::write(&path, &original).unwrap();
+        let display = MockDisplay::default();
+        let mut editor = Editor::new(display, vec![path.display().to_string()], false, EditorConfig::new()).unwrap();
+
+        editor.feed_keys(&"x".repeat(500)).unwrap();
+        editor.feed_keys("\x1bu").unwrap();
+        assert_eq!(editor.content(), format!("{}{}", "x".repeat(499), original));
+        // Each insert kept costs its one byte, not the size of the buffer
+        let changes: Vec<&EditorAction> = editor.undo_system.actions().collect();
+        assert!(changes.len() <= 500);
+        assert!(changes.iter().all(|change| change.text_len() == 1));
+
+        editor.feed_keys("uur").unwrap();
+        assert_eq!(editor.content(), format!("{}{}", "x".repeat(498), original));
+
+        // Undo gives back a file's last newline along with the lines
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+        let mut editor = Editor::new(MockDisplay::default(), vec![path.display().to_string()], false, EditorConfig::new()).unwrap();
+        editor.feed_keys("\x1bddu").unwrap();
+        assert_eq!(editor.content(), "one\ntwo\n");
+        editor.feed_keys(":%s/o/0/g\n").unwrap();
+        assert_eq!(editor.content(), "0ne\ntw0\n");
+        editor.feed_keys("\x1bu").unwrap();
+        assert_eq!(editor.content(), "one\ntwo\n");
+    }
+
+    #[test]
+    fn test_fold_and_surround_keys_take_any_character() {
+        let mut editor = editor_with("");
+        editor.feed_keys("one\ntwo\nthree\x1bgg").unwrap();
+
+        // Left arrow after zf is not a motion, and isn't a single byte either
+        for key in [122, 102, 1003] {
+            editor.feed_key(key).unwrap();
+        }
+        assert!(editor.folds.closed_fold_at(0).is_none());
+        editor.feed_keys("zf2j").unwrap();
+        assert!(editor.folds.closed_fold_at(0).is_some());
+
+        // cs waits for a second delimiter after a multibyte first one
+        editor.feed_keys("zocsé").unwrap();
+        assert_eq!(editor.pending_keys, "csé");
+    }
+
+    #[test]
+    fn test_zf_folds_over_motions_and_selections() {
+        let mut editor = editor_with("");
+        editor.feed_keys("one\ntwo\n\nfour\nfive\x1bgg").unwrap();
+        let fold_lines = |editor: &Editor<MockDisplay>| {
+            editor.folds.folds().iter().map(|fold| (fold.start_line, fold.end_line)).collect::<Vec<_>>()
+        };
+
+        editor.feed_keys("zf}").unwrap();
+        assert_eq!(fold_lines(&editor), vec![(0, 1)]);
+        editor.folds.clear();
+        editor.feed_keys("zfip").unwrap();
+        assert_eq!(fold_lines(&editor), vec![(0, 1)]);
+        editor.folds.clear();
+        editor.feed_keys("zfG").unwrap();
+        assert_eq!(fold_lines(&editor), vec![(0, 4)]);
+        editor.folds.clear();
+
+        // In visual mode zf folds the selected lines and leaves visual mode
+        editor.feed_keys("4ggvjzf").unwrap();
+        assert_eq!(fold_lines(&editor), vec![(3, 4)]);
+        assert!(editor.folds.closed_fold_at(3).is_some());
+        assert_eq!(editor.mode, EditorMode::Command);
+    }
+
+    #[test]
+    fn test_escape_closes_the_hex_view() {
+        let mut editor = editor_with("");
+        editor.feed_keys("abc\x1b:set binary\n").unwrap();
+        assert!(editor.hex_view.is_some());
+        // Keys scroll the view rather than editing the buffer behind it
+        editor.feed_keys("xdd").unwrap();
+        assert_eq!(editor.content(), "abc");
+
+        editor.feed_key(27).unwrap();
+        assert!(editor.hex_view.is_none() && !editor.config.binary);
+        assert_eq!(editor.mode(), EditorMode::Command);
+        editor.feed_keys("x").unwrap();
+        assert_eq!(editor.content(), "ab");
+    }
+
+    #[test]
+    fn test_render_follows_the_cursor_down_a_long_buffer() {
+        let mut editor = editor_with("");
+        let text: Vec<String> = (1..=100).map(|line| line.to_string()).collect();
+        editor.feed_keys(&text.join("\n")).unwrap();
+        editor.render().unwrap();
+        // The cursor is on the last row above the status line
+        assert_eq!(editor.display().top_line, 77);
+        assert_eq!(editor.screen_cursor(), Position::new(3, 22));
+        assert!(editor.display().status.contains(&editor.status_line.format()));
+    }
+
+    #[test]
+    fn test_line_numbers_shift_the_cursor_but_not_the_column() {
+        let mut editor = editor_with("");
+        let text: Vec<String> = (1..=100).map(|line| line.to_string()).collect();
+        editor.feed_keys(&text.join("\n")).unwrap();
+        editor.render().unwrap();
+        let status = editor.display().status.clone();
+
+        // Lines 78 to 100 are in view, so the gutter is four cells wide
+        editor.run_commands(&["set number".to_string()]).unwrap();
+        editor.render().unwrap();
+        assert_eq!(editor.screen_cursor(), Position::new(7, 22));
+        assert_eq!(editor.display().status, status);
+    }
+
+    #[test]
+    fn test_search_and_repeat_with_n() {
+        let mut editor = editor_with("");
+        editor.feed_keys("one Two\ntwo\nthree two").unwrap();
+        editor.feed_keys("\x1b(").unwrap();
+        editor.run_commands(&["set incsearch".to_string()]).unwrap();
+        editor.feed_key(27).unwrap();
+
+        // The cursor follows the pattern as it is typed
+        editor.feed_keys("/tw").unwrap();
+        assert_eq!((editor.mode(), editor.cursor()), (EditorMode::Search, Position::new(0, 1)));
+        editor.feed_key(10).unwrap();
+        assert_eq!((editor.mode(), editor.cursor()), (EditorMode::Command, Position::new(0, 1)));
+
+        editor.feed_keys("n").unwrap();
+        assert_eq!(editor.cursor(), Position::new(6, 2));
+        editor.feed_keys("n").unwrap();
+        assert_eq!(editor.cursor(), Position::new(0, 1));
+        editor.feed_keys("N").unwrap();
+        assert_eq!(editor.cursor(), Position::new(6, 2));
+
+        editor.run_commands(&["set ignorecase".to_string()]).unwrap();
+        editor.feed_keys("\x1bn").unwrap();
+        assert_eq!(editor.cursor(), Position::new(4, 0));
+    }
+
+    #[test]
+    fn test_go_to_line() {
+        let mut editor = editor_with("");
+        let text: Vec<String> = (1..=50).map(|line| format!("line {}", line)).collect();
+        editor.feed_keys(&text.join("\n")).unwrap();
+        editor.feed_key(27).unwrap();
+
+        editor.run_commands(&["20".to_string()]).unwrap();
+        assert_eq!(editor.cursor(), Position::new(0, 19));
+        editor.run_commands(&["999".to_string()]).unwrap();
+        assert_eq!(editor.cursor(), Position::new(0, 49));
+
+        editor.feed_keys("\x1bgg").unwrap();
+        assert_eq!(editor.cursor(), Position::origin());
+        editor.feed_keys("G").unwrap();
+        assert_eq!(editor.cursor(), Position::new(0, 49));
+        editor.feed_keys("7gg").unwrap();
+        assert_eq!(editor.cursor(), Position::new(0, 6));
+        editor.feed_keys("12G").unwrap();
+        assert_eq!(editor.cursor(), Position::new(0, 11));
+        // gu still waits for its motion after a g
+        editor.feed_keys("guiw").unwrap();
+        assert_eq!(editor.content().lines().nth(11), Some("line 12"));
+    }
+}
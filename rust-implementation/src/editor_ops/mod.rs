@@ -1,12 +1,57 @@
-use crate::core::{EditorError, EditorOperations, Position, Result, TextBuffer};
-use std::collections::HashMap;
+use crate::core::{EditorError, EditorOperations, Position, Result, TextBuffer, TAB_SIZE};
+use crate::search::match_ranges;
+use crate::snippet::{Snippet, SnippetSession};
+
+mod motion;
+mod register;
+pub use motion::{Motion, MotionRange, Operator};
+pub use register::{Register, Registers, NUMBERED_REGISTERS};
+
+/// How the clipboard text was taken, which decides where it is put back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClipboardKind {
+    /// Part of a line, pasted after the cursor's character.
+    #[default]
+    Charwise,
+    /// Whole lines, pasted below the cursor's line.
+    Linewise,
+}
+
+/// What `showmatch` highlights for a bracket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BracketMatch {
+    /// Where the bracket's partner is.
+    Partner(Position),
+    /// Where a bracket without a partner is.
+    Unmatched(Position),
+}
 
 pub struct EditorOps<T: TextBuffer> {
     buffer: T,
     cursor: Position,
-    clipboard: String,
+    registers: Registers,
+    /// The register picked with `"x` for the next yank, delete or put.
+    register: Option<char>,
     selection_start: Option<usize>,
     screen_size: (usize, usize),
+    /// The first line on screen, which follows the cursor.
+    scroll_offset: usize,
+    /// The column vertical motion returns to, so moving through a short
+    /// line does not lose the column the cursor started in.
+    desired_column: usize,
+    /// Spaces inserted for a Tab.
+    tab_size: usize,
+    /// Whether Tab inserts spaces rather than a tab character.
+    expandtab: bool,
+    /// Columns `>` and `<` shift lines by.
+    shift_width: usize,
+    /// Whether every edit is refused, as with `--readonly`.
+    readonly: bool,
+    /// Whether `find_next` and `find_previous` tell upper and lower case
+    /// apart.
+    search_case_sensitive: bool,
+    /// Whether searches go on from the other end of the buffer.
+    search_wraps: bool,
 }
 
 impl<T: TextBuffer> EditorOps<T> {
@@ -14,10 +59,137 @@ impl<T: TextBuffer> EditorOps<T> {
         Self {
             buffer,
             cursor: Position::origin(),
-            clipboard: String::new(),
+            registers: Registers::new(),
+            register: None,
             selection_start: None,
             screen_size,
+            scroll_offset: 0,
+            desired_column: 0,
+            tab_size: TAB_SIZE,
+            expandtab: true,
+            shift_width: TAB_SIZE,
+            readonly: false,
+            search_case_sensitive: true,
+            search_wraps: true,
+        }
+    }
+
+    pub fn set_readonly(&mut self, readonly: bool) {
+        self.readonly = readonly;
+    }
+
+    /// Whether edits are allowed. Every edit fails when they are not.
+    pub fn can_modify(&self) -> bool {
+        self.check_modifiable().is_ok()
+    }
+
+    /// Why an edit would be refused: everything is read-only, or the
+    /// current buffer is not modifiable.
+    pub fn check_modifiable(&self) -> Result<()> {
+        if self.readonly {
+            return Err(EditorError::ReadOnly);
+        }
+        if !self.buffer.is_modifiable() {
+            return Err(EditorError::NotModifiable);
+        }
+        Ok(())
+    }
+
+    pub fn tab_size(&self) -> usize {
+        self.tab_size
+    }
+
+    pub fn set_tab_size(&mut self, tab_size: usize) {
+        self.tab_size = tab_size.max(1);
+    }
+
+    pub fn set_expandtab(&mut self, expandtab: bool) {
+        self.expandtab = expandtab;
+    }
+
+    pub fn set_shift_width(&mut self, shift_width: usize) {
+        self.shift_width = shift_width.max(1);
+    }
+
+    /// Inserts `tab_size` spaces at the cursor, or a tab character when
+    /// `expandtab` is off.
+    pub fn insert_tab(&mut self) -> Result<()> {
+        if !self.expandtab {
+            return self.type_text("\t");
+        }
+        self.type_text(&" ".repeat(self.tab_size))
+    }
+
+    /// Inserts `text` at the cursor as one edit and leaves the cursor just
+    /// after it: on the last inserted line when `text` has line breaks,
+    /// and past any multibyte characters. Pasting, expressions and tabs all
+    /// go through here, so callers never work out offsets themselves.
+    pub fn type_text(&mut self, text: &str) -> Result<()> {
+        self.check_modifiable()?;
+        if text.is_empty() {
+            return Ok(());
         }
+        self.constrain_cursor();
+        let offset = self.position_to_buffer_offset();
+        let start = self.buffer_offset_to_position(offset);
+        self.buffer.insert_str(offset, text)?;
+
+        self.cursor = match text.rsplit_once('\n') {
+            Some((before, last)) => Position::new(last.len(), start.y + before.matches('\n').count() + 1),
+            None => Position::new(start.x + text.len(), start.y),
+        };
+        self.settle_cursor();
+        Ok(())
+    }
+
+    /// Adds `text` at the end of the buffer, as command output and `:r`
+    /// do, and moves the cursor to the new end.
+    pub fn append_text(&mut self, text: &str) -> Result<()> {
+        self.check_modifiable()?;
+        self.buffer.append(text)?;
+        let last_line = self.buffer.line_count().saturating_sub(1);
+        self.cursor = Position::new(self.buffer.line_length(last_line), last_line);
+        self.settle_cursor();
+        Ok(())
+    }
+
+    /// The run of keyword characters just before the cursor, which is what
+    /// a snippet is triggered by.
+    pub fn word_before_cursor(&self) -> &str {
+        let before = self.text_before_cursor();
+        let start = before
+            .char_indices()
+            .rev()
+            .take_while(|&(_, ch)| word_class(ch) == WordClass::Keyword)
+            .last()
+            .map_or(before.len(), |(byte, _)| byte);
+        &before[start..]
+    }
+
+    /// Replaces the `trigger_length` bytes before the cursor with
+    /// `snippet`, indenting its lines like the cursor line, and puts the
+    /// cursor at the end of the first stop's default.
+    pub fn insert_snippet(&mut self, trigger_length: usize, snippet: &Snippet) -> Result<SnippetSession> {
+        self.check_modifiable()?;
+        let end = self.offset_of(Position::new(0, self.cursor.y)) + self.text_before_cursor().len();
+        let origin = end.saturating_sub(trigger_length);
+        self.buffer.delete_range(origin..end)?;
+
+        let line = self.buffer.get_line(self.cursor.y).unwrap_or("");
+        let indent = line[..line.len() - line.trim_start().len()].to_string();
+        let expansion = snippet.expand(&indent);
+        self.cursor = self.buffer_offset_to_position(origin);
+        self.type_text(&expansion.text)?;
+
+        let session = SnippetSession::new(origin, &expansion, self.buffer.content().len());
+        self.jump_to_offset(session.current().1);
+        Ok(session)
+    }
+
+    /// Moves the cursor to a byte offset into the buffer.
+    pub fn jump_to_offset(&mut self, offset: usize) {
+        self.cursor = self.buffer_offset_to_position(offset);
+        self.settle_cursor();
     }
 
     pub fn buffer(&self) -> &T {
@@ -28,243 +200,1175 @@ impl<T: TextBuffer> EditorOps<T> {
         &mut self.buffer
     }
 
+    /// Puts the cursor back at the top and drops the selection, for when
+    /// the buffer shown has been replaced. The registers are kept.
+    pub fn reset_view(&mut self, screen_size: (usize, usize)) {
+        self.cursor = Position::origin();
+        self.selection_start = None;
+        self.desired_column = 0;
+        self.screen_size = screen_size;
+        self.scroll_offset = 0;
+    }
+
     pub fn set_screen_size(&mut self, size: (usize, usize)) {
         self.screen_size = size;
+        self.scroll_to_cursor();
     }
 
-    pub fn clipboard(&self) -> &str {
-        &self.clipboard
+    pub fn scroll_offset(&self) -> usize {
+        self.scroll_offset
     }
 
-    pub fn has_selection(&self) -> bool {
-        self.selection_start.is_some()
+    /// The buffer lines on screen, below which the status line is drawn.
+    pub fn visible_range(&self) -> std::ops::Range<usize> {
+        let height = self.screen_size.1.saturating_sub(1).max(1);
+        let end = (self.scroll_offset + height).min(self.buffer.line_count());
+        self.scroll_offset.min(end)..end
     }
 
-    pub fn start_selection(&mut self) {
-        self.selection_start = Some(self.position_to_buffer_offset());
+    /// Scrolls just enough to bring the cursor line on screen: down by as
+    /// many lines as it is below the bottom, or up to it when it is above
+    /// the top.
+    pub fn scroll_to_cursor(&mut self) {
+        let height = self.screen_size.1.saturating_sub(1).max(1);
+        if self.cursor.y < self.scroll_offset {
+            self.scroll_offset = self.cursor.y;
+        } else if self.cursor.y >= self.scroll_offset + height {
+            self.scroll_offset = self.cursor.y + 1 - height;
+        }
     }
 
-    pub fn clear_selection(&mut self) {
-        self.selection_start = None;
+    pub fn desired_column(&self) -> usize {
+        self.desired_column
     }
 
-    pub fn get_selection_range(&self) -> Option<(usize, usize)> {
-        self.selection_start.map(|start| {
-            let end = self.position_to_buffer_offset();
-            if start <= end {
-                (start, end)
-            } else {
-                (end, start)
-            }
-        })
+    /// Moves to `line`, going back to the desired column where the line is
+    /// long enough.
+    pub fn move_to_line(&mut self, line: usize) {
+        self.cursor = Position::new(self.desired_column, line);
+        self.constrain_cursor();
     }
 
-    fn position_to_buffer_offset(&self) -> usize {
-        let mut offset = 0;
-        for line_idx in 0..self.cursor.y.min(self.buffer.line_count()) {
-            offset += self.buffer.line_length(line_idx) + 1; // +1 for newline
-        }
-        offset + self.cursor.x.min(self.buffer.line_length(self.cursor.y))
+    /// Moves to the 1-based `line` and `column`, clamped to the buffer.
+    /// The column becomes the desired column even when the line is shorter.
+    pub fn goto(&mut self, line: usize, column: usize) {
+        self.cursor = Position::new(column.saturating_sub(1), line.saturating_sub(1));
+        self.constrain_cursor();
+        self.desired_column = column.saturating_sub(1);
     }
 
-    fn buffer_offset_to_position(&self, offset: usize) -> Position {
-        let mut current_offset = 0;
-
-        for (line_idx, _) in (0..self.buffer.line_count()).enumerate() {
-            let line_len = self.buffer.line_length(line_idx);
+    /// Moves to the start of the 1-based `line`, clamped to the buffer, as
+    /// `:42`, `G` and `gg` do, and scrolls to put it in the middle of the
+    /// screen where the buffer is long enough.
+    pub fn goto_line(&mut self, line: usize) {
+        self.cursor = Position::new(0, line.saturating_sub(1));
+        self.settle_cursor();
+        let height = self.screen_size.1.saturating_sub(1).max(1);
+        let last_top = self.buffer.line_count().saturating_sub(height);
+        self.scroll_offset = self.cursor.y.saturating_sub(height / 2).min(last_top);
+    }
 
-            if current_offset + line_len >= offset {
-                return Position::new(offset - current_offset, line_idx);
-            }
+    /// `ignorecase` and `wrapscan` for `find_next` and `find_previous`.
+    pub fn set_search_options(&mut self, case_sensitive: bool, wraps: bool) {
+        self.search_case_sensitive = case_sensitive;
+        self.search_wraps = wraps;
+    }
 
-            current_offset += line_len + 1; // +1 for newline
-        }
+    /// `n`: where the first match of `pattern` after `from` starts, going
+    /// on from the top of the buffer if nothing matches below.
+    pub fn find_next(&self, pattern: &str, from: Position) -> Option<Position> {
+        self.find_match(pattern, from, true)
+    }
 
-        // If offset is beyond buffer, return end position
-        let last_line = self.buffer.line_count().saturating_sub(1);
-        Position::new(self.buffer.line_length(last_line), last_line)
+    /// `N`: where the last match of `pattern` before `from` starts, going
+    /// on from the bottom of the buffer if nothing matches above.
+    pub fn find_previous(&self, pattern: &str, from: Position) -> Option<Position> {
+        self.find_match(pattern, from, false)
     }
 
-    fn constrain_cursor(&mut self) {
+    fn find_match(&self, pattern: &str, from: Position, forward: bool) -> Option<Position> {
         let line_count = self.buffer.line_count();
-        if line_count == 0 {
-            self.cursor = Position::origin();
-            return;
+        if line_count == 0 || pattern.is_empty() {
+            return None;
         }
+        let from_line = from.y.min(line_count - 1);
 
-        // Constrain Y to valid lines
-        if self.cursor.y >= line_count {
-            self.cursor.y = line_count - 1;
+        // The last step comes back round to the starting line
+        for step in 0..=line_count {
+            let past_end = if forward { from_line + step >= line_count } else { step > from_line };
+            if past_end && !self.search_wraps {
+                break;
+            }
+            let line = if forward {
+                (from_line + step) % line_count
+            } else {
+                (from_line + line_count - step % line_count) % line_count
+            };
+            let text = self.buffer.get_line(line).unwrap_or("");
+            let mut starts = match_ranges(text, pattern, self.search_case_sensitive).into_iter().map(|(start, _)| start);
+            let hit = match (forward, step) {
+                (true, 0) => starts.find(|&start| start > from.x),
+                (true, step) if step == line_count => starts.find(|&start| start <= from.x),
+                (true, _) => starts.next(),
+                (false, 0) => starts.rfind(|&start| start < from.x),
+                (false, step) if step == line_count => starts.rfind(|&start| start >= from.x),
+                (false, _) => starts.next_back(),
+            };
+            if let Some(column) = hit {
+                return Some(Position::new(column, line));
+            }
         }
+        None
+    }
+
+    /// Moves to the next empty line after the cursor's paragraph, like
+    /// vim's `}`, or back to the empty line before it for `{`. Without one
+    /// the cursor goes to the end of the last line or the start of the
+    /// first.
+    pub fn move_paragraph(&mut self, forward: bool) {
+        self.cursor = self.paragraph_target(self.cursor, forward);
+        self.settle_cursor();
+    }
+
+    /// Where `move_paragraph` goes from `from`.
+    fn paragraph_target(&self, from: Position, forward: bool) -> Position {
+        let empty = |y: usize| self.buffer.line_length(y) == 0;
+        let line_count = self.buffer.line_count();
+        let boundary = if forward {
+            (from.y + 1..line_count).find(|&y| empty(y) && !empty(y - 1))
+        } else {
+            (0..from.y).rev().find(|&y| empty(y) && !empty(y + 1))
+        };
 
-        // Constrain X to line length
-        let line_length = self.buffer.line_length(self.cursor.y);
-        if self.cursor.x > line_length {
-            self.cursor.x = line_length;
+        match boundary {
+            Some(y) => Position::new(0, y),
+            None if forward => {
+                let last = line_count.saturating_sub(1);
+                Position::new(self.buffer.line_length(last), last)
+            }
+            None => Position::origin(),
         }
     }
-}
 
-impl<T: TextBuffer> EditorOperations for EditorOps<T> {
-    fn insert_char(&mut self, ch: char) -> Result<()> {
-        let offset = self.position_to_buffer_offset();
-        self.buffer.insert(offset, ch)?;
+    /// Moves to the start of the next sentence, like vim's `)`, or of the
+    /// current or previous one for `(`. Empty lines count as sentences of
+    /// their own. Past the last sentence the cursor goes to the end of the
+    /// buffer, and before the first to its start.
+    pub fn move_sentence(&mut self, forward: bool) {
+        self.cursor = self.sentence_target(self.cursor, forward);
+        self.settle_cursor();
+    }
 
-        if ch == '\n' {
-            self.cursor.y += 1;
-            self.cursor.x = 0;
+    /// Where `move_sentence` goes from `from`.
+    fn sentence_target(&self, from: Position, forward: bool) -> Position {
+        let cursor = (from.y, from.x);
+        let mut target = None;
+        if forward {
+            self.sentence_starts(from.y, |start| {
+                if (start.y, start.x) > cursor {
+                    target = Some(start);
+                }
+                target.is_none()
+            });
         } else {
-            self.cursor.x += 1;
+            // Sentences never run across an empty line
+            let first = (0..from.y).rev().find(|&y| self.buffer.line_length(y) == 0).unwrap_or(0);
+            self.sentence_starts(first, |start| {
+                let before = (start.y, start.x) < cursor;
+                if before {
+                    target = Some(start);
+                }
+                before
+            });
         }
 
-        self.constrain_cursor();
-        Ok(())
+        target.unwrap_or_else(|| {
+            if forward {
+                let last = self.buffer.line_count().saturating_sub(1);
+                Position::new(self.buffer.line_length(last), last)
+            } else {
+                Position::origin()
+            }
+        })
     }
 
-    fn delete_char(&mut self) -> Result<()> {
-        if self.cursor.x == 0 && self.cursor.y == 0 {
-            return Ok(()); // Nothing to delete at start of buffer
+    /// Calls `visit` with each sentence start from line `first` on, in
+    /// order, while it returns true. A sentence starts at the first
+    /// character of a paragraph and after `.`, `!` or `?`, with any closing
+    /// brackets or quotes, followed by a blank or the end of the line.
+    fn sentence_starts(&self, first: usize, mut visit: impl FnMut(Position) -> bool) {
+        let ends_sentence = |line: &str| {
+            line.trim_end().trim_end_matches([')', ']', '"', '\'']).ends_with(['.', '!', '?'])
+        };
+        let mut ended = first == 0
+            || self.buffer.get_line(first - 1).is_none_or(|line| line.is_empty() || ends_sentence(line));
+
+        for y in first..self.buffer.line_count() {
+            let line = self.buffer.get_line(y).unwrap_or("");
+            if line.is_empty() {
+                if !visit(Position::new(0, y)) {
+                    return;
+                }
+                ended = true;
+                continue;
+            }
+
+            let mut after_terminator = false;
+            for (x, ch) in line.char_indices() {
+                if ch.is_whitespace() {
+                    ended |= after_terminator;
+                    after_terminator = false;
+                    continue;
+                }
+                if ended {
+                    if !visit(Position::new(x, y)) {
+                        return;
+                    }
+                    ended = false;
+                }
+                if matches!(ch, '.' | '!' | '?') {
+                    after_terminator = true;
+                } else if !matches!(ch, ')' | ']' | '"' | '\'') {
+                    after_terminator = false;
+                }
+            }
+            ended |= after_terminator;
         }
+    }
 
-        let offset = if self.cursor.x == 0 {
-            // Delete newline at beginning of line
-            self.cursor.y -= 1;
-            self.cursor.x = self.buffer.line_length(self.cursor.y);
-            self.position_to_buffer_offset()
-        } else {
-            // Delete character before cursor
-            self.cursor.x -= 1;
-            self.position_to_buffer_offset()
-        };
+    /// The `Ctrl-G` summary of the cursor and buffer, formatted like vim's:
+    /// `"notes.txt" [Modified] line 3 of 12 --25%-- col 5, 340 bytes`.
+    pub fn file_info(&self, name: &str, modified: bool) -> String {
+        let lines = self.buffer.line_count().max(1);
+        let line = (self.cursor.y + 1).min(lines);
+        format!(
+            "\"{}\"{} line {} of {} --{}%-- col {}, {} bytes",
+            name,
+            if modified { " [Modified]" } else { "" },
+            line,
+            lines,
+            line * 100 / lines,
+            self.cursor.x + 1,
+            self.buffer.length()
+        )
+    }
 
-        self.buffer.delete(offset)?;
-        self.constrain_cursor();
-        Ok(())
+    /// The character offsets `(start, end)` of the word under the cursor, for
+    /// the `iw` and `aw` text objects. A word is a run of keyword characters,
+    /// of other punctuation, or of blanks; `around` also takes the blanks
+    /// after the word, or before it when the word ends the line.
+    pub fn word_object(&self, around: bool) -> (usize, usize) {
+        let line_start = self.offset_of(Position::new(0, self.cursor.y));
+        let line = self.buffer.get_line(self.cursor.y).unwrap_or("");
+        let chars: Vec<(usize, char)> = line.char_indices().collect();
+        if chars.is_empty() {
+            let start = self.char_index(line_start);
+            return (start, start);
+        }
+        // Past the end of the line counts as on its last character
+        let cursor = chars
+            .iter()
+            .position(|&(byte, _)| byte >= self.cursor.x)
+            .unwrap_or(chars.len() - 1);
+
+        let class = word_class(chars[cursor].1);
+        let same_class = |index: &usize| word_class(chars[*index].1) == class;
+        let mut start = (0..cursor).rev().take_while(same_class).last().unwrap_or(cursor);
+        let mut end = (cursor..chars.len()).take_while(same_class).last().unwrap_or(cursor) + 1;
+
+        if around && class != WordClass::Blank {
+            let is_blank = |index: &usize| chars[*index].1.is_whitespace();
+            let trailing = (end..chars.len()).take_while(is_blank).count();
+            if trailing > 0 {
+                end += trailing;
+            } else {
+                start -= (0..start).rev().take_while(is_blank).count();
+            }
+        }
+
+        let byte = |index: usize| chars.get(index).map_or(line.len(), |&(byte, _)| byte);
+        (self.char_index(line_start + byte(start)), self.char_index(line_start + byte(end)))
     }
 
-    fn move_cursor(&mut self, dx: i32, dy: i32) -> Result<()> {
-        let new_x = (self.cursor.x as i32 + dx).max(0) as usize;
-        let new_y = (self.cursor.y as i32 + dy).max(0) as usize;
+    /// The bracket that pairs with the one at `position`, searching forward
+    /// from an opening bracket and backward from a closing one, skipping
+    /// nested pairs. `None` off a bracket or when it is unbalanced.
+    pub fn match_bracket(&self, position: Position) -> Option<Position> {
+        let ch = self.buffer.get_line(position.y)?.get(position.x..)?.chars().next()?;
+        let (open, close) = bracket_pair(ch)?;
+        let forward = ch == open;
+        let (same, other) = if forward { (open, close) } else { (close, open) };
 
-        self.cursor = Position::new(new_x, new_y);
-        self.constrain_cursor();
-        Ok(())
+        let mut depth = 0;
+        for (pos, ch) in self.chars_from(position, forward) {
+            if ch == same {
+                depth += 1;
+            } else if ch == other {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(pos);
+                }
+            }
+        }
+        None
     }
 
-    fn move_to_position(&mut self, position: Position) -> Result<()> {
-        self.cursor = position;
-        self.constrain_cursor();
+    /// The `showmatch` highlight for the bracket at `position`, or `None`
+    /// when there is no bracket there.
+    pub fn bracket_match(&self, position: Position) -> Option<BracketMatch> {
+        let ch = self.buffer.get_line(position.y)?.get(position.x..)?.chars().next()?;
+        bracket_pair(ch)?;
+        Some(match self.match_bracket(position) {
+            Some(partner) => BracketMatch::Partner(partner),
+            None => BracketMatch::Unmatched(position),
+        })
+    }
+
+    /// The character offsets of the `i(`, `a"` and similar text objects: the
+    /// text inside the pair of `delimiter`s around the cursor, and with
+    /// `around` the delimiters too. Brackets may span lines and nest;
+    /// quotes pair up within the cursor's line. `None` outside any pair.
+    pub fn pair_object(&self, delimiter: char, around: bool) -> Option<(usize, usize)> {
+        let (open, close) = match bracket_pair(delimiter) {
+            Some(pair) => (self.enclosing_bracket(pair)?, None),
+            None if matches!(delimiter, '"' | '\'' | '`') => {
+                let (open, close) = self.enclosing_quotes(delimiter)?;
+                (open, Some(close))
+            }
+            None => return None,
+        };
+        let close = match close {
+            Some(close) => close,
+            None => self.match_bracket(open)?,
+        };
+
+        let (start, end) = (self.char_offset_of(open), self.char_offset_of(close));
+        Some(if around { (start, end + 1) } else { (start + 1, end) })
+    }
+
+    /// Wraps the characters from `start` to `end` in `open` and `close`, as
+    /// `S)` does for a selection, leaving the cursor on `open`.
+    pub fn surround(&mut self, (start, end): (usize, usize), open: char, close: char) -> Result<()> {
+        self.check_modifiable()?;
+        let (start, end) = (self.byte_index(start), self.byte_index(end.max(start)));
+        self.buffer.insert(end, close)?;
+        self.buffer.insert(start, open)?;
+        self.cursor = self.buffer_offset_to_position(start);
+        self.settle_cursor();
         Ok(())
     }
 
-    fn get_cursor_position(&self) -> Position {
-        self.cursor
+    /// `ds(`: removes the pair of `delimiter`s around the cursor, keeping
+    /// what they held. `false` when the cursor is in no such pair.
+    pub fn delete_surround(&mut self, delimiter: char) -> Result<bool> {
+        self.replace_surround(delimiter, None)
     }
 
-    fn copy_selection(&mut self, start: usize, end: usize) -> Result<String> {
-        if start >= self.buffer.length() || end > self.buffer.length() || start >= end {
-            return Err(EditorError::InvalidOperation("Invalid selection range".to_string()));
+    /// `cs"'`: swaps the pair of `from`s around the cursor for the pair
+    /// `to` belongs to. `false` when there is no such pair or `to` is not
+    /// a bracket or quote.
+    pub fn change_surround(&mut self, from: char, to: char) -> Result<bool> {
+        match delimiter_pair(to) {
+            Some(pair) => self.replace_surround(from, Some(pair)),
+            None => Ok(false),
         }
+    }
 
-        let content = self.buffer.content();
-        let selected = content.chars().skip(start).take(end - start).collect::<String>();
-        self.clipboard = selected.clone();
-        Ok(selected)
+    fn replace_surround(&mut self, delimiter: char, with: Option<(char, char)>) -> Result<bool> {
+        self.check_modifiable()?;
+        let Some((start, end)) = self.pair_object(delimiter, true) else {
+            return Ok(false);
+        };
+        // The closing delimiter first, so the opening one's offset holds
+        let (start, end) = (self.byte_index(start), self.byte_index(end - 1));
+        self.buffer.delete(end)?;
+        self.buffer.delete(start)?;
+        if let Some((open, close)) = with {
+            self.buffer.insert(end - 1, close)?;
+            self.buffer.insert(start, open)?;
+        }
+        self.cursor = self.buffer_offset_to_position(start);
+        self.settle_cursor();
+        Ok(true)
     }
 
-    fn cut_selection(&mut self, start: usize, end: usize) -> Result<String> {
-        let selected = self.copy_selection(start, end)?;
+    /// The opening bracket of the innermost `pair` holding the cursor, which
+    /// may be on either bracket of it.
+    fn enclosing_bracket(&self, (open, close): (char, char)) -> Option<Position> {
+        let mut depth = 0;
+        for (pos, ch) in self.chars_from(self.cursor, false) {
+            if ch == close && pos != self.cursor {
+                depth += 1;
+            } else if ch == open {
+                if depth == 0 {
+                    return Some(pos);
+                }
+                depth -= 1;
+            }
+        }
+        None
+    }
 
-        // Delete the selected text
-        for _ in start..end {
-            if start < self.buffer.length() {
-                self.buffer.delete(start)?;
+    /// The positions of the `quote`s around the cursor on its line, taking
+    /// quotes left to right in pairs and skipping escaped ones.
+    fn enclosing_quotes(&self, quote: char) -> Option<(Position, Position)> {
+        let line = self.buffer.get_line(self.cursor.y)?;
+        let mut quotes = Vec::new();
+        let mut escaped = false;
+        for (x, ch) in line.char_indices() {
+            if ch == quote && !escaped {
+                quotes.push(x);
             }
+            escaped = ch == '\\' && !escaped;
         }
 
-        // Adjust cursor position
-        self.cursor = self.buffer_offset_to_position(start);
-        self.constrain_cursor();
+        quotes
+            .chunks_exact(2)
+            .find(|pair| pair[0] <= self.cursor.x && self.cursor.x <= pair[1])
+            .map(|pair| (Position::new(pair[0], self.cursor.y), Position::new(pair[1], self.cursor.y)))
+    }
 
-        Ok(selected)
+    /// Deletes `count` characters from the one under the cursor towards the
+    /// end of its line, like vim's `x`, and yanks them. Past the end of the
+    /// line the cursor counts as on its last character, and it ends up on
+    /// the character after the deleted ones, or on the new last character.
+    pub fn delete_under_cursor(&mut self, count: usize) -> Result<String> {
+        let columns = self.char_columns(self.cursor.y);
+        let Some(last) = columns.len().checked_sub(1) else {
+            return Ok(String::new());
+        };
+        let start = columns.iter().position(|&x| x >= self.cursor.x).unwrap_or(last);
+        let deleted = self.delete_in_line(start, (start + count).min(columns.len()))?;
+
+        let columns = self.char_columns(self.cursor.y);
+        self.cursor.x = columns.get(start).or(columns.last()).copied().unwrap_or(0);
+        self.settle_cursor();
+        Ok(deleted)
     }
 
-    fn paste(&mut self, text: &str) -> Result<()> {
+    /// The Delete key: removes the character at the cursor, or at the end of
+    /// a line joins the next line onto it. The cursor stays put. Returns
+    /// whether anything was deleted, which it is not at the end of the
+    /// buffer.
+    pub fn delete_forward(&mut self) -> Result<bool> {
+        self.check_modifiable()?;
+        let last_line = self.buffer.line_count().saturating_sub(1);
+        let at_line_end = self.cursor.x >= self.buffer.line_length(self.cursor.y);
+        if at_line_end && self.cursor.y >= last_line {
+            return Ok(false);
+        }
+
         let offset = self.position_to_buffer_offset();
+        self.buffer.delete(offset)?;
+        self.cursor = self.buffer_offset_to_position(offset);
+        self.settle_cursor();
+        Ok(true)
+    }
 
-        for ch in text.chars() {
-            self.buffer.insert(offset + (self.position_to_buffer_offset() - offset), ch)?;
-            if ch == '\n' {
-                self.cursor.y += 1;
-                self.cursor.x = 0;
-            } else {
-                self.cursor.x += 1;
+    /// Deletes up to `count` characters before the cursor on its line, like
+    /// vim's `X`, and yanks them.
+    pub fn delete_before_cursor(&mut self, count: usize) -> Result<String> {
+        self.constrain_cursor();
+        let columns = self.char_columns(self.cursor.y);
+        let end = columns.iter().position(|&x| x >= self.cursor.x).unwrap_or(columns.len());
+        let start = end.saturating_sub(count);
+        let deleted = self.delete_in_line(start, end)?;
+
+        self.cursor.x = columns.get(start).copied().unwrap_or(0);
+        self.settle_cursor();
+        Ok(deleted)
+    }
+
+    /// Ctrl-W while typing: deletes back over any blanks before the cursor
+    /// and then the word before them, like a shell. Returns whether
+    /// anything was deleted, which it is not at the start of a line.
+    pub fn delete_word_before(&mut self) -> Result<bool> {
+        let before = self.text_before_cursor();
+        let mut chars = before.char_indices().rev().skip_while(|&(_, ch)| ch.is_whitespace()).peekable();
+        let start = match chars.peek() {
+            Some(&(_, ch)) => {
+                let class = word_class(ch);
+                chars.take_while(|&(_, ch)| word_class(ch) == class).last().map_or(0, |(x, _)| x)
             }
+            None => 0,
+        };
+        self.delete_back_to(start)
+    }
+
+    /// Ctrl-U while typing: deletes from the start of the line to the
+    /// cursor. Returns whether anything was deleted.
+    pub fn delete_to_line_start(&mut self) -> Result<bool> {
+        self.delete_back_to(0)
+    }
+
+    /// Deletes the cursor line from byte `column` up to the cursor, which
+    /// is left at `column`. Unlike `x` and `X` this yanks nothing.
+    fn delete_back_to(&mut self, column: usize) -> Result<bool> {
+        self.check_modifiable()?;
+        let end = self.text_before_cursor().len();
+        if column >= end {
+            return Ok(false);
         }
+        let line_start = self.offset_of(Position::new(0, self.cursor.y));
+        self.buffer.delete_range(line_start + column..line_start + end)?;
+        self.cursor.x = column;
+        self.settle_cursor();
+        Ok(true)
+    }
 
-        self.constrain_cursor();
-        Ok(())
+    /// The cursor line up to the cursor. A cursor inside a multibyte
+    /// character counts from the start of that character.
+    fn text_before_cursor(&self) -> &str {
+        let line = self.buffer.get_line(self.cursor.y).unwrap_or("");
+        let mut end = self.cursor.x.min(line.len());
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        &line[..end]
     }
-}
 
-pub struct ClipboardManager {
-    clipboard: String,
-}
+    /// Removes characters `start..end` of the cursor line, counted in
+    /// characters, into the registers.
+    fn delete_in_line(&mut self, start: usize, end: usize) -> Result<String> {
+        self.check_modifiable()?;
+        let line = self.buffer.get_line(self.cursor.y).unwrap_or("");
+        let deleted: String = line.chars().skip(start).take(end - start).collect();
+        let offset = self.offset_of(Position::new(0, self.cursor.y))
+            + line.char_indices().nth(start).map_or(line.len(), |(x, _)| x);
 
-impl ClipboardManager {
-    pub fn new() -> Self {
-        Self {
-            clipboard: String::new(),
+        self.buffer.delete_range(offset..offset + deleted.len())?;
+        if !deleted.is_empty() {
+            self.store_deleted(deleted.clone(), ClipboardKind::Charwise);
         }
+        Ok(deleted)
     }
 
-    pub fn copy(&mut self, text: String) {
-        self.clipboard = text;
+    /// The byte column of each character on `line`.
+    fn char_columns(&self, line: usize) -> Vec<usize> {
+        self.buffer
+            .get_line(line)
+            .map(|text| text.char_indices().map(|(x, _)| x).collect())
+            .unwrap_or_default()
     }
 
-    pub fn paste(&self) -> &str {
-        &self.clipboard
+    /// The unnamed register's text, which `p` puts.
+    pub fn clipboard(&self) -> &str {
+        &self.registers.unnamed().text
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.clipboard.is_empty()
+    pub fn clipboard_kind(&self) -> ClipboardKind {
+        self.registers.unnamed().kind
     }
-}
 
-impl Default for ClipboardManager {
-    fn default() -> Self {
-        Self::new()
+    pub fn registers(&self) -> &Registers {
+        &self.registers
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::buffer::Buffer;
+    /// `"name`: the register the next yank, delete or put uses instead of
+    /// the unnamed one. `false` for a name that is no register.
+    pub fn select_register(&mut self, name: char) -> bool {
+        self.register = Some(name).filter(|&name| Registers::is_register(name));
+        self.register.is_some()
+    }
 
-    #[test]
-    fn test_insert_and_move_cursor() {
-        let buffer = Buffer::new();
-        let mut ops = EditorOps::new(buffer, (80, 24));
+    /// Stores yanked text, as `y` does. Line-wise text is whole lines,
+    /// each ending in a newline.
+    pub fn set_clipboard(&mut self, text: String, kind: ClipboardKind) {
+        let name = self.register.take();
+        self.registers.yank(name, Register::new(text, kind));
+    }
 
-        ops.insert_char('H').unwrap();
-        ops.insert_char('i').unwrap();
+    /// Stores deleted or changed text, which also shifts it into the
+    /// numbered registers.
+    fn store_deleted(&mut self, text: String, kind: ClipboardKind) {
+        let name = self.register.take();
+        self.registers.delete(name, Register::new(text, kind));
+    }
 
-        assert_eq!(ops.buffer().content(), "Hi");
-        assert_eq!(ops.get_cursor_position(), Position::new(2, 0));
+    /// Characters `start..end` of the buffer, as a selection covers them.
+    /// Empty for an empty range, as a selection in an empty buffer is.
+    fn selected_text(&self, start: usize, end: usize) -> Result<String> {
+        if start > end || end > self.buffer.content().chars().count() {
+            return Err(EditorError::InvalidOperation("Invalid selection range".to_string()));
+        }
+        Ok(self.buffer.content().chars().skip(start).take(end - start).collect())
     }
 
-    #[test]
-    fn test_delete_char() {
-        let buffer = Buffer::from_content("Hello".to_string());
-        let mut ops = EditorOps::new(buffer, (80, 24));
+    /// Removes characters `start..end` without keeping them, leaving the
+    /// cursor at `start`.
+    fn remove_chars(&mut self, start: usize, end: usize) -> Result<()> {
+        let (start, end) = (self.byte_index(start), self.byte_index(end));
+        self.buffer.delete_range(start..end)?;
+        self.cursor = self.buffer_offset_to_position(start);
+        self.settle_cursor();
+        Ok(())
+    }
 
-        // Move to end
-        ops.move_to_position(Position::new(5, 0)).unwrap();
-        ops.delete_char().unwrap();
+    /// Puts the unnamed or selected register after the cursor, like vim's
+    /// `p`: char-wise text after the character under the cursor, leaving
+    /// the cursor on the last pasted character, and line-wise text below
+    /// the cursor line, leaving the cursor on the first pasted line. After
+    /// `x` this swaps two characters.
+    pub fn paste_after(&mut self) -> Result<()> {
+        self.put_register(true)
+    }
 
-        assert_eq!(ops.buffer().content(), "Hell");
+    /// Puts the unnamed or selected register before the cursor, like vim's
+    /// `P`: char-wise text at the cursor and line-wise text above the
+    /// cursor line.
+    pub fn paste_before(&mut self) -> Result<()> {
+        self.put_register(false)
+    }
+
+    fn put_register(&mut self, after: bool) -> Result<()> {
+        self.check_modifiable()?;
+        self.constrain_cursor();
+        let name = self.register.take().unwrap_or('"');
+        let Some(register) = self.registers.get(name).filter(|register| !register.text.is_empty()).cloned() else {
+            return Ok(());
+        };
+        match register.kind {
+            ClipboardKind::Charwise => self.paste_chars(&register.text, after),
+            ClipboardKind::Linewise => self.paste_lines(&register.text, after),
+        }
+    }
+
+    fn paste_chars(&mut self, text: &str, after: bool) -> Result<()> {
+        if after {
+            let columns = self.char_columns(self.cursor.y);
+            let line_length = self.buffer.line_length(self.cursor.y);
+            self.cursor.x = columns.iter().position(|&x| x >= self.cursor.x).map_or(line_length, |index| {
+                columns.get(index + 1).copied().unwrap_or(line_length)
+            });
+        }
+
+        self.type_text(text)?;
+        if let Some(last) = text.chars().last().filter(|&ch| ch != '\n') {
+            self.cursor.x = self.cursor.x.saturating_sub(last.len_utf8());
+        }
+        self.settle_cursor();
+        Ok(())
+    }
+
+    fn paste_lines(&mut self, text: &str, below: bool) -> Result<()> {
+        let lines = text.strip_suffix('\n').unwrap_or(text);
+        let line = self.cursor.y;
+        let first = if below {
+            self.cursor.x = self.buffer.line_length(line);
+            self.type_text(&format!("\n{}", lines))?;
+            line + 1
+        } else {
+            self.cursor.x = 0;
+            self.type_text(&format!("{}\n", lines))?;
+            line
+        };
+
+        // On the first non-blank of the first pasted line, as in vim
+        let indent = self.buffer.get_line(first).map_or(0, |text| text.len() - text.trim_start().len());
+        self.cursor = Position::new(indent, first);
+        self.settle_cursor();
+        Ok(())
+    }
+
+    pub fn has_selection(&self) -> bool {
+        self.selection_start.is_some()
+    }
+
+    pub fn start_selection(&mut self) {
+        self.selection_start = Some(self.char_offset_of(self.cursor));
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection_start = None;
+    }
+
+    /// The selection as character offsets, as `copy_selection` takes them.
+    pub fn get_selection_range(&self) -> Option<(usize, usize)> {
+        self.selection_start.map(|start| {
+            let end = self.char_offset_of(self.cursor);
+            if start <= end {
+                (start, end)
+            } else {
+                (end, start)
+            }
+        })
+    }
+
+    /// The selection as visual mode shows it, taking the character under
+    /// its later end too.
+    pub fn visual_range(&self) -> Option<(usize, usize)> {
+        let length = self.buffer.content().chars().count();
+        self.get_selection_range().map(|(start, end)| (start, (end + 1).min(length)))
+    }
+
+    /// The visual range as buffer positions, for drawing it.
+    pub fn visual_positions(&self) -> Option<(Position, Position)> {
+        let position = |offset| self.buffer_offset_to_position(self.byte_index(offset));
+        self.visual_range().map(|(start, end)| (position(start), position(end)))
+    }
+
+    fn position_to_buffer_offset(&self) -> usize {
+        self.offset_of(self.cursor)
+    }
+
+    fn offset_of(&self, position: Position) -> usize {
+        // Past the last line, as a cursor is left when the buffer shrinks
+        // under it, counts as the end of the buffer
+        let last_line = self.buffer.line_count().saturating_sub(1);
+        let (x, y) = if position.y > last_line { (usize::MAX, last_line) } else { (position.x, position.y) };
+        let mut offset = 0;
+        for line_idx in 0..y {
+            offset += self.buffer.line_length(line_idx) + 1; // +1 for newline
+        }
+        offset + x.min(self.buffer.line_length(y))
+    }
+
+    /// The offset of `position` counted in characters rather than bytes.
+    fn char_offset_of(&self, position: Position) -> usize {
+        self.char_index(self.offset_of(position))
+    }
+
+    fn char_index(&self, byte_offset: usize) -> usize {
+        let content = self.buffer.content();
+        // An offset inside a character counts from that character's start
+        let mut end = byte_offset.min(content.len());
+        while !content.is_char_boundary(end) {
+            end -= 1;
+        }
+        content[..end].chars().count()
+    }
+
+    fn byte_index(&self, char_offset: usize) -> usize {
+        let content = self.buffer.content();
+        content.char_indices().nth(char_offset).map_or(content.len(), |(byte, _)| byte)
+    }
+
+    /// The characters from `position` to the end of the buffer, or back to
+    /// its start, with their positions. `position` itself comes first.
+    fn chars_from(&self, position: Position, forward: bool) -> Box<dyn Iterator<Item = (Position, char)> + '_> {
+        let line_chars = move |y: usize| {
+            let line = self.buffer.get_line(y).unwrap_or("");
+            line.char_indices().map(move |(x, ch)| (Position::new(x, y), ch))
+        };
+        if forward {
+            let lines = position.y..self.buffer.line_count();
+            Box::new(lines.flat_map(line_chars).filter(move |(pos, _)| pos.y > position.y || pos.x >= position.x))
+        } else {
+            let lines = (0..=position.y).rev();
+            Box::new(
+                lines
+                    .flat_map(move |y| line_chars(y).rev())
+                    .filter(move |(pos, _)| pos.y < position.y || pos.x <= position.x),
+            )
+        }
+    }
+
+    fn buffer_offset_to_position(&self, offset: usize) -> Position {
+        let mut current_offset = 0;
+
+        for (line_idx, _) in (0..self.buffer.line_count()).enumerate() {
+            let line_len = self.buffer.line_length(line_idx);
+
+            if current_offset + line_len >= offset {
+                return Position::new(offset - current_offset, line_idx);
+            }
+
+            current_offset += line_len + 1; // +1 for newline
+        }
+
+        // If offset is beyond buffer, return end position
+        let last_line = self.buffer.line_count().saturating_sub(1);
+        Position::new(self.buffer.line_length(last_line), last_line)
+    }
+
+    fn constrain_cursor(&mut self) {
+        let line_count = self.buffer.line_count();
+        if line_count == 0 {
+            self.cursor = Position::origin();
+            return;
+        }
+
+        // Constrain Y to valid lines
+        if self.cursor.y >= line_count {
+            self.cursor.y = line_count - 1;
+        }
+
+        // Constrain X to line length, and to the start of a character: the
+        // desired column kept from a line above may fall inside one here
+        let line = self.buffer.get_line(self.cursor.y).unwrap_or("");
+        self.cursor.x = self.cursor.x.min(line.len());
+        while !line.is_char_boundary(self.cursor.x) {
+            self.cursor.x -= 1;
+        }
+        self.scroll_to_cursor();
+    }
+
+    /// Constrains the cursor after a move that sets its column on purpose.
+    fn settle_cursor(&mut self) {
+        self.constrain_cursor();
+        self.desired_column = self.cursor.x;
+    }
+}
+
+/// The characters `S`, `cs` and `ds` take `ch` to mean: the bracket pair it
+/// belongs to, or a quote on both sides.
+pub fn delimiter_pair(ch: char) -> Option<(char, char)> {
+    match ch {
+        '"' | '\'' | '`' => Some((ch, ch)),
+        _ => bracket_pair(ch),
+    }
+}
+
+/// The opening and closing characters of the bracket pair `ch` belongs to.
+fn bracket_pair(ch: char) -> Option<(char, char)> {
+    match ch {
+        '(' | ')' => Some(('(', ')')),
+        '[' | ']' => Some(('[', ']')),
+        '{' | '}' => Some(('{', '}')),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WordClass {
+    Blank,
+    Keyword,
+    Punctuation,
+}
+
+fn word_class(ch: char) -> WordClass {
+    if ch.is_whitespace() {
+        WordClass::Blank
+    } else if ch.is_alphanumeric() || ch == '_' {
+        WordClass::Keyword
+    } else {
+        WordClass::Punctuation
+    }
+}
+
+impl<T: TextBuffer> EditorOperations for EditorOps<T> {
+    fn insert_char(&mut self, ch: char) -> Result<()> {
+        self.type_text(ch.encode_utf8(&mut [0; 4]))
+    }
+
+    fn delete_char(&mut self) -> Result<()> {
+        self.check_modifiable()?;
+        self.constrain_cursor();
+        if self.cursor.x == 0 && self.cursor.y == 0 {
+            return Ok(()); // Nothing to delete at start of buffer
+        }
+
+        let offset = if self.cursor.x == 0 {
+            // Delete newline at beginning of line
+            self.cursor.y -= 1;
+            self.cursor.x = self.buffer.line_length(self.cursor.y);
+            self.position_to_buffer_offset()
+        } else {
+            // Delete character before cursor
+            self.cursor.x -= 1;
+            self.position_to_buffer_offset()
+        };
+
+        self.buffer.delete(offset)?;
+        self.settle_cursor();
+        Ok(())
+    }
+
+    fn move_cursor(&mut self, dx: i32, dy: i32) -> Result<()> {
+        let new_y = (self.cursor.y as i32 + dy).max(0) as usize;
+        if dx == 0 && dy != 0 {
+            self.move_to_line(new_y);
+            return Ok(());
+        }
+
+        let new_x = (self.cursor.x as i32 + dx).max(0) as usize;
+        self.cursor = Position::new(new_x, new_y);
+        self.settle_cursor();
+        Ok(())
+    }
+
+    fn move_to_position(&mut self, position: Position) -> Result<()> {
+        self.cursor = position;
+        self.settle_cursor();
+        Ok(())
+    }
+
+    fn get_cursor_position(&self) -> Position {
+        self.cursor
+    }
+
+    fn copy_selection(&mut self, start: usize, end: usize) -> Result<String> {
+        let selected = self.selected_text(start, end)?;
+        if !selected.is_empty() {
+            self.set_clipboard(selected.clone(), ClipboardKind::Charwise);
+        }
+        Ok(selected)
+    }
+
+    fn cut_selection(&mut self, start: usize, end: usize) -> Result<String> {
+        self.check_modifiable()?;
+        let selected = self.selected_text(start, end)?;
+        if !selected.is_empty() {
+            self.store_deleted(selected.clone(), ClipboardKind::Charwise);
+            self.remove_chars(start, end)?;
+        }
+        Ok(selected)
+    }
+
+    fn paste(&mut self, text: &str) -> Result<()> {
+        self.type_text(text)
+    }
+}
+
+pub struct ClipboardManager {
+    clipboard: String,
+}
+
+impl ClipboardManager {
+    pub fn new() -> Self {
+        Self {
+            clipboard: String::new(),
+        }
+    }
+
+    pub fn copy(&mut self, text: String) {
+        self.clipboard = text;
+    }
+
+    pub fn paste(&self) -> &str {
+        &self.clipboard
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clipboard.is_empty()
+    }
+}
+
+impl Default for ClipboardManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{Buffer, MultiBuffer};
+    use crate::core::BufferManager;
+    use crate::file_io::FileSystem;
+
+    #[test]
+    fn test_insert_and_move_cursor() {
+        let buffer = Buffer::new();
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        ops.insert_char('H').unwrap();
+        ops.insert_char('i').unwrap();
+
+        assert_eq!(ops.buffer().content(), "Hi");
+        assert_eq!(ops.get_cursor_position(), Position::new(2, 0));
+    }
+
+    #[test]
+    fn test_edits_go_straight_into_the_owned_buffers() {
+        // MultiBuffer is not Clone, so there is no second copy to keep in
+        // step after each key: edits land in the one EditorOps owns
+        let mut ops = EditorOps::new(MultiBuffer::new(FileSystem::new().unwrap()), (80, 24));
+        ops.type_text("one\ntwo\nthree").unwrap();
+        let revision = ops.buffer().get_current_buffer().unwrap().revision();
+
+        ops.move_to_position(Position::new(3, 1)).unwrap();
+        ops.insert_char('!').unwrap();
+        let buffer = ops.buffer().get_current_buffer().unwrap();
+        assert_eq!(buffer.content(), "one\ntwo!\nthree");
+        // Only the edited line was touched
+        assert_eq!(buffer.changed_lines_since(revision), vec![1]);
+        assert!(ops.buffer().get_current_buffer_info().unwrap().is_modified);
+
+        // Switching buffers resets the view but keeps the clipboard
+        ops.set_clipboard("kept".to_string(), ClipboardKind::Charwise);
+        ops.buffer_mut().new_buffer();
+        ops.reset_view((80, 24));
+        assert_eq!(ops.get_cursor_position(), Position::origin());
+        assert_eq!(ops.buffer().content(), "");
+        assert_eq!(ops.clipboard(), "kept");
+        ops.buffer_mut().switch_to_buffer(0).unwrap();
+        assert_eq!(ops.buffer().content(), "one\ntwo!\nthree");
+    }
+
+    #[test]
+    fn test_append_text_moves_cursor_to_the_end() {
+        let mut multi_buffer = MultiBuffer::new(FileSystem::new().unwrap());
+        multi_buffer.insert_str(0, "first").unwrap();
+        multi_buffer.get_current_buffer_info_mut().unwrap().is_modified = false;
+        let mut ops = EditorOps::new(multi_buffer, (80, 24));
+
+        ops.append_text("\nsecond\nthird line").unwrap();
+        assert_eq!(ops.buffer().content(), "first\nsecond\nthird line");
+        assert_eq!(ops.buffer().line_count(), 3);
+        assert!(ops.buffer().get_current_buffer_info().unwrap().is_modified);
+        assert_eq!(ops.get_cursor_position(), Position::new(10, 2));
+        assert_eq!(ops.desired_column(), 10);
+
+        // Typing carries on from the new end
+        ops.insert_char('!').unwrap();
+        assert_eq!(ops.buffer().get_line(2), Some("third line!"));
+    }
+
+    #[test]
+    fn test_delete_forward() {
+        let buffer = Buffer::from_content("año\nb".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        // Mid-line, a whole multibyte character goes
+        ops.move_to_position(Position::new(1, 0)).unwrap();
+        assert!(ops.delete_forward().unwrap());
+        assert_eq!(ops.buffer().content(), "ao\nb");
+        assert_eq!(ops.get_cursor_position(), Position::new(1, 0));
+
+        // At the end of a line the next one joins it
+        ops.move_to_position(Position::new(2, 0)).unwrap();
+        assert!(ops.delete_forward().unwrap());
+        assert_eq!(ops.buffer().content(), "aob");
+        assert_eq!(ops.get_cursor_position(), Position::new(2, 0));
+
+        // At the end of the buffer nothing happens
+        ops.move_to_position(Position::new(3, 0)).unwrap();
+        assert!(!ops.delete_forward().unwrap());
+        assert_eq!(ops.buffer().content(), "aob");
+    }
+
+    #[test]
+    fn test_delete_word_before_cursor() {
+        let buffer = Buffer::from_content("let café = foo.bar  \nx".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        // Trailing blanks go with the word before them
+        ops.move_to_position(Position::new(22, 0)).unwrap();
+        assert!(ops.delete_word_before().unwrap());
+        assert_eq!(ops.buffer().content(), "let café = foo.\nx");
+        // Punctuation is a word of its own
+        assert!(ops.delete_word_before().unwrap());
+        assert_eq!(ops.buffer().content(), "let café = foo\nx");
+
+        // From the middle of a word only the part before the cursor goes
+        ops.move_to_position(Position::new(7, 0)).unwrap();
+        assert!(ops.delete_word_before().unwrap());
+        assert_eq!(ops.buffer().content(), "let é = foo\nx");
         assert_eq!(ops.get_cursor_position(), Position::new(4, 0));
+
+        ops.move_to_position(Position::new(0, 1)).unwrap();
+        assert!(!ops.delete_word_before().unwrap());
+
+        // Down from column 1 onto a line starting with "é", and from inside it
+        let buffer = Buffer::from_content("ab\néclair done".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+        ops.move_to_position(Position::new(1, 0)).unwrap();
+        ops.move_cursor(0, 1).unwrap();
+        assert!(!ops.delete_word_before().unwrap());
+        ops.cursor = Position::new(1, 1);
+        assert!(!ops.delete_word_before().unwrap());
+        ops.cursor = Position::new(7, 1);
+        assert!(ops.delete_word_before().unwrap());
+        assert_eq!(ops.buffer().content(), "ab\n done");
+    }
+
+    #[test]
+    fn test_delete_to_line_start() {
+        let buffer = Buffer::from_content("one\n    two three".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        ops.move_to_position(Position::new(7, 1)).unwrap();
+        assert!(ops.delete_to_line_start().unwrap());
+        assert_eq!(ops.buffer().content(), "one\n three");
+        assert_eq!(ops.get_cursor_position(), Position::new(0, 1));
+        assert!(!ops.delete_to_line_start().unwrap());
+
+        // The whole line from its end, leaving the line itself
+        ops.move_to_position(Position::new(3, 0)).unwrap();
+        assert!(ops.delete_to_line_start().unwrap());
+        assert_eq!(ops.buffer().content(), "\n three");
+    }
+
+    #[test]
+    fn test_insert_snippet_replaces_trigger() {
+        let buffer = Buffer::from_content("x\n  if".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+        ops.move_to_position(Position::new(4, 1)).unwrap();
+        assert_eq!(ops.word_before_cursor(), "if");
+
+        let snippet = Snippet::parse("if ${1:cond} {\n    $0\n}");
+        let mut session = ops.insert_snippet(2, &snippet).unwrap();
+        assert_eq!(ops.buffer().content(), "x\n  if cond {\n      \n  }");
+        assert_eq!(ops.get_cursor_position(), Position::new(9, 1));
+
+        let stop = session.advance(ops.buffer().content().len()).unwrap();
+        ops.jump_to_offset(stop.1);
+        assert_eq!(ops.get_cursor_position(), Position::new(6, 2));
+
+        // After non-ASCII text, and with the cursor inside a character
+        let buffer = Buffer::from_content("çé if".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+        ops.move_to_position(Position::new(7, 0)).unwrap();
+        ops.insert_snippet(ops.word_before_cursor().len(), &snippet).unwrap();
+        assert_eq!(ops.buffer().content(), "çé if cond {\n    \n}");
+        ops.cursor = Position::new(3, 0);
+        assert_eq!(ops.word_before_cursor(), "ç");
+    }
+
+    #[test]
+    fn test_type_text_moves_cursor_past_it() {
+        let buffer = Buffer::from_content("ab".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+        ops.move_to_position(Position::new(1, 0)).unwrap();
+
+        ops.type_text("héllo").unwrap();
+        assert_eq!(ops.buffer().content(), "ahéllob");
+        // Columns are bytes, so the é counts twice
+        assert_eq!(ops.get_cursor_position(), Position::new(7, 0));
+
+        ops.type_text("1\n→ 2\n日本").unwrap();
+        assert_eq!(ops.buffer().content(), "ahéllo1\n→ 2\n日本b");
+        assert_eq!(ops.get_cursor_position(), Position::new(6, 2));
+
+        // One character at a time lands in the same place
+        ops.insert_char('ü').unwrap();
+        ops.insert_char('\n').unwrap();
+        ops.insert_char('x').unwrap();
+        assert_eq!(ops.buffer().content(), "ahéllo1\n→ 2\n日本ü\nxb");
+        assert_eq!(ops.get_cursor_position(), Position::new(1, 3));
+    }
+
+    #[test]
+    fn test_delete_char() {
+        let buffer = Buffer::from_content("Hello".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        // Move to end
+        ops.move_to_position(Position::new(5, 0)).unwrap();
+        ops.delete_char().unwrap();
+
+        assert_eq!(ops.buffer().content(), "Hell");
+        assert_eq!(ops.get_cursor_position(), Position::new(4, 0));
+    }
+
+    #[test]
+    fn test_insert_tab_uses_tab_size() {
+        let mut ops = EditorOps::new(Buffer::from_content("x".to_string()), (80, 24));
+        assert_eq!(ops.tab_size(), TAB_SIZE);
+
+        ops.set_tab_size(2);
+        ops.insert_tab().unwrap();
+        assert_eq!(ops.buffer().content(), "  x");
+        assert_eq!(ops.get_cursor_position(), Position::new(2, 0));
+
+        ops.set_tab_size(8);
+        ops.insert_tab().unwrap();
+        assert_eq!(ops.buffer().content(), "          x");
+
+        ops.set_expandtab(false);
+        ops.insert_tab().unwrap();
+        assert_eq!(ops.buffer().content(), "          \tx");
     }
 
     #[test]
@@ -280,4 +1384,579 @@ mod tests {
 
         assert_eq!(ops.buffer().content(), "Hello WorldHello");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_vertical_motion_restores_desired_column() {
+        let buffer = Buffer::from_content("a long line\nab\nanother long line".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        ops.move_to_position(Position::new(8, 0)).unwrap();
+        ops.move_cursor(0, 1).unwrap();
+        assert_eq!(ops.get_cursor_position(), Position::new(2, 1));
+        ops.move_cursor(0, 1).unwrap();
+        assert_eq!(ops.get_cursor_position(), Position::new(8, 2));
+
+        ops.move_cursor(0, -2).unwrap();
+        assert_eq!(ops.get_cursor_position(), Position::new(8, 0));
+    }
+
+    #[test]
+    fn test_vertical_motion_lands_on_a_character() {
+        let buffer = Buffer::from_content("a long line\nçafé\nétoile\n".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        // Byte 5 of "çafé" is inside the "é", which starts at byte 4
+        ops.move_to_position(Position::new(5, 0)).unwrap();
+        ops.move_cursor(0, 1).unwrap();
+        assert_eq!(ops.get_cursor_position(), Position::new(4, 1));
+        ops.move_to_position(Position::new(1, 0)).unwrap();
+        ops.move_to_line(2);
+        assert_eq!(ops.get_cursor_position(), Position::new(0, 2));
+
+        // The desired column is kept for the lines after
+        ops.move_to_position(Position::new(5, 0)).unwrap();
+        ops.move_cursor(0, 1).unwrap();
+        ops.move_cursor(0, -1).unwrap();
+        assert_eq!(ops.get_cursor_position(), Position::new(5, 0));
+    }
+
+    #[test]
+    fn test_horizontal_motion_updates_desired_column() {
+        let buffer = Buffer::from_content("a long line\nab\nanother long line".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        ops.move_to_position(Position::new(8, 0)).unwrap();
+        ops.move_cursor(0, 1).unwrap();
+        ops.move_cursor(-1, 0).unwrap();
+        assert_eq!(ops.desired_column(), 1);
+
+        ops.move_to_line(2);
+        assert_eq!(ops.get_cursor_position(), Position::new(1, 2));
+    }
+
+    #[test]
+    fn test_scrolling_keeps_the_cursor_on_screen() {
+        let lines: Vec<String> = (1..=1000).map(|line| format!("line {}", line)).collect();
+        let mut ops = EditorOps::new(Buffer::from_content(lines.join("\n")), (80, 24));
+        assert_eq!(ops.visible_range(), 0..23);
+
+        // 23 rows above the status line, the last of them the end of the file
+        ops.move_to_position(Position::new(4, 999)).unwrap();
+        assert_eq!((ops.scroll_offset(), ops.visible_range()), (977, 977..1000));
+        assert_eq!(ops.get_cursor_position(), Position::new(4, 999));
+
+        // Moving within the window leaves it be; going above it scrolls up
+        ops.move_cursor(0, -22).unwrap();
+        assert_eq!(ops.scroll_offset(), 977);
+        ops.move_cursor(0, -5).unwrap();
+        assert_eq!((ops.scroll_offset(), ops.get_cursor_position()), (972, Position::new(4, 972)));
+        ops.move_cursor(0, 1).unwrap();
+        assert_eq!(ops.scroll_offset(), 972);
+        ops.goto(1, 1);
+        assert_eq!(ops.visible_range(), 0..23);
+    }
+
+    #[test]
+    fn test_find_next_and_previous_wrap_around() {
+        let buffer = Buffer::from_content("foo bar\nbaz Foo\nfoo".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+        let at = |x, y| Some(Position::new(x, y));
+
+        assert_eq!(ops.find_next("foo", Position::new(0, 0)), at(0, 2));
+        assert_eq!(ops.find_next("foo", Position::new(0, 2)), at(0, 0));
+        assert_eq!(ops.find_previous("foo", Position::new(0, 2)), at(0, 0));
+        assert_eq!(ops.find_previous("foo", Position::new(0, 0)), at(0, 2));
+        assert_eq!(ops.find_previous("ba", Position::new(5, 0)), at(4, 0));
+        assert_eq!(ops.find_next("missing", Position::new(0, 0)), None);
+
+        ops.set_search_options(false, true);
+        assert_eq!(ops.find_next("foo", Position::new(0, 0)), at(4, 1));
+        ops.set_search_options(false, false);
+        assert_eq!(ops.find_next("foo", Position::new(0, 2)), None);
+        assert_eq!(ops.find_previous("foo", Position::new(0, 0)), None);
+    }
+
+    #[test]
+    fn test_goto_line_centers_it() {
+        let lines: Vec<String> = (1..=100).map(|line| format!("  line {}", line)).collect();
+        let mut ops = EditorOps::new(Buffer::from_content(lines.join("\n")), (80, 24));
+
+        ops.goto_line(50);
+        assert_eq!(ops.get_cursor_position(), Position::new(0, 49));
+        assert_eq!(ops.visible_range(), 38..61);
+
+        // Near either end the screen stays full of lines
+        ops.goto_line(3);
+        assert_eq!((ops.get_cursor_position(), ops.scroll_offset()), (Position::new(0, 2), 0));
+        ops.goto_line(1000);
+        assert_eq!((ops.get_cursor_position(), ops.visible_range()), (Position::new(0, 99), 77..100));
+        ops.goto_line(0);
+        assert_eq!(ops.get_cursor_position(), Position::origin());
+    }
+
+    #[test]
+    fn test_goto_clamps_column_to_line() {
+        let buffer = Buffer::from_content("first line\nab\nthird line".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        ops.goto(2, 9);
+        assert_eq!(ops.get_cursor_position(), Position::new(2, 1));
+        // The requested column is kept for the next vertical move
+        ops.move_cursor(0, 1).unwrap();
+        assert_eq!(ops.get_cursor_position(), Position::new(8, 2));
+
+        ops.goto(99, 0);
+        assert_eq!(ops.get_cursor_position(), Position::new(0, 2));
+    }
+
+    #[test]
+    fn test_file_info() {
+        let buffer = Buffer::from_content("one\ntwo\nthree\nfour".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+        ops.goto(2, 3);
+
+        assert_eq!(ops.file_info("notes.txt", false), "\"notes.txt\" line 2 of 4 --50%-- col 3, 18 bytes");
+        assert_eq!(
+            ops.file_info("notes.txt", true),
+            "\"notes.txt\" [Modified] line 2 of 4 --50%-- col 3, 18 bytes"
+        );
+    }
+
+    #[test]
+    fn test_inner_word_object() {
+        let buffer = Buffer::from_content("let value = 42;\nfoo(bar)".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        // Start, middle and end of "value"
+        for column in [4, 6, 8] {
+            ops.move_to_position(Position::new(column, 0)).unwrap();
+            assert_eq!(ops.word_object(false), (4, 9));
+        }
+
+        // Punctuation is a word of its own
+        ops.move_to_position(Position::new(3, 1)).unwrap();
+        assert_eq!(ops.word_object(false), (19, 20));
+    }
+
+    #[test]
+    fn test_around_word_object() {
+        let buffer = Buffer::from_content("let value = 42".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        ops.move_to_position(Position::new(4, 0)).unwrap();
+        assert_eq!(ops.word_object(true), (4, 10));
+
+        // The last word takes the blank before it instead
+        ops.move_to_position(Position::new(13, 0)).unwrap();
+        assert_eq!(ops.word_object(true), (11, 14));
+
+        ops.cut_selection(11, 14).unwrap();
+        assert_eq!(ops.buffer().content(), "let value =");
+    }
+
+    #[test]
+    fn test_pair_object_in_nested_brackets() {
+        let buffer = Buffer::from_content("f(a, (b + c), d)\nx = \"say \\\"hi\\\"\" + 'q'".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        // On "b": the inner parens; on "d": the outer ones
+        ops.move_to_position(Position::new(6, 0)).unwrap();
+        assert_eq!(ops.pair_object('(', false), Some((6, 11)));
+        assert_eq!(ops.pair_object(')', true), Some((5, 12)));
+        ops.move_to_position(Position::new(14, 0)).unwrap();
+        assert_eq!(ops.pair_object('(', false), Some((2, 15)));
+        assert_eq!(ops.match_bracket(Position::new(1, 0)), Some(Position::new(15, 0)));
+        assert_eq!(ops.match_bracket(Position::new(11, 0)), Some(Position::new(5, 0)));
+
+        // Escaped quotes stay inside the string
+        ops.move_to_position(Position::new(7, 1)).unwrap();
+        assert_eq!(ops.pair_object('"', false), Some((22, 32)));
+        assert_eq!(ops.pair_object('\'', false), None);
+    }
+
+    #[test]
+    fn test_pair_object_unbalanced() {
+        let buffer = Buffer::from_content("call(a, [b)\nnone".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        ops.move_to_position(Position::new(9, 0)).unwrap();
+        assert_eq!(ops.pair_object('[', false), None);
+        assert_eq!(ops.pair_object('(', false), Some((5, 10)));
+        assert_eq!(ops.match_bracket(Position::new(8, 0)), None);
+
+        ops.move_to_position(Position::new(1, 1)).unwrap();
+        assert_eq!(ops.pair_object('(', false), None);
+        assert_eq!(ops.pair_object('{', true), None);
+    }
+
+    #[test]
+    fn test_surround_selection() {
+        let buffer = Buffer::from_content("let x = a + b;".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        // "a + b" selected
+        ops.move_to_position(Position::new(8, 0)).unwrap();
+        ops.start_selection();
+        ops.move_to_position(Position::new(13, 0)).unwrap();
+        let (open, close) = delimiter_pair(')').unwrap();
+        ops.surround(ops.get_selection_range().unwrap(), open, close).unwrap();
+        assert_eq!(ops.buffer().content(), "let x = (a + b);");
+        assert_eq!(ops.get_cursor_position(), Position::new(8, 0));
+
+        let (open, close) = delimiter_pair('"').unwrap();
+        ops.surround((4, 5), open, close).unwrap();
+        assert_eq!(ops.buffer().content(), "let \"x\" = (a + b);");
+        assert_eq!(delimiter_pair('x'), None);
+    }
+
+    #[test]
+    fn test_change_and_delete_surround() {
+        let buffer = Buffer::from_content("say(\"hi\", [1, 2])".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        ops.move_to_position(Position::new(6, 0)).unwrap();
+        assert!(ops.change_surround('"', '\'').unwrap());
+        assert_eq!(ops.buffer().content(), "say('hi', [1, 2])");
+        assert_eq!(ops.get_cursor_position(), Position::new(4, 0));
+
+        ops.move_to_position(Position::new(12, 0)).unwrap();
+        assert!(ops.change_surround(']', '{').unwrap());
+        assert_eq!(ops.buffer().content(), "say('hi', {1, 2})");
+        assert!(ops.delete_surround('(').unwrap());
+        assert_eq!(ops.buffer().content(), "say'hi', {1, 2}");
+
+        // Nothing to change outside a pair
+        assert!(!ops.delete_surround('"').unwrap());
+        assert!(!ops.change_surround('{', 'x').unwrap());
+        assert_eq!(ops.buffer().content(), "say'hi', {1, 2}");
+    }
+
+    #[test]
+    fn test_bracket_match_for_each_kind() {
+        let buffer = Buffer::from_content("fn f(a: [u8]) {\n    a[0]\n}\n)".to_string());
+        let ops = EditorOps::new(buffer, (80, 24));
+
+        let partner = |x, y| Some(BracketMatch::Partner(Position::new(x, y)));
+        assert_eq!(ops.bracket_match(Position::new(4, 0)), partner(12, 0));
+        assert_eq!(ops.bracket_match(Position::new(12, 0)), partner(4, 0));
+        assert_eq!(ops.bracket_match(Position::new(8, 0)), partner(11, 0));
+        assert_eq!(ops.bracket_match(Position::new(11, 0)), partner(8, 0));
+        // Braces pair across lines
+        assert_eq!(ops.bracket_match(Position::new(14, 0)), partner(0, 2));
+        assert_eq!(ops.bracket_match(Position::new(0, 2)), partner(14, 0));
+
+        assert_eq!(ops.bracket_match(Position::new(0, 3)), Some(BracketMatch::Unmatched(Position::new(0, 3))));
+        assert_eq!(ops.bracket_match(Position::new(0, 0)), None);
+    }
+
+    #[test]
+    fn test_delete_under_cursor() {
+        let buffer = Buffer::from_content("abcdef\nxyz".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        ops.move_to_position(Position::new(2, 0)).unwrap();
+        assert_eq!(ops.delete_under_cursor(1).unwrap(), "c");
+        assert_eq!(ops.get_cursor_position(), Position::new(2, 0));
+        assert_eq!(ops.clipboard(), "c");
+
+        // 3x stops at the end of the line rather than joining the next one
+        assert_eq!(ops.delete_under_cursor(3).unwrap(), "def");
+        assert_eq!(ops.buffer().content(), "ab\nxyz");
+        assert_eq!(ops.get_cursor_position(), Position::new(1, 0));
+    }
+
+    #[test]
+    fn test_delete_at_line_end() {
+        let buffer = Buffer::from_content("abc\n\nxyz".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        ops.move_to_position(Position::new(3, 0)).unwrap();
+        assert_eq!(ops.delete_under_cursor(1).unwrap(), "c");
+        assert_eq!(ops.get_cursor_position(), Position::new(1, 0));
+
+        assert_eq!(ops.delete_before_cursor(5).unwrap(), "a");
+        assert_eq!(ops.buffer().content(), "b\n\nxyz");
+        assert_eq!(ops.get_cursor_position(), Position::new(0, 0));
+
+        // Nothing to delete on an empty line
+        ops.move_to_position(Position::new(0, 1)).unwrap();
+        assert_eq!(ops.delete_under_cursor(1).unwrap(), "");
+        assert_eq!(ops.clipboard(), "a");
+    }
+
+    #[test]
+    fn test_xp_swaps_characters() {
+        let buffer = Buffer::from_content("ba".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        ops.delete_under_cursor(1).unwrap();
+        assert_eq!(ops.clipboard_kind(), ClipboardKind::Charwise);
+        ops.paste_after().unwrap();
+        assert_eq!(ops.buffer().content(), "ab");
+        assert_eq!(ops.get_cursor_position(), Position::new(1, 0));
+
+        // On the last character, x leaves the cursor on the new last one
+        let buffer = Buffer::from_content("xzy".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+        ops.move_to_position(Position::new(1, 0)).unwrap();
+        ops.delete_under_cursor(1).unwrap();
+        ops.paste_after().unwrap();
+        assert_eq!(ops.buffer().content(), "xyz");
+        assert_eq!(ops.get_cursor_position(), Position::new(2, 0));
+    }
+
+    #[test]
+    fn test_charwise_paste_after_and_before() {
+        let buffer = Buffer::from_content("abcd".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+        ops.set_clipboard("XY".to_string(), ClipboardKind::Charwise);
+
+        ops.move_to_position(Position::new(1, 0)).unwrap();
+        ops.paste_after().unwrap();
+        assert_eq!(ops.buffer().content(), "abXYcd");
+        assert_eq!(ops.get_cursor_position(), Position::new(3, 0));
+
+        ops.move_to_position(Position::new(0, 0)).unwrap();
+        ops.paste_before().unwrap();
+        assert_eq!(ops.buffer().content(), "XYabXYcd");
+        assert_eq!(ops.get_cursor_position(), Position::new(1, 0));
+    }
+
+    #[test]
+    fn test_linewise_paste_below_and_above() {
+        let buffer = Buffer::from_content("one\ntwo".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+        ops.set_clipboard("  new\nlines\n".to_string(), ClipboardKind::Linewise);
+
+        ops.move_to_position(Position::new(2, 0)).unwrap();
+        ops.paste_after().unwrap();
+        assert_eq!(ops.buffer().content(), "one\n  new\nlines\ntwo");
+        assert_eq!(ops.get_cursor_position(), Position::new(2, 1));
+
+        ops.move_to_position(Position::new(1, 3)).unwrap();
+        ops.paste_before().unwrap();
+        assert_eq!(ops.buffer().content(), "one\n  new\nlines\n  new\nlines\ntwo");
+        assert_eq!(ops.get_cursor_position(), Position::new(2, 3));
+
+        // Below the last line too
+        ops.move_to_position(Position::new(0, 5)).unwrap();
+        ops.paste_after().unwrap();
+        assert_eq!(ops.get_cursor_position(), Position::new(2, 6));
+        assert_eq!(ops.buffer().line_count(), 8);
+    }
+
+    #[test]
+    fn test_paste_multiline_into_line() {
+        let buffer = Buffer::from_content("héllo wörld\nend".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        // After "héllo " (7 bytes, as é is two)
+        ops.move_to_position(Position::new(7, 0)).unwrap();
+        ops.paste("big\nwide ").unwrap();
+        assert_eq!(ops.buffer().content(), "héllo big\nwide wörld\nend");
+        assert_eq!(ops.get_cursor_position(), Position::new(5, 1));
+        assert_eq!(ops.buffer().line_count(), 3);
+    }
+
+    #[test]
+    fn test_selection_counts_accented_characters() {
+        let buffer = Buffer::from_content("café crème".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+
+        assert_eq!(ops.copy_selection(3, 6).unwrap(), "é c");
+        assert!(ops.copy_selection(5, 11).is_err());
+
+        // "crème" starts at byte 6 but character 5
+        ops.move_to_position(Position::new(6, 0)).unwrap();
+        assert_eq!(ops.word_object(false), (5, 10));
+        assert_eq!(ops.cut_selection(5, 10).unwrap(), "crème");
+        assert_eq!(ops.buffer().content(), "café ");
+
+        assert_eq!(ops.cut_selection(2, 4).unwrap(), "fé");
+        assert_eq!(ops.buffer().content(), "ca ");
+        assert_eq!(ops.get_cursor_position(), Position::new(2, 0));
+    }
+
+    #[test]
+    fn test_move_paragraph() {
+        let text = "one\ntwo\n\n\nthree\n\nfour\nlast";
+        let mut ops = EditorOps::new(Buffer::from_content(text.to_string()), (80, 24));
+        ops.move_to_position(Position::new(1, 0)).unwrap();
+
+        // Stops on the first empty line after each paragraph, then the end
+        let mut stops = Vec::new();
+        for _ in 0..4 {
+            ops.move_paragraph(true);
+            stops.push(ops.get_cursor_position());
+        }
+        let end = Position::new(4, 7);
+        assert_eq!(stops, vec![Position::new(0, 2), Position::new(0, 5), end, end]);
+
+        let mut stops = Vec::new();
+        for _ in 0..4 {
+            ops.move_paragraph(false);
+            stops.push(ops.get_cursor_position());
+        }
+        // Empty lines right above a paragraph count, not the ones before
+        let expected = [Position::new(0, 5), Position::new(0, 3), Position::origin(), Position::origin()];
+        assert_eq!(stops, expected);
+    }
+
+    #[test]
+    fn test_move_sentence() {
+        let text = "Hello there. How are\nyou? Fine!)  Ok\n\nNext one.";
+        let mut ops = EditorOps::new(Buffer::from_content(text.to_string()), (80, 24));
+
+        // Across the line break, after a closing bracket and at the empty line
+        let mut stops = Vec::new();
+        for _ in 0..7 {
+            ops.move_sentence(true);
+            stops.push(ops.get_cursor_position());
+        }
+        let expected = [(13, 0), (5, 1), (13, 1), (0, 2), (0, 3), (9, 3), (9, 3)];
+        assert_eq!(stops, expected.map(|(x, y)| Position::new(x, y)));
+
+        let mut stops = Vec::new();
+        for _ in 0..7 {
+            ops.move_sentence(false);
+            stops.push(ops.get_cursor_position());
+        }
+        let expected = [(0, 3), (0, 2), (13, 1), (5, 1), (13, 0), (0, 0), (0, 0)];
+        assert_eq!(stops, expected.map(|(x, y)| Position::new(x, y)));
+
+        // From inside a sentence, ( goes to its own start
+        ops.move_to_position(Position::new(3, 1)).unwrap();
+        ops.move_sentence(false);
+        assert_eq!(ops.get_cursor_position(), Position::new(13, 0));
+    }
+
+    #[test]
+    fn test_readonly_blocks_every_edit() {
+        let buffer = Buffer::from_content("hello\nworld".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+        ops.set_clipboard("yank".to_string(), ClipboardKind::Charwise);
+        ops.move_to_position(Position::new(2, 1)).unwrap();
+        ops.set_readonly(true);
+        assert!(!ops.can_modify());
+
+        assert!(matches!(ops.insert_char('x'), Err(EditorError::ReadOnly)));
+        assert!(matches!(ops.insert_tab(), Err(EditorError::ReadOnly)));
+        assert!(matches!(ops.delete_char(), Err(EditorError::ReadOnly)));
+        assert!(matches!(ops.delete_under_cursor(1), Err(EditorError::ReadOnly)));
+        assert!(matches!(ops.delete_before_cursor(1), Err(EditorError::ReadOnly)));
+        assert!(matches!(ops.paste_after(), Err(EditorError::ReadOnly)));
+        assert!(matches!(ops.paste_before(), Err(EditorError::ReadOnly)));
+        assert!(matches!(ops.paste("text"), Err(EditorError::ReadOnly)));
+        assert!(matches!(ops.cut_selection(0, 3), Err(EditorError::ReadOnly)));
+
+        // Nothing moved, changed or was yanked
+        assert_eq!(ops.buffer().content(), "hello\nworld");
+        assert_eq!(ops.get_cursor_position(), Position::new(2, 1));
+        assert_eq!(ops.clipboard(), "yank");
+
+        ops.set_readonly(false);
+        ops.insert_char('x').unwrap();
+        assert_eq!(ops.buffer().content(), "hello\nwoxrld");
+    }
+
+    #[test]
+    fn test_operations_on_an_empty_buffer() {
+        let empty = || EditorOps::new(Buffer::new(), (80, 24));
+        let mut ops = empty();
+
+        // Moving nowhere and deleting nothing succeed quietly
+        ops.move_cursor(1, 1).unwrap();
+        ops.move_to_position(Position::new(4, 4)).unwrap();
+        ops.move_paragraph(true);
+        ops.move_sentence(false);
+        ops.goto(3, 3);
+        assert_eq!(ops.get_cursor_position(), Position::origin());
+        ops.delete_char().unwrap();
+        assert!(!ops.delete_forward().unwrap());
+        assert_eq!(ops.delete_under_cursor(2).unwrap(), "");
+        assert_eq!(ops.delete_before_cursor(2).unwrap(), "");
+        assert!(!ops.delete_word_before().unwrap());
+        assert_eq!(ops.file_info("new", false), "\"new\" line 1 of 1 --100%-- col 1, 0 bytes");
+
+        // Copying the empty selection yanks nothing; pasting nothing is fine
+        ops.start_selection();
+        assert_eq!(ops.visual_range(), Some((0, 0)));
+        assert_eq!(ops.copy_selection(0, 0).unwrap(), "");
+        assert_eq!(ops.cut_selection(0, 0).unwrap(), "");
+        assert_eq!(ops.operate_on_selection(Operator::Delete).unwrap().as_deref(), Some(""));
+        ops.paste_after().unwrap();
+        ops.paste_before().unwrap();
+        for motion in [Motion::WordForward, Motion::LineEnd, Motion::ParagraphBackward, Motion::Line] {
+            assert_eq!(ops.operate(Operator::Delete, motion, 1).unwrap().as_deref(), Some(""));
+        }
+        assert_eq!(ops.buffer().content(), "");
+
+        ops.insert_char('a').unwrap();
+        assert_eq!((ops.buffer().content(), ops.get_cursor_position()), ("a", Position::new(1, 0)));
+        let mut ops = empty();
+        ops.set_clipboard("pasted".to_string(), ClipboardKind::Charwise);
+        ops.paste_after().unwrap();
+        assert_eq!(ops.buffer().content(), "pasted");
+        let mut ops = empty();
+        ops.set_clipboard("line\n".to_string(), ClipboardKind::Linewise);
+        ops.paste_before().unwrap();
+        assert_eq!(ops.buffer().get_line(0), Some("line"));
+    }
+
+    #[test]
+    fn test_operations_with_the_cursor_inside_a_character() {
+        // The cursor sits on the second byte of 'é'
+        let inside = || {
+            let mut ops = EditorOps::new(Buffer::from_content("é wörd\nçb é".to_string()), (80, 24));
+            ops.cursor = Position::new(1, 0);
+            ops
+        };
+
+        // Operators count from the start of the character under the cursor
+        let mut ops = inside();
+        assert_eq!(ops.operate(Operator::Delete, Motion::LineEnd, 1).unwrap().as_deref(), Some("é wörd"));
+        assert_eq!(ops.buffer().content(), "\nçb é");
+        let mut ops = inside();
+        assert_eq!(ops.operate(Operator::Yank, Motion::WordForward, 1).unwrap().as_deref(), Some("é "));
+        let mut ops = inside();
+        ops.operate(Operator::Uppercase, Motion::LineEnd, 1).unwrap();
+        assert_eq!(ops.buffer().get_line(0), Some("É WÖRD"));
+
+        // Deleting inside the character is refused rather than splitting it
+        let mut ops = inside();
+        assert!(ops.delete_forward().is_err());
+        assert!(!ops.delete_word_before().unwrap());
+        assert_eq!(ops.word_before_cursor(), "");
+        assert_eq!(ops.buffer().content(), "é wörd\nçb é");
+
+        // Moving off the line puts the cursor back on a character
+        let mut ops = inside();
+        ops.move_cursor(0, 1).unwrap();
+        assert_eq!(ops.get_cursor_position(), Position::new(0, 1));
+        let mut ops = inside();
+        ops.move_word_forward();
+        assert_eq!(ops.get_cursor_position(), Position::new(3, 0));
+    }
+
+    #[test]
+    fn test_cursor_left_past_the_end_of_a_shrunk_buffer() {
+        let stale = || {
+            let mut ops = EditorOps::new(Buffer::from_content("one\ntwo\nthree".to_string()), (80, 24));
+            ops.move_to_position(Position::new(3, 2)).unwrap();
+            ops.buffer_mut().clear();
+            ops
+        };
+
+        let mut ops = stale();
+        assert!(ops.file_info("x", false).contains("line 1 of 1 --100%--"));
+        ops.type_text("new").unwrap();
+        assert_eq!(ops.buffer().content(), "new");
+        let mut ops = stale();
+        ops.delete_char().unwrap();
+        assert_eq!(ops.delete_before_cursor(1).unwrap(), "");
+        ops.set_clipboard("put\n".to_string(), ClipboardKind::Linewise);
+        ops.paste_after().unwrap();
+        assert_eq!(ops.buffer().content(), "\nput");
+        assert_eq!(ops.get_cursor_position(), Position::new(0, 1));
+    }
+}
@@ -0,0 +1,742 @@
+use super::{word_class, ClipboardKind, EditorOps, WordClass};
+use crate::core::{Position, Result, TextBuffer};
+
+/// An operator waiting for a motion, like vim's `d`, `y` and `c`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Yank,
+    /// Deletes, then the caller starts inserting.
+    Change,
+    /// `>`: indents the lines covered by one level.
+    ShiftRight,
+    /// `<`: takes one level of indent off the lines covered.
+    ShiftLeft,
+    /// `gu`
+    Lowercase,
+    /// `gU`
+    Uppercase,
+    /// `g~`, and `~` on a selection.
+    ToggleCase,
+}
+
+impl Operator {
+    const KEYS: [(&'static str, Operator); 8] = [
+        ("d", Operator::Delete),
+        ("y", Operator::Yank),
+        ("c", Operator::Change),
+        (">", Operator::ShiftRight),
+        ("<", Operator::ShiftLeft),
+        ("gu", Operator::Lowercase),
+        ("gU", Operator::Uppercase),
+        ("g~", Operator::ToggleCase),
+    ];
+
+    /// The operator `keys` start with, like `d` or `gU`, and the keys
+    /// after it.
+    pub fn parse(keys: &str) -> Option<(Self, &str)> {
+        Self::KEYS
+            .iter()
+            .find_map(|&(name, operator)| keys.strip_prefix(name).map(|rest| (operator, rest)))
+    }
+
+    /// The key that repeats the operator over whole lines, as in `dd`,
+    /// `>>` or `gUU`.
+    pub fn line_key(&self) -> char {
+        let (name, _) = Self::KEYS.iter().find(|(_, operator)| operator == self).unwrap_or(&("d", Operator::Delete));
+        name.chars().last().unwrap_or('d')
+    }
+
+    /// Whether the operator changes the buffer rather than only yanking.
+    pub fn modifies(&self) -> bool {
+        *self != Operator::Yank
+    }
+
+    /// Whether the operator takes the text into the registers, as
+    /// deleting, yanking and changing do.
+    fn takes_text(&self) -> bool {
+        matches!(self, Operator::Delete | Operator::Yank | Operator::Change)
+    }
+}
+
+/// A motion an operator acts over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    /// `w`
+    WordForward,
+    /// `b`
+    WordBackward,
+    /// `e`
+    WordEnd,
+    /// `0`
+    LineStart,
+    /// `$`
+    LineEnd,
+    /// `}`
+    ParagraphForward,
+    /// `{`
+    ParagraphBackward,
+    /// `)`
+    SentenceForward,
+    /// `(`
+    SentenceBackward,
+    /// `j`: the cursor line and those below it.
+    LineDown,
+    /// `k`: the cursor line and those above it.
+    LineUp,
+    /// `G`: the lines from the cursor line to the last.
+    LastLine,
+    /// The operator's own key again, as in `dd`: whole lines.
+    Line,
+    /// `iw`, `a(`, `ip` and the like.
+    TextObject { object: char, around: bool },
+}
+
+impl Motion {
+    /// The motion `keys` name after the operator typed with `operator_key`,
+    /// or `None` for keys that are not a motion.
+    pub fn from_keys(keys: &str, operator_key: char) -> Option<Self> {
+        let mut chars = keys.chars();
+        let motion = match chars.next()? {
+            'w' => Motion::WordForward,
+            'b' => Motion::WordBackward,
+            'e' => Motion::WordEnd,
+            '0' => Motion::LineStart,
+            '$' => Motion::LineEnd,
+            '}' => Motion::ParagraphForward,
+            '{' => Motion::ParagraphBackward,
+            ')' => Motion::SentenceForward,
+            '(' => Motion::SentenceBackward,
+            'j' => Motion::LineDown,
+            'k' => Motion::LineUp,
+            'G' => Motion::LastLine,
+            scope @ ('i' | 'a') => Motion::TextObject {
+                object: chars.next()?,
+                around: scope == 'a',
+            },
+            key if key == operator_key => Motion::Line,
+            _ => return None,
+        };
+        chars.next().is_none().then_some(motion)
+    }
+}
+
+/// What a motion covers: characters `start..end`, counted in characters.
+/// A line-wise range runs from the start of its first line to the end of
+/// its last, without the final line break.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotionRange {
+    pub start: usize,
+    pub end: usize,
+    pub linewise: bool,
+}
+
+impl<T: TextBuffer> EditorOps<T> {
+    /// Applies `operator` over `count` repetitions of `motion` from the
+    /// cursor, as `d3w`, `y$` or `ciw` do, and returns the text it took,
+    /// which also goes to the registers. `None` when the motion finds
+    /// nothing to act on, like a missing bracket pair.
+    pub fn operate(&mut self, operator: Operator, motion: Motion, count: usize) -> Result<Option<String>> {
+        // `cw` on a word changes only the word, like `ce`, as in vim
+        let on_word = self.char_under_cursor().is_some_and(|ch| !ch.is_whitespace());
+        let motion = match motion {
+            Motion::WordForward if operator == Operator::Change && on_word => Motion::WordEnd,
+            motion => motion,
+        };
+
+        match self.motion_range(motion, count) {
+            Some(range) => self.apply_operator(operator, range).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// `dd`: deletes the cursor line into the registers and moves to the
+    /// line that took its place, or to the line above when it was the
+    /// last. The only line is emptied rather than removed.
+    pub fn delete_line(&mut self) -> Result<()> {
+        self.operate(Operator::Delete, Motion::Line, 1).map(|_| ())
+    }
+
+    /// `yy`: the cursor line with its line break, which is also put in the
+    /// registers.
+    pub fn yank_line(&mut self) -> String {
+        self.operate(Operator::Yank, Motion::Line, 1)
+            .ok()
+            .flatten()
+            .map(|line| line + "\n")
+            .unwrap_or_default()
+    }
+
+    /// The characters `count` repetitions of `motion` cover from the
+    /// cursor. As in vim, an exclusive motion that ends in the first column
+    /// of a later line stops at the end of the line before, and then covers
+    /// whole lines when it started at or before the first non-blank.
+    pub fn motion_range(&self, motion: Motion, count: usize) -> Option<MotionRange> {
+        let count = count.max(1);
+        let chars: Vec<char> = self.buffer.content().chars().collect();
+        let at = self.char_offset_of(self.cursor);
+        let line = self.cursor.y;
+        let charwise = |start: usize, end: usize| MotionRange { start, end, linewise: false };
+
+        let range = match motion {
+            Motion::WordForward => {
+                let (mut from, mut to) = (at, at);
+                for _ in 0..count {
+                    from = to;
+                    to = next_word_start(&chars, to);
+                }
+                // The last word moved over ends the text at its line's end
+                let from_line = self.position_of_char(from).y;
+                if self.position_of_char(to).y > from_line {
+                    to = self.line_end(from_line).max(at);
+                }
+                charwise(at, to)
+            }
+            Motion::WordEnd => {
+                let end = (0..count).fold(at, |index, _| word_end(&chars, index));
+                charwise(at, (end + 1).min(chars.len()))
+            }
+            Motion::WordBackward => {
+                let start = (0..count).fold(at, |index, _| previous_word_start(&chars, index));
+                self.exclusive_range(start, at)
+            }
+            Motion::LineStart => charwise(self.char_offset_of(Position::new(0, line)), at),
+            Motion::LineEnd => {
+                let last = (line + count - 1).min(self.last_line());
+                charwise(at, self.line_end(last))
+            }
+            Motion::ParagraphForward | Motion::ParagraphBackward => {
+                let forward = motion == Motion::ParagraphForward;
+                let target = (0..count).fold(self.cursor, |from, _| self.paragraph_target(from, forward));
+                self.exclusive_range(at, self.char_offset_of(target))
+            }
+            Motion::SentenceForward | Motion::SentenceBackward => {
+                let forward = motion == Motion::SentenceForward;
+                let target = (0..count).fold(self.cursor, |from, _| self.sentence_target(from, forward));
+                self.exclusive_range(at, self.char_offset_of(target))
+            }
+            Motion::Line => self.line_range(line, (line + count - 1).min(self.last_line())),
+            Motion::LineDown => self.line_range(line, (line + count).min(self.last_line())),
+            Motion::LineUp => self.line_range(line.saturating_sub(count), line),
+            Motion::LastLine => self.line_range(line, self.last_line()),
+            Motion::TextObject { object: 'p', around } => self.paragraph_object(around),
+            Motion::TextObject { object, around } => {
+                let (start, end) = match object {
+                    'w' => self.word_object(around),
+                    'b' => self.pair_object('(', around)?,
+                    'B' => self.pair_object('{', around)?,
+                    delimiter => self.pair_object(delimiter, around)?,
+                };
+                charwise(start, end)
+            }
+        };
+        Some(range)
+    }
+
+    /// The first and last lines `count` repetitions of `motion` from the
+    /// cursor touch, as `zf` folds them.
+    pub fn motion_lines(&self, motion: Motion, count: usize) -> Option<(usize, usize)> {
+        self.motion_range(motion, count).map(|range| self.range_lines(range))
+    }
+
+    /// The first and last lines the selection touches.
+    pub fn selection_lines(&self) -> Option<(usize, usize)> {
+        let (start, end) = self.visual_range()?;
+        Some(self.range_lines(MotionRange { start, end, linewise: false }))
+    }
+
+    fn range_lines(&self, range: MotionRange) -> (usize, usize) {
+        // A character-wise range ends before its `end`
+        let last = if range.linewise { range.end } else { range.end.saturating_sub(1).max(range.start) };
+        (self.position_of_char(range.start).y, self.position_of_char(last).y)
+    }
+
+    /// Applies `operator` to the selection, as pressing it in visual mode
+    /// does, and drops the selection. Works just as the operator does over
+    /// a motion covering the same characters.
+    pub fn operate_on_selection(&mut self, operator: Operator) -> Result<Option<String>> {
+        let Some((start, end)) = self.visual_range() else {
+            return Ok(None);
+        };
+        self.clear_selection();
+        self.apply_operator(operator, MotionRange { start, end, linewise: false }).map(Some)
+    }
+
+    /// Applies `operator` to `range`, leaving the cursor where vim does,
+    /// and returns the text it acted on.
+    fn apply_operator(&mut self, operator: Operator, range: MotionRange) -> Result<String> {
+        if operator.modifies() {
+            self.check_modifiable()?;
+        }
+        let text: String = self.buffer.content().chars().skip(range.start).take(range.end - range.start).collect();
+        let first_line = self.position_of_char(range.start).y;
+
+        if !operator.takes_text() {
+            if matches!(operator, Operator::ShiftRight | Operator::ShiftLeft) {
+                let (_, last_line) = self.range_lines(range);
+                self.shift_lines(first_line, last_line, operator == Operator::ShiftRight)?;
+            } else {
+                self.change_case(range, operator, &text)?;
+            }
+            return Ok(text);
+        }
+
+        if !range.linewise {
+            if operator == Operator::Yank {
+                self.cursor = self.position_of_char(range.start);
+                self.settle_cursor();
+            } else {
+                self.cut_range(range.start, range.end)?;
+            }
+            if !text.is_empty() {
+                self.store_taken(operator, text.clone(), ClipboardKind::Charwise);
+            }
+            return Ok(text);
+        }
+
+        match operator {
+            Operator::Yank => {
+                if first_line < self.cursor.y {
+                    self.move_to_line(first_line);
+                }
+            }
+            // The lines stay, emptied, to insert into
+            Operator::Change => self.cut_range(range.start, range.end)?,
+            // Delete, the only operator left
+            _ => {
+                // Takes the line break after the lines, or before the last line
+                let total = self.buffer.content().chars().count();
+                let (start, end) = if range.end < total {
+                    (range.start, range.end + 1)
+                } else {
+                    (range.start.saturating_sub(1), range.end)
+                };
+                self.cut_range(start, end)?;
+
+                let line = first_line.min(self.last_line());
+                let text = self.buffer.get_line(line).unwrap_or("");
+                self.cursor = Position::new(text.len() - text.trim_start().len(), line);
+                self.settle_cursor();
+            }
+        }
+        self.store_taken(operator, format!("{}\n", text), ClipboardKind::Linewise);
+        Ok(text)
+    }
+
+    /// Indents lines `first..=last` by one level, or takes a level of
+    /// leading blanks off them, leaving the cursor on the first non-blank
+    /// of the first line. Empty lines are not indented.
+    fn shift_lines(&mut self, first: usize, last: usize, right: bool) -> Result<()> {
+        let level = if self.expandtab { " ".repeat(self.shift_width) } else { "\t".to_string() };
+        for line in first..=last {
+            let start = self.offset_of(Position::new(0, line));
+            let text = self.buffer.get_line(line).unwrap_or("");
+            if right {
+                if !text.is_empty() {
+                    self.buffer.insert_str(start, &level)?;
+                }
+                continue;
+            }
+
+            // A tab counts as the columns it spans
+            let (mut columns, mut bytes) = (0, 0);
+            for ch in text.chars() {
+                let width = match ch {
+                    ' ' => 1,
+                    '\t' => self.tab_size,
+                    _ => break,
+                };
+                if columns >= self.shift_width {
+                    break;
+                }
+                columns += width;
+                bytes += 1;
+            }
+            if bytes > 0 {
+                self.buffer.delete_range(start..start + bytes)?;
+            }
+        }
+
+        let text = self.buffer.get_line(first).unwrap_or("");
+        self.cursor = Position::new(text.len() - text.trim_start().len(), first);
+        self.settle_cursor();
+        Ok(())
+    }
+
+    /// Rewrites `text`, the characters of `range`, in the case `operator`
+    /// asks for, leaving the cursor at the start of the range.
+    fn change_case(&mut self, range: MotionRange, operator: Operator, text: &str) -> Result<()> {
+        let cased: String = match operator {
+            Operator::Lowercase => text.to_lowercase(),
+            Operator::Uppercase => text.to_uppercase(),
+            _ => text
+                .chars()
+                .flat_map(|ch| if ch.is_lowercase() { ch.to_uppercase().collect::<Vec<_>>() } else { ch.to_lowercase().collect() })
+                .collect(),
+        };
+        if cased != text {
+            self.remove_chars(range.start, range.end)?;
+            self.buffer.insert_str(self.byte_index(range.start), &cased)?;
+        }
+        self.cursor = self.position_of_char(range.start);
+        self.settle_cursor();
+        Ok(())
+    }
+
+    /// Yanks go to `"0`, deletions and changes to `"1` and on.
+    fn store_taken(&mut self, operator: Operator, text: String, kind: ClipboardKind) {
+        if operator == Operator::Yank {
+            self.set_clipboard(text, kind);
+        } else {
+            self.store_deleted(text, kind);
+        }
+    }
+
+    /// Cuts characters `start..end`, which may be empty, leaving the
+    /// cursor at `start`.
+    fn cut_range(&mut self, start: usize, end: usize) -> Result<()> {
+        if start < end {
+            self.check_modifiable()?;
+            self.remove_chars(start, end)?;
+        } else {
+            self.cursor = self.position_of_char(start);
+            self.settle_cursor();
+        }
+        Ok(())
+    }
+
+    /// `start..end` in either order, adjusted by vim's rules for exclusive
+    /// motions ending in the first column of a line.
+    fn exclusive_range(&self, a: usize, b: usize) -> MotionRange {
+        let (start, mut end) = (a.min(b), a.max(b));
+        let (from, to) = (self.position_of_char(start), self.position_of_char(end));
+        if to.x == 0 && to.y > from.y {
+            let line = self.buffer.get_line(from.y).unwrap_or("");
+            if from.x <= line.len() - line.trim_start().len() {
+                return self.line_range(from.y, to.y - 1);
+            }
+            end = self.line_end(to.y - 1);
+        }
+        MotionRange { start, end, linewise: false }
+    }
+
+    /// `ip`: the run of lines around the cursor that are all blank or all
+    /// not. `ap` adds the blank lines after it.
+    fn paragraph_object(&self, around: bool) -> MotionRange {
+        let blank = |y: usize| self.buffer.get_line(y).unwrap_or("").trim().is_empty();
+        let line = self.cursor.y;
+        let last_line = self.last_line();
+        let same = |y: usize| blank(y) == blank(line);
+        let first = (0..line).rev().take_while(|&y| same(y)).last().unwrap_or(line);
+        let mut last = (line + 1..=last_line).take_while(|&y| same(y)).last().unwrap_or(line);
+        if around && !blank(line) {
+            last = (last + 1..=last_line).take_while(|&y| blank(y)).last().unwrap_or(last);
+        }
+        self.line_range(first, last)
+    }
+
+    fn line_range(&self, first: usize, last: usize) -> MotionRange {
+        MotionRange {
+            start: self.char_offset_of(Position::new(0, first)),
+            end: self.line_end(last),
+            linewise: true,
+        }
+    }
+
+    /// The character offset of the end of `line`, before its line break.
+    fn line_end(&self, line: usize) -> usize {
+        self.char_offset_of(Position::new(self.buffer.line_length(line), line))
+    }
+
+    fn last_line(&self) -> usize {
+        self.buffer.line_count().saturating_sub(1)
+    }
+
+    /// `w`: to the start of the next word, on a later line when this one
+    /// has no more. A run of punctuation is a word of its own.
+    pub fn move_word_forward(&mut self) {
+        self.move_by_chars(next_word_start);
+    }
+
+    /// `b`: to the start of this word, or of the one before when already
+    /// there.
+    pub fn move_word_backward(&mut self) {
+        self.move_by_chars(previous_word_start);
+    }
+
+    /// `e`: to the last character of this word, or of the next one when
+    /// already there.
+    pub fn move_word_end(&mut self) {
+        self.move_by_chars(word_end);
+    }
+
+    /// Moves to where `target` goes from the cursor, in the buffer as
+    /// characters. Past the last character the cursor stays on it.
+    fn move_by_chars(&mut self, target: fn(&[char], usize) -> usize) {
+        let chars: Vec<char> = self.buffer.content().chars().collect();
+        let index = target(&chars, self.char_offset_of(self.cursor));
+        self.cursor = self.position_of_char(index.min(chars.len().saturating_sub(1)));
+        self.settle_cursor();
+    }
+
+    fn position_of_char(&self, char_offset: usize) -> Position {
+        self.buffer_offset_to_position(self.byte_index(char_offset))
+    }
+
+    fn char_under_cursor(&self) -> Option<char> {
+        let line = self.buffer.get_line(self.cursor.y)?;
+        line.get(self.cursor.x..)?.chars().next()
+    }
+}
+
+/// `w`: the start of the next word after `index`, where a line break
+/// counts as a blank.
+fn next_word_start(chars: &[char], mut index: usize) -> usize {
+    if let Some(&ch) = chars.get(index) {
+        let class = word_class(ch);
+        if class != WordClass::Blank {
+            while chars.get(index).is_some_and(|&ch| word_class(ch) == class) {
+                index += 1;
+            }
+        }
+    }
+    while chars.get(index).is_some_and(|ch| ch.is_whitespace()) {
+        index += 1;
+    }
+    index
+}
+
+/// `e`: the last character of the word ending after `index`.
+fn word_end(chars: &[char], index: usize) -> usize {
+    let mut index = index + 1;
+    while chars.get(index).is_some_and(|ch| ch.is_whitespace()) {
+        index += 1;
+    }
+    let Some(&ch) = chars.get(index) else {
+        return chars.len().saturating_sub(1);
+    };
+    let class = word_class(ch);
+    while chars.get(index + 1).is_some_and(|&ch| word_class(ch) == class) {
+        index += 1;
+    }
+    index
+}
+
+/// `b`: the start of the word before `index`.
+fn previous_word_start(chars: &[char], mut index: usize) -> usize {
+    while index > 0 && chars[index - 1].is_whitespace() {
+        index -= 1;
+    }
+    let Some(&ch) = index.checked_sub(1).and_then(|before| chars.get(before)) else {
+        return 0;
+    };
+    let class = word_class(ch);
+    index -= 1;
+    while index > 0 && word_class(chars[index - 1]) == class {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Buffer;
+    use crate::core::EditorOperations;
+
+    fn ops_at(text: &str, x: usize, y: usize) -> EditorOps<Buffer> {
+        let mut ops = EditorOps::new(Buffer::from_content(text.to_string()), (80, 24));
+        ops.move_to_position(Position::new(x, y)).unwrap();
+        ops
+    }
+
+    fn run(ops: &mut EditorOps<Buffer>, keys: &str, count: usize) -> Option<String> {
+        let (operator, motion) = Operator::parse(keys).unwrap();
+        let motion = Motion::from_keys(motion, operator.line_key()).unwrap();
+        ops.operate(operator, motion, count).unwrap()
+    }
+
+    #[test]
+    fn test_word_motions_move_the_cursor() {
+        let mut ops = ops_at("\"foo.bar baz\"\n  next", 0, 0);
+        let mut columns = Vec::new();
+        for _ in 0..6 {
+            ops.move_word_forward();
+            columns.push(ops.get_cursor_position().x);
+        }
+        // Each run of punctuation stops w, and the last word goes on to the next line
+        assert_eq!(columns, [1, 4, 5, 9, 12, 2]);
+        assert_eq!(ops.get_cursor_position().y, 1);
+
+        let mut ends = Vec::new();
+        let mut ops = ops_at("\"foo.bar baz\"", 0, 0);
+        for _ in 0..5 {
+            ops.move_word_end();
+            ends.push(ops.get_cursor_position().x);
+        }
+        assert_eq!(ends, [3, 4, 7, 11, 12]);
+
+        let mut starts = Vec::new();
+        for _ in 0..5 {
+            ops.move_word_backward();
+            starts.push(ops.get_cursor_position().x);
+        }
+        assert_eq!(starts, [9, 5, 4, 1, 0]);
+    }
+
+    #[test]
+    fn test_word_operators() {
+        let mut ops = ops_at("one two, three\nfour", 0, 0);
+        assert_eq!(run(&mut ops, "dw", 1).as_deref(), Some("one "));
+        assert_eq!(ops.buffer().content(), "two, three\nfour");
+
+        // Punctuation is a word of its own; the last word stops at the line end
+        assert_eq!(run(&mut ops, "dw", 3).as_deref(), Some("two, three"));
+        assert_eq!(ops.buffer().content(), "\nfour");
+        assert_eq!(ops.clipboard_kind(), ClipboardKind::Charwise);
+
+        // `cw` keeps the blank after the word; `yb` moves back over it
+        let mut ops = ops_at("alpha beta gamma", 6, 0);
+        assert_eq!(run(&mut ops, "cw", 1).as_deref(), Some("beta"));
+        assert_eq!(ops.buffer().content(), "alpha  gamma");
+        ops.move_to_position(Position::new(7, 0)).unwrap();
+        assert_eq!(run(&mut ops, "yb", 2).as_deref(), Some("alpha  "));
+        assert_eq!(ops.get_cursor_position(), Position::new(0, 0));
+        assert_eq!(ops.buffer().content(), "alpha  gamma");
+        assert_eq!(run(&mut ops, "de", 1).as_deref(), Some("alpha"));
+        assert_eq!(ops.clipboard(), "alpha");
+    }
+
+    #[test]
+    fn test_line_end_and_paragraph_operators() {
+        let mut ops = ops_at("keep this\nand that\n\nnext", 5, 0);
+        assert_eq!(run(&mut ops, "y$", 1).as_deref(), Some("this"));
+        assert_eq!(ops.buffer().content(), "keep this\nand that\n\nnext");
+        assert_eq!(run(&mut ops, "d0", 1).as_deref(), Some("keep "));
+
+        // From the first column `d}` takes the paragraph's lines
+        ops.move_to_position(Position::new(0, 0)).unwrap();
+        assert_eq!(run(&mut ops, "d}", 1).as_deref(), Some("this\nand that"));
+        assert_eq!(ops.buffer().content(), "\nnext");
+        assert_eq!(ops.clipboard(), "this\nand that\n");
+        assert_eq!(ops.clipboard_kind(), ClipboardKind::Linewise);
+
+        // Mid-line it stops at the end of the paragraph's last line
+        let mut ops = ops_at("a b\nc d\n\ne", 2, 0);
+        assert_eq!(run(&mut ops, "d}", 1).as_deref(), Some("b\nc d"));
+        assert_eq!(ops.buffer().content(), "a \n\ne");
+    }
+
+    #[test]
+    fn test_linewise_and_object_operators() {
+        let mut ops = ops_at("one\n  two\nthree\nfour", 1, 0);
+        assert_eq!(run(&mut ops, "dd", 1).as_deref(), Some("one"));
+        assert_eq!(ops.get_cursor_position(), Position::new(2, 0));
+        assert_eq!(run(&mut ops, "yy", 2).as_deref(), Some("  two\nthree"));
+        assert_eq!(ops.clipboard(), "  two\nthree\n");
+
+        // On the last lines the line break before them goes instead
+        ops.move_to_position(Position::new(0, 1)).unwrap();
+        assert_eq!(run(&mut ops, "dd", 5).as_deref(), Some("three\nfour"));
+        assert_eq!(ops.buffer().content(), "  two");
+        assert_eq!(run(&mut ops, "cc", 1).as_deref(), Some("  two"));
+        assert_eq!(ops.buffer().content(), "");
+
+        let mut ops = ops_at("call(a, b) done", 6, 0);
+        assert_eq!(run(&mut ops, "ci(", 1).as_deref(), Some("a, b"));
+        assert_eq!(ops.buffer().content(), "call() done");
+        ops.move_to_position(Position::new(7, 0)).unwrap();
+        assert_eq!(run(&mut ops, "daw", 1).as_deref(), Some(" done"));
+        assert_eq!(ops.buffer().content(), "call()");
+        assert_eq!(ops.operate(Operator::Delete, Motion::TextObject { object: '[', around: false }, 1).unwrap(), None);
+        let mut ops = ops_at("one\ntwo\n\nthree", 0, 1);
+        assert_eq!(run(&mut ops, "yip", 1).as_deref(), Some("one\ntwo"));
+        assert_eq!(run(&mut ops, "dap", 1).as_deref(), Some("one\ntwo\n"));
+        assert_eq!(ops.buffer().content(), "three");
+        // j, k and G act over whole lines
+        let mut ops = ops_at("one\ntwo\nthree\nfour", 0, 1);
+        assert_eq!(run(&mut ops, "yk", 1).as_deref(), Some("one\ntwo"));
+        assert_eq!(ops.get_cursor_position().y, 0);
+        assert_eq!(run(&mut ops, "dj", 1).as_deref(), Some("one\ntwo"));
+        assert_eq!(ops.motion_lines(Motion::LastLine, 1), Some((0, 1)));
+        assert_eq!(run(&mut ops, "dG", 1).as_deref(), Some("three\nfour"));
+        assert_eq!(ops.buffer().content(), "");
+        assert_eq!(Motion::from_keys("x", 'd'), None);
+        assert_eq!(Motion::from_keys("dw", 'd'), None);
+    }
+
+    #[test]
+    fn test_delete_and_yank_line() {
+        let mut ops = ops_at("first\nsecond\nlast", 3, 1);
+        assert_eq!(ops.yank_line(), "second\n");
+        assert_eq!((ops.clipboard(), ops.clipboard_kind()), ("second\n", ClipboardKind::Linewise));
+
+        ops.delete_line().unwrap();
+        assert_eq!((ops.buffer().content(), ops.get_cursor_position()), ("first\nlast", Position::new(0, 1)));
+        // The last line goes back up to the one before it
+        ops.delete_line().unwrap();
+        assert_eq!((ops.buffer().content(), ops.get_cursor_position()), ("first", Position::new(0, 0)));
+        assert_eq!(ops.clipboard(), "last\n");
+
+        // The only line leaves one empty line to stand on
+        ops.delete_line().unwrap();
+        assert_eq!((ops.buffer().content(), ops.buffer().line_count()), ("", 1));
+        assert_eq!(ops.get_cursor_position(), Position::origin());
+        ops.move_cursor(0, 1).unwrap();
+        assert_eq!(ops.yank_line(), "\n");
+    }
+
+    #[test]
+    fn test_shift_and_case_operators() {
+        let mut ops = ops_at("fn main() {\nbody();\n\n}", 2, 0);
+        ops.set_shift_width(4);
+        run(&mut ops, ">>", 1);
+        assert_eq!(ops.buffer().get_line(0), Some("    fn main() {"));
+        assert_eq!(ops.get_cursor_position(), Position::new(4, 0));
+        // Blank lines stay empty; `<` takes off at most one level
+        ops.move_to_position(Position::new(0, 1)).unwrap();
+        run(&mut ops, ">}", 1);
+        assert_eq!(ops.buffer().content(), "    fn main() {\n    body();\n\n}");
+        ops.move_to_position(Position::new(0, 0)).unwrap();
+        run(&mut ops, "<}", 1);
+        assert_eq!(ops.buffer().content(), "fn main() {\nbody();\n\n}");
+
+        let mut ops = ops_at("Hello wörld", 0, 0);
+        assert_eq!(run(&mut ops, "gUw", 2).as_deref(), Some("Hello wörld"));
+        assert_eq!(ops.buffer().content(), "HELLO WÖRLD");
+        ops.move_to_position(Position::new(6, 0)).unwrap();
+        run(&mut ops, "guiw", 1);
+        run(&mut ops, "g~~", 1);
+        assert_eq!(ops.buffer().content(), "hello WÖRLD");
+        assert_eq!((ops.get_cursor_position(), ops.clipboard()), (Position::new(0, 0), ""));
+    }
+
+    #[test]
+    fn test_visual_operators_match_motion_operators() {
+        let text = "one two three\nfour";
+        let mut by_motion = ops_at(text, 0, 0);
+        run(&mut by_motion, "dw", 1);
+
+        // `v` over "one " then `d`
+        let mut by_selection = ops_at(text, 0, 0);
+        by_selection.start_selection();
+        by_selection.move_to_position(Position::new(3, 0)).unwrap();
+        assert_eq!(by_selection.operate_on_selection(Operator::Delete).unwrap().as_deref(), Some("one "));
+        assert_eq!(by_selection.buffer().content(), by_motion.buffer().content());
+        assert_eq!(by_selection.get_cursor_position(), by_motion.get_cursor_position());
+        assert_eq!(by_selection.clipboard(), by_motion.clipboard());
+        assert!(!by_selection.has_selection());
+
+        // Upper-casing and shifting a selection reach the same result too
+        for keys in ["gUe", ">e"] {
+            let mut by_motion = ops_at(text, 4, 0);
+            run(&mut by_motion, keys, 1);
+            let mut by_selection = ops_at(text, 4, 0);
+            by_selection.start_selection();
+            by_selection.move_to_position(Position::new(6, 0)).unwrap();
+            let (operator, _) = Operator::parse(keys).unwrap();
+            by_selection.operate_on_selection(operator).unwrap();
+            assert_eq!(by_selection.buffer().content(), by_motion.buffer().content());
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use super::ClipboardKind;
+use std::collections::{HashMap, VecDeque};
+
+/// How many deletions the numbered registers `"1` to `"9` remember.
+pub const NUMBERED_REGISTERS: usize = 9;
+
+/// Text held in a register, and how it was taken.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Register {
+    pub text: String,
+    pub kind: ClipboardKind,
+}
+
+impl Register {
+    pub fn new(text: String, kind: ClipboardKind) -> Self {
+        Self { text, kind }
+    }
+}
+
+/// vim's registers: the unnamed one `p` puts by default, `"0` with the
+/// last yank, `"1` to `"9` with the last deletions and `"a` to `"z`.
+#[derive(Debug, Clone, Default)]
+pub struct Registers {
+    unnamed: Register,
+    yanked: Register,
+    /// `"1` at the front. Each deletion pushes onto the front, shifting the
+    /// others one register along, and `"9` falls off the back.
+    deleted: VecDeque<Register>,
+    named: HashMap<char, Register>,
+}
+
+impl Registers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `"name` picks a register, as `"` and `0` to `9` and `a`
+    /// to `z` do.
+    pub fn is_register(name: char) -> bool {
+        matches!(name, '"' | '0'..='9' | 'a'..='z')
+    }
+
+    /// The register `"name`, if it holds anything yet.
+    pub fn get(&self, name: char) -> Option<&Register> {
+        match name {
+            '"' => Some(&self.unnamed),
+            '0' => Some(&self.yanked),
+            '1'..='9' => self.deleted.get(name as usize - '1' as usize),
+            _ => self.named.get(&name),
+        }
+    }
+
+    /// What `p` puts when no register is given.
+    pub fn unnamed(&self) -> &Register {
+        &self.unnamed
+    }
+
+    /// Stores yanked text in `"name`, or in `"0` when no letter is given.
+    pub fn yank(&mut self, name: Option<char>, register: Register) {
+        self.unnamed = register.clone();
+        match name {
+            Some(letter @ 'a'..='z') => {
+                self.named.insert(letter, register);
+            }
+            _ => self.yanked = register,
+        }
+    }
+
+    /// Stores deleted or changed text in `"name`, or otherwise in `"1`,
+    /// shifting the older deletions towards `"9`. Deleting a single
+    /// character within a line, as `x` does, leaves the numbered registers
+    /// alone.
+    pub fn delete(&mut self, name: Option<char>, register: Register) {
+        self.unnamed = register.clone();
+        match name {
+            Some(letter @ 'a'..='z') => {
+                self.named.insert(letter, register);
+            }
+            _ if register.kind == ClipboardKind::Linewise || register.text.chars().nth(1).is_some() => {
+                self.deleted.push_front(register);
+                self.deleted.truncate(NUMBERED_REGISTERS);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Buffer;
+    use crate::core::{EditorOperations, Position, TextBuffer};
+    use crate::editor_ops::{EditorOps, Motion, Operator};
+
+    fn charwise(text: &str) -> Register {
+        Register::new(text.to_string(), ClipboardKind::Charwise)
+    }
+
+    #[test]
+    fn test_deletions_shift_through_numbered_registers() {
+        let mut registers = Registers::new();
+        for text in ["first", "second", "third"] {
+            registers.delete(None, charwise(text));
+        }
+        let numbered: Vec<&str> = ('1'..='3').map(|name| registers.get(name).unwrap().text.as_str()).collect();
+        assert_eq!(numbered, ["third", "second", "first"]);
+        assert!(registers.get('4').is_none());
+
+        // A single character, a named register and a yank leave them be
+        registers.delete(None, charwise("x"));
+        registers.delete(Some('a'), charwise("into a"));
+        registers.yank(None, charwise("yanked"));
+        assert_eq!(registers.get('1').unwrap().text, "third");
+        assert_eq!(registers.get('a').unwrap().text, "into a");
+        assert_eq!(registers.get('0').unwrap().text, "yanked");
+        assert_eq!(registers.unnamed().text, "yanked");
+
+        // Only the last nine are kept
+        for index in 0..10 {
+            registers.delete(None, charwise(&format!("delete {}", index)));
+        }
+        assert_eq!(registers.get('1').unwrap().text, "delete 9");
+        assert_eq!(registers.get('9').unwrap().text, "delete 1");
+        assert!(!Registers::is_register('!'));
+    }
+
+    #[test]
+    fn test_paste_from_numbered_register() {
+        let buffer = Buffer::from_content("one\ntwo\nthree\nfour".to_string());
+        let mut ops = EditorOps::new(buffer, (80, 24));
+        for _ in 0..3 {
+            ops.operate(Operator::Delete, Motion::Line, 1).unwrap();
+        }
+        assert_eq!(ops.buffer().content(), "four");
+
+        // "3p puts back the oldest of the three, "1P the latest
+        assert!(ops.select_register('3'));
+        ops.paste_after().unwrap();
+        assert_eq!(ops.buffer().content(), "four\none");
+        ops.move_to_position(Position::new(0, 0)).unwrap();
+        assert!(ops.select_register('1'));
+        ops.paste_before().unwrap();
+        assert_eq!(ops.buffer().content(), "three\nfour\none");
+
+        // Without a register p puts the last deletion again
+        ops.paste_after().unwrap();
+        assert_eq!(ops.buffer().get_line(1), Some("three"));
+    }
+}
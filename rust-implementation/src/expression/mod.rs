@@ -0,0 +1,80 @@
+use crate::datetime::DateTime;
+
+/// A value `:put` and `Ctrl-R =` can insert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expression {
+    Date,
+    Time,
+    Filename,
+    LineNumber,
+}
+
+/// What an expression is evaluated against. `now` is passed in rather than
+/// read from the clock so the result can be checked.
+#[derive(Debug, Clone, Copy)]
+pub struct ExpressionContext<'a> {
+    pub now: DateTime,
+    pub date_format: &'a str,
+    pub time_format: &'a str,
+    pub filename: &'a str,
+    /// The cursor line, 0-based.
+    pub line: usize,
+}
+
+impl Expression {
+    /// The expression called `name`, as typed after `:put` or `Ctrl-R =`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim() {
+            "date" => Some(Expression::Date),
+            "time" => Some(Expression::Time),
+            "filename" | "file" => Some(Expression::Filename),
+            "line" | "linenumber" => Some(Expression::LineNumber),
+            _ => None,
+        }
+    }
+
+    pub fn evaluate(&self, context: &ExpressionContext) -> String {
+        match self {
+            Expression::Date => context.now.format(context.date_format),
+            Expression::Time => context.now.format(context.time_format),
+            Expression::Filename => context.filename.to_string(),
+            Expression::LineNumber => (context.line + 1).to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(filename: &str, line: usize) -> ExpressionContext<'_> {
+        ExpressionContext {
+            // 2001-09-09 01:46:40 UTC
+            now: DateTime::from_unix_seconds(1_000_000_000),
+            date_format: "%Y-%m-%d",
+            time_format: "%H:%M",
+            filename,
+            line,
+        }
+    }
+
+    #[test]
+    fn test_evaluate_buffer_expressions() {
+        let context = context("notes/log.md", 41);
+        assert_eq!(Expression::Filename.evaluate(&context), "notes/log.md");
+        // Lines count from 1, as on the status line
+        assert_eq!(Expression::LineNumber.evaluate(&context), "42");
+        assert_eq!(Expression::from_name(" line "), Some(Expression::LineNumber));
+        assert_eq!(Expression::from_name("strftime"), None);
+    }
+
+    #[test]
+    fn test_evaluate_date_from_fixed_clock() {
+        let mut context = context("", 0);
+        assert_eq!(Expression::Date.evaluate(&context), "2001-09-09");
+        assert_eq!(Expression::Time.evaluate(&context), "01:46");
+
+        context.date_format = "%d/%m/%Y %H:%M:%S";
+        assert_eq!(Expression::Date.evaluate(&context), "09/09/2001 01:46:40");
+    }
+}
@@ -0,0 +1,126 @@
+use crate::core::{EditorError, Encoding, Result};
+
+const UTF16_LE_BOM: [u8; 2] = [0xFF, 0xFE];
+const UTF16_BE_BOM: [u8; 2] = [0xFE, 0xFF];
+
+/// Picks the encoding of raw file bytes: UTF-16 when a BOM says so, UTF-8
+/// when the bytes are valid UTF-8, and Latin-1 otherwise.
+pub fn detect(bytes: &[u8]) -> Encoding {
+    if bytes.starts_with(&UTF16_LE_BOM) {
+        Encoding::Utf16Le
+    } else if bytes.starts_with(&UTF16_BE_BOM) {
+        Encoding::Utf16Be
+    } else if std::str::from_utf8(bytes).is_ok() {
+        Encoding::Utf8
+    } else {
+        Encoding::Latin1
+    }
+}
+
+/// Decodes `bytes`, dropping a UTF-16 BOM. Unpaired UTF-16 surrogates
+/// become replacement characters; an odd number of UTF-16 bytes is an
+/// error.
+pub fn decode(bytes: &[u8], encoding: Encoding) -> Result<String> {
+    match encoding {
+        Encoding::Utf8 => String::from_utf8(bytes.to_vec())
+            .map_err(|e| EditorError::InvalidOperation(format!("Invalid UTF-8: {}", e))),
+        Encoding::Latin1 => Ok(bytes.iter().map(|&b| b as char).collect()),
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let body = bytes
+                .strip_prefix(&bom(encoding)[..])
+                .unwrap_or(bytes);
+            if !body.len().is_multiple_of(2) {
+                return Err(EditorError::InvalidOperation(format!("Invalid UTF-16: odd length of {} bytes", body.len())));
+            }
+            let units: Vec<u16> = body
+                .chunks_exact(2)
+                .map(|pair| {
+                    let pair = [pair[0], pair[1]];
+                    if encoding == Encoding::Utf16Le {
+                        u16::from_le_bytes(pair)
+                    } else {
+                        u16::from_be_bytes(pair)
+                    }
+                })
+                .collect();
+            Ok(String::from_utf16_lossy(&units))
+        }
+    }
+}
+
+/// Encodes `text` for writing, with a BOM for UTF-16. Fails for Latin-1
+/// when a character lies outside its range.
+pub fn encode(text: &str, encoding: Encoding) -> Result<Vec<u8>> {
+    match encoding {
+        Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+        Encoding::Latin1 => text
+            .chars()
+            .map(|ch| {
+                u8::try_from(ch as u32).map_err(|_| {
+                    EditorError::InvalidOperation(format!("Cannot encode '{}' as latin1", ch))
+                })
+            })
+            .collect(),
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let mut bytes = bom(encoding).to_vec();
+            for unit in text.encode_utf16() {
+                let pair = if encoding == Encoding::Utf16Le {
+                    unit.to_le_bytes()
+                } else {
+                    unit.to_be_bytes()
+                };
+                bytes.extend_from_slice(&pair);
+            }
+            Ok(bytes)
+        }
+    }
+}
+
+fn bom(encoding: Encoding) -> [u8; 2] {
+    if encoding == Encoding::Utf16Be {
+        UTF16_BE_BOM
+    } else {
+        UTF16_LE_BOM
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utf16_with_bom_round_trip() {
+        // "hé\n" in UTF-16LE with a BOM
+        let bytes = [0xFF, 0xFE, b'h', 0x00, 0xE9, 0x00, b'\n', 0x00];
+
+        assert_eq!(detect(&bytes), Encoding::Utf16Le);
+        let text = decode(&bytes, Encoding::Utf16Le).unwrap();
+        assert_eq!(text, "hé\n");
+        assert_eq!(encode(&text, Encoding::Utf16Le).unwrap(), bytes);
+
+        let be = encode("hé\n", Encoding::Utf16Be).unwrap();
+        assert_eq!(&be[..4], &[0xFE, 0xFF, 0x00, b'h']);
+        assert_eq!(decode(&be, detect(&be)).unwrap(), "hé\n");
+    }
+
+    #[test]
+    fn test_utf16_odd_length_is_rejected() {
+        // "h" and half of "é" in UTF-16BE with a BOM
+        let bytes = [0xFE, 0xFF, 0x00, b'h', 0x00];
+
+        assert_eq!(detect(&bytes), Encoding::Utf16Be);
+        assert!(decode(&bytes, Encoding::Utf16Be).is_err());
+        assert!(decode(&bytes[2..4], Encoding::Utf16Be).is_ok());
+    }
+
+    #[test]
+    fn test_latin1_fallback() {
+        let bytes = b"caf\xe9";
+
+        assert_eq!(detect(bytes), Encoding::Latin1);
+        assert_eq!(decode(bytes, Encoding::Latin1).unwrap(), "café");
+        assert_eq!(encode("café", Encoding::Latin1).unwrap(), bytes);
+        assert!(encode("€", Encoding::Latin1).is_err());
+        assert_eq!(detect("café".as_bytes()), Encoding::Utf8);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::core::Result;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// How many bytes are scanned at a time while indexing line starts.
+pub const LAZY_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Read-only access to a file too large to load whole. Line start offsets
+/// are indexed only as far as the lines asked for, and each line is read
+/// from disk when requested.
+#[derive(Debug)]
+pub struct LazyFile {
+    file: BufReader<File>,
+    len: u64,
+    line_starts: Vec<u64>,
+    scanned: u64,
+}
+
+impl LazyFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            file: BufReader::new(file),
+            len,
+            line_starts: vec![0],
+            scanned: 0,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bytes indexed so far; less than `len` until the whole file is needed.
+    pub fn bytes_scanned(&self) -> u64 {
+        self.scanned
+    }
+
+    /// Indexes chunks until `line` is known to start, or the file ends.
+    fn index_through(&mut self, line: usize) -> Result<()> {
+        let mut chunk = vec![0; LAZY_CHUNK_SIZE];
+        while self.line_starts.len() <= line + 1 && self.scanned < self.len {
+            self.file.seek(SeekFrom::Start(self.scanned))?;
+            let read = self.file.read(&mut chunk)?;
+            if read == 0 {
+                break;
+            }
+            for (i, &byte) in chunk[..read].iter().enumerate() {
+                if byte == b'\n' {
+                    self.line_starts.push(self.scanned + i as u64 + 1);
+                }
+            }
+            self.scanned += read as u64;
+        }
+        Ok(())
+    }
+
+    /// Total number of lines. This indexes the whole file.
+    pub fn line_count(&mut self) -> Result<usize> {
+        self.index_through(usize::MAX - 1)?;
+        let trailing_newline = self.line_starts.last() == Some(&self.len) && self.len > 0;
+        Ok(self.line_starts.len() - usize::from(trailing_newline))
+    }
+
+    /// Reads line `index` without its newline, or `None` past the end.
+    pub fn line(&mut self, index: usize) -> Result<Option<String>> {
+        self.index_through(index)?;
+        let Some(&start) = self.line_starts.get(index) else {
+            return Ok(None);
+        };
+        if start >= self.len && index > 0 {
+            return Ok(None);
+        }
+
+        self.file.seek(SeekFrom::Start(start))?;
+        let mut bytes = Vec::new();
+        self.file.read_until(b'\n', &mut bytes)?;
+        while matches!(bytes.last(), Some(b'\n' | b'\r')) {
+            bytes.pop();
+        }
+        Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+    }
+
+    /// Up to `count` lines starting at `first`.
+    pub fn lines(&mut self, first: usize, count: usize) -> Result<Vec<String>> {
+        let mut lines = Vec::with_capacity(count);
+        for index in first..first.saturating_add(count) {
+            match self.line(index)? {
+                Some(line) => lines.push(line),
+                None => break,
+            }
+        }
+        Ok(lines)
+    }
+}
+
+/// A scrolling, read-only window onto a `LazyFile`.
+#[derive(Debug)]
+pub struct LargeFileView {
+    file: LazyFile,
+    top_line: usize,
+}
+
+impl LargeFileView {
+    pub fn new(file: LazyFile) -> Self {
+        Self { file, top_line: 0 }
+    }
+
+    pub fn top_line(&self) -> usize {
+        self.top_line
+    }
+
+    /// Scrolls by `rows`, stopping at the first and last lines.
+    pub fn scroll(&mut self, rows: i32) -> Result<()> {
+        let target = (self.top_line as i64 + rows as i64).max(0) as usize;
+        self.top_line = if rows > 0 && self.file.line(target)?.is_none() {
+            self.file.line_count()?.saturating_sub(1)
+        } else {
+            target
+        };
+        Ok(())
+    }
+
+    /// The visible page of `rows` lines, joined for `render_text`.
+    pub fn render(&mut self, rows: usize) -> Result<String> {
+        Ok(self.file.lines(self.top_line, rows)?.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_first_page_reads_without_loading_everything() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("big.log");
+        let mut file = std::io::BufWriter::new(File::create(&path).unwrap());
+        for i in 0..200_000 {
+            writeln!(file, "line {:06} of the log", i).unwrap();
+        }
+        drop(file);
+
+        let mut lazy = LazyFile::open(&path).unwrap();
+        let page = lazy.lines(0, 3).unwrap();
+        assert_eq!(page, vec!["line 000000 of the log", "line 000001 of the log", "line 000002 of the log"]);
+        assert!(lazy.bytes_scanned() <= LAZY_CHUNK_SIZE as u64);
+        assert!(lazy.bytes_scanned() < lazy.len());
+
+        assert_eq!(lazy.line(150_000).unwrap().as_deref(), Some("line 150000 of the log"));
+        assert!(lazy.bytes_scanned() < lazy.len());
+
+        assert_eq!(lazy.line_count().unwrap(), 200_000);
+        assert_eq!(lazy.line(200_000).unwrap(), None);
+    }
+
+    #[test]
+    fn test_view_scrolls_within_the_file() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("small.txt");
+        std::fs::write(&path, "a\nb\nc").unwrap();
+
+        let mut view = LargeFileView::new(LazyFile::open(&path).unwrap());
+        view.scroll(1).unwrap();
+        assert_eq!(view.render(5).unwrap(), "b\nc");
+        view.scroll(10).unwrap();
+        assert_eq!(view.top_line(), 2);
+        view.scroll(-10).unwrap();
+        assert_eq!(view.render(2).unwrap(), "a\nb");
+    }
+}
@@ -0,0 +1,101 @@
+use crate::core::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A `.filename.lock` file next to an open file, holding the editor's PID so
+/// that a second instance can warn before editing the same file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockFile {
+    path: PathBuf,
+    pid: u32,
+}
+
+impl LockFile {
+    /// Where the lock for `file` lives: `dir/.name.lock`.
+    pub fn path_for(file: &Path) -> PathBuf {
+        let name = file.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        file.with_file_name(format!(".{}.lock", name))
+    }
+
+    /// PID of another running editor holding `file`'s lock. Locks left by
+    /// processes that no longer exist are stale and ignored.
+    pub fn holder(file: &Path) -> Option<u32> {
+        let pid = fs::read_to_string(Self::path_for(file)).ok()?.trim().parse::<u32>().ok()?;
+        (pid != std::process::id() && process_alive(pid)).then_some(pid)
+    }
+
+    /// Writes the lock for `file`, replacing a stale one.
+    pub fn acquire(file: &Path) -> Result<Self> {
+        let lock = Self {
+            path: Self::path_for(file),
+            pid: std::process::id(),
+        };
+        fs::write(&lock.path, lock.pid.to_string())?;
+        Ok(lock)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Removes the lock file, unless another instance has since taken it over.
+    pub fn release(&self) -> Result<()> {
+        let owner = fs::read_to_string(&self.path).ok().and_then(|pid| pid.trim().parse::<u32>().ok());
+        if owner == Some(self.pid) {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks `/proc` where available; elsewhere every PID is assumed alive so
+/// a lock is never wrongly discarded.
+fn process_alive(pid: u32) -> bool {
+    let proc_dir = Path::new("/proc");
+    !proc_dir.is_dir() || proc_dir.join(pid.to_string()).exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_lock_creation_and_removal() {
+        let temp_dir = tempdir().unwrap();
+        let file = temp_dir.path().join("notes.txt");
+
+        let lock = LockFile::acquire(&file).unwrap();
+        assert_eq!(lock.path(), temp_dir.path().join(".notes.txt.lock"));
+        assert_eq!(fs::read_to_string(lock.path()).unwrap(), std::process::id().to_string());
+        // Our own lock does not count as held by another editor
+        assert_eq!(LockFile::holder(&file), None);
+
+        lock.release().unwrap();
+        assert!(!lock.path().exists());
+    }
+
+    #[test]
+    fn test_stale_lock_is_ignored() {
+        let temp_dir = tempdir().unwrap();
+        let file = temp_dir.path().join("notes.txt");
+
+        // PIDs are far below u32::MAX, so this process cannot exist
+        fs::write(LockFile::path_for(&file), (u32::MAX - 1).to_string()).unwrap();
+        assert_eq!(LockFile::holder(&file), None);
+
+        let lock = LockFile::acquire(&file).unwrap();
+        assert_eq!(fs::read_to_string(lock.path()).unwrap(), std::process::id().to_string());
+    }
+
+    #[test]
+    fn test_release_keeps_a_lock_taken_over_by_another_editor() {
+        let temp_dir = tempdir().unwrap();
+        let file = temp_dir.path().join("notes.txt");
+
+        let lock = LockFile::acquire(&file).unwrap();
+        fs::write(lock.path(), "1").unwrap();
+        lock.release().unwrap();
+        assert!(lock.path().exists());
+    }
+}
@@ -1,7 +1,24 @@
-use crate::core::{EditorError, FileManager, Result};
+use crate::core::{EditorError, Encoding, FileManager, Result};
+use crate::search::match_ranges;
 use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
+pub mod encoding;
+pub mod lazy;
+pub mod lock;
+pub use lazy::{LargeFileView, LazyFile};
+pub use lock::LockFile;
+
+/// How much of a file's head is sampled when checking for binary content.
+pub const BINARY_SAMPLE_SIZE: u64 = 8192;
+
+/// Bytes read between progress reports in `open_with_progress`.
+pub const PROGRESS_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Files larger than this are skipped by `grep`.
+pub const GREP_MAX_FILE_SIZE: u64 = 1_000_000;
+
 #[derive(Clone)]
 pub struct FileSystem {
     current_directory: PathBuf,
@@ -10,7 +27,7 @@ pub struct FileSystem {
 impl FileSystem {
     pub fn new() -> Result<Self> {
         let current_directory = std::env::current_dir()
-            .map_err(|e| EditorError::Io(e))?;
+            .map_err(EditorError::Io)?;
 
         Ok(Self {
             current_directory,
@@ -69,11 +86,39 @@ impl FileSystem {
         }
     }
 
-    pub fn backup_file<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+    /// Reads a file as raw bytes, without requiring valid UTF-8.
+    pub fn read_bytes<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
         let path = self.resolve_path(path);
-        let backup_path = path.with_extension(
-            format!("{}.backup", path.extension().unwrap_or_default().to_string_lossy())
-        );
+        Ok(fs::read(path)?)
+    }
+
+    /// Samples the head of a file for NUL bytes or invalid UTF-8.
+    pub fn is_binary<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let path = self.resolve_path(path);
+        let mut sample = Vec::new();
+        fs::File::open(path)?
+            .take(BINARY_SAMPLE_SIZE)
+            .read_to_end(&mut sample)?;
+        Ok(is_binary_sample(&sample))
+    }
+
+    /// Copies the file to a `.backup` file next to it or, given `dir`, into
+    /// that directory. Backups in a shared directory are named after the
+    /// whole path, with `%` for each separator, so that files of the same
+    /// name in different directories keep separate backups.
+    pub fn backup_file<P: AsRef<Path>>(&self, path: P, dir: Option<&Path>) -> Result<PathBuf> {
+        let path = self.resolve_path(path);
+        let backup_path = match dir {
+            Some(dir) => {
+                let dir = self.resolve_path(dir);
+                fs::create_dir_all(&dir)?;
+                let name = path.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "%");
+                dir.join(format!("{}.backup", name))
+            }
+            None => path.with_extension(
+                format!("{}.backup", path.extension().unwrap_or_default().to_string_lossy())
+            ),
+        };
 
         if path.exists() {
             fs::copy(&path, &backup_path)?;
@@ -82,6 +127,85 @@ impl FileSystem {
         Ok(backup_path)
     }
 
+    /// Lists the entries of a directory in the order the OS returns them.
+    pub fn list_directory<P: AsRef<Path>>(&self, path: P) -> Result<Vec<DirEntry>> {
+        let path = self.resolve_path(path);
+        let mut entries = Vec::new();
+
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            entries.push(DirEntry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                is_dir: entry.path().is_dir(),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// Files under the current directory whose path matches `glob`, sorted.
+    /// A glob without `/` is matched against file names only. Hidden files
+    /// and directories are skipped.
+    pub fn find_files(&self, glob: &str) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        self.collect_files(Path::new(""), glob, &mut files)?;
+        files.sort();
+        Ok(files)
+    }
+
+    fn collect_files(&self, relative: &Path, glob: &str, files: &mut Vec<PathBuf>) -> Result<()> {
+        for entry in self.list_directory(relative)? {
+            if entry.name.starts_with('.') {
+                continue;
+            }
+
+            let path = relative.join(&entry.name);
+            if entry.is_dir {
+                self.collect_files(&path, glob, files)?;
+            } else {
+                let subject = if glob.contains('/') {
+                    path.to_string_lossy().into_owned()
+                } else {
+                    entry.name
+                };
+                if glob_match(glob, &subject) {
+                    files.push(path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The first occurrence of `pattern` on each line of `files`. Files over
+    /// `GREP_MAX_FILE_SIZE`, binary files and unreadable files are skipped.
+    pub fn grep(&self, pattern: &str, files: &[PathBuf]) -> Result<Vec<Match>> {
+        let mut matches = Vec::new();
+
+        for file in files {
+            let path = self.resolve_path(file);
+            let too_large = fs::metadata(&path).map(|m| m.len() > GREP_MAX_FILE_SIZE).unwrap_or(true);
+            if too_large || self.is_binary(&path).unwrap_or(true) {
+                continue;
+            }
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+
+            for (line, text) in content.lines().enumerate() {
+                if let Some(&(column, _)) = match_ranges(text, pattern, true).first() {
+                    matches.push(Match {
+                        path: file.clone(),
+                        line,
+                        column,
+                        text: text.to_string(),
+                    });
+                }
+            }
+        }
+
+        Ok(matches)
+    }
+
     pub fn get_file_metadata<P: AsRef<Path>>(&self, path: P) -> Result<FileMetadata> {
         let path = self.resolve_path(path);
         let metadata = fs::metadata(&path)?;
@@ -104,7 +228,23 @@ impl Default for FileSystem {
 }
 
 impl FileManager for FileSystem {
+    fn resolve(&self, filename: &str) -> PathBuf {
+        self.resolve_path(filename)
+    }
+
     fn open(&self, filename: &str) -> Result<String> {
+        self.open_with_encoding(filename).map(|(content, _)| content)
+    }
+
+    fn save(&self, filename: &str, content: &str) -> Result<()> {
+        self.save_with_encoding(filename, content, Encoding::Utf8)
+    }
+
+    fn open_with_encoding(&self, filename: &str) -> Result<(String, Encoding)> {
+        self.open_with_progress(filename, &mut |_| {})
+    }
+
+    fn open_with_progress(&self, filename: &str, progress: &mut dyn FnMut(f64)) -> Result<(String, Encoding)> {
         let path = self.resolve_path(filename);
 
         if !path.exists() {
@@ -121,19 +261,21 @@ impl FileManager for FileSystem {
             )));
         }
 
-        let content = fs::read_to_string(&path)
-            .map_err(|e| EditorError::Io(e))?;
+        if self.is_binary(&path)? {
+            return Err(EditorError::InvalidOperation(
+                format!("{} is a binary file", filename)
+            ));
+        }
 
-        Ok(content)
+        let bytes = read_with_progress(&path, PROGRESS_CHUNK_SIZE, progress)?;
+        let detected = encoding::detect(&bytes);
+
+        Ok((encoding::decode(&bytes, detected)?, detected))
     }
 
-    fn save(&self, filename: &str, content: &str) -> Result<()> {
+    fn save_with_encoding(&self, filename: &str, content: &str, file_encoding: Encoding) -> Result<()> {
         let path = self.resolve_path(filename);
-
-        // Create backup if file exists
-        if path.exists() {
-            self.backup_file(&path)?;
-        }
+        let bytes = encoding::encode(content, file_encoding)?;
 
         // Check if we can write to the file
         if !self.is_writable(&path)? {
@@ -150,13 +292,151 @@ impl FileManager for FileSystem {
             }
         }
 
-        fs::write(&path, content)
-            .map_err(|e| EditorError::Io(e))?;
+        fs::write(&path, bytes)
+            .map_err(EditorError::Io)?;
 
         Ok(())
     }
 }
 
+/// Parses a size such as `2048`, `500K`, `10M` or `1G` into bytes. The
+/// suffixes are powers of 1024 and may be lower case.
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let (digits, multiplier) = match text.char_indices().last()? {
+        (idx, 'k' | 'K') => (&text[..idx], 1 << 10),
+        (idx, 'm' | 'M') => (&text[..idx], 1 << 20),
+        (idx, 'g' | 'G') => (&text[..idx], 1 << 30),
+        _ => (text, 1),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Splits a trailing `:line` or `:line:column`, as in `src/main.rs:42:5`,
+/// off a file argument, returning the file name and the 1-based line and
+/// column (1 when not given). A name that `exists` wins: a file called
+/// `log:12` opens whole, and `log:12:3` opens it at line 3. Arguments
+/// without a numeric suffix are taken whole.
+pub fn split_file_position(arg: &str, exists: impl Fn(&str) -> bool) -> (&str, Option<(usize, usize)>) {
+    if exists(arg) {
+        return (arg, None);
+    }
+    let number = |text: &str| text.parse::<usize>().ok().filter(|_| text.bytes().all(|b| b.is_ascii_digit()));
+    let split = |text: &str| -> Option<(usize, usize)> {
+        let (name, last) = text.rsplit_once(':')?;
+        Some((name.len(), number(last)?))
+    };
+
+    // `name:line:column` before `name:line`
+    let mut candidates = Vec::new();
+    if let Some((end, last)) = split(arg) {
+        if let Some((name, line)) = split(&arg[..end]).filter(|&(name, _)| name > 0) {
+            candidates.push((&arg[..name], (line, last)));
+        }
+        if end > 0 {
+            candidates.push((&arg[..end], (last, 1)));
+        }
+    }
+    match candidates.iter().find(|(name, _)| exists(name)).or(candidates.first()) {
+        Some(&(name, position)) => (name, Some(position)),
+        None => (arg, None),
+    }
+}
+
+/// Reads a whole file `chunk_size` bytes at a time, reporting the fraction
+/// read after each chunk.
+fn read_with_progress(path: &Path, chunk_size: usize, progress: &mut dyn FnMut(f64)) -> Result<Vec<u8>> {
+    let mut file = fs::File::open(path)?;
+    let total = file.metadata()?.len();
+    let mut bytes = Vec::with_capacity(total as usize);
+    let mut chunk = vec![0; chunk_size];
+
+    loop {
+        let read = file.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&chunk[..read]);
+        if total > 0 {
+            progress((bytes.len() as f64 / total as f64).min(1.0));
+        }
+    }
+    progress(1.0);
+
+    Ok(bytes)
+}
+
+/// A sample is binary if it holds a NUL byte, or is invalid UTF-8 that also
+/// contains control bytes (plain invalid UTF-8 is read as Latin-1). UTF-16
+/// with a BOM is text, and a multi-byte character cut off at the end of the
+/// sample does not count as invalid.
+fn is_binary_sample(sample: &[u8]) -> bool {
+    if matches!(encoding::detect(sample), Encoding::Utf16Le | Encoding::Utf16Be) {
+        return false;
+    }
+    if sample.contains(&0) {
+        return true;
+    }
+    match std::str::from_utf8(sample) {
+        Ok(_) => false,
+        Err(e) if e.error_len().is_none() => false,
+        Err(_) => sample
+            .iter()
+            .any(|&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b)),
+    }
+}
+
+/// Shell-style matching where `*` matches any run of characters other than
+/// `/` and `?` matches exactly one.
+fn glob_match(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut g, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match glob.get(g) {
+            Some('*') => {
+                backtrack = Some((g, t));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                g += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` swallow one more character
+                Some((star, from)) if text[from] != '/' => {
+                    backtrack = Some((star, from + 1));
+                    g = star + 1;
+                    t = from + 1;
+                }
+                _ => return false,
+            },
+        }
+    }
+
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+/// One `grep` hit; `line` and `column` are zero-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct FileMetadata {
     pub size: u64,
@@ -169,6 +449,8 @@ pub struct FileMetadata {
 pub struct SafeFileManager {
     file_system: FileSystem,
     auto_backup: bool,
+    /// Where backups go; next to the file when `None`.
+    backup_dir: Option<PathBuf>,
     max_file_size: u64,
 }
 
@@ -177,6 +459,7 @@ impl SafeFileManager {
         Ok(Self {
             file_system: FileSystem::new()?,
             auto_backup,
+            backup_dir: None,
             max_file_size,
         })
     }
@@ -189,6 +472,23 @@ impl SafeFileManager {
         self.auto_backup = enabled;
     }
 
+    pub fn set_backup_dir(&mut self, dir: Option<PathBuf>) {
+        self.backup_dir = dir;
+    }
+
+    pub fn max_file_size(&self) -> u64 {
+        self.max_file_size
+    }
+
+    /// Whether `filename` is over the size limit and must be opened with
+    /// `LazyFile` instead.
+    pub fn exceeds_size_limit(&self, filename: &str) -> bool {
+        self.file_system
+            .get_file_metadata(filename)
+            .map(|metadata| metadata.size > self.max_file_size)
+            .unwrap_or(false)
+    }
+
     pub fn set_max_file_size(&mut self, size: u64) {
         self.max_file_size = size;
     }
@@ -224,10 +524,26 @@ impl SafeFileManager {
 }
 
 impl FileManager for SafeFileManager {
+    fn resolve(&self, filename: &str) -> PathBuf {
+        self.file_system.resolve_path(filename)
+    }
+
     fn open(&self, filename: &str) -> Result<String> {
+        self.open_with_encoding(filename).map(|(content, _)| content)
+    }
+
+    fn save(&self, filename: &str, content: &str) -> Result<()> {
+        self.save_with_encoding(filename, content, Encoding::Utf8)
+    }
+
+    fn open_with_encoding(&self, filename: &str) -> Result<(String, Encoding)> {
+        self.open_with_progress(filename, &mut |_| {})
+    }
+
+    fn open_with_progress(&self, filename: &str, progress: &mut dyn FnMut(f64)) -> Result<(String, Encoding)> {
         Self::validate_filename(filename)?;
 
-        let content = self.file_system.open(filename)?;
+        let (content, file_encoding) = self.file_system.open_with_progress(filename, progress)?;
 
         // Check file size
         if content.len() as u64 > self.max_file_size {
@@ -236,27 +552,37 @@ impl FileManager for SafeFileManager {
             ));
         }
 
-        Ok(content)
+        Ok((content, file_encoding))
     }
 
-    fn save(&self, filename: &str, content: &str) -> Result<()> {
+    fn save_with_encoding(&self, filename: &str, content: &str, file_encoding: Encoding) -> Result<()> {
         Self::validate_filename(filename)?;
         self.validate_file_size(content)?;
 
         // Create automatic backup if enabled and file exists
         if self.auto_backup && self.file_system.file_exists(filename) {
-            let backup_path = self.file_system.backup_file(filename)?;
-            eprintln!("Backup created: {}", backup_path.display());
+            self.file_system.backup_file(filename, self.backup_dir.as_deref())?;
         }
 
-        self.file_system.save(filename, content)
+        self.file_system.save_with_encoding(filename, content, file_encoding)
+    }
+
+    fn append(&self, filename: &str, content: &str) -> Result<()> {
+        Self::validate_filename(filename)?;
+        self.validate_file_size(content)?;
+        self.file_system.append(filename, content)
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        Self::validate_filename(to)?;
+        fs::rename(self.resolve(from), self.resolve(to))?;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
     use tempfile::tempdir;
 
     #[test]
@@ -281,6 +607,9 @@ mod tests {
         // Test metadata
         let metadata = fs.get_file_metadata(test_file).unwrap();
         assert_eq!(metadata.size, test_content.len() as u64);
+
+        // Test raw read
+        assert_eq!(fs.read_bytes(test_file).unwrap(), test_content.as_bytes());
     }
 
     #[test]
@@ -304,6 +633,202 @@ mod tests {
         assert!(safe_manager.save("large.txt", &large_content).is_err());
     }
 
+    #[test]
+    fn test_append_to_existing_and_new_files() {
+        let temp_dir = tempdir().unwrap();
+        let mut safe_manager = SafeFileManager::new(true, 1024).unwrap();
+        safe_manager.file_system.set_current_directory(temp_dir.path()).unwrap();
+
+        // Appending starts a new line and leaves no backup behind
+        safe_manager.save("log.txt", "first").unwrap();
+        safe_manager.append("log.txt", "second\nthird").unwrap();
+        assert_eq!(safe_manager.open("log.txt").unwrap(), "first\nsecond\nthird");
+        assert!(!temp_dir.path().join("log.txt.backup").exists());
+
+        safe_manager.append("new.txt", "created").unwrap();
+        assert_eq!(safe_manager.open("new.txt").unwrap(), "created");
+        assert!(safe_manager.append("new.txt", &"x".repeat(2048)).is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("2048"), Some(2048));
+        assert_eq!(parse_size("500K"), Some(500 * 1024));
+        assert_eq!(parse_size("10m"), Some(10 * 1024 * 1024));
+        assert_eq!(parse_size("1G"), Some(1 << 30));
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("-5K"), None);
+        assert_eq!(parse_size("ten"), None);
+        assert_eq!(parse_size("99999999999G"), None);
+    }
+
+    #[test]
+    fn test_split_file_position() {
+        let missing = |_: &str| false;
+        assert_eq!(split_file_position("src/main.rs:42", missing), ("src/main.rs", Some((42, 1))));
+        assert_eq!(split_file_position("src/main.rs:42:5", missing), ("src/main.rs", Some((42, 5))));
+        assert_eq!(split_file_position("notes.txt", missing), ("notes.txt", None));
+        assert_eq!(split_file_position("a:b:7", missing), ("a:b", Some((7, 1))));
+        // Suffixes that are not line numbers stay part of the name
+        assert_eq!(split_file_position("notes.txt:", missing), ("notes.txt:", None));
+        assert_eq!(split_file_position("notes.txt:+3", missing), ("notes.txt:+3", None));
+        assert_eq!(split_file_position(":42", missing), (":42", None));
+    }
+
+    #[test]
+    fn test_file_named_with_a_colon_is_opened_whole() {
+        let temp_dir = tempdir().unwrap();
+        let file_system = {
+            let mut file_system = FileSystem::new().unwrap();
+            file_system.set_current_directory(temp_dir.path()).unwrap();
+            file_system
+        };
+        fs::write(temp_dir.path().join("log:12"), "x").unwrap();
+        let exists = |name: &str| file_system.file_exists(name);
+
+        assert_eq!(split_file_position("log:12", exists), ("log:12", None));
+        assert_eq!(split_file_position("log:12:3", exists), ("log:12", Some((3, 1))));
+        // Neither log nor log:5 exists, so the line is split off
+        assert_eq!(split_file_position("log:5", exists), ("log", Some((5, 1))));
+    }
+
+    #[test]
+    fn test_configured_size_limit() {
+        let temp_dir = tempdir().unwrap();
+        let mut safe_manager = SafeFileManager::new(false, 1024).unwrap();
+        safe_manager.file_system.set_current_directory(temp_dir.path()).unwrap();
+        fs::write(temp_dir.path().join("big.txt"), "x".repeat(2000)).unwrap();
+
+        assert!(safe_manager.exceeds_size_limit("big.txt"));
+        assert!(safe_manager.open("big.txt").is_err());
+
+        safe_manager.set_max_file_size(parse_size("2K").unwrap());
+        assert!(!safe_manager.exceeds_size_limit("big.txt"));
+        assert_eq!(safe_manager.open("big.txt").unwrap().len(), 2000);
+        assert!(safe_manager.save("bigger.txt", &"y".repeat(3000)).is_err());
+    }
+
+    #[test]
+    fn test_backups_in_a_shared_directory() {
+        let temp_dir = tempdir().unwrap();
+        let mut safe_manager = SafeFileManager::new(true, 1024).unwrap();
+        safe_manager.file_system.set_current_directory(temp_dir.path()).unwrap();
+        fs::create_dir_all(temp_dir.path().join("a")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("b")).unwrap();
+        fs::write(temp_dir.path().join("a/notes.txt"), "from a").unwrap();
+        fs::write(temp_dir.path().join("b/notes.txt"), "from b").unwrap();
+
+        safe_manager.set_backup_dir(Some(PathBuf::from("backups")));
+        safe_manager.save("a/notes.txt", "new a").unwrap();
+        safe_manager.save("b/notes.txt", "new b").unwrap();
+
+        // Same file name, separate backups, none left beside the files
+        let mut backups: Vec<String> = fs::read_dir(temp_dir.path().join("backups"))
+            .unwrap()
+            .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        backups.sort();
+        assert_eq!(backups, ["from a", "from b"]);
+        assert!(!temp_dir.path().join("a/notes.txt.backup").exists());
+    }
+
+    #[test]
+    fn test_disabled_backups() {
+        let temp_dir = tempdir().unwrap();
+        let mut safe_manager = SafeFileManager::new(true, 1024).unwrap();
+        safe_manager.file_system.set_current_directory(temp_dir.path()).unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), "old").unwrap();
+
+        safe_manager.save("notes.txt", "newer").unwrap();
+        assert_eq!(fs::read_to_string(temp_dir.path().join("notes.txt.backup")).unwrap(), "old");
+
+        safe_manager.set_auto_backup(false);
+        safe_manager.save("notes.txt", "newest").unwrap();
+        assert_eq!(fs::read_to_string(temp_dir.path().join("notes.txt.backup")).unwrap(), "old");
+        assert_eq!(fs::read_to_string(temp_dir.path().join("notes.txt")).unwrap(), "newest");
+    }
+
+    #[test]
+    fn test_binary_detection() {
+        let temp_dir = tempdir().unwrap();
+        let mut fs = FileSystem::new().unwrap();
+        fs.set_current_directory(temp_dir.path()).unwrap();
+
+        fs::write(temp_dir.path().join("text.txt"), "héllo\nworld\n").unwrap();
+        fs::write(temp_dir.path().join("nul.bin"), b"abc\0def").unwrap();
+        fs::write(temp_dir.path().join("invalid.bin"), b"\x80\x81\x02\x03abc").unwrap();
+        fs::write(temp_dir.path().join("latin1.txt"), b"caf\xe9 au lait").unwrap();
+        fs::write(temp_dir.path().join("utf16.txt"), b"\xff\xfeh\0i\0").unwrap();
+
+        assert!(!fs.is_binary("text.txt").unwrap());
+        assert!(fs.is_binary("nul.bin").unwrap());
+        assert!(fs.is_binary("invalid.bin").unwrap());
+        // Invalid UTF-8 without control bytes is Latin-1 text, and UTF-16 has a BOM
+        assert!(!fs.is_binary("latin1.txt").unwrap());
+        assert!(!fs.is_binary("utf16.txt").unwrap());
+
+        // Opening refuses binary files instead of failing inside read_to_string
+        let err = fs.open("nul.bin").unwrap_err();
+        assert!(err.to_string().contains("binary file"));
+
+        // A character split by the sample boundary is not treated as invalid
+        assert!(!is_binary_sample("é".as_bytes().split_last().unwrap().1));
+    }
+
+    #[test]
+    fn test_progress_is_reported_per_chunk() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("chunks.txt");
+        fs::write(&path, "x".repeat(1000)).unwrap();
+
+        let mut reports = Vec::new();
+        let bytes = read_with_progress(&path, 300, &mut |fraction| reports.push(fraction)).unwrap();
+
+        assert_eq!(bytes.len(), 1000);
+        assert_eq!(reports, vec![0.3, 0.6, 0.9, 1.0, 1.0]);
+        assert!(reports.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let mut fs = FileSystem::new().unwrap();
+        fs.set_current_directory(temp_dir.path()).unwrap();
+        let mut last = 0.0;
+        let (content, _) = fs.open_with_progress("chunks.txt", &mut |fraction| last = fraction).unwrap();
+        assert_eq!(content.len(), 1000);
+        assert_eq!(last, 1.0);
+    }
+
+    #[test]
+    fn test_grep_collects_matches() {
+        let temp_dir = tempdir().unwrap();
+        let mut fs = FileSystem::new().unwrap();
+        fs.set_current_directory(temp_dir.path()).unwrap();
+
+        fs::create_dir(temp_dir.path().join("src")).unwrap();
+        fs::write(temp_dir.path().join("src/main.rs"), "fn main() {\n    todo();\n}\n").unwrap();
+        fs::write(temp_dir.path().join("lib.rs"), "// todo: docs\nfn todo() {}\n").unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), "todo everywhere\n").unwrap();
+        fs::write(temp_dir.path().join("blob.rs"), b"todo\0").unwrap();
+
+        let files = fs.find_files("*.rs").unwrap();
+        assert_eq!(files, vec![PathBuf::from("blob.rs"), PathBuf::from("lib.rs"), PathBuf::from("src/main.rs")]);
+
+        let matches = fs.grep("todo", &files).unwrap();
+        let found: Vec<(String, usize, usize)> = matches
+            .iter()
+            .map(|m| (m.path.to_string_lossy().into_owned(), m.line, m.column))
+            .collect();
+        // The binary file is skipped
+        assert_eq!(found, vec![
+            ("lib.rs".to_string(), 0, 3),
+            ("lib.rs".to_string(), 1, 3),
+            ("src/main.rs".to_string(), 1, 4),
+        ]);
+        assert_eq!(matches[2].text, "    todo();");
+
+        assert!(glob_match("src/*.rs", "src/main.rs"));
+        assert!(!glob_match("*.rs", "src/main.rs"));
+    }
+
     #[test]
     fn test_filename_validation() {
         assert!(SafeFileManager::validate_filename("valid.txt").is_ok());
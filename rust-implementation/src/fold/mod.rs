@@ -0,0 +1,233 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fold {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub closed: bool,
+}
+
+impl Fold {
+    pub fn new(start_line: usize, end_line: usize) -> Self {
+        Self {
+            start_line: start_line.min(end_line),
+            end_line: start_line.max(end_line),
+            closed: true,
+        }
+    }
+
+    pub fn contains(&self, line: usize) -> bool {
+        line >= self.start_line && line <= self.end_line
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.end_line - self.start_line + 1
+    }
+}
+
+/// Manually created fold ranges. Folds may nest; a line is hidden when it
+/// lies inside a closed fold without being that fold's first line, which is
+/// rendered as the summary row instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FoldSet {
+    folds: Vec<Fold>,
+}
+
+impl FoldSet {
+    pub fn new() -> Self {
+        Self { folds: Vec::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.folds.is_empty()
+    }
+
+    pub fn folds(&self) -> &[Fold] {
+        &self.folds
+    }
+
+    /// Creates a closed fold over the given lines (in either order). A fold
+    /// with exactly the same range replaces the existing one.
+    pub fn create(&mut self, start_line: usize, end_line: usize) -> Fold {
+        let fold = Fold::new(start_line, end_line);
+        self.folds.retain(|f| f.start_line != fold.start_line || f.end_line != fold.end_line);
+        self.folds.push(fold);
+        self.folds.sort_by_key(|f| (f.start_line, std::cmp::Reverse(f.end_line)));
+        fold
+    }
+
+    pub fn clear(&mut self) {
+        self.folds.clear();
+    }
+
+    /// Opens the outermost closed fold containing `line`.
+    pub fn open_at(&mut self, line: usize) -> bool {
+        let outermost = self
+            .folds
+            .iter_mut()
+            .filter(|f| f.closed && f.contains(line))
+            .max_by_key(|f| f.line_count());
+
+        match outermost {
+            Some(fold) => {
+                fold.closed = false;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Closes the innermost open fold containing `line`.
+    pub fn close_at(&mut self, line: usize) -> bool {
+        let innermost = self
+            .folds
+            .iter_mut()
+            .filter(|f| !f.closed && f.contains(line))
+            .min_by_key(|f| f.line_count());
+
+        match innermost {
+            Some(fold) => {
+                fold.closed = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn toggle_at(&mut self, line: usize) -> bool {
+        if self.folds.iter().any(|f| f.closed && f.contains(line)) {
+            self.open_at(line)
+        } else {
+            self.close_at(line)
+        }
+    }
+
+    pub fn is_hidden(&self, line: usize) -> bool {
+        self.folds
+            .iter()
+            .any(|f| f.closed && line > f.start_line && line <= f.end_line)
+    }
+
+    pub fn hidden_lines(&self, line_count: usize) -> Vec<usize> {
+        (0..line_count).filter(|&line| self.is_hidden(line)).collect()
+    }
+
+    /// The closed fold to summarize on `line`, if `line` is a visible fold start.
+    pub fn closed_fold_at(&self, line: usize) -> Option<&Fold> {
+        if self.is_hidden(line) {
+            return None;
+        }
+        self.folds
+            .iter()
+            .filter(|f| f.closed && f.start_line == line)
+            .max_by_key(|f| f.line_count())
+    }
+
+    /// Maps `line` to the row the cursor should sit on: the start of the
+    /// outermost closed fold hiding it, or `line` itself when visible.
+    pub fn visible_line(&self, line: usize) -> usize {
+        self.folds
+            .iter()
+            .filter(|f| f.closed && f.contains(line))
+            .map(|f| f.start_line)
+            .min()
+            .unwrap_or(line)
+    }
+
+    /// Moves `delta` visible lines from `line`, treating each closed fold as
+    /// a single row.
+    pub fn move_line(&self, line: usize, delta: i32, line_count: usize) -> usize {
+        let last_line = line_count.saturating_sub(1);
+        let mut current = self.visible_line(line.min(last_line));
+
+        for _ in 0..delta.unsigned_abs() {
+            if delta > 0 {
+                let end = self
+                    .closed_fold_at(current)
+                    .map(|f| f.end_line)
+                    .unwrap_or(current);
+                if end >= last_line {
+                    break;
+                }
+                current = end + 1;
+            } else {
+                if current == 0 {
+                    break;
+                }
+                current = self.visible_line(current - 1);
+            }
+        }
+
+        current
+    }
+}
+
+/// Text shown in place of a closed fold, e.g. `+--  3 lines: fn main() {`.
+pub fn fold_summary(fold: &Fold, first_line: &str) -> String {
+    format!("+--{:>3} lines: {}", fold.line_count(), first_line.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_fold() {
+        let mut folds = FoldSet::new();
+        let fold = folds.create(5, 2);
+
+        assert_eq!(fold, Fold { start_line: 2, end_line: 5, closed: true });
+        assert_eq!(folds.folds().len(), 1);
+        assert_eq!(fold.line_count(), 4);
+        assert_eq!(fold_summary(&fold, "    fn main() {"), "+--  4 lines: fn main() {");
+    }
+
+    #[test]
+    fn test_hidden_lines_when_closed() {
+        let mut folds = FoldSet::new();
+        folds.create(2, 4);
+
+        assert_eq!(folds.hidden_lines(8), vec![3, 4]);
+        assert!(folds.closed_fold_at(2).is_some());
+
+        assert!(folds.open_at(3));
+        assert!(folds.hidden_lines(8).is_empty());
+        assert!(folds.closed_fold_at(2).is_none());
+
+        assert!(folds.toggle_at(4));
+        assert_eq!(folds.hidden_lines(8), vec![3, 4]);
+    }
+
+    #[test]
+    fn test_nested_folds() {
+        let mut folds = FoldSet::new();
+        folds.create(3, 4);
+        folds.create(1, 6);
+
+        assert_eq!(folds.hidden_lines(8), vec![2, 3, 4, 5, 6]);
+
+        // Opening reveals the outer fold first, leaving the inner one closed
+        folds.open_at(3);
+        assert_eq!(folds.hidden_lines(8), vec![4]);
+
+        // Closing picks the innermost open fold again
+        folds.open_at(3);
+        folds.close_at(4);
+        assert_eq!(folds.hidden_lines(8), vec![4]);
+    }
+
+    #[test]
+    fn test_cursor_moves_over_closed_fold() {
+        let mut folds = FoldSet::new();
+        folds.create(2, 4);
+
+        assert_eq!(folds.move_line(1, 1, 8), 2);
+        assert_eq!(folds.move_line(2, 1, 8), 5);
+        assert_eq!(folds.move_line(5, -1, 8), 2);
+        assert_eq!(folds.move_line(0, 3, 8), 5);
+        assert_eq!(folds.move_line(0, 4, 8), 6);
+        assert_eq!(folds.visible_line(3), 2);
+
+        // A fold reaching the end of the buffer stops downward movement
+        folds.create(6, 7);
+        assert_eq!(folds.move_line(6, 1, 8), 6);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::diff::{diff_lines, DiffOp};
+use crate::signs::SignKind;
+use std::path::Path;
+use std::process::Command;
+
+/// How a line of the working text differs from the committed version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    Added,
+    Modified,
+    /// Lines were removed just below this line (or above the first line).
+    Deleted,
+}
+
+impl LineChange {
+    pub fn sign(&self) -> SignKind {
+        match self {
+            LineChange::Added => SignKind::Added,
+            LineChange::Modified => SignKind::Modified,
+            LineChange::Deleted => SignKind::Deleted,
+        }
+    }
+}
+
+/// The staged version of `path` from `git show :path`, or `None` when the
+/// file is not tracked or git is unavailable.
+pub fn committed_text(path: &Path) -> Option<String> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = path.file_name()?.to_string_lossy();
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .arg("show")
+        .arg(format!(":./{}", name))
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Classifies the lines of `current` against `original`. Changed lines
+/// are modifications and inserted ones additions; a run of removals that
+/// no line replaced leaves a deletion marker on the line above it.
+pub fn classify_changes(original: &[&str], current: &[&str]) -> Vec<(usize, LineChange)> {
+    let mut changes = Vec::new();
+    let mut previous = None;
+    let mut next_line = 0;
+
+    for op in diff_lines(original, current) {
+        match op {
+            DiffOp::Equal { new, .. } => next_line = new + 1,
+            DiffOp::Change { new, .. } => {
+                changes.push((new, LineChange::Modified));
+                next_line = new + 1;
+            }
+            DiffOp::Insert { new } => {
+                changes.push((new, LineChange::Added));
+                next_line = new + 1;
+            }
+            DiffOp::Delete { .. } => {
+                if !matches!(previous, Some(DiffOp::Delete { .. } | DiffOp::Change { .. })) {
+                    changes.push((next_line.saturating_sub(1), LineChange::Deleted));
+                }
+            }
+        }
+        previous = Some(op);
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_added_and_modified_lines() {
+        let original = ["fn main() {", "    old();", "}"];
+        let current = ["// entry point", "fn main() {", "    new();", "    more();", "}"];
+
+        assert_eq!(classify_changes(&original, &current), vec![
+            (0, LineChange::Added),
+            (2, LineChange::Modified),
+            (3, LineChange::Added),
+        ]);
+    }
+
+    #[test]
+    fn test_deleted_lines() {
+        let original = ["a", "b", "c", "d"];
+
+        assert_eq!(classify_changes(&original, &["a", "d"]), vec![(0, LineChange::Deleted)]);
+        // Removing the first line marks the new first line
+        assert_eq!(classify_changes(&original, &["b", "c", "d"]), vec![(0, LineChange::Deleted)]);
+        assert!(classify_changes(&original, &original).is_empty());
+    }
+}
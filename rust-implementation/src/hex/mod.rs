@@ -0,0 +1,112 @@
+pub const BYTES_PER_ROW: usize = 16;
+
+/// Formats one hex dump row: the offset, up to `BYTES_PER_ROW` bytes in hex
+/// and an ASCII gutter where non-printable bytes show as `.`.
+pub fn format_row(offset: usize, bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let ascii: String = bytes
+        .iter()
+        .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+        .collect();
+
+    format!(
+        "{:08x}  {:<width$}  |{}|",
+        offset,
+        hex.join(" "),
+        ascii,
+        width = BYTES_PER_ROW * 3 - 1
+    )
+}
+
+/// Read-only hex rendering of a byte buffer.
+#[derive(Debug, Clone, Default)]
+pub struct HexView {
+    bytes: Vec<u8>,
+    top_row: usize,
+}
+
+impl HexView {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes, top_row: 0 }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.bytes.len().div_ceil(BYTES_PER_ROW).max(1)
+    }
+
+    pub fn top_row(&self) -> usize {
+        self.top_row
+    }
+
+    pub fn scroll(&mut self, rows: i32) {
+        let max_top = self.row_count() - 1;
+        self.top_row = (self.top_row as i64 + rows as i64).clamp(0, max_top as i64) as usize;
+    }
+
+    pub fn row(&self, index: usize) -> Option<String> {
+        let offset = index * BYTES_PER_ROW;
+        if offset > self.bytes.len() || (offset == self.bytes.len() && index > 0) {
+            return None;
+        }
+        let end = (offset + BYTES_PER_ROW).min(self.bytes.len());
+        Some(format_row(offset, &self.bytes[offset..end]))
+    }
+
+    /// Rows from the current scroll position, one per line.
+    pub fn render(&self, max_rows: usize) -> String {
+        (self.top_row..self.row_count())
+            .take(max_rows)
+            .filter_map(|index| self.row(index))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_full_row() {
+        let bytes: Vec<u8> = (0x41..0x51).collect(); // "ABCDEFGHIJKLMNOP"
+        assert_eq!(
+            format_row(0, &bytes),
+            "00000000  41 42 43 44 45 46 47 48 49 4a 4b 4c 4d 4e 4f 50  |ABCDEFGHIJKLMNOP|"
+        );
+    }
+
+    #[test]
+    fn test_format_partial_row_with_non_printables() {
+        let row = format_row(0x20, b"Hi\x00\n\xff ");
+        assert_eq!(
+            row,
+            format!("00000020  48 69 00 0a ff 20{}  |Hi... |", " ".repeat(30))
+        );
+    }
+
+    #[test]
+    fn test_view_rows_and_scrolling() {
+        let mut view = HexView::new((0..40).collect());
+        assert_eq!(view.row_count(), 3);
+        assert!(view.row(2).unwrap().starts_with("00000020  20 21"));
+        assert_eq!(view.row(3), None);
+
+        view.scroll(5);
+        assert_eq!(view.top_row(), 2);
+        assert_eq!(view.render(10).lines().count(), 1);
+
+        view.scroll(-1);
+        assert_eq!(view.render(1).lines().next().unwrap(), view.row(1).unwrap());
+    }
+
+    #[test]
+    fn test_empty_view_has_one_row() {
+        let view = HexView::new(Vec::new());
+        assert_eq!(view.row_count(), 1);
+        assert_eq!(view.render(5), format!("00000000  {}  ||", " ".repeat(47)));
+    }
+}
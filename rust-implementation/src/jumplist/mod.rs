@@ -0,0 +1,139 @@
+use crate::core::Position;
+
+/// Most jumps remembered; the oldest are dropped first.
+pub const MAX_JUMPS: usize = 100;
+
+/// A buffer index and a cursor position in it.
+pub type Jump = (usize, Position);
+
+/// Positions the cursor left through large motions, walked with `Ctrl-O`
+/// (back) and `Ctrl-I` (forward). Recording a new jump while partway back
+/// discards the forward history, as in vim.
+#[derive(Debug, Clone, Default)]
+pub struct JumpList {
+    jumps: Vec<Jump>,
+    index: usize,
+}
+
+impl JumpList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.jumps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jumps.is_empty()
+    }
+
+    /// Records the position the cursor is about to leave.
+    pub fn push(&mut self, jump: Jump) {
+        self.jumps.truncate(self.index);
+        if self.jumps.last() != Some(&jump) {
+            self.jumps.push(jump);
+        }
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.index = self.jumps.len();
+    }
+
+    /// The jump before `current`. The first step back also records
+    /// `current`, so `forward` can return to it.
+    pub fn back(&mut self, current: Jump) -> Option<Jump> {
+        if self.index == self.jumps.len() {
+            if self.jumps.last() != Some(&current) {
+                self.jumps.push(current);
+            }
+            self.index = self.jumps.len() - 1;
+        }
+        if self.index == 0 {
+            return None;
+        }
+        self.index -= 1;
+        Some(self.jumps[self.index])
+    }
+
+    pub fn forward(&mut self) -> Option<Jump> {
+        if self.index + 1 >= self.jumps.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(self.jumps[self.index])
+    }
+
+    /// Drops jumps into buffer `index` and shifts later buffers down, after
+    /// that buffer is closed.
+    pub fn remove_buffer(&mut self, index: usize) {
+        let before = self.jumps[..self.index.min(self.jumps.len())]
+            .iter()
+            .filter(|(buffer, _)| *buffer == index)
+            .count();
+        self.jumps.retain(|(buffer, _)| *buffer != index);
+        for (buffer, _) in &mut self.jumps {
+            if *buffer > index {
+                *buffer -= 1;
+            }
+        }
+        self.index = self.index.saturating_sub(before).min(self.jumps.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line: usize) -> Jump {
+        (0, Position::new(0, line))
+    }
+
+    #[test]
+    fn test_back_and_forward() {
+        let mut jumps = JumpList::new();
+        jumps.push(at(1));
+        jumps.push(at(10));
+
+        assert_eq!(jumps.back(at(20)), Some(at(10)));
+        assert_eq!(jumps.back(at(10)), Some(at(1)));
+        assert_eq!(jumps.back(at(1)), None);
+        assert_eq!(jumps.forward(), Some(at(10)));
+        assert_eq!(jumps.forward(), Some(at(20)));
+        assert_eq!(jumps.forward(), None);
+        assert!(JumpList::new().back(at(0)).is_none());
+    }
+
+    #[test]
+    fn test_new_jump_truncates_forward_history() {
+        let mut jumps = JumpList::new();
+        jumps.push(at(1));
+        jumps.push(at(2));
+        jumps.push(at(3));
+        jumps.back(at(4));
+        jumps.back(at(3));
+
+        // A new jump made after going back drops lines 2 to 4
+        jumps.push(at(7));
+        assert_eq!(jumps.len(), 2);
+        assert_eq!(jumps.forward(), None);
+        assert_eq!(jumps.back(at(9)), Some(at(7)));
+        assert_eq!(jumps.back(at(7)), Some(at(1)));
+    }
+
+    #[test]
+    fn test_push_is_bounded_and_skips_repeats() {
+        let mut jumps = JumpList::new();
+        jumps.push(at(5));
+        jumps.push(at(5));
+        assert_eq!(jumps.len(), 1);
+
+        for line in 0..MAX_JUMPS + 10 {
+            jumps.push(at(line));
+        }
+        assert_eq!(jumps.len(), MAX_JUMPS);
+
+        jumps.remove_buffer(0);
+        assert!(jumps.is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+use crate::core::Position;
+
+/// A screen area in cells, relative to the top-left of the editor window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self { x, y, width, height }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitDirection {
+    /// `:split`: panes stacked top to bottom.
+    Horizontal,
+    /// `:vsplit`: panes side by side.
+    Vertical,
+}
+
+/// One window onto a buffer, with its own cursor and first visible line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pane {
+    pub buffer_index: usize,
+    pub cursor: Position,
+    pub top_line: usize,
+}
+
+impl Pane {
+    pub fn new(buffer_index: usize, cursor: Position) -> Self {
+        Self {
+            buffer_index,
+            cursor,
+            top_line: 0,
+        }
+    }
+
+    /// Scrolls just enough to keep the cursor line inside `height` rows.
+    pub fn scroll_to_cursor(&mut self, height: usize) {
+        let height = height.max(1);
+        if self.cursor.y < self.top_line {
+            self.top_line = self.cursor.y;
+        } else if self.cursor.y >= self.top_line + height {
+            self.top_line = self.cursor.y + 1 - height;
+        }
+    }
+}
+
+/// Tracks the panes of the editor area and which one has focus. Up to two
+/// panes are supported, separated by a one-cell divider.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    panes: Vec<Pane>,
+    split: Option<SplitDirection>,
+    focused: usize,
+}
+
+impl Layout {
+    pub fn new(buffer_index: usize) -> Self {
+        Self {
+            panes: vec![Pane::new(buffer_index, Position::origin())],
+            split: None,
+            focused: 0,
+        }
+    }
+
+    pub fn panes(&self) -> &[Pane] {
+        &self.panes
+    }
+
+    pub fn split_direction(&self) -> Option<SplitDirection> {
+        self.split
+    }
+
+    pub fn is_split(&self) -> bool {
+        self.split.is_some()
+    }
+
+    pub fn focused(&self) -> usize {
+        self.focused
+    }
+
+    pub fn focused_pane(&self) -> &Pane {
+        &self.panes[self.focused]
+    }
+
+    pub fn focused_pane_mut(&mut self) -> &mut Pane {
+        &mut self.panes[self.focused]
+    }
+
+    /// Splits the focused pane, giving the new pane focus. The new pane
+    /// starts as a copy of the focused one. Fails once two panes exist.
+    pub fn split(&mut self, direction: SplitDirection) -> bool {
+        if self.split.is_some() {
+            return false;
+        }
+        let pane = *self.focused_pane();
+        self.panes.insert(0, pane);
+        self.split = Some(direction);
+        self.focused = 0;
+        true
+    }
+
+    /// Closes the focused pane unless it is the last one.
+    pub fn close_focused(&mut self) -> bool {
+        if self.panes.len() < 2 {
+            return false;
+        }
+        self.panes.remove(self.focused);
+        self.split = None;
+        self.focused = 0;
+        true
+    }
+
+    /// Keeps only the focused pane.
+    pub fn only(&mut self) {
+        let pane = *self.focused_pane();
+        self.panes = vec![pane];
+        self.split = None;
+        self.focused = 0;
+    }
+
+    /// Moves focus to the next pane, wrapping around (`Ctrl-W w`).
+    pub fn focus_next(&mut self) {
+        self.focused = (self.focused + 1) % self.panes.len();
+    }
+
+    /// Moves focus toward `h`/`j`/`k`/`l`; returns whether focus changed.
+    pub fn focus_toward(&mut self, direction: char) -> bool {
+        let target = match (self.split, direction) {
+            (Some(SplitDirection::Horizontal), 'k') | (Some(SplitDirection::Vertical), 'h') => 0,
+            (Some(SplitDirection::Horizontal), 'j') | (Some(SplitDirection::Vertical), 'l') => 1,
+            _ => return false,
+        };
+        let changed = target != self.focused;
+        self.focused = target;
+        changed
+    }
+
+    /// Rectangles of each pane within an editor area of `width` x `height`
+    /// cells, in pane order. The divider between two panes is not included.
+    pub fn pane_rects(&self, width: usize, height: usize) -> Vec<Rect> {
+        match self.split {
+            None => vec![Rect::new(0, 0, width, height)],
+            Some(SplitDirection::Horizontal) => {
+                let first = height.saturating_sub(1) / 2;
+                let second = height.saturating_sub(first + 1);
+                vec![Rect::new(0, 0, width, first), Rect::new(0, first + 1, width, second)]
+            }
+            Some(SplitDirection::Vertical) => {
+                let first = width.saturating_sub(1) / 2;
+                let second = width.saturating_sub(first + 1);
+                vec![Rect::new(0, 0, first, height), Rect::new(first + 1, 0, second, height)]
+            }
+        }
+    }
+
+    /// The divider row or column between the panes, if split.
+    pub fn divider(&self, width: usize, height: usize) -> Option<Rect> {
+        let rects = self.pane_rects(width, height);
+        match self.split? {
+            SplitDirection::Horizontal => Some(Rect::new(0, rects[0].height, width, 1)),
+            SplitDirection::Vertical => Some(Rect::new(rects[0].width, 0, 1, height)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_pane_fills_editor_area() {
+        let layout = Layout::new(0);
+        assert_eq!(layout.pane_rects(80, 23), vec![Rect::new(0, 0, 80, 23)]);
+        assert_eq!(layout.divider(80, 23), None);
+    }
+
+    #[test]
+    fn test_horizontal_split_rects() {
+        let mut layout = Layout::new(0);
+        assert!(layout.split(SplitDirection::Horizontal));
+        assert!(!layout.split(SplitDirection::Vertical));
+
+        // 23 rows: 11 for the top pane, a divider row, 11 for the bottom pane
+        assert_eq!(layout.pane_rects(80, 23), vec![Rect::new(0, 0, 80, 11), Rect::new(0, 12, 80, 11)]);
+        assert_eq!(layout.divider(80, 23), Some(Rect::new(0, 11, 80, 1)));
+        assert_eq!(layout.pane_rects(80, 10), vec![Rect::new(0, 0, 80, 4), Rect::new(0, 5, 80, 5)]);
+    }
+
+    #[test]
+    fn test_vertical_split_rects() {
+        let mut layout = Layout::new(0);
+        layout.split(SplitDirection::Vertical);
+
+        assert_eq!(layout.pane_rects(80, 23), vec![Rect::new(0, 0, 39, 23), Rect::new(40, 0, 40, 23)]);
+        assert_eq!(layout.divider(80, 23), Some(Rect::new(39, 0, 1, 23)));
+    }
+
+    #[test]
+    fn test_focus_movement() {
+        let mut layout = Layout::new(0);
+        layout.split(SplitDirection::Vertical);
+        assert_eq!(layout.focused(), 0);
+
+        assert!(layout.focus_toward('l'));
+        assert!(!layout.focus_toward('j'));
+        layout.focus_next();
+        assert_eq!(layout.focused(), 0);
+
+        layout.focused_pane_mut().cursor = Position::new(0, 30);
+        layout.focused_pane_mut().scroll_to_cursor(11);
+        assert_eq!(layout.focused_pane().top_line, 20);
+
+        assert!(layout.close_focused());
+        assert!(!layout.is_split());
+        assert!(!layout.close_focused());
+    }
+}
@@ -1,13 +1,50 @@
+pub mod autosave;
 pub mod core;
+pub mod browser;
 pub mod buffer;
+pub mod config;
+pub mod datetime;
+pub mod diff;
 pub mod display;
+pub mod editor;
 pub mod editor_ops;
+pub mod expression;
 pub mod file_io;
+pub mod fold;
+pub mod git;
+pub mod hex;
+pub mod jumplist;
+pub mod layout;
+pub mod quickfix;
+pub mod search;
+pub mod shell;
+pub mod signs;
+pub mod snippet;
+pub mod spell;
+pub mod syntax;
 pub mod undo;
 
 pub use core::*;
-pub use buffer::{Buffer, MultiBuffer};
-pub use display::{TerminalDisplay, StatusLine};
-pub use editor_ops::{EditorOps, ClipboardManager};
-pub use file_io::{FileSystem, SafeFileManager};
-pub use undo::{UndoRedoStack, ActionHistory, EditorAction};
\ No newline at end of file
+pub use autosave::AutoSaveTimer;
+pub use browser::{DirBuffer, DirTarget};
+pub use buffer::{Buffer, DiskChange, MultiBuffer, QuitChoice};
+pub use config::{detect_indentation, EditorConfig, EditorConfigPatch, IndentStyle};
+pub use datetime::DateTime;
+pub use diff::{DiffOp, DiffView};
+pub use display::{buffer_to_screen, char_width, line_number, number_column_width, virtual_column, CursorShape, HeadlessDisplay, TerminalDisplay, StatusLine};
+pub use editor::Editor;
+pub use editor_ops::{BracketMatch, ClipboardKind, EditorOps, ClipboardManager, Motion, MotionRange, Operator, Register, Registers};
+pub use expression::{Expression, ExpressionContext};
+pub use file_io::{parse_size, split_file_position, FileSystem, LargeFileView, LazyFile, LockFile, SafeFileManager};
+pub use quickfix::QuickfixList;
+pub use fold::{Fold, FoldSet};
+pub use hex::HexView;
+pub use jumplist::{Jump, JumpList};
+pub use layout::{Layout, Pane, Rect, SplitDirection};
+pub use search::{IncrementalSearch, Substitution};
+pub use shell::ShellOutput;
+pub use signs::{SignKind, Signs};
+pub use snippet::{Expansion, Segment, Snippet, SnippetLibrary, SnippetSession};
+pub use spell::SpellChecker;
+pub use syntax::{detect_filetype, SyntaxProfile};
+pub use undo::{UndoRedoStack, ActionHistory, EditorAction, HistoryStep, TimestampedHistory, UndoFile};
\ No newline at end of file
@@ -0,0 +1,126 @@
+use crate::file_io::Match;
+
+/// Results of `:grep`, one `file:line: text` entry per match. `:cn` and
+/// `:cp` wrap around at either end.
+#[derive(Debug, Clone, Default)]
+pub struct QuickfixList {
+    entries: Vec<Match>,
+    selected: usize,
+}
+
+impl QuickfixList {
+    pub fn new(entries: Vec<Match>) -> Self {
+        Self { entries, selected: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn entries(&self) -> &[Match] {
+        &self.entries
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn current(&self) -> Option<&Match> {
+        self.entries.get(self.selected)
+    }
+
+    pub fn move_selection(&mut self, delta: i32) {
+        let last = self.entries.len().saturating_sub(1);
+        self.selected = (self.selected as i64 + delta as i64).clamp(0, last as i64) as usize;
+    }
+
+    pub fn next_entry(&mut self) -> Option<&Match> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.selected = (self.selected + 1) % self.entries.len();
+        self.current()
+    }
+
+    pub fn previous_entry(&mut self) -> Option<&Match> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.selected = (self.selected + self.entries.len() - 1) % self.entries.len();
+        self.current()
+    }
+
+    /// Selects entry `number`, counting from 1 as `:cc` does.
+    pub fn jump(&mut self, number: usize) -> Option<&Match> {
+        if number == 0 || number > self.entries.len() {
+            return None;
+        }
+        self.selected = number - 1;
+        self.current()
+    }
+
+    /// Position of the selected entry, e.g. `(3 of 12)`.
+    pub fn position_label(&self) -> String {
+        format!("({} of {})", self.selected + 1, self.entries.len())
+    }
+
+    pub fn render(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| format!("{}:{}: {}", entry.path.display(), entry.line + 1, entry.text.trim()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn entry(path: &str, line: usize, text: &str) -> Match {
+        Match {
+            path: PathBuf::from(path),
+            line,
+            column: 0,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_entries() {
+        let mut list = QuickfixList::new(vec![entry("src/main.rs", 4, "    todo();"), entry("lib.rs", 0, "// todo")]);
+
+        assert_eq!(list.render(), "src/main.rs:5: todo();\nlib.rs:1: // todo");
+        list.move_selection(3);
+        assert_eq!(list.current().map(|m| m.line), Some(0));
+    }
+
+    #[test]
+    fn test_next_and_previous_wrap() {
+        let mut list = QuickfixList::new((0..3).map(|line| entry("a.rs", line, "x")).collect());
+
+        assert_eq!(list.next_entry().map(|m| m.line), Some(1));
+        assert_eq!(list.next_entry().map(|m| m.line), Some(2));
+        assert_eq!(list.next_entry().map(|m| m.line), Some(0));
+        assert_eq!(list.previous_entry().map(|m| m.line), Some(2));
+        assert_eq!(list.position_label(), "(3 of 3)");
+
+        assert!(QuickfixList::default().next_entry().is_none());
+    }
+
+    #[test]
+    fn test_jump_to_entry() {
+        let mut list = QuickfixList::new((0..12).map(|line| entry("a.rs", line, "x")).collect());
+
+        assert_eq!(list.jump(3).map(|m| m.line), Some(2));
+        assert_eq!(list.position_label(), "(3 of 12)");
+        assert!(list.jump(0).is_none());
+        assert!(list.jump(13).is_none());
+        assert_eq!(list.selected(), 2);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::core::Position;
+
+mod substitute;
+pub use substitute::Substitution;
+
+/// Byte ranges `(start, end)` of every non-overlapping occurrence of
+/// `pattern` in `line`, scanning left to right.
+pub fn match_ranges(line: &str, pattern: &str, case_sensitive: bool) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    if pattern.is_empty() || pattern.len() > line.len() {
+        return ranges;
+    }
+
+    let haystack = line.as_bytes();
+    let needle = pattern.as_bytes();
+    let mut start = 0;
+
+    while start + needle.len() <= haystack.len() {
+        let window = &haystack[start..start + needle.len()];
+        let matched = if case_sensitive {
+            window == needle
+        } else {
+            window.eq_ignore_ascii_case(needle)
+        };
+
+        if matched && line.is_char_boundary(start) && line.is_char_boundary(start + needle.len()) {
+            ranges.push((start, start + needle.len()));
+            start += needle.len();
+        } else {
+            start += 1;
+        }
+    }
+
+    ranges
+}
+
+/// A `/` pattern being typed, and where the cursor was when the search
+/// began so it can be put back if the search is cancelled.
+#[derive(Debug, Clone)]
+pub struct IncrementalSearch {
+    pattern: String,
+    origin: Position,
+}
+
+impl IncrementalSearch {
+    pub fn new(origin: Position) -> Self {
+        Self {
+            pattern: String::new(),
+            origin,
+        }
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn origin(&self) -> Position {
+        self.origin
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pattern.is_empty()
+    }
+
+    pub fn push(&mut self, ch: char) {
+        self.pattern.push(ch);
+    }
+
+    pub fn pop(&mut self) -> Option<char> {
+        self.pattern.pop()
+    }
+
+    /// Ends the search, handing back the pattern typed so far.
+    pub fn finish(&mut self) -> String {
+        std::mem::take(&mut self.pattern)
+    }
+
+    /// Abandons the search and returns where the cursor started.
+    pub fn cancel(&mut self) -> Position {
+        self.pattern.clear();
+        self.origin
+    }
+}
+
+impl Default for IncrementalSearch {
+    fn default() -> Self {
+        Self::new(Position::new(0, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_ranges_on_line() {
+        let line = "let foo = foo_bar(foo);";
+        assert_eq!(match_ranges(line, "foo", true), vec![(4, 7), (10, 13), (18, 21)]);
+        assert!(match_ranges(line, "baz", true).is_empty());
+        assert!(match_ranges(line, "", true).is_empty());
+    }
+
+    #[test]
+    fn test_match_ranges_case_and_overlap() {
+        assert_eq!(match_ranges("Foo fOO foo", "foo", false), vec![(0, 3), (4, 7), (8, 11)]);
+        assert_eq!(match_ranges("Foo fOO foo", "foo", true), vec![(8, 11)]);
+        // Matches do not overlap
+        assert_eq!(match_ranges("aaaa", "aa", true), vec![(0, 2), (2, 4)]);
+    }
+
+    #[test]
+    fn test_match_ranges_multibyte() {
+        assert_eq!(match_ranges("café cafe", "caf", true), vec![(0, 3), (6, 9)]);
+        assert_eq!(match_ranges("naïve", "ïv", true), vec![(2, 5)]);
+    }
+
+    #[test]
+    fn test_cancel_returns_to_origin() {
+        let mut search = IncrementalSearch::new(Position::new(1, 0));
+        search.push('t');
+        search.push('h');
+
+        assert_eq!(search.cancel(), Position::new(1, 0));
+        assert!(search.is_empty());
+    }
+}
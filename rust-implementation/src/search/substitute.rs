@@ -0,0 +1,132 @@
+use super::match_ranges;
+
+/// A parsed `:s/pattern/replacement/flags`. The pattern is literal text,
+/// matched within each line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitution {
+    pub pattern: String,
+    /// `&` in it stands for the matched text, `\&` for a literal `&`.
+    pub replacement: String,
+    /// `g`: every match on a line rather than the first.
+    pub global: bool,
+    /// `i`: match regardless of case.
+    pub ignore_case: bool,
+}
+
+impl Substitution {
+    /// Parses an ex command such as `s/old/new/g` or `substitute#a/b#c#`.
+    /// The delimiter is whatever follows the command name, and may appear
+    /// in the pattern or replacement escaped with `\`. The replacement and
+    /// flags may be left off. `None` when `command` is not a substitution.
+    pub fn parse(command: &str) -> Option<Self> {
+        let rest = command.strip_prefix("substitute").or_else(|| command.strip_prefix('s'))?;
+        let delimiter = rest.chars().next()?;
+        if delimiter.is_alphanumeric() || delimiter.is_whitespace() || matches!(delimiter, '\\' | '"' | '|') {
+            return None;
+        }
+
+        let mut fields = split_fields(&rest[delimiter.len_utf8()..], delimiter).into_iter();
+        let pattern = fields.next().filter(|pattern| !pattern.is_empty())?;
+        let replacement = fields.next().unwrap_or_default();
+        let mut substitution = Self { pattern, replacement, global: false, ignore_case: false };
+        for flag in fields.next().unwrap_or_default().trim_end().chars() {
+            match flag {
+                'g' => substitution.global = true,
+                'i' => substitution.ignore_case = true,
+                'I' => substitution.ignore_case = false,
+                _ => return None,
+            }
+        }
+        Some(substitution)
+    }
+
+    /// `line` with the pattern replaced, and how many times it was.
+    pub fn apply(&self, line: &str) -> (String, usize) {
+        let mut ranges = match_ranges(line, &self.pattern, !self.ignore_case);
+        if !self.global {
+            ranges.truncate(1);
+        }
+
+        let mut result = String::with_capacity(line.len());
+        let mut copied = 0;
+        for &(start, end) in &ranges {
+            result.push_str(&line[copied..start]);
+            self.push_replacement(&mut result, &line[start..end]);
+            copied = end;
+        }
+        result.push_str(&line[copied..]);
+        (result, ranges.len())
+    }
+
+    fn push_replacement(&self, result: &mut String, matched: &str) {
+        let mut chars = self.replacement.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '&' => result.push_str(matched),
+                '\\' => result.push(chars.next().unwrap_or('\\')),
+                _ => result.push(ch),
+            }
+        }
+    }
+}
+
+/// The text between unescaped `delimiter`s, with `\delimiter` unescaped.
+/// Other escapes are kept for the replacement to read.
+fn split_fields(text: &str, delimiter: char) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        let field = fields.last_mut().expect("fields start with one");
+        match ch {
+            '\\' => match chars.next() {
+                Some(next) if next == delimiter => field.push(next),
+                Some(next) => {
+                    field.push('\\');
+                    field.push(next);
+                }
+                None => field.push('\\'),
+            },
+            _ if ch == delimiter => fields.push(String::new()),
+            _ => field.push(ch),
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_substitution() {
+        let substitution = Substitution::parse("s/old/new/gi").unwrap();
+        assert_eq!(substitution.pattern, "old");
+        assert_eq!(substitution.replacement, "new");
+        assert!(substitution.global && substitution.ignore_case);
+
+        // Another delimiter, escaped inside the pattern, and no flags
+        let substitution = Substitution::parse("substitute#a\\#b#c").unwrap();
+        assert_eq!((substitution.pattern.as_str(), substitution.replacement.as_str()), ("a#b", "c"));
+        assert!(!substitution.global);
+
+        assert_eq!(Substitution::parse("s/gone").unwrap().replacement, "");
+        assert!(Substitution::parse("set ts=4").is_none());
+        assert!(Substitution::parse("sort").is_none());
+        assert!(Substitution::parse("s//x/").is_none());
+        assert!(Substitution::parse("s/a/b/q").is_none());
+    }
+
+    #[test]
+    fn test_apply_to_line() {
+        let line = "foo and Foo and foo";
+        let first = Substitution::parse("s/foo/bar/").unwrap();
+        assert_eq!(first.apply(line), ("bar and Foo and foo".to_string(), 1));
+
+        let every = Substitution::parse("s/foo/[&]/gi").unwrap();
+        assert_eq!(every.apply(line), ("[foo] and [Foo] and [foo]".to_string(), 3));
+
+        let literal = Substitution::parse("s/and/\\&/g").unwrap();
+        assert_eq!(literal.apply(line), ("foo & Foo & foo".to_string(), 2));
+        assert_eq!(literal.apply("none here"), ("none here".to_string(), 0));
+    }
+}
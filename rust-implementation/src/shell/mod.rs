@@ -0,0 +1,158 @@
+use crate::core::{EditorError, Result};
+use std::io::{Read, Write};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long a command from `:!`, `:r !` or a filter may run before it is
+/// killed.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most output kept from a command. Anything longer is refused rather
+/// than cut short, so a runaway command can't fill the buffer.
+pub const MAX_OUTPUT: usize = 10 * 1024 * 1024;
+
+/// What a shell command printed, and how it exited.
+#[derive(Debug)]
+pub struct ShellOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub status: ExitStatus,
+}
+
+impl ShellOutput {
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+
+    /// `shell returned 1` for a command that failed, as vim reports it.
+    pub fn failure_message(&self) -> Option<String> {
+        match self.status.code() {
+            _ if self.success() => None,
+            Some(code) => Some(format!("shell returned {}", code)),
+            None => Some("shell command was killed".to_string()),
+        }
+    }
+}
+
+/// Runs `command` with the platform's shell, `sh -c` or `cmd /C`, feeding
+/// it `input` on stdin when given. Gives up after `TIMEOUT`.
+pub fn run(command: &str, input: Option<&str>) -> Result<ShellOutput> {
+    run_with_timeout(command, input, TIMEOUT)
+}
+
+pub fn run_with_timeout(command: &str, input: Option<&str>, timeout: Duration) -> Result<ShellOutput> {
+    let mut child = shell_command(command)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Written and read on their own threads, so a command that fills one
+    // pipe while we wait on another can't deadlock
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+        let input = input.to_string();
+        thread::spawn(move || stdin.write_all(input.as_bytes()));
+    }
+    let stdout = read_capped(child.stdout.take());
+    let stderr = read_capped(child.stderr.take());
+
+    let status = wait(&mut child, timeout);
+    let (stdout, stderr) = (joined(stdout)?, joined(stderr)?);
+    let status = status.ok_or_else(|| {
+        EditorError::InvalidOperation(format!("{} timed out after {} seconds", command, timeout.as_secs()))
+    })??;
+    if stdout.len() > MAX_OUTPUT {
+        return Err(EditorError::InvalidOperation(format!("Output of {} exceeds {} bytes", command, MAX_OUTPUT)));
+    }
+    Ok(ShellOutput {
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        status,
+    })
+}
+
+/// The shell running `command`. On unix it leads a process group of its
+/// own, so a timeout can kill whatever it started as well.
+fn shell_command(command: &str) -> Command {
+    let mut shell = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+    shell.arg(if cfg!(windows) { "/C" } else { "-c" }).arg(command);
+    #[cfg(unix)]
+    shell.process_group(0);
+    shell
+}
+
+/// Kills `child` and the commands it started, which would otherwise keep
+/// running and hold its output pipes open.
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    let _ = Command::new("kill")
+        .args(["-KILL", "--", &format!("-{}", child.id())])
+        .stderr(Stdio::null())
+        .status();
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Reads up to one byte past `MAX_OUTPUT`, enough to tell the output was
+/// too long. Dropping the pipe then stops a command that keeps writing.
+fn read_capped(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<std::io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(pipe) = pipe {
+            pipe.take(MAX_OUTPUT as u64 + 1).read_to_end(&mut bytes)?;
+        }
+        Ok(bytes)
+    })
+}
+
+fn joined(reader: JoinHandle<std::io::Result<Vec<u8>>>) -> Result<Vec<u8>> {
+    Ok(reader.join().unwrap_or_else(|_| Ok(Vec::new()))?)
+}
+
+/// The child's exit status, or `None` after killing it once `timeout`
+/// has passed.
+fn wait(child: &mut Child, timeout: Duration) -> Option<Result<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(Ok(status)),
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            Ok(None) => {
+                kill(child);
+                return None;
+            }
+            Err(e) => return Some(Err(e.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_and_exit_status() {
+        let output = run("echo hello", None).unwrap();
+        assert_eq!(output.stdout.trim_end(), "hello");
+        assert!(output.failure_message().is_none());
+
+        let output = run("exit 3", None).unwrap();
+        assert_eq!(output.failure_message().as_deref(), Some("shell returned 3"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_input_and_timeout() {
+        assert_eq!(run("cat", Some("piped\nin")).unwrap().stdout, "piped\nin");
+        assert!(run_with_timeout("sleep 5", None, Duration::from_millis(50)).is_err());
+
+        // What the shell started dies with it, rather than keeping the
+        // output pipes open
+        let start = Instant::now();
+        assert!(run_with_timeout("sleep 100; echo x", None, Duration::from_millis(50)).is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
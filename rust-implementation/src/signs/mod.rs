@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+
+/// Width of the sign column, in cells, whenever any sign is placed.
+pub const SIGN_COLUMN_WIDTH: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignKind {
+    Breakpoint,
+    Error,
+    Warning,
+    Added,
+    Modified,
+    Deleted,
+    /// A line that only exists on the left of a `:diff`.
+    Removed,
+}
+
+impl SignKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "breakpoint" | "bp" => Some(SignKind::Breakpoint),
+            "error" => Some(SignKind::Error),
+            "warning" => Some(SignKind::Warning),
+            "added" => Some(SignKind::Added),
+            "modified" => Some(SignKind::Modified),
+            "deleted" => Some(SignKind::Deleted),
+            "removed" => Some(SignKind::Removed),
+            _ => None,
+        }
+    }
+
+    pub fn glyph(&self) -> char {
+        match self {
+            SignKind::Breakpoint => '●',
+            SignKind::Error => 'E',
+            SignKind::Warning => 'W',
+            SignKind::Added => '+',
+            SignKind::Modified => '~',
+            SignKind::Deleted => '_',
+            SignKind::Removed => '-',
+        }
+    }
+}
+
+/// Markers shown in the sign column, at most one per line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Signs {
+    signs: BTreeMap<usize, SignKind>,
+}
+
+impl Signs {
+    pub fn new() -> Self {
+        Self {
+            signs: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signs.is_empty()
+    }
+
+    /// Places `kind` on `line`, replacing any sign already there.
+    pub fn place_sign(&mut self, line: usize, kind: SignKind) {
+        self.signs.insert(line, kind);
+    }
+
+    pub fn remove_sign(&mut self, line: usize) -> Option<SignKind> {
+        self.signs.remove(&line)
+    }
+
+    /// Removes every sign of the given kinds, keeping the others.
+    pub fn remove_kinds(&mut self, kinds: &[SignKind]) {
+        self.signs.retain(|_, kind| !kinds.contains(kind));
+    }
+
+    pub fn clear(&mut self) {
+        self.signs.clear();
+    }
+
+    pub fn get(&self, line: usize) -> Option<SignKind> {
+        self.signs.get(&line).copied()
+    }
+
+    /// The column is only reserved while at least one sign is placed.
+    pub fn column_width(&self) -> usize {
+        if self.signs.is_empty() {
+            0
+        } else {
+            SIGN_COLUMN_WIDTH
+        }
+    }
+
+    /// The sign column cells for `line`, padded to `column_width`.
+    pub fn gutter_text(&self, line: usize) -> String {
+        let width = self.column_width();
+        match self.get(line) {
+            Some(kind) => format!("{:<width$}", kind.glyph()),
+            None => " ".repeat(width),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_is_reserved_only_with_signs() {
+        let mut signs = Signs::new();
+        assert_eq!(signs.column_width(), 0);
+        assert_eq!(signs.gutter_text(0), "");
+
+        signs.place_sign(3, SignKind::Error);
+        assert_eq!(signs.column_width(), SIGN_COLUMN_WIDTH);
+
+        assert_eq!(signs.remove_sign(3), Some(SignKind::Error));
+        assert_eq!(signs.column_width(), 0);
+    }
+
+    #[test]
+    fn test_gutter_placement() {
+        let mut signs = Signs::new();
+        signs.place_sign(1, SignKind::Warning);
+        signs.place_sign(1, SignKind::Breakpoint);
+        signs.place_sign(4, SignKind::Added);
+
+        let gutter: Vec<String> = (0..5).map(|line| signs.gutter_text(line)).collect();
+        assert_eq!(gutter, vec!["  ", "● ", "  ", "  ", "+ "]);
+        assert_eq!(SignKind::from_name("bp"), Some(SignKind::Breakpoint));
+
+        signs.remove_kinds(&[SignKind::Added]);
+        assert_eq!(signs.get(4), None);
+        assert_eq!(signs.get(1), Some(SignKind::Breakpoint));
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+/// A piece of a snippet template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Text(String),
+    /// `$1` or `${1:default}`. Stop 0 is where the cursor ends up.
+    TabStop { index: usize, default: String },
+}
+
+/// A parsed snippet template such as `for ${1:i} in $2 {\n    $0\n}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    segments: Vec<Segment>,
+}
+
+/// The text a snippet expands to, and where its tab stops are within it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub text: String,
+    /// Byte ranges into `text` covering each stop's default, in the order
+    /// Tab visits them: `$1`, `$2`, ... and `$0` last.
+    pub stops: Vec<(usize, usize)>,
+}
+
+impl Snippet {
+    /// Parses `$N`, `${N}` and `${N:default}` stops out of `template`.
+    /// `\$` is a literal dollar, as is a `$` that starts no stop.
+    pub fn parse(template: &str) -> Self {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut rest = template;
+
+        while let Some(ch) = rest.chars().next() {
+            if let Some(after) = rest.strip_prefix("\\$") {
+                text.push('$');
+                rest = after;
+                continue;
+            }
+            if let Some((stop, after)) = rest.strip_prefix('$').and_then(parse_stop) {
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(stop);
+                rest = after;
+                continue;
+            }
+            text.push(ch);
+            rest = &rest[ch.len_utf8()..];
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Self { segments }
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// The text to insert, with every line after the first indented by
+    /// `indent` to line up with the line the snippet is typed on. A stop
+    /// used twice is only visited the first time; later uses repeat its
+    /// default. Without a `$0` the last stop is the end of the text.
+    pub fn expand(&self, indent: &str) -> Expansion {
+        let mut text = String::new();
+        let mut stops: Vec<(usize, (usize, usize))> = Vec::new();
+        let mut defaults: HashMap<usize, &str> = HashMap::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(literal) => text.push_str(&literal.replace('\n', &format!("\n{}", indent))),
+                Segment::TabStop { index, default } => {
+                    if let Some(first) = defaults.get(index) {
+                        text.push_str(first);
+                        continue;
+                    }
+                    let start = text.len();
+                    text.push_str(default);
+                    defaults.insert(*index, default);
+                    stops.push((*index, (start, text.len())));
+                }
+            }
+        }
+
+        // $1, $2, ... then $0
+        stops.sort_by_key(|&(index, _)| if index == 0 { usize::MAX } else { index });
+        if stops.last().is_none_or(|&(index, _)| index != 0) {
+            stops.push((0, (text.len(), text.len())));
+        }
+        Expansion {
+            text,
+            stops: stops.into_iter().map(|(_, range)| range).collect(),
+        }
+    }
+}
+
+/// `1}...` or `{1:default}...` after a `$`, with what follows it.
+fn parse_stop(rest: &str) -> Option<(Segment, &str)> {
+    let digits = |text: &str| text.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(text.len());
+
+    if let Some(inner) = rest.strip_prefix('{') {
+        let end = digits(inner);
+        let index = inner[..end].parse().ok()?;
+        let (default, after) = match inner[end..].strip_prefix(':') {
+            Some(body) => body.split_once('}')?,
+            None => ("", inner[end..].strip_prefix('}')?),
+        };
+        return Some((Segment::TabStop { index, default: default.to_string() }, after));
+    }
+    let end = digits(rest);
+    let index = rest[..end].parse().ok()?;
+    Some((Segment::TabStop { index, default: String::new() }, &rest[end..]))
+}
+
+/// Snippets by trigger word, each either global or for one filetype.
+#[derive(Debug, Clone, Default)]
+pub struct SnippetLibrary {
+    global: HashMap<String, Snippet>,
+    by_filetype: HashMap<String, HashMap<String, Snippet>>,
+}
+
+impl SnippetLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The snippets every buffer gets.
+    pub fn with_defaults() -> Self {
+        let mut library = Self::new();
+        library.add(None, "todo", "TODO(${1:name}): $0");
+        library.add(None, "fixme", "FIXME(${1:name}): $0");
+        library
+    }
+
+    /// Adds a snippet for `filetype`, or for every buffer when it is `None`.
+    pub fn add(&mut self, filetype: Option<&str>, trigger: &str, template: &str) {
+        let snippets = match filetype {
+            Some(filetype) => self.by_filetype.entry(filetype.to_string()).or_default(),
+            None => &mut self.global,
+        };
+        snippets.insert(trigger.to_string(), Snippet::parse(template));
+    }
+
+    /// The snippet `trigger` expands to, a filetype's own before a global.
+    pub fn get(&self, filetype: Option<&str>, trigger: &str) -> Option<&Snippet> {
+        filetype
+            .and_then(|filetype| self.by_filetype.get(filetype))
+            .and_then(|snippets| snippets.get(trigger))
+            .or_else(|| self.global.get(trigger))
+    }
+}
+
+/// The tab stops of a snippet that has just been expanded, as byte offsets
+/// into the buffer. Only the stop being typed in is expected to change, so
+/// the stops written after it move by however much the buffer grew or
+/// shrank.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnippetSession {
+    stops: Vec<(usize, usize)>,
+    current: usize,
+    length: usize,
+}
+
+impl SnippetSession {
+    /// Starts on the first stop of `expansion`, inserted at `origin` into
+    /// a buffer now `length` bytes long.
+    pub fn new(origin: usize, expansion: &Expansion, length: usize) -> Self {
+        let stops = expansion.stops.iter().map(|&(start, end)| (origin + start, origin + end)).collect();
+        Self { stops, current: 0, length }
+    }
+
+    /// The range of the stop being typed in.
+    pub fn current(&self) -> (usize, usize) {
+        self.stops[self.current]
+    }
+
+    pub fn is_last(&self) -> bool {
+        self.current + 1 == self.stops.len()
+    }
+
+    /// Moves to the next stop of a buffer now `length` bytes long, or
+    /// `None` when the last one was left.
+    pub fn advance(&mut self, length: usize) -> Option<(usize, usize)> {
+        let grown = length as isize - self.length as isize;
+        let (_, edited) = self.current();
+        self.length = length;
+        self.current += 1;
+        for stop in self.stops.iter_mut().skip(self.current).filter(|stop| stop.0 >= edited) {
+            stop.0 = stop.0.saturating_add_signed(grown);
+            stop.1 = stop.1.saturating_add_signed(grown);
+        }
+        self.stops.get(self.current).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(index: usize, default: &str) -> Segment {
+        Segment::TabStop { index, default: default.to_string() }
+    }
+
+    #[test]
+    fn test_parse_template_into_segments() {
+        let snippet = Snippet::parse("for ${1:i} in $2 {\n\t$0\n}");
+        assert_eq!(
+            snippet.segments(),
+            &[
+                Segment::Text("for ".to_string()),
+                stop(1, "i"),
+                Segment::Text(" in ".to_string()),
+                stop(2, ""),
+                Segment::Text(" {\n\t".to_string()),
+                stop(0, ""),
+                Segment::Text("\n}".to_string()),
+            ]
+        );
+
+        // Dollars that start no stop are kept as text
+        let snippet = Snippet::parse("cost \\$5, $x or ${oops}");
+        assert_eq!(snippet.segments(), &[Segment::Text("cost $5, $x or ${oops}".to_string())]);
+    }
+
+    #[test]
+    fn test_expand_computes_stop_positions() {
+        let expansion = Snippet::parse("if ${2:cond} {\n    ${1:body}\n}$0").expand("  ");
+        assert_eq!(expansion.text, "if cond {\n      body\n  }");
+        // $1 comes first even though $2 is written first
+        assert_eq!(&expansion.text[expansion.stops[0].0..expansion.stops[0].1], "body");
+        assert_eq!(expansion.stops, vec![(16, 20), (3, 7), (24, 24)]);
+
+        // A missing $0 ends at the end, and a repeated stop is visited once
+        let expansion = Snippet::parse("${1:x} = ${1:y}").expand("");
+        assert_eq!(expansion.text, "x = x");
+        assert_eq!(expansion.stops, vec![(0, 1), (5, 5)]);
+    }
+
+    #[test]
+    fn test_session_follows_typing_in_a_stop() {
+        // "f(a, b)" with stops on a, b and the end, inserted at offset 10
+        let expansion = Snippet::parse("f(${1:a}, ${2:b})$0").expand("");
+        let mut session = SnippetSession::new(10, &expansion, 20);
+        assert_eq!(session.current(), (12, 13));
+
+        // "a" became "alpha", four bytes longer
+        assert_eq!(session.advance(24), Some((19, 20)));
+        // "b" was erased
+        assert_eq!(session.advance(23), Some((20, 20)));
+        assert!(session.is_last());
+        assert_eq!(session.advance(23), None);
+
+        let library = SnippetLibrary::with_defaults();
+        assert!(library.get(Some("rust"), "todo").is_some());
+        assert!(library.get(None, "nope").is_none());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::core::{Position, Result, TextBuffer};
+use crate::syntax::{tokenize_line, SyntaxProfile, TokenKind};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+pub const DEFAULT_WORD_LIST: &str = "/usr/share/dict/words";
+
+/// Dictionary-backed spell checker. Only comment and string regions are
+/// checked so that code identifiers are never flagged.
+#[derive(Debug, Clone, Default)]
+pub struct SpellChecker {
+    words: HashSet<String>,
+}
+
+impl SpellChecker {
+    pub fn new() -> Self {
+        Self {
+            words: HashSet::new(),
+        }
+    }
+
+    pub fn from_words<I, S>(words: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            words: words
+                .into_iter()
+                .map(|word| word.as_ref().trim().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Loads a word list with one word per line; `#` lines are ignored.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Ok(Self::from_words(
+            content.lines().filter(|line| !line.trim_start().starts_with('#')),
+        ))
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_misspelled(&self, word: &str) -> bool {
+        !word.is_empty() && !self.words.contains(&word.to_lowercase())
+    }
+
+    /// Byte ranges of misspelled words inside the comments and strings of `line`.
+    pub fn misspelled_ranges(&self, line: &str, profile: &SyntaxProfile) -> Vec<(usize, usize)> {
+        tokenize_line(line, profile)
+            .iter()
+            .filter(|token| matches!(token.kind, TokenKind::Comment | TokenKind::String))
+            .flat_map(|token| {
+                words_in(token.text(line))
+                    .into_iter()
+                    .map(move |(start, end)| (token.start + start, token.start + end))
+            })
+            .filter(|&(start, end)| self.is_misspelled(&line[start..end]))
+            .collect()
+    }
+
+    /// Position of the next (or previous) misspelled word from `from`,
+    /// wrapping around the buffer.
+    pub fn find_misspelling<T: TextBuffer>(&self, buffer: &T, from: Position, forward: bool, profile: &SyntaxProfile) -> Option<Position> {
+        let line_count = buffer.line_count();
+        if line_count == 0 {
+            return None;
+        }
+        let from_line = from.y.min(line_count - 1);
+
+        for step in 0..=line_count {
+            let line_idx = if forward {
+                (from_line + step) % line_count
+            } else {
+                (from_line + line_count - step % line_count) % line_count
+            };
+            let ranges = self.misspelled_ranges(buffer.get_line(line_idx).unwrap_or(""), profile);
+            let starts = ranges.iter().map(|&(start, _)| start);
+
+            let hit = match (forward, step) {
+                (true, 0) => starts.filter(|&start| start > from.x).min(),
+                (false, 0) => starts.filter(|&start| start < from.x).max(),
+                (true, s) if s == line_count => starts.filter(|&start| start <= from.x).min(),
+                (false, s) if s == line_count => starts.filter(|&start| start >= from.x).max(),
+                (true, _) => starts.min(),
+                (false, _) => starts.max(),
+            };
+
+            if let Some(start) = hit {
+                return Some(Position::new(start, line_idx));
+            }
+        }
+
+        None
+    }
+}
+
+/// Byte ranges of the alphabetic words in `text`.
+fn words_in(text: &str) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut word_start = None;
+
+    for (idx, ch) in text.char_indices() {
+        match (ch.is_alphabetic(), word_start) {
+            (true, None) => word_start = Some(idx),
+            (false, Some(start)) => {
+                words.push((start, idx));
+                word_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = word_start {
+        words.push((start, text.len()));
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Buffer;
+    use tempfile::tempdir;
+
+    fn checker() -> SpellChecker {
+        SpellChecker::from_words(["the", "quick", "brown", "fox", "hello", "world"])
+    }
+
+    #[test]
+    fn test_word_list_lookup() {
+        let checker = checker();
+        assert!(!checker.is_misspelled("quick"));
+        assert!(!checker.is_misspelled("Hello"));
+        assert!(checker.is_misspelled("qiuck"));
+    }
+
+    #[test]
+    fn test_load_word_list_from_file() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("words.txt");
+        fs::write(&path, "# project words\nfoo\n\nBar\n").unwrap();
+
+        let checker = SpellChecker::load(&path).unwrap();
+        assert_eq!(checker.word_count(), 2);
+        assert!(!checker.is_misspelled("bar"));
+        assert!(checker.is_misspelled("project"));
+    }
+
+    #[test]
+    fn test_only_comments_and_strings_are_checked() {
+        let checker = checker();
+        let line = r#"let qiuck = "helo world"; // the brwn fox"#;
+        let flagged: Vec<&str> = checker
+            .misspelled_ranges(line, &SyntaxProfile::default())
+            .into_iter()
+            .map(|(start, end)| &line[start..end])
+            .collect();
+
+        // `let` and `qiuck` are code and never checked
+        assert_eq!(flagged, vec!["helo", "brwn"]);
+    }
+
+    #[test]
+    fn test_find_misspelling_jumps_between_words() {
+        let checker = checker();
+        let buffer = Buffer::from_content("// the fxo\nlet x = 1;\n// brwn fox".to_string());
+
+        let first = checker.find_misspelling(&buffer, Position::origin(), true, &SyntaxProfile::default());
+        assert_eq!(first, Some(Position::new(7, 0)));
+        let second = checker.find_misspelling(&buffer, Position::new(7, 0), true, &SyntaxProfile::default());
+        assert_eq!(second, Some(Position::new(3, 2)));
+        let back = checker.find_misspelling(&buffer, Position::new(3, 2), false, &SyntaxProfile::default());
+        assert_eq!(back, Some(Position::new(7, 0)));
+    }
+}
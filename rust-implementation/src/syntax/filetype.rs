@@ -0,0 +1,75 @@
+use std::path::Path;
+
+/// The language id of a file, from its extension or, for files without a
+/// known extension, a `#!` line at the top of `content`.
+pub fn detect_filetype(filename: &str, content: &str) -> Option<&'static str> {
+    let extension = Path::new(filename).extension().and_then(|ext| ext.to_str());
+    extension
+        .and_then(|ext| from_extension(&ext.to_ascii_lowercase()))
+        .or_else(|| from_shebang(content.lines().next().unwrap_or("")))
+}
+
+fn from_extension(extension: &str) -> Option<&'static str> {
+    Some(match extension {
+        "rs" => "rust",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" => "cpp",
+        "py" | "pyw" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" => "typescript",
+        "go" => "go",
+        "java" => "java",
+        "sh" | "bash" | "zsh" => "sh",
+        "rb" => "ruby",
+        "pl" | "pm" => "perl",
+        "toml" => "toml",
+        "md" | "markdown" => "markdown",
+        "txt" => "text",
+        _ => return None,
+    })
+}
+
+/// Maps `#!/usr/bin/python3` or `#!/usr/bin/env -S node` to a language id.
+fn from_shebang(line: &str) -> Option<&'static str> {
+    let command = line.strip_prefix("#!")?;
+    let mut words = command.split_whitespace();
+    let mut program = Path::new(words.next()?).file_name()?.to_str()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+
+    // python3.12 -> python
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(match name {
+        "python" => "python",
+        "sh" | "bash" | "zsh" | "dash" | "ksh" => "sh",
+        "node" | "deno" => "javascript",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_from_extension() {
+        assert_eq!(detect_filetype("src/main.rs", ""), Some("rust"));
+        assert_eq!(detect_filetype("setup.PY", ""), Some("python"));
+        assert_eq!(detect_filetype("include/list.h", ""), Some("c"));
+        assert_eq!(detect_filetype("Makefile", ""), None);
+        // The extension wins over a shebang
+        assert_eq!(detect_filetype("build.sh", "#!/usr/bin/env python3"), Some("sh"));
+    }
+
+    #[test]
+    fn test_detect_from_shebang() {
+        assert_eq!(detect_filetype("manage", "#!/usr/bin/env python3\nprint()"), Some("python"));
+        assert_eq!(detect_filetype("deploy", "#!/bin/bash -e"), Some("sh"));
+        assert_eq!(detect_filetype("tool", "#!/usr/bin/env -S node --harmony"), Some("javascript"));
+        assert_eq!(detect_filetype("notes", "just some text"), None);
+        assert_eq!(detect_filetype("weird.xyz", "#!/usr/bin/python3.12"), Some("python"));
+    }
+}
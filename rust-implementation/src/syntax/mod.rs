@@ -0,0 +1,506 @@
+pub mod filetype;
+pub use filetype::detect_filetype;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    Number,
+    String,
+    /// A backslash escape such as `\n` inside a string or character.
+    Escape,
+    /// A character literal such as `'a'`, without its escapes.
+    Char,
+    Comment,
+    /// A word such as `TODO` that calls for attention inside a comment.
+    Attention,
+}
+
+/// A highlighted span of a line as a byte range. Text between tokens is
+/// plain and carries no token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Token {
+    pub fn new(kind: TokenKind, start: usize, end: usize) -> Self {
+        Self { kind, start, end }
+    }
+
+    pub fn text<'a>(&self, line: &'a str) -> &'a str {
+        &line[self.start..self.end]
+    }
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "fn", "let", "mut", "if", "else", "while", "for", "match", "struct", "enum",
+    "impl", "trait", "pub", "use", "mod", "return", "break", "continue", "loop",
+    "true", "false", "None", "Some", "Ok", "Err", "const", "static", "unsafe",
+    "async", "await", "move", "ref", "where", "type", "as", "in",
+];
+
+const C_KEYWORDS: &[&str] = &[
+    "if", "else", "while", "for", "do", "switch", "case", "default", "break",
+    "continue", "return", "goto", "struct", "union", "enum", "typedef", "const",
+    "static", "extern", "sizeof", "void", "int", "char", "long", "short",
+    "unsigned", "signed", "float", "double", "NULL",
+];
+
+const CPP_KEYWORDS: &[&str] = &[
+    "class", "namespace", "template", "typename", "public", "private",
+    "protected", "virtual", "new", "delete", "this", "auto", "bool", "true",
+    "false", "nullptr", "using",
+];
+
+const PYTHON_KEYWORDS: &[&str] = &[
+    "def", "class", "if", "elif", "else", "while", "for", "in", "return",
+    "import", "from", "as", "with", "try", "except", "finally", "raise",
+    "lambda", "yield", "pass", "break", "continue", "and", "or", "not", "is",
+    "None", "True", "False", "self", "async", "await",
+];
+
+const JAVASCRIPT_KEYWORDS: &[&str] = &[
+    "function", "const", "let", "var", "if", "else", "while", "for", "of", "in",
+    "return", "class", "extends", "new", "this", "import", "export", "from",
+    "async", "await", "try", "catch", "finally", "throw", "true", "false",
+    "null", "undefined", "typeof",
+];
+
+const GO_KEYWORDS: &[&str] = &[
+    "func", "package", "import", "var", "const", "type", "struct", "interface",
+    "if", "else", "for", "range", "switch", "case", "default", "return", "go",
+    "defer", "chan", "map", "nil", "true", "false",
+];
+
+const JAVA_KEYWORDS: &[&str] = &[
+    "class", "interface", "extends", "implements", "public", "private",
+    "protected", "static", "final", "void", "new", "return", "if", "else",
+    "while", "for", "switch", "case", "try", "catch", "finally", "throw",
+    "throws", "import", "package", "this", "null", "true", "false",
+];
+
+const SHELL_KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "for", "while", "do", "done", "case",
+    "esac", "in", "function", "return", "local", "export",
+];
+
+/// The words marked in comments unless `todowords` says otherwise.
+pub const DEFAULT_ATTENTION_WORDS: &[&str] = &["TODO", "FIXME", "HACK", "XXX"];
+
+/// What the highlighter needs to know about a language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxProfile {
+    pub keywords: Vec<String>,
+    /// Text that starts a comment running to the end of the line.
+    pub line_comment: Option<&'static str>,
+    /// Whether `'a'` is a character literal. Where it is not, a `'` is left
+    /// alone, since it is more likely an apostrophe or a string quote.
+    pub char_literals: bool,
+    /// Words picked out inside comments, matched whole and case-sensitively.
+    pub attention_words: Vec<String>,
+}
+
+impl SyntaxProfile {
+    pub fn new(keywords: &[&str], line_comment: Option<&'static str>) -> Self {
+        Self {
+            keywords: keywords.iter().map(|keyword| keyword.to_string()).collect(),
+            line_comment,
+            char_literals: false,
+            attention_words: DEFAULT_ATTENTION_WORDS.iter().map(|word| word.to_string()).collect(),
+        }
+    }
+
+    pub fn with_char_literals(mut self) -> Self {
+        self.char_literals = true;
+        self
+    }
+
+    /// The profile for a language id from `detect_filetype`. Languages
+    /// without a profile of their own get the default one.
+    pub fn for_filetype(filetype: Option<&str>) -> Self {
+        match filetype {
+            Some("c") => Self::new(C_KEYWORDS, Some("//")).with_char_literals(),
+            Some("cpp") => Self::new(&[C_KEYWORDS, CPP_KEYWORDS].concat(), Some("//")).with_char_literals(),
+            Some("python") => Self::new(PYTHON_KEYWORDS, Some("#")),
+            Some("javascript") | Some("typescript") => Self::new(JAVASCRIPT_KEYWORDS, Some("//")),
+            Some("go") => Self::new(GO_KEYWORDS, Some("//")).with_char_literals(),
+            Some("java") => Self::new(JAVA_KEYWORDS, Some("//")).with_char_literals(),
+            Some("sh") => Self::new(SHELL_KEYWORDS, Some("#")),
+            Some("ruby") | Some("perl") | Some("toml") => Self::new(&[], Some("#")),
+            Some("text") | Some("markdown") => Self::new(&[], None),
+            _ => Self::default(),
+        }
+    }
+}
+
+impl Default for SyntaxProfile {
+    /// Rust keywords, `//` comments and character literals.
+    fn default() -> Self {
+        Self::new(RUST_KEYWORDS, Some("//")).with_char_literals()
+    }
+}
+
+/// Splits a single line into highlighted tokens.
+pub fn tokenize_line(line: &str, profile: &SyntaxProfile) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((start, ch)) = chars.next() {
+        if profile.line_comment.is_some_and(|marker| line[start..].starts_with(marker)) {
+            push_comment(&mut tokens, line, start, profile);
+            break;
+        }
+
+        if ch == '"' {
+            // Continue until the closing quote, splitting the string
+            // around its escapes
+            let mut end = line.len();
+            let mut piece = start;
+            while let Some((idx, next_ch)) = chars.next() {
+                if next_ch == '\\' {
+                    let escape = escape_end(line, idx);
+                    if idx > piece {
+                        tokens.push(Token::new(TokenKind::String, piece, idx));
+                    }
+                    tokens.push(Token::new(TokenKind::Escape, idx, escape));
+                    while chars.next_if(|&(next, _)| next < escape).is_some() {}
+                    piece = escape;
+                } else if next_ch == '"' {
+                    end = idx + 1;
+                    break;
+                }
+            }
+            if end > piece {
+                tokens.push(Token::new(TokenKind::String, piece, end));
+            }
+        } else if ch == '\'' && profile.char_literals {
+            // A lone quote, as in a Rust lifetime, is not a literal
+            let Some((escape, end)) = char_literal(line, start) else {
+                continue;
+            };
+            match escape {
+                Some(escape) => tokens.extend([
+                    Token::new(TokenKind::Char, start, start + 1),
+                    Token::new(TokenKind::Escape, start + 1, escape),
+                    Token::new(TokenKind::Char, escape, end),
+                ]),
+                None => tokens.push(Token::new(TokenKind::Char, start, end)),
+            }
+            while chars.next_if(|&(next, _)| next < end).is_some() {}
+        } else if ch.is_alphabetic() || ch == '_' {
+            let mut end = start + ch.len_utf8();
+
+            // Find the end of the word
+            while let Some(&(next_idx, next_ch)) = chars.peek() {
+                if next_ch.is_alphanumeric() || next_ch == '_' {
+                    end = next_idx + next_ch.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+
+            if profile.keywords.iter().any(|keyword| keyword == &line[start..end]) {
+                tokens.push(Token::new(TokenKind::Keyword, start, end));
+            }
+        } else if ch.is_ascii_digit() {
+            let end = number_end(line, start);
+            while chars.next_if(|&(idx, _)| idx < end).is_some() {}
+            tokens.push(Token::new(TokenKind::Number, start, end));
+        }
+    }
+
+    tokens
+}
+
+/// Adds the comment running from byte `start` to the end of `line`,
+/// splitting it around any attention words.
+fn push_comment(tokens: &mut Vec<Token>, line: &str, start: usize, profile: &SyntaxProfile) {
+    let mut piece = start;
+    let mut word_start = None;
+
+    for (idx, ch) in line[start..].char_indices().map(|(idx, ch)| (start + idx, ch)).chain([(line.len(), ' ')]) {
+        let in_word = ch.is_alphanumeric() || ch == '_';
+        match (in_word, word_start) {
+            (true, None) => word_start = Some(idx),
+            (false, Some(begin)) => {
+                word_start = None;
+                if profile.attention_words.iter().any(|word| *word == line[begin..idx]) {
+                    if begin > piece {
+                        tokens.push(Token::new(TokenKind::Comment, piece, begin));
+                    }
+                    tokens.push(Token::new(TokenKind::Attention, begin, idx));
+                    piece = idx;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if line.len() > piece {
+        tokens.push(Token::new(TokenKind::Comment, piece, line.len()));
+    }
+}
+
+/// The end of the backslash escape at byte `start`: `\u{...}`, `\x41`, or
+/// a backslash and one character.
+fn escape_end(line: &str, start: usize) -> usize {
+    let rest = &line[start + 1..];
+    if rest.starts_with("u{") {
+        if let Some(close) = rest.find('}') {
+            return start + 1 + close + 1;
+        }
+    }
+    let hex = rest.strip_prefix('x').map_or(0, |digits| digits.bytes().take(2).take_while(u8::is_ascii_hexdigit).count());
+    if hex == 2 {
+        return start + 4;
+    }
+    start + 1 + rest.chars().next().map_or(0, char::len_utf8)
+}
+
+/// The character literal whose opening quote is at byte `start`: where its
+/// escape ends, if it holds one, and where the closing quote ends.
+fn char_literal(line: &str, start: usize) -> Option<(Option<usize>, usize)> {
+    let body = start + 1;
+    let (escape, close) = match line[body..].chars().next()? {
+        '\\' => {
+            let escape = escape_end(line, body);
+            (Some(escape), escape)
+        }
+        '\'' => return None,
+        ch => (None, body + ch.len_utf8()),
+    };
+    line[close..].starts_with('\'').then_some((escape, close + 1))
+}
+
+/// The end of the numeric literal starting at byte `start`: `0x`, `0b` and
+/// `0o` integers, or decimals with a fraction and exponent, all allowing
+/// `_` separators and a type suffix such as `u8` or `f64`.
+fn number_end(line: &str, start: usize) -> usize {
+    let bytes = line.as_bytes();
+    let digits_from = |mut idx: usize, radix: u32| {
+        while idx < bytes.len() && (bytes[idx] == b'_' || (bytes[idx] as char).is_digit(radix)) {
+            idx += 1;
+        }
+        idx
+    };
+    let is_digit_at = |idx: usize| bytes.get(idx).is_some_and(u8::is_ascii_digit);
+
+    let radix = match bytes.get(start..start + 2) {
+        Some([b'0', b'x' | b'X']) => 16,
+        Some([b'0', b'b' | b'B']) => 2,
+        Some([b'0', b'o' | b'O']) => 8,
+        _ => 10,
+    };
+
+    let mut end = if radix == 10 {
+        let mut end = digits_from(start, 10);
+        if bytes.get(end) == Some(&b'.') && is_digit_at(end + 1) {
+            end = digits_from(end + 1, 10);
+        }
+        if matches!(bytes.get(end), Some(b'e' | b'E')) {
+            let sign = usize::from(matches!(bytes.get(end + 1), Some(b'+' | b'-')));
+            if is_digit_at(end + 1 + sign) {
+                end = digits_from(end + 1 + sign, 10);
+            }
+        }
+        end
+    } else {
+        digits_from(start + 2, radix)
+    };
+
+    // Suffix
+    while end < bytes.len() && (bytes[end].is_ascii_alphanumeric() || bytes[end] == b'_') {
+        end += 1;
+    }
+    end
+}
+
+/// The token covering byte `idx`, if any.
+pub fn token_at(tokens: &[Token], idx: usize) -> Option<&Token> {
+    tokens.iter().find(|token| idx >= token.start && idx < token.end)
+}
+
+/// The nesting depth of each bracket in `line` as `(byte, depth)`, for
+/// rainbow highlighting. `depth` is carried over from the lines above, and
+/// the depth at the end of the line is returned with the brackets. An
+/// opening bracket and its closing one share a depth. Brackets in strings
+/// and comments are skipped.
+pub fn bracket_depths(line: &str, profile: &SyntaxProfile, mut depth: usize) -> (Vec<(usize, usize)>, usize) {
+    let tokens = tokenize_line(line, profile);
+    let mut brackets = Vec::new();
+
+    for (idx, ch) in line.char_indices() {
+        let quoted = token_at(&tokens, idx).is_some_and(|token| {
+            !matches!(token.kind, TokenKind::Keyword | TokenKind::Number)
+        });
+        if quoted {
+            continue;
+        }
+        match ch {
+            '(' | '[' | '{' => {
+                brackets.push((idx, depth));
+                depth += 1;
+            }
+            ')' | ']' | '}' => {
+                depth = depth.saturating_sub(1);
+                brackets.push((idx, depth));
+            }
+            _ => {}
+        }
+    }
+
+    (brackets, depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> SyntaxProfile {
+        SyntaxProfile::new(&["let", "fn"], Some("//"))
+    }
+
+    #[test]
+    fn test_tokenize_keywords_and_numbers() {
+        let line = "let var2 = 7;";
+        let tokens = tokenize_line(line, &profile());
+
+        assert_eq!(tokens, vec![
+            Token::new(TokenKind::Keyword, 0, 3),
+            Token::new(TokenKind::Number, 11, 12),
+        ]);
+    }
+
+    #[test]
+    fn test_tokenize_number_literals() {
+        let numbers = |line: &str| -> Vec<String> {
+            tokenize_line(line, &profile())
+                .iter()
+                .filter(|token| token.kind == TokenKind::Number)
+                .map(|token| token.text(line).to_string())
+                .collect()
+        };
+
+        assert_eq!(numbers("x = 3.14 + 2.5e-3 * 6E10"), ["3.14", "2.5e-3", "6E10"]);
+        assert_eq!(numbers("mask = 0xFF_FF | 0b1010 ^ 0o755"), ["0xFF_FF", "0b1010", "0o755"]);
+        assert_eq!(numbers("n = 1_000_000u64 + 2f32"), ["1_000_000u64", "2f32"]);
+        // Ranges and field access are not fractions
+        assert_eq!(numbers("for i in 0..10 { t.0 }"), ["0", "10", "0"]);
+        assert!(numbers("let var2 = x1_y;").is_empty());
+    }
+
+    #[test]
+    fn test_tokenize_strings_and_comments() {
+        let line = r#"let s = "fn 1"; // let it be"#;
+        let tokens = tokenize_line(line, &profile());
+
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[1].kind, TokenKind::String);
+        assert_eq!(tokens[1].text(line), "\"fn 1\"");
+        assert_eq!(tokens[2].kind, TokenKind::Comment);
+        assert_eq!(tokens[2].text(line), "// let it be");
+    }
+
+    #[test]
+    fn test_tokenize_string_escapes() {
+        let line = r#"s = "say \"hi\"\n" + "\u{1F600}";"#;
+        let tokens = tokenize_line(line, &profile());
+        let spans: Vec<(TokenKind, &str)> = tokens.iter().map(|token| (token.kind, token.text(line))).collect();
+
+        assert_eq!(spans, vec![
+            (TokenKind::String, "\"say "),
+            (TokenKind::Escape, "\\\""),
+            (TokenKind::String, "hi"),
+            (TokenKind::Escape, "\\\""),
+            (TokenKind::Escape, "\\n"),
+            (TokenKind::String, "\""),
+            (TokenKind::String, "\""),
+            (TokenKind::Escape, "\\u{1F600}"),
+            (TokenKind::String, "\""),
+        ]);
+    }
+
+    #[test]
+    fn test_tokenize_char_literals() {
+        let line = r"let c = '\n'; let d = 'x'; fn f<'a>(s: &'a str)";
+        let rust = SyntaxProfile::default();
+        let spans: Vec<(TokenKind, &str)> = tokenize_line(line, &rust)
+            .iter()
+            .filter(|token| token.kind != TokenKind::Keyword)
+            .map(|token| (token.kind, token.text(line)))
+            .collect();
+        // Lifetimes are not character literals
+        assert_eq!(spans, vec![
+            (TokenKind::Char, "'"),
+            (TokenKind::Escape, "\\n"),
+            (TokenKind::Char, "'"),
+            (TokenKind::Char, "'x'"),
+        ]);
+
+        // Prose has apostrophes, not literals
+        let prose = "it's Bob's 'x' here";
+        assert!(tokenize_line(prose, &SyntaxProfile::for_filetype(Some("text"))).is_empty());
+    }
+
+    #[test]
+    fn test_attention_words_in_comments() {
+        let line = "let TODO = 1; // TODO: fix FIXMEs, todo (XXX)";
+        let spans: Vec<(TokenKind, &str)> = tokenize_line(line, &profile())
+            .iter()
+            .map(|token| (token.kind, token.text(line)))
+            .collect();
+
+        // Only whole, upper-case words inside the comment count
+        assert_eq!(spans, vec![
+            (TokenKind::Keyword, "let"),
+            (TokenKind::Number, "1"),
+            (TokenKind::Comment, "// "),
+            (TokenKind::Attention, "TODO"),
+            (TokenKind::Comment, ": fix FIXMEs, todo ("),
+            (TokenKind::Attention, "XXX"),
+            (TokenKind::Comment, ")"),
+        ]);
+
+        let mut custom = profile();
+        custom.attention_words = vec!["NOTE".to_string()];
+        let kinds: Vec<TokenKind> = tokenize_line("// NOTE: not a TODO", &custom).iter().map(|token| token.kind).collect();
+        assert_eq!(kinds, vec![TokenKind::Comment, TokenKind::Attention, TokenKind::Comment]);
+    }
+
+    #[test]
+    fn test_unterminated_string_runs_to_end_of_line() {
+        let line = "x = \"open";
+        let tokens = tokenize_line(line, &SyntaxProfile::new(&[], None));
+        assert_eq!(tokens, vec![Token::new(TokenKind::String, 4, 9)]);
+        assert_eq!(token_at(&tokens, 6).map(|t| t.kind), Some(TokenKind::String));
+        assert_eq!(token_at(&tokens, 0), None);
+    }
+
+    #[test]
+    fn test_bracket_depths() {
+        let line = r#"f(a[0], g("(", {b}))"#;
+        let (brackets, depth) = bracket_depths(line, &profile(), 0);
+        // The bracket in the string is not counted
+        assert_eq!(brackets, vec![(1, 0), (3, 1), (5, 1), (9, 1), (15, 2), (17, 2), (18, 1), (19, 0)]);
+        assert_eq!(depth, 0);
+
+        // Depth carries over from an unclosed line and closes on the next
+        let (brackets, depth) = bracket_depths("call(x, [ // (", &profile(), 0);
+        assert_eq!(brackets, vec![(4, 0), (8, 1)]);
+        assert_eq!(bracket_depths("])", &profile(), depth), (vec![(0, 1), (1, 0)], 0));
+    }
+
+    #[test]
+    fn test_profile_comment_marker() {
+        let line = "x = 1  # let it be";
+        let python = SyntaxProfile::for_filetype(Some("python"));
+        let tokens = tokenize_line(line, &python);
+
+        assert_eq!(tokens.last().map(|t| t.text(line)), Some("# let it be"));
+        assert!(tokenize_line(line, &SyntaxProfile::default()).iter().all(|t| t.kind != TokenKind::Comment));
+        assert!(python.keywords.iter().any(|keyword| keyword == "def"));
+    }
+}
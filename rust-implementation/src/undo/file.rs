@@ -0,0 +1,216 @@
+use super::{EditorAction, TimestampedAction, TimestampedHistory};
+use crate::core::{EditorError, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Bumped whenever the layout below changes, so older files are refused
+/// rather than misread.
+const VERSION: u32 = 2;
+
+/// A buffer's undo history saved in a `.filename.un~` file, like vim's
+/// persistent undo. The file records the checksum of the text it was
+/// written with, and is only trusted when the file still has that text.
+///
+/// The layout is line based: a `undofile <version>` header, the
+/// `checksum`, how many changes are `current` (the rest were undone), and
+/// then each change, oldest first, as `state <unix seconds> <edits>`
+/// followed by its edits. Each edit is `insert <offset> <bytes>` or
+/// `delete <offset> <bytes>` followed by that many bytes of text and a
+/// newline.
+pub struct UndoFile;
+
+impl UndoFile {
+    /// Where the undo history for `file` lives: `dir/.name.un~`.
+    pub fn path_for(file: &Path) -> PathBuf {
+        let name = file.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        file.with_file_name(format!(".{}.un~", name))
+    }
+
+    /// The history as written to disk for a file holding `content`.
+    pub fn serialize(history: &TimestampedHistory<EditorAction>, content: &str) -> String {
+        let now = SystemTime::now();
+        let done = &history.history.undo_stack;
+        // The next state to redo is at the back of the redo stack
+        let undone = history.history.redo_stack.iter().rev();
+
+        let mut text = format!("undofile {}\nchecksum {:016x}\ncurrent {}\n", VERSION, checksum(content), done.len());
+        for state in done.iter().chain(undone) {
+            let saved = now.checked_sub(state.age()).unwrap_or(now);
+            let seconds = saved.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            let edits = edits(&state.action);
+            text.push_str(&format!("state {} {}\n", seconds, edits.len()));
+            for (kind, position, edit) in edits {
+                text.push_str(&format!("{} {} {}\n{}\n", kind, position, edit.len(), edit));
+            }
+        }
+        text
+    }
+
+    /// Reads back a history written by `serialize`, refusing it unless it
+    /// was written for `content` by this version of the format.
+    pub fn deserialize(text: &str, content: &str, max_age: Duration) -> Result<TimestampedHistory<EditorAction>> {
+        let mut rest = text;
+        let version = header(&mut rest, "undofile")?;
+        if version.parse() != Ok(VERSION) {
+            return Err(invalid(&format!("unsupported version {}", version)));
+        }
+        if u64::from_str_radix(header(&mut rest, "checksum")?, 16) != Ok(checksum(content)) {
+            return Err(invalid("the file has changed since it was written"));
+        }
+        let current: usize = header(&mut rest, "current")?.parse().map_err(|_| corrupt())?;
+
+        let (now, wall_clock) = (Instant::now(), SystemTime::now());
+        let mut states = Vec::new();
+        while !rest.is_empty() {
+            let (seconds, count) = header(&mut rest, "state")?.split_once(' ').ok_or_else(corrupt)?;
+            let seconds: u64 = seconds.parse().map_err(|_| corrupt())?;
+            let count: usize = count.parse().map_err(|_| corrupt())?;
+            let edits = (0..count).map(|_| edit(&mut rest)).collect::<Result<Vec<_>>>()?;
+
+            let age = wall_clock.duration_since(UNIX_EPOCH + Duration::from_secs(seconds)).unwrap_or_default();
+            states.push(TimestampedAction::at(EditorAction::Compound(edits), now.checked_sub(age).unwrap_or(now)));
+        }
+        if current > states.len() {
+            return Err(corrupt());
+        }
+
+        let mut history = TimestampedHistory::new(max_age);
+        let undone = states.split_off(current);
+        history.history.undo_stack.extend(states);
+        history.history.redo_stack.extend(undone.into_iter().rev());
+        Ok(history)
+    }
+
+    /// Saves `history` beside `file`, which has just been written with
+    /// `content`.
+    pub fn write(file: &Path, history: &TimestampedHistory<EditorAction>, content: &str) -> Result<()> {
+        fs::write(Self::path_for(file), Self::serialize(history, content))?;
+        Ok(())
+    }
+
+    /// The history saved beside `file`, now holding `content`, or `None`
+    /// when there is none.
+    pub fn read(file: &Path, content: &str, max_age: Duration) -> Result<Option<TimestampedHistory<EditorAction>>> {
+        match fs::read_to_string(Self::path_for(file)) {
+            Ok(text) => Self::deserialize(&text, content, max_age).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// The insertions and deletions `action` makes, in order, as the kind,
+/// byte offset and text of each.
+fn edits(action: &EditorAction) -> Vec<(&'static str, usize, String)> {
+    match action {
+        EditorAction::Insert { position, character } => vec![("insert", *position, character.to_string())],
+        EditorAction::Delete { position, character } => vec![("delete", *position, character.to_string())],
+        EditorAction::InsertText { position, text } => vec![("insert", *position, text.clone())],
+        EditorAction::DeleteText { position, text } => vec![("delete", *position, text.clone())],
+        EditorAction::Compound(actions) => actions.iter().flat_map(edits).collect(),
+    }
+}
+
+/// Reads the edit at the start of `rest`, which is moved past it.
+fn edit(rest: &mut &str) -> Result<EditorAction> {
+    let (line, after) = rest.split_once('\n').ok_or_else(corrupt)?;
+    let mut fields = line.split(' ');
+    let (Some(kind), Some(position), Some(length), None) = (fields.next(), fields.next(), fields.next(), fields.next()) else {
+        return Err(corrupt());
+    };
+    let position: usize = position.parse().map_err(|_| corrupt())?;
+    let length: usize = length.parse().map_err(|_| corrupt())?;
+    let text = after.get(..length).ok_or_else(corrupt)?.to_string();
+    *rest = after[length..].strip_prefix('\n').ok_or_else(corrupt)?;
+    match kind {
+        "insert" => Ok(EditorAction::InsertText { position, text }),
+        "delete" => Ok(EditorAction::DeleteText { position, text }),
+        _ => Err(corrupt()),
+    }
+}
+
+/// FNV-1a, which unlike the standard hasher is fixed, so a checksum
+/// written by one build can be checked by another.
+fn checksum(content: &str) -> u64 {
+    content
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// The value of the `name value` line at the start of `rest`, which is
+/// moved past it.
+fn header<'a>(rest: &mut &'a str, name: &str) -> Result<&'a str> {
+    let (line, after) = rest.split_once('\n').ok_or_else(corrupt)?;
+    let value = line.strip_prefix(name).and_then(|value| value.strip_prefix(' ')).ok_or_else(corrupt)?;
+    *rest = after;
+    Ok(value)
+}
+
+fn invalid(reason: &str) -> EditorError {
+    EditorError::InvalidOperation(format!("Undo file not used: {}", reason))
+}
+
+fn corrupt() -> EditorError {
+    invalid("it is corrupt")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Buffer;
+    use crate::core::TextBuffer;
+    use crate::undo::HistoryStep;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// A history of the changes from each of `states` to the next.
+    fn history_of(states: &[&str]) -> TimestampedHistory<EditorAction> {
+        let mut history = TimestampedHistory::new(DAY);
+        history.save_action(EditorAction::Compound(Vec::new()));
+        for pair in states.windows(2) {
+            history.save_action(EditorAction::between(pair[0], pair[1]).unwrap());
+        }
+        history
+    }
+
+    #[test]
+    fn test_history_survives_a_round_trip() {
+        let mut history = history_of(&["", "one", "one\ntwo\n", "one\ntwo\nthree"]);
+        history.undo_entry();
+
+        let text = UndoFile::serialize(&history, "one\ntwo\n");
+        assert!(text.starts_with("undofile 2\n"));
+        let mut loaded = UndoFile::deserialize(&text, "one\ntwo\n", DAY).unwrap();
+        assert_eq!((loaded.undo_count(), loaded.redo_count()), (2, 1));
+
+        // Replaying the loaded changes gets back every state
+        let mut buffer = Buffer::from_content("one\ntwo\n".to_string());
+        buffer.apply(&loaded.undo_entry().unwrap().inverse()).unwrap();
+        assert_eq!(buffer.content(), "one");
+        for change in loaded.later_entries(HistoryStep::Count(2)) {
+            buffer.apply(&change).unwrap();
+        }
+        assert_eq!(buffer.content(), "one\ntwo\nthree");
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("notes.txt");
+        assert_eq!(UndoFile::path_for(&file), dir.path().join(".notes.txt.un~"));
+        assert!(UndoFile::read(&file, "", DAY).unwrap().is_none());
+        UndoFile::write(&file, &history, "saved").unwrap();
+        assert_eq!(UndoFile::read(&file, "saved", DAY).unwrap().unwrap().undo_count(), 2);
+    }
+
+    #[test]
+    fn test_history_for_other_content_is_refused() {
+        let history = history_of(&["before", "after"]);
+        let text = UndoFile::serialize(&history, "after");
+
+        // The file was changed by something else since the history was saved
+        assert!(UndoFile::deserialize(&text, "after, edited elsewhere", DAY).is_err());
+        // A file from another version of the format
+        assert!(UndoFile::deserialize(&text.replacen("undofile 2", "undofile 1", 1), "after", DAY).is_err());
+        // Cut short
+        assert!(UndoFile::deserialize(&text[..text.len() - 3], "after", DAY).is_err());
+    }
+}
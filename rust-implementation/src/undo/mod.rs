@@ -1,6 +1,9 @@
-use crate::core::{Result, UndoRedoSystem, MAX_HISTORY};
+use crate::core::{UndoRedoSystem, MAX_HISTORY};
 use std::collections::VecDeque;
 
+mod file;
+pub use file::UndoFile;
+
 #[derive(Debug, Clone)]
 pub struct UndoRedoStack<T: Clone> {
     undo_stack: VecDeque<T>,
@@ -37,6 +40,18 @@ impl<T: Clone> UndoRedoStack<T> {
         self.redo_stack.len()
     }
 
+    /// Moves the most recent entry onto the redo stack and returns it.
+    ///
+    /// Unlike `undo`, which hands back the state *before* the popped one
+    /// (snapshot semantics), this returns the popped entry itself, which is
+    /// what action-based histories need in order to invert it.
+    pub fn undo_entry(&mut self) -> Option<T> {
+        let entry = self.undo_stack.pop_back()?;
+        self.redo_stack.push_back(entry.clone());
+        self.enforce_capacity();
+        Some(entry)
+    }
+
     fn enforce_capacity(&mut self) {
         while self.undo_stack.len() > self.max_history {
             self.undo_stack.pop_front();
@@ -100,9 +115,47 @@ pub enum EditorAction {
     Delete { position: usize, character: char },
     InsertText { position: usize, text: String },
     DeleteText { position: usize, text: String },
+    /// Actions made together, such as a group, undone and redone as one.
+    Compound(Vec<EditorAction>),
 }
 
 impl EditorAction {
+    /// The edit turning `old` into `new`: the text between their common
+    /// start and end deleted and the new text put in its place, with
+    /// positions as byte offsets. `None` when they are the same.
+    #[cfg(test)]
+    pub fn between(old: &str, new: &str) -> Option<Self> {
+        let mut prefix = old.bytes().zip(new.bytes()).take_while(|(a, b)| a == b).count();
+        while !old.is_char_boundary(prefix) {
+            prefix -= 1;
+        }
+        let longest = old.len().min(new.len()) - prefix;
+        let mut suffix = old.bytes().rev().zip(new.bytes().rev()).take(longest).take_while(|(a, b)| a == b).count();
+        while !old.is_char_boundary(old.len() - suffix) {
+            suffix -= 1;
+        }
+
+        let removed = &old[prefix..old.len() - suffix];
+        let added = &new[prefix..new.len() - suffix];
+        let delete = EditorAction::DeleteText { position: prefix, text: removed.to_string() };
+        let insert = EditorAction::InsertText { position: prefix, text: added.to_string() };
+        match (removed.is_empty(), added.is_empty()) {
+            (true, true) => None,
+            (false, true) => Some(delete),
+            (true, false) => Some(insert),
+            (false, false) => Some(EditorAction::Compound(vec![delete, insert])),
+        }
+    }
+
+    /// How many bytes of text the action holds.
+    pub fn text_len(&self) -> usize {
+        match self {
+            EditorAction::Insert { character, .. } | EditorAction::Delete { character, .. } => character.len_utf8(),
+            EditorAction::InsertText { text, .. } | EditorAction::DeleteText { text, .. } => text.len(),
+            EditorAction::Compound(actions) => actions.iter().map(EditorAction::text_len).sum(),
+        }
+    }
+
     pub fn inverse(&self) -> Self {
         match self {
             EditorAction::Insert { position, character } => {
@@ -117,6 +170,8 @@ impl EditorAction {
             EditorAction::DeleteText { position, text } => {
                 EditorAction::InsertText { position: *position, text: text.clone() }
             }
+            // Undone last first, so each inverse sees the text its action left
+            EditorAction::Compound(actions) => EditorAction::Compound(actions.iter().rev().map(EditorAction::inverse).collect()),
         }
     }
 }
@@ -150,17 +205,14 @@ impl ActionHistory {
         self.current_group.clear();
     }
 
+    /// Saves the actions recorded since `start_group` as one `Compound`
+    /// action, so a single undo takes back all of them.
     pub fn end_group(&mut self) {
-        if self.group_actions && !self.current_group.is_empty() {
-            // Save the group as a single compound action
-            // For simplicity, we'll save the last action of the group
-            // In a real implementation, you might want to create a CompoundAction type
-            if let Some(last_action) = self.current_group.last() {
-                self.actions.save_state(last_action.clone());
-            }
+        let group = std::mem::take(&mut self.current_group);
+        if self.group_actions && !group.is_empty() {
+            self.actions.save_state(EditorAction::Compound(group));
         }
         self.group_actions = false;
-        self.current_group.clear();
     }
 
     pub fn record_action(&mut self, action: EditorAction) {
@@ -171,8 +223,10 @@ impl ActionHistory {
         }
     }
 
+    /// The action undoing the most recent one. A group comes back as a
+    /// single `Compound` action, to be applied whole.
     pub fn undo_action(&mut self) -> Option<EditorAction> {
-        self.actions.undo().map(|action| action.inverse())
+        self.actions.undo_entry().map(|action| action.inverse())
     }
 
     pub fn redo_action(&mut self) -> Option<EditorAction> {
@@ -211,10 +265,11 @@ pub struct TimestampedAction<T> {
 
 impl<T> TimestampedAction<T> {
     pub fn new(action: T) -> Self {
-        Self {
-            action,
-            timestamp: std::time::Instant::now(),
-        }
+        Self::at(action, std::time::Instant::now())
+    }
+
+    pub fn at(action: T, timestamp: std::time::Instant) -> Self {
+        Self { action, timestamp }
     }
 
     pub fn age(&self) -> std::time::Duration {
@@ -231,6 +286,33 @@ impl<T: Clone> Clone for TimestampedAction<T> {
     }
 }
 
+/// How far `:earlier` and `:later` move: a number of changes, or a time
+/// span such as `30s`, `5m` or `1h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryStep {
+    Count(usize),
+    Time(std::time::Duration),
+}
+
+impl HistoryStep {
+    /// Parses `10`, `30s`, `5m` or `2h`; no argument means one change.
+    pub fn parse(arg: Option<&str>) -> Option<Self> {
+        let Some(arg) = arg else {
+            return Some(HistoryStep::Count(1));
+        };
+        let (digits, unit) = arg.split_at(arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len()));
+        let amount: u64 = digits.parse().ok()?;
+        let seconds = match unit {
+            "" => return Some(HistoryStep::Count(amount as usize)),
+            "s" => amount,
+            "m" => amount * 60,
+            "h" => amount * 60 * 60,
+            _ => return None,
+        };
+        Some(HistoryStep::Time(std::time::Duration::from_secs(seconds)))
+    }
+}
+
 pub struct TimestampedHistory<T: Clone> {
     history: UndoRedoStack<TimestampedAction<T>>,
     max_age: std::time::Duration,
@@ -245,14 +327,118 @@ impl<T: Clone> TimestampedHistory<T> {
     }
 
     pub fn save_action(&mut self, action: T) {
-        let timestamped = TimestampedAction::new(action);
-        self.history.save_state(timestamped);
+        self.save_action_at(action, std::time::Instant::now());
+    }
+
+    pub fn save_action_at(&mut self, action: T, timestamp: std::time::Instant) {
+        self.history.save_state(TimestampedAction::at(action, timestamp));
         self.cleanup_old_actions();
     }
 
+    /// Steps back through the history, by a number of changes or to the
+    /// last entry saved at least the given time before the current one.
+    /// Returns every entry undone on the way, newest first.
+    pub fn earlier_entries(&mut self, step: HistoryStep) -> Vec<T> {
+        let mut undone = Vec::new();
+        match step {
+            HistoryStep::Count(count) => {
+                for _ in 0..count {
+                    let Some(entry) = self.undo_entry() else {
+                        break;
+                    };
+                    undone.push(entry);
+                }
+            }
+            HistoryStep::Time(window) => {
+                let Some(current) = self.history.undo_stack.back().map(|action| action.timestamp) else {
+                    return undone;
+                };
+                let target = current.checked_sub(window);
+                while self.history.undo_stack.len() > 1 {
+                    let newest = self.history.undo_stack.back().map(|action| action.timestamp);
+                    if target.is_some_and(|target| newest.is_some_and(|newest| newest <= target)) {
+                        break;
+                    }
+                    undone.extend(self.undo_entry());
+                }
+            }
+        }
+        undone
+    }
+
+    /// Steps forward through undone changes, by a number of changes or
+    /// over every entry saved within the given time after the current one.
+    /// Returns every entry redone on the way, oldest first.
+    pub fn later_entries(&mut self, step: HistoryStep) -> Vec<T> {
+        let mut redone = Vec::new();
+        match step {
+            HistoryStep::Count(count) => {
+                for _ in 0..count {
+                    let Some(next) = self.redo() else {
+                        break;
+                    };
+                    redone.push(next);
+                }
+            }
+            HistoryStep::Time(window) => {
+                let Some(current) = self.history.undo_stack.back().map(|action| action.timestamp) else {
+                    return redone;
+                };
+                while let Some(next) = self.history.redo_stack.back() {
+                    if next.timestamp > current + window {
+                        break;
+                    }
+                    redone.extend(self.redo());
+                }
+            }
+        }
+        redone
+    }
+
+    /// Undoes the newest state and returns the one before it.
     pub fn undo(&mut self) -> Option<T> {
+        self.undo_entry()?;
+        self.latest().cloned()
+    }
+
+    /// Undoes the newest entry and returns it, for a history of changes
+    /// where the entry itself is what gets inverted.
+    pub fn undo_entry(&mut self) -> Option<T> {
         self.cleanup_old_actions();
-        self.history.undo().map(|timestamped| timestamped.action)
+        // The oldest state is where undoing stops, so it is never undone
+        if self.history.undo_stack.len() < 2 {
+            return None;
+        }
+        self.history.undo_entry().map(|timestamped| timestamped.action)
+    }
+
+    /// Every entry kept, to undo or to redo.
+    pub fn actions(&self) -> impl Iterator<Item = &T> {
+        let done = self.history.undo_stack.iter();
+        done.chain(self.history.redo_stack.iter()).map(|timestamped| &timestamped.action)
+    }
+
+    /// The most recently saved state that hasn't been undone.
+    pub fn latest(&self) -> Option<&T> {
+        self.history.undo_stack.back().map(|timestamped| &timestamped.action)
+    }
+
+    /// How many times `undo` can step back.
+    pub fn undo_count(&self) -> usize {
+        self.history.undo_count().saturating_sub(1)
+    }
+
+    pub fn redo_count(&self) -> usize {
+        self.history.redo_count()
+    }
+
+    /// The age of every state in the history, oldest first, and whether it
+    /// has been undone. The last state not undone is the current one.
+    pub fn entries(&self) -> Vec<(std::time::Duration, bool)> {
+        let done = self.history.undo_stack.iter().map(|action| (action.age(), false));
+        // The next state to redo is at the back of the redo stack
+        let undone = self.history.redo_stack.iter().rev().map(|action| (action.age(), true));
+        done.chain(undone).collect()
     }
 
     pub fn redo(&mut self) -> Option<T> {
@@ -261,22 +447,13 @@ impl<T: Clone> TimestampedHistory<T> {
     }
 
     fn cleanup_old_actions(&mut self) {
-        // Remove actions older than max_age from both stacks
-        // This is a simplified implementation - in practice you might want
-        // to be more careful about maintaining stack integrity
-        while let Some(action) = self.history.undo_stack.back() {
-            if action.age() > self.max_age {
-                self.history.undo_stack.pop_back();
-            } else {
-                break;
-            }
-        }
-
-        while let Some(action) = self.history.redo_stack.back() {
-            if action.age() > self.max_age {
-                self.history.redo_stack.pop_back();
-            } else {
-                break;
+        // Drop the oldest undo states first, always keeping the current one
+        while self.history.undo_stack.len() > 1 {
+            match self.history.undo_stack.front() {
+                Some(action) if action.age() > self.max_age => {
+                    self.history.undo_stack.pop_front();
+                }
+                _ => break,
             }
         }
     }
@@ -352,19 +529,99 @@ mod tests {
         assert_eq!(redo_action, Some(action2));
     }
 
+    /// Applies `action` to `text`, with positions as byte offsets.
+    fn apply(text: &mut String, action: &EditorAction) {
+        match action {
+            EditorAction::Insert { position, character } => text.insert(*position, *character),
+            EditorAction::Delete { position, .. } => {
+                text.remove(*position);
+            }
+            EditorAction::InsertText { position, text: inserted } => text.insert_str(*position, inserted),
+            EditorAction::DeleteText { position, text: deleted } => text.replace_range(*position..*position + deleted.len(), ""),
+            EditorAction::Compound(actions) => actions.iter().for_each(|action| apply(text, action)),
+        }
+    }
+
     #[test]
     fn test_action_grouping() {
         let mut history = ActionHistory::new();
+        let mut text = String::from("!");
 
         history.start_group();
-        history.record_action(EditorAction::Insert { position: 0, character: 'H' });
-        history.record_action(EditorAction::Insert { position: 1, character: 'i' });
+        for (position, character) in [(0, 'H'), (1, 'i')] {
+            let action = EditorAction::Insert { position, character };
+            apply(&mut text, &action);
+            history.record_action(action);
+        }
         history.end_group();
+        assert_eq!(text, "Hi!");
 
-        assert!(history.can_undo());
-        // After grouping, only the last action of the group should be in the stack
+        // The whole group is one entry, undone and redone together
         let (undo_count, _) = history.get_stats();
         assert_eq!(undo_count, 1);
+        apply(&mut text, &history.undo_action().unwrap());
+        assert_eq!(text, "!");
+        assert!(!history.can_undo());
+        apply(&mut text, &history.redo_action().unwrap());
+        assert_eq!(text, "Hi!");
+    }
+
+    #[test]
+    fn test_history_step_parsing() {
+        use std::time::Duration;
+
+        assert_eq!(HistoryStep::parse(None), Some(HistoryStep::Count(1)));
+        assert_eq!(HistoryStep::parse(Some("10")), Some(HistoryStep::Count(10)));
+        assert_eq!(HistoryStep::parse(Some("30s")), Some(HistoryStep::Time(Duration::from_secs(30))));
+        assert_eq!(HistoryStep::parse(Some("5m")), Some(HistoryStep::Time(Duration::from_secs(300))));
+        assert_eq!(HistoryStep::parse(Some("2h")), Some(HistoryStep::Time(Duration::from_secs(7200))));
+        assert_eq!(HistoryStep::parse(Some("5d")), None);
+        assert_eq!(HistoryStep::parse(Some("m")), None);
+    }
+
+    #[test]
+    fn test_earlier_and_later_by_time() {
+        use std::time::{Duration, Instant};
+
+        let start = Instant::now();
+        let mut history = TimestampedHistory::new(Duration::from_secs(24 * 60 * 60));
+        for (state, seconds) in [("a", 0), ("b", 100), ("c", 130), ("d", 140)] {
+            history.save_action_at(state.to_string(), start + Duration::from_secs(seconds));
+        }
+
+        // 30 seconds before "d" (t=140) the text was "b" (t=100)
+        assert_eq!(history.earlier_entries(HistoryStep::Time(Duration::from_secs(30))), ["d", "c"]);
+        assert_eq!(history.latest().map(String::as_str), Some("b"));
+        assert_eq!(history.earlier_entries(HistoryStep::Time(Duration::from_secs(3600))), ["b"]);
+        assert!(history.earlier_entries(HistoryStep::Count(1)).is_empty());
+
+        assert_eq!(history.later_entries(HistoryStep::Time(Duration::from_secs(120))), ["b"]);
+        assert_eq!(history.later_entries(HistoryStep::Count(5)), ["c", "d"]);
+        assert_eq!(history.earlier_entries(HistoryStep::Count(2)), ["d", "c"]);
+        assert_eq!(history.latest().map(String::as_str), Some("b"));
+    }
+
+    #[test]
+    fn test_undo_and_redo_counts() {
+        use std::time::Duration;
+
+        let mut history = TimestampedHistory::new(Duration::from_secs(60));
+        assert_eq!((history.undo_count(), history.redo_count()), (0, 0));
+        for state in ["start", "one", "two", "three"] {
+            history.save_action(state.to_string());
+        }
+        assert_eq!((history.undo_count(), history.redo_count()), (3, 0));
+
+        assert_eq!(history.undo(), Some("two".to_string()));
+        assert_eq!((history.undo_count(), history.redo_count()), (2, 1));
+        let undone: Vec<bool> = history.entries().into_iter().map(|(_, undone)| undone).collect();
+        assert_eq!(undone, vec![false, false, false, true]);
+
+        // Undoing stops at the oldest state
+        assert_eq!(history.earlier_entries(HistoryStep::Count(5)), ["two", "one"]);
+        assert_eq!(history.latest().map(String::as_str), Some("start"));
+        assert_eq!(history.undo(), None);
+        assert_eq!((history.undo_count(), history.redo_count()), (0, 3));
     }
 
     #[test]